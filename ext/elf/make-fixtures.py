@@ -0,0 +1,75 @@
+#!/usr/bin/env python3
+#
+# Writes the ELF fixtures used by the kernel's loader tests
+# (kern/src/process/elf/tests.rs) into this directory.
+
+import os
+import struct
+
+BASE = 0xffffffffc0000000
+PAGE = 0x10000
+
+PT_LOAD = 1
+PT_GNU_STACK = 0x6474e551
+PF_X, PF_W, PF_R = 1, 2, 4
+
+# mov x0, #42; svc #3; b .
+CODE = struct.pack("<3I", 0xd2800540, 0xd4000061, 0x14000000)
+
+
+def ehdr(entry, phnum, machine=183, cls=2):
+    ident = b"\x7fELF" + bytes([cls, 1, 1, 0]) + bytes(8)
+    return ident + struct.pack("<HHIQQQIHHHHHH",
+                               2, machine, 1, entry, 64, 0, 0, 64, 56, phnum, 0, 0, 0)
+
+
+def phdr(kind, flags, offset, vaddr, filesz, memsz):
+    return struct.pack("<IIQQQQQQ", kind, flags, offset, vaddr, vaddr, filesz, memsz, PAGE)
+
+
+def image(segments, entry, machine=183, contents=b""):
+    """`segments` is a list of (kind, flags, offset, vaddr, filesz, memsz)."""
+    data = ehdr(entry, len(segments), machine) + b"".join(phdr(*s) for s in segments)
+    assert len(data) <= 0x100
+    return data.ljust(0x100, b"\0") + contents
+
+
+def hello(machine=183):
+    # Headers and code share a read-only executable segment; the data segment
+    # follows on the same page, with 0x38 bytes of .bss.
+    code = 0x100
+    data = code + len(CODE)
+    return image([
+        (PT_LOAD, PF_R | PF_X, 0, BASE, data, data),
+        (PT_LOAD, PF_R | PF_W, data, BASE + data, 8, 0x40),
+        (PT_GNU_STACK, PF_R | PF_W, 0, 0, 0, 0),
+    ], BASE + code, machine, CODE + struct.pack("<Q", 0x1122334455667788))
+
+
+def bss():
+    # A data segment starting just before a page boundary whose .bss spans
+    # two more pages.
+    code = 0x100
+    return image([
+        (PT_LOAD, PF_R | PF_X, code, BASE + code, len(CODE), len(CODE)),
+        (PT_LOAD, PF_R | PF_W, code + len(CODE), BASE + PAGE - 0x10, 4, 0x20000),
+    ], BASE + code, contents=CODE + b"data")
+
+
+def elf32():
+    ident = b"\x7fELF" + bytes([1, 1, 1, 0]) + bytes(8)
+    return ident + struct.pack("<HHIIIIIHHHHHH", 2, 183, 1, 0, 52, 0, 0, 52, 32, 0, 0, 0, 0)
+
+
+FIXTURES = {
+    "hello.elf": hello(),
+    "x86_64.elf": hello(machine=62),
+    "bss.elf": bss(),
+    "elf32.elf": elf32(),
+}
+
+if __name__ == "__main__":
+    here = os.path.dirname(os.path.abspath(__file__))
+    for name, data in FIXTURES.items():
+        with open(os.path.join(here, name), "wb") as f:
+            f.write(data)
@@ -23,6 +23,15 @@ pub trait LocalAlloc {
     unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout);
 }
 
+/// Memory usage of an allocator, in bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Usage {
+    /// The size of the region the allocator hands out memory from.
+    pub total: usize,
+    /// The part of the region currently allocated.
+    pub used: usize,
+}
+
 /// Thread-safe (locking) wrapper around a particular memory allocator.
 pub struct Allocator(Mutex<Option<AllocatorImpl>>);
 
@@ -46,6 +55,12 @@ impl Allocator {
         let (start, end) = memory_map().expect("failed to find memory map");
         *self.0.lock() = Some(AllocatorImpl::new(start, end));
     }
+
+    /// Returns the memory usage of the allocator, or `None` if it is not yet
+    /// initialized or does not track its usage.
+    pub fn usage(&self) -> Option<Usage> {
+        self.0.lock().as_ref().and_then(|alloc| alloc.usage())
+    }
 }
 
 unsafe impl GlobalAlloc for Allocator {
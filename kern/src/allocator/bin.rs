@@ -4,7 +4,7 @@ use core::ptr;
 
 use crate::allocator::linked_list::LinkedList;
 use crate::allocator::util::*;
-use crate::allocator::LocalAlloc;
+use crate::allocator::{LocalAlloc, Usage};
 
 /// A simple allocator that allocates based on size classes.
 ///   bin 0 (2^3 bytes)    : handles allocations in (0, 2^3]
@@ -25,6 +25,11 @@ impl Allocator {
     pub fn new(start: usize, end: usize) -> Allocator {
         unimplemented!("bin allocator")
     }
+
+    /// Returns `None`: the bin allocator does not track the memory in use.
+    pub fn usage(&self) -> Option<Usage> {
+        None
+    }
 }
 
 impl LocalAlloc for Allocator {
@@ -2,11 +2,12 @@ use core::alloc::Layout;
 use core::ptr;
 
 use crate::allocator::util::*;
-use crate::allocator::LocalAlloc;
+use crate::allocator::{LocalAlloc, Usage};
 
 /// A "bump" allocator: allocates memory by bumping a pointer; never frees.
 #[derive(Debug)]
 pub struct Allocator {
+    start: usize,
     current: usize,
     end: usize,
 }
@@ -17,10 +18,20 @@ impl Allocator {
     #[allow(dead_code)]
     pub fn new(start: usize, end: usize) -> Allocator {
         Allocator {
+            start: start,
             current: start,
             end: end,
         }
     }
+
+    /// Returns the memory handed out so far out of the whole region. Freed
+    /// memory is never reclaimed, so it counts as used.
+    pub fn usage(&self) -> Option<Usage> {
+        Some(Usage {
+            total: self.end - self.start,
+            used: self.current - self.start,
+        })
+    }
 }
 
 impl LocalAlloc for Allocator {
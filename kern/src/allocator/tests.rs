@@ -76,7 +76,7 @@ mod allocator {
 
     use core::alloc::Layout;
 
-    use crate::allocator::{bin, bump, LocalAlloc};
+    use crate::allocator::{bin, bump, LocalAlloc, Usage};
 
     macro_rules! test_allocators {
         (@$kind:ident, $name:ident, $mem:expr, |$info:pat| $block:expr) => {
@@ -162,6 +162,15 @@ mod allocator {
         assert!(result.is_null());
     });
 
+    test_allocators!(@bump, bump_usage, 4096, |(_, _, mut a)| {
+        assert_eq!(a.usage(), Some(Usage { total: 4096, used: 0 }));
+
+        assert!(!a.alloc(layout!(1024, 16)).is_null());
+        let usage = a.usage().expect("usage");
+        assert!(usage.used >= 1024);
+        assert!(usage.used <= usage.total);
+    });
+
     test_allocators!(bin_alloc, bump_alloc, 8 * (1 << 20), |(start, end, a)| {
         let layouts = [
             layout!(16, 16),
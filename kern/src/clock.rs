@@ -0,0 +1,34 @@
+//! Wall-clock time.
+//!
+//! The board has no real-time clock, so the time of day is kept as the Unix
+//! time at which the system timer started counting. It is taken from the
+//! `date=<seconds since the Unix epoch>` kernel command line option at boot
+//! and may be changed later with `set()`; until then the clock starts at the
+//! Unix epoch.
+
+use core::time::Duration;
+
+use pi::timer::current_time;
+
+use crate::mutex::Mutex;
+use crate::param::cmdline_option;
+
+/// The Unix time at which the system timer read zero.
+static BOOT_TIME: Mutex<Duration> = Mutex::new(Duration::from_secs(0));
+
+/// Sets the clock from the `date=` kernel command line option, if present.
+pub fn initialize() {
+    if let Some(secs) = cmdline_option("date").and_then(|date| date.parse().ok()) {
+        set(Duration::from_secs(secs));
+    }
+}
+
+/// Returns the current time as the time since the Unix epoch.
+pub fn now() -> Duration {
+    *BOOT_TIME.lock() + current_time()
+}
+
+/// Sets the current time to `now`, a time since the Unix epoch.
+pub fn set(now: Duration) {
+    *BOOT_TIME.lock() = now.checked_sub(current_time()).unwrap_or(Duration::from_secs(0));
+}
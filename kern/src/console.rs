@@ -1,4 +1,5 @@
 use core::fmt;
+use core::time::Duration;
 use pi::uart::MiniUart;
 use shim::io;
 
@@ -30,6 +31,11 @@ impl Console {
         self.inner.as_mut().unwrap()
     }
 
+    /// Returns `true` if there is at least one byte ready to be read.
+    pub fn has_byte(&mut self) -> bool {
+        self.inner().has_byte()
+    }
+
     /// Reads a byte from the UART device, blocking until a byte is available.
     pub fn read_byte(&mut self) -> u8 {
         self.inner().read_byte()
@@ -39,6 +45,15 @@ impl Console {
     pub fn write_byte(&mut self, byte: u8) {
         self.inner().write_byte(byte)
     }
+
+    /// Sets the read timeout of the UART device to `t`, or makes reads block
+    /// indefinitely if `t` is `None`.
+    pub fn set_read_timeout(&mut self, t: Option<Duration>) {
+        match t {
+            Some(t) => self.inner().set_read_timeout(t),
+            None => self.inner().clear_read_timeout(),
+        }
+    }
 }
 
 impl io::Read for Console {
@@ -1,16 +1,33 @@
+pub mod devfs;
+pub mod procfs;
+pub mod ramdisk;
 pub mod sd;
+pub mod vfs;
 
+use alloc::format;
 use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::fmt::{self, Debug};
 use shim::io;
 use shim::ioerr;
+use shim::newioerr;
 use shim::path::Path;
 
 pub use fat32::traits;
-use fat32::vfat::{Dir, Entry, File, VFat, VFatHandle};
+use fat32::format::{Layout, Options};
+use fat32::partition::Selector;
+use fat32::traits::BlockDevice;
+use fat32::vfat::{CacheStats, Error, StatFs, VFat, VFatHandle};
+use pi::fb::FrameBuffer;
 
+use self::devfs::DevFs;
+use self::procfs::ProcFs;
 use self::sd::Sd;
+use self::vfs::{Mount, MountTable, Mountable};
+use crate::clock;
 use crate::mutex::Mutex;
+use crate::param::cmdline_option;
 
 #[derive(Clone)]
 pub struct PiVFatHandle(Rc<Mutex<VFat<Self>>>);
@@ -38,7 +55,133 @@ impl VFatHandle for PiVFatHandle {
         f(&mut self.0.lock())
     }
 }
-pub struct FileSystem(Mutex<Option<PiVFatHandle>>);
+
+/// Returns the partition named by the `root=` option of the kernel command
+/// line, if any.
+fn root_partition() -> Option<Selector> {
+    cmdline_option("root").and_then(|root| root.parse().ok())
+}
+
+/// Has the volume behind `handle` timestamp entries with the wall clock.
+fn with_clock(handle: PiVFatHandle) -> PiVFatHandle {
+    handle.lock(|vfat| vfat.set_clock(clock::now));
+    handle
+}
+
+/// Returns `e`, an error mounting a FAT volume, as an I/O error.
+fn mount_error(e: Error) -> io::Error {
+    match e {
+        Error::Io(e) => e,
+        _ => newioerr!(InvalidData, "no FAT volume found"),
+    }
+}
+
+/// The SD card, shared by every volume mounted from it so that the
+/// controller is initialized only once.
+#[derive(Clone)]
+struct SharedSd(Rc<Mutex<Sd>>);
+
+// Unsound for the same reasons as the impls for `PiVFatHandle`.
+unsafe impl Send for SharedSd {}
+
+impl BlockDevice for SharedSd {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().read_sector(n, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().write_sector(n, buf)
+    }
+
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().read_sectors(start, count, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().write_sectors(start, count, buf)
+    }
+}
+
+impl SharedSd {
+    /// Returns the capacity of the card in sectors.
+    fn sectors(&self) -> u64 {
+        self.0.lock().sectors()
+    }
+
+    /// Returns the FAT volume on the partition chosen by `selector`, or as
+    /// found by `VFat::from()` if there is none.
+    fn volume(&self, selector: Option<Selector>) -> io::Result<PiVFatHandle> {
+        let vfat = match selector {
+            Some(selector) => VFat::from_partition(self.clone(), selector),
+            None => VFat::from(self.clone()),
+        };
+        vfat.map(with_clock).map_err(mount_error)
+    }
+}
+
+/// Returns the name of the SD card volume chosen by `selector` in mount
+/// listings: `sd` or `sd:<selector>`.
+fn sd_source(selector: Option<Selector>) -> String {
+    match selector {
+        Some(selector) => format!("sd:{}", selector),
+        None => String::from("sd"),
+    }
+}
+
+impl Mountable for PiVFatHandle {
+    fn kind(&self) -> &'static str {
+        "vfat"
+    }
+
+    fn open(&self, path: &Path) -> io::Result<vfs::Entry> {
+        traits::FileSystem::open(self, path).map(vfs::Entry::from_fs)
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<vfs::File> {
+        traits::FileSystem::create_file(self, path).map(vfs::File::from_fs)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<vfs::Dir> {
+        traits::FileSystem::create_dir(self, path).map(vfs::Dir::from_fs)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        traits::FileSystem::remove(self, path)
+    }
+
+    fn statfs(&self) -> io::Result<StatFs> {
+        self.lock(|vfat| vfat.statfs())
+    }
+
+    fn check(&self, repair: bool) -> io::Result<fat32::check::Report> {
+        fat32::check::check(self, repair)
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.lock(|vfat| vfat.cache_stats()))
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.lock(|vfat| vfat.flush())
+    }
+
+    fn device_range(&self) -> Option<(u64, u64)> {
+        Some(self.lock(|vfat| vfat.device_range()))
+    }
+
+    fn busy(&self) -> bool {
+        Rc::strong_count(&self.0) > 1
+    }
+}
+
+struct Mounts {
+    sd: SharedSd,
+    table: MountTable,
+}
+
+/// The kernel's virtual file system: a tree of file systems mounted at paths,
+/// with the SD card's FAT volume at the root.
+pub struct FileSystem(Mutex<Option<Mounts>>);
 
 impl FileSystem {
     /// Returns an uninitialized `FileSystem`.
@@ -53,26 +196,214 @@ impl FileSystem {
     /// The caller should assure that the method is invoked only once during the
     /// kernel initialization.
     ///
+    /// The partition to mount at `/` may be chosen on the kernel command line
+    /// with `root=<index>` or `root=<GUID>`; otherwise the first FAT partition
+    /// is mounted. Nothing is mounted if there is no FAT volume; otherwise
+    /// `ProcFs` is mounted at `/proc`.
+    ///
     /// # Panics
     ///
-    /// Panics if the underlying disk or file sytem failed to initialize.
+    /// Panics if the underlying disk failed to initialize.
     pub unsafe fn initialize(&self) {
-        let vfat = VFat::from(Sd::new().expect("error initializing Sd")).ok();
-        *self.0.lock() = vfat
+        let sd = SharedSd(Rc::new(Mutex::new(Sd::new().expect("error initializing Sd"))));
+        let mut table = MountTable::new();
+        if let Ok(vfat) = sd.volume(root_partition()) {
+            table.mount(Path::new("/"), &sd_source(root_partition()), Rc::new(vfat))
+                .expect("error mounting root file system");
+            table.mount(Path::new("/proc"), "proc", Rc::new(ProcFs))
+                .expect("error mounting /proc");
+        }
+        *self.0.lock() = Some(Mounts { sd, table });
+    }
+
+    /// Calls `f` with the mounts, once initialized.
+    fn with<R>(&self, f: impl FnOnce(&mut Mounts) -> io::Result<R>) -> io::Result<R> {
+        match self.0.lock().as_mut() {
+            Some(mounts) => f(mounts),
+            None => ioerr!(NotFound, "no file system mounted"),
+        }
+    }
+
+    /// Returns the current mounts, ordered by path.
+    pub fn mounts(&self) -> Vec<Mount> {
+        self.with(|mounts| Ok(mounts.table.mounts().to_vec())).unwrap_or_default()
+    }
+
+    /// Mounts `fs` at `path`, naming it `source` in listings. See
+    /// `MountTable::mount()`.
+    pub fn mount<P: AsRef<Path>>(&self, source: &str, fs: Rc<dyn Mountable>, path: P) -> io::Result<()> {
+        let path = fat32::path::normalize(path)?;
+        self.with(|mounts| mounts.table.mount(&path, source, fs))
+    }
+
+    /// Mounts the FAT volume on `device`, found as by `VFat::from()`, at
+    /// `path`, e.g. to use a `RamDisk` as scratch space.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if `device` holds no FAT volume, or
+    /// any error from `MountTable::mount()`.
+    pub fn mount_device<T, P>(&self, device: T, source: &str, path: P) -> io::Result<()>
+        where T: BlockDevice + 'static, P: AsRef<Path>
+    {
+        let vfat = VFat::from(device).map_err(mount_error)?;
+        self.mount(source, Rc::new(with_clock(vfat)), path)
+    }
+
+    /// Mounts the FAT volume on the partition of the SD card chosen by
+    /// `selector`, or the first one if `None`, at `path`.
+    pub fn mount_sd<P: AsRef<Path>>(&self, selector: Option<Selector>, path: P) -> io::Result<()> {
+        let path = fat32::path::normalize(path)?;
+        self.with(|mounts| {
+            let vfat = mounts.sd.volume(selector)?;
+            mounts.table.mount(&path, &sd_source(selector), Rc::new(vfat))
+        })
+    }
+
+    /// Mounts a `DevFs` at `path`, exposing `framebuffer` as `fb0` if given.
+    pub fn mount_devices<P: AsRef<Path>>(&self, framebuffer: Option<FrameBuffer>, path: P) -> io::Result<()> {
+        let path = fat32::path::normalize(path)?;
+        self.with(|mounts| {
+            let devfs = DevFs::new(mounts.sd.clone(), framebuffer);
+            mounts.table.mount(&path, "dev", Rc::new(devfs))
+        })
+    }
+
+    /// Flushes and unmounts the file system mounted at `path`. See
+    /// `MountTable::unmount()`.
+    pub fn unmount<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = fat32::path::normalize(path)?;
+        self.with(|mounts| mounts.table.unmount(&path).map(|_| ()))
+    }
+
+    /// Writes any data cached by the mounted file systems back to their
+    /// devices.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from flushing a file system; the others are
+    /// still flushed.
+    pub fn sync(&self) -> io::Result<()> {
+        self.with(|mounts| {
+            let mut result = Ok(());
+            for mount in mounts.table.mounts() {
+                let flushed = mount.fs().flush();
+                if result.is_ok() {
+                    result = flushed;
+                }
+            }
+            result
+        })
+    }
+
+    /// Flushes every volume mounted from the SD card that overlaps the
+    /// `sectors` sectors of the card starting at `start`, so that raw reads
+    /// see their writes. Returns whether there were any.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from flushing a volume.
+    pub fn flush_sd(&self, start: u64, sectors: u64) -> io::Result<bool> {
+        self.with(|mounts| {
+            let mut mounted = false;
+            for mount in mounts.table.mounts().iter().filter(|mount| mount.source().starts_with("sd")) {
+                match mount.fs().device_range() {
+                    Some((first, len)) if first < start + sectors && start < first + len => {
+                        mount.fs().flush()?;
+                        mounted = true;
+                    }
+                    _ => (),
+                }
+            }
+            Ok(mounted)
+        })
+    }
+
+    /// Checks the consistency of the file system holding `path`, repairing
+    /// any problem found if `repair` is `true`. See `fat32::check`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `NotFound` if no file system holds `path`, of
+    /// `Other` if it cannot be checked, or any error from reading or writing
+    /// the disk.
+    pub fn check<P: AsRef<Path>>(&self, path: P, repair: bool) -> io::Result<fat32::check::Report> {
+        let path = fat32::path::normalize(path)?;
+        self.with(|mounts| mounts.table.resolve(&path)?.0.fs().check(repair))
+    }
+
+    /// Returns the space usage of the file system holding `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `NotFound` if no file system holds `path`, of
+    /// `Other` if it has no notion of space, or any error from reading the
+    /// FAT.
+    pub fn statfs<P: AsRef<Path>>(&self, path: P) -> io::Result<StatFs> {
+        let path = fat32::path::normalize(path)?;
+        self.with(|mounts| mounts.table.resolve(&path)?.0.fs().statfs())
+    }
+
+    /// Creates an empty FAT volume on the SD card as described by `options`
+    /// and mounts it at `/` in place of the SD card volume there, if any. See
+    /// `fat32::format`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` if another partition of the card is
+    /// mounted or files or directories of the volume at `/` are still open,
+    /// or any error from formatting the card. Nothing is mounted at `/` if
+    /// formatting fails.
+    pub fn format(&self, options: &Options) -> io::Result<Layout> {
+        let root = Path::new("/");
+        self.with(|mounts| {
+            let on_sd = |mount: &Mount| mount.source().starts_with("sd");
+            if mounts.table.mounts().iter().any(|mount| mount.path() != root && on_sd(mount)) {
+                return ioerr!(Other, "a partition of the SD card is mounted");
+            }
+
+            let replace = mounts.table.get(root).map_or(true, on_sd);
+            if replace && mounts.table.get(root).is_some() {
+                mounts.table.detach(root)?;
+            }
+
+            let layout = fat32::format::format(&mut mounts.sd, options)?;
+            if replace {
+                let vfat = mounts.sd.volume(None)?;
+                mounts.table.mount(root, &sd_source(None), Rc::new(vfat))?;
+            }
+            Ok(layout)
+        })
     }
 }
 
 // Implement `fat32::traits::FileSystem` for `&FileSystem`
 impl<'a> fat32::traits::FileSystem for &'a FileSystem {
-    type File = <&'a PiVFatHandle as fat32::traits::FileSystem>::File;
+    type File = vfs::File;
 
     /// The type of directories in this file system.
-    type Dir = <&'a PiVFatHandle as fat32::traits::FileSystem>::Dir;
+    type Dir = vfs::Dir;
 
     /// The type of directory entries in this file system.
-    type Entry = <&'a PiVFatHandle as fat32::traits::FileSystem>::Entry;
+    type Entry = vfs::Entry;
 
     fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
-        self.0.lock().as_ref().expect("handle").open(path)
+        let path = fat32::path::normalize(path)?;
+        self.with(|mounts| mounts.table.open(&path))
+    }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let path = fat32::path::normalize(path)?;
+        self.with(|mounts| mounts.table.create_file(&path))
+    }
+
+    fn create_dir<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Dir> {
+        let path = fat32::path::normalize(path)?;
+        self.with(|mounts| mounts.table.create_dir(&path))
+    }
+
+    fn remove<P: AsRef<Path>>(self, path: P) -> io::Result<()> {
+        let path = fat32::path::normalize(path)?;
+        self.with(|mounts| mounts.table.remove(&path))
     }
 }
@@ -0,0 +1,506 @@
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::{self, Vec};
+use core::cmp;
+use core::fmt;
+
+use shim::io::{self, Read, Seek, SeekFrom, Write};
+use shim::ioerr;
+use shim::path::{Component, Path};
+
+use fat32::partition::{self, PartitionInfo};
+use fat32::traits::{self, BlockDevice};
+use fat32::vfat::{Attributes, Metadata, Timestamp};
+use kernel_api::FB_GET_MODE;
+use pi::fb::FrameBuffer;
+use pi::gpio::Gpio;
+
+use crate::clock;
+use crate::console::CONSOLE;
+use crate::fs::vfs::{self, Mountable};
+use crate::fs::SharedSd;
+use crate::mutex::Mutex;
+use crate::FILESYSTEM;
+
+/// The number of GPIO pins, each exposed as `gpio<N>` unless reserved.
+const GPIO_PINS: u8 = 54;
+
+/// Returns `true` if `pin` drives a device the kernel owns: the mini UART on
+/// 14 and 15 or the SD card on 48 to 53.
+fn is_reserved_pin(pin: u8) -> bool {
+    pin == 14 || pin == 15 || (48..=53).contains(&pin)
+}
+
+/// The framebuffer, shared by `DevFs` and every open `fb0`.
+#[derive(Clone)]
+struct SharedFb(Rc<Mutex<FrameBuffer>>);
+
+// Unsound for the same reasons as the impls for `PiVFatHandle`.
+unsafe impl Send for SharedFb {}
+
+impl SharedFb {
+    /// Returns the size of the framebuffer in bytes.
+    fn len(&self) -> u64 {
+        let fb = self.0.lock();
+        fb.pitch as u64 * fb.height as u64
+    }
+
+    /// Returns a pointer to byte `offset` of the framebuffer.
+    fn at(&self, offset: u64) -> *mut u8 {
+        unsafe { (self.0.lock().fb_addr as *mut u8).add(offset as usize) }
+    }
+}
+
+/// A node of the file system: the root directory or a device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Node {
+    Root,
+    Console,
+    Uart,
+    FrameBuffer,
+    Gpio(u8),
+    Sd,
+    SdPartition(PartitionInfo),
+}
+
+/// A file system of devices:
+///
+///   * `console`: the console, translating line endings as a terminal does
+///   * `uart0`: the mini UART behind the console, untranslated
+///   * `fb0`: the framebuffer's pixels, if there is a framebuffer
+///   * `gpio<N>`: the level of GPIO pin `N`, as `0` or `1`, for every pin not
+///     used by the UART or SD card
+///   * `sd0`: the whole SD card
+///   * `sd0p<N>`: partition `N` of the SD card, counting from 1
+///
+/// The SD card devices read what mounted volumes on them have written, and
+/// refuse writes while any volume they cover is mounted.
+#[derive(Clone)]
+pub struct DevFs {
+    sd: SharedSd,
+    framebuffer: Option<SharedFb>,
+}
+
+impl DevFs {
+    /// Returns a `DevFs` exposing `sd` and `framebuffer`, if any.
+    pub(super) fn new(sd: SharedSd, framebuffer: Option<FrameBuffer>) -> DevFs {
+        DevFs { sd, framebuffer: framebuffer.map(|fb| SharedFb(Rc::new(Mutex::new(fb)))) }
+    }
+
+    /// Returns the devices of the root directory with their names. The
+    /// partition table is read anew each time as the card may be formatted.
+    fn nodes(&self) -> Vec<(String, Node)> {
+        let mut nodes = Vec::new();
+        nodes.push((String::from("console"), Node::Console));
+        nodes.push((String::from("uart0"), Node::Uart));
+        if self.framebuffer.is_some() {
+            nodes.push((String::from("fb0"), Node::FrameBuffer));
+        }
+        for pin in (0..GPIO_PINS).filter(|&pin| !is_reserved_pin(pin)) {
+            nodes.push((format!("gpio{}", pin), Node::Gpio(pin)));
+        }
+        nodes.push((String::from("sd0"), Node::Sd));
+        for part in partition::read_partitions(self.sd.clone()).unwrap_or_default() {
+            nodes.push((format!("sd0p{}", part.index + 1), Node::SdPartition(part)));
+        }
+        nodes
+    }
+
+    /// Returns the entry for `node`, named `name`.
+    fn entry(&self, name: String, node: Node) -> Entry {
+        let device = match node {
+            Node::Root => return Entry { name, metadata: metadata(node), inner: Inner::Dir(Dir(self.clone())) },
+            Node::Console => Device::Console,
+            Node::Uart => Device::Uart,
+            Node::FrameBuffer => match self.framebuffer {
+                Some(ref fb) => Device::FrameBuffer(fb.clone()),
+                None => unreachable!("fb0 without a framebuffer"),
+            },
+            Node::Gpio(pin) => Device::Gpio(pin),
+            Node::Sd => Device::Block { sd: self.sd.clone(), start: 0, sectors: self.sd.sectors() },
+            Node::SdPartition(part) => {
+                Device::Block { sd: self.sd.clone(), start: part.start, sectors: part.num_sectors }
+            }
+        };
+        Entry { name, metadata: metadata(node), inner: Inner::File(File { device, offset: 0 }) }
+    }
+}
+
+impl fmt::Debug for DevFs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DevFs").field("framebuffer", &self.framebuffer.is_some()).finish()
+    }
+}
+
+/// Returns the console as `/dev/console` opens it, whether or not a `DevFs`
+/// is mounted.
+pub fn console() -> vfs::File {
+    vfs::File::from_fs(File { device: Device::Console, offset: 0 })
+}
+
+/// Returns the metadata of every node, stamped with the current time.
+fn metadata(node: Node) -> Metadata {
+    let directory = if node == Node::Root { 0x10 } else { 0 };
+    let now = Timestamp::from_unix(clock::now());
+    Metadata { attr: Attributes::from(directory), ctime: now, atime: now, mtime: now }
+}
+
+/// Returns the offset `pos` resolves to in a file at `offset` of `size`
+/// bytes, if known.
+///
+/// # Errors
+///
+/// Returns an error of `InvalidInput` if the offset would be negative or past
+/// the end of the file, or if `pos` is relative to an unknown end.
+fn seek_to(offset: u64, size: Option<u64>, pos: SeekFrom) -> io::Result<u64> {
+    let (base, delta) = match pos {
+        SeekFrom::Start(n) => (0, n as i64),
+        SeekFrom::Current(n) => (offset as i64, n),
+        SeekFrom::End(n) => match size {
+            Some(size) => (size as i64, n),
+            None => return ioerr!(InvalidInput, "device size unknown"),
+        },
+    };
+
+    match base.checked_add(delta) {
+        Some(new) if new >= 0 && size.map_or(true, |size| new as u64 <= size) => Ok(new as u64),
+        _ => ioerr!(InvalidInput, "seek out of bounds"),
+    }
+}
+
+enum Device {
+    Console,
+    Uart,
+    FrameBuffer(SharedFb),
+    Gpio(u8),
+    Block { sd: SharedSd, start: u64, sectors: u64 },
+}
+
+impl fmt::Debug for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Device::Console => write!(f, "Console"),
+            Device::Uart => write!(f, "Uart"),
+            Device::FrameBuffer(_) => write!(f, "FrameBuffer"),
+            Device::Gpio(pin) => write!(f, "Gpio({})", pin),
+            Device::Block { start, sectors, .. } => {
+                f.debug_struct("Block").field("start", &start).field("sectors", &sectors).finish()
+            }
+        }
+    }
+}
+
+/// An open device of `DevFs`. The console and UART are streams that cannot
+/// seek, and reading one fails with `WouldBlock` until input arrives; a GPIO
+/// pin reads as two bytes, its level and a newline, sampled when
+/// read from the start.
+#[derive(Debug)]
+pub struct File {
+    device: Device,
+    offset: u64,
+}
+
+impl File {
+    /// Returns the size of the device in bytes, if it has one.
+    fn len(&self) -> Option<u64> {
+        match self.device {
+            Device::Console | Device::Uart => None,
+            Device::FrameBuffer(ref fb) => Some(fb.len()),
+            Device::Gpio(_) => Some(2),
+            Device::Block { ref sd, sectors, .. } => Some(sectors * sd.sector_size()),
+        }
+    }
+
+    /// Returns how many of `len` bytes from the current offset lie within the
+    /// device.
+    fn available(&self, len: usize) -> usize {
+        match self.len() {
+            Some(size) => cmp::min(len as u64, size.saturating_sub(self.offset)) as usize,
+            None => len,
+        }
+    }
+}
+
+/// Reads from the block device `sd` at byte `offset` into `buf`, reading
+/// whole sectors straight into `buf` and others through a sector buffer.
+fn read_block(sd: &mut SharedSd, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+    let sector_size = sd.sector_size();
+    let (sector, within) = (offset / sector_size, (offset % sector_size) as usize);
+    let whole = buf.len() as u64 / sector_size;
+    if within == 0 && whole > 0 {
+        return sd.read_sectors(sector, whole, &mut buf[..(whole * sector_size) as usize]);
+    }
+
+    let mut data = [0u8; 512];
+    let data = &mut data[..sector_size as usize];
+    sd.read_sector(sector, data)?;
+    let n = cmp::min(buf.len(), data.len() - within);
+    buf[..n].copy_from_slice(&data[within..within + n]);
+    Ok(n)
+}
+
+/// Writes `buf` to the block device `sd` at byte `offset`, reading and
+/// rewriting the sector when `buf` covers only part of it.
+fn write_block(sd: &mut SharedSd, offset: u64, buf: &[u8]) -> io::Result<usize> {
+    let sector_size = sd.sector_size();
+    let (sector, within) = (offset / sector_size, (offset % sector_size) as usize);
+    let whole = buf.len() as u64 / sector_size;
+    if within == 0 && whole > 0 {
+        return sd.write_sectors(sector, whole, &buf[..(whole * sector_size) as usize]);
+    }
+
+    let mut data = [0u8; 512];
+    let data = &mut data[..sector_size as usize];
+    sd.read_sector(sector, data)?;
+    let n = cmp::min(buf.len(), data.len() - within);
+    data[within..within + n].copy_from_slice(&buf[..n]);
+    sd.write_sector(sector, data)?;
+    Ok(n)
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.available(buf.len());
+        let buf = &mut buf[..len];
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let read = match self.device {
+            Device::Console | Device::Uart if !CONSOLE.lock().has_byte() => {
+                return ioerr!(WouldBlock, "no input waiting");
+            }
+            Device::Console => {
+                let read = CONSOLE.lock().read(buf)?;
+                for byte in buf[..read].iter_mut().filter(|byte| **byte == b'\r') {
+                    *byte = b'\n';
+                }
+                read
+            }
+            Device::Uart => CONSOLE.lock().read(buf)?,
+            Device::FrameBuffer(ref fb) => {
+                unsafe { core::ptr::copy_nonoverlapping(fb.at(self.offset), buf.as_mut_ptr(), len) };
+                len
+            }
+            Device::Gpio(pin) => {
+                let level = if Gpio::new(pin).level() { b'1' } else { b'0' };
+                let contents = [level, b'\n'];
+                buf.copy_from_slice(&contents[self.offset as usize..self.offset as usize + len]);
+                len
+            }
+            Device::Block { ref mut sd, start, sectors } => {
+                // Mounted volumes cache sectors; write theirs back first.
+                FILESYSTEM.flush_sd(start, sectors)?;
+                read_block(sd, start * sd.sector_size() + self.offset, buf)?
+            }
+        };
+
+        if self.len().is_some() {
+            self.offset += read as u64;
+        }
+        Ok(read)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.device {
+            Device::Console => {
+                let mut console = CONSOLE.lock();
+                for &byte in buf {
+                    if byte == b'\n' {
+                        console.write_byte(b'\r');
+                    }
+                    console.write_byte(byte);
+                }
+                return Ok(buf.len());
+            }
+            Device::Uart => return CONSOLE.lock().write(buf),
+            Device::Gpio(pin) => {
+                let mut pin = Gpio::new(pin).into_output();
+                match buf.iter().find(|byte| !byte.is_ascii_whitespace()) {
+                    Some(b'0') => pin.clear(),
+                    Some(b'1') => pin.set(),
+                    Some(_) => return ioerr!(InvalidInput, "level must be 0 or 1"),
+                    None => (),
+                }
+                return Ok(buf.len());
+            }
+            _ => (),
+        }
+
+        let len = self.available(buf.len());
+        let written = match self.device {
+            _ if len == 0 => 0,
+            Device::FrameBuffer(ref fb) => {
+                unsafe { core::ptr::copy_nonoverlapping(buf.as_ptr(), fb.at(self.offset), len) };
+                len
+            }
+            Device::Block { ref mut sd, start, sectors } => {
+                // A write under a mounted volume would be lost to, or corrupt,
+                // its cache.
+                if FILESYSTEM.flush_sd(start, sectors)? {
+                    return ioerr!(PermissionDenied, "a volume on the device is mounted");
+                }
+                write_block(sd, start * sd.sector_size() + self.offset, &buf[..len])?
+            }
+            _ => unreachable!("stream devices are written above"),
+        };
+
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self.device {
+            Device::Console | Device::Uart => ioerr!(InvalidInput, "device cannot seek"),
+            _ => {
+                self.offset = seek_to(self.offset, self.len(), pos)?;
+                Ok(self.offset)
+            }
+        }
+    }
+}
+
+impl traits::File for File {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.len().unwrap_or(0)
+    }
+
+    /// Supports `FB_GET_MODE` on `fb0`.
+    fn control(&mut self, request: u32, data: &mut [u8]) -> io::Result<usize> {
+        let fb = match (&self.device, request) {
+            (Device::FrameBuffer(fb), FB_GET_MODE) => fb.0.lock(),
+            (Device::FrameBuffer(_), _) => return ioerr!(InvalidInput, "unknown request"),
+            _ => return ioerr!(Other, "operation not supported by device"),
+        };
+
+        let mode = [fb.width, fb.height, fb.pitch, fb.depth, fb.isrgb];
+        if data.len() < mode.len() * 4 {
+            return ioerr!(InvalidInput, "buffer too small");
+        }
+        for (chunk, value) in data.chunks_mut(4).zip(mode.iter()) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        Ok(mode.len() * 4)
+    }
+}
+
+/// The root directory of `DevFs`.
+#[derive(Debug)]
+pub struct Dir(DevFs);
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = vec::IntoIter<Entry>;
+
+    fn entries(&self) -> io::Result<Self::Iter> {
+        let fs = &self.0;
+        Ok(fs.nodes().into_iter().map(|(name, node)| fs.entry(name, node)).collect::<Vec<_>>().into_iter())
+    }
+}
+
+#[derive(Debug)]
+enum Inner {
+    File(File),
+    Dir(Dir),
+}
+
+/// An entry of `DevFs`.
+#[derive(Debug)]
+pub struct Entry {
+    name: String,
+    metadata: Metadata,
+    inner: Inner,
+}
+
+impl traits::Entry for Entry {
+    type File = File;
+    type Dir = Dir;
+    type Metadata = Metadata;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        match self.inner {
+            Inner::File(ref file) => Some(file),
+            Inner::Dir(_) => None,
+        }
+    }
+
+    fn as_dir(&self) -> Option<&Dir> {
+        match self.inner {
+            Inner::Dir(ref dir) => Some(dir),
+            Inner::File(_) => None,
+        }
+    }
+
+    fn into_file(self) -> Option<File> {
+        match self.inner {
+            Inner::File(file) => Some(file),
+            Inner::Dir(_) => None,
+        }
+    }
+
+    fn into_dir(self) -> Option<Dir> {
+        match self.inner {
+            Inner::Dir(dir) => Some(dir),
+            Inner::File(_) => None,
+        }
+    }
+}
+
+impl<'a> traits::FileSystem for &'a DevFs {
+    type File = File;
+    type Dir = Dir;
+    type Entry = Entry;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Entry> {
+        let path = fat32::path::normalize(path)?;
+        let mut names = path.components().filter_map(|component| match component {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        });
+
+        match (names.next(), names.next()) {
+            (None, _) => Ok(self.entry(String::from("/"), Node::Root)),
+            (Some(name), rest) => {
+                let (name, node) = match self.nodes().into_iter().find(|(node, _)| name.to_str() == Some(node)) {
+                    Some(node) => node,
+                    None => return ioerr!(NotFound, "no such device"),
+                };
+                match rest {
+                    Some(_) => ioerr!(InvalidInput, "not a directory"),
+                    None => Ok(self.entry(name, node)),
+                }
+            }
+        }
+    }
+}
+
+impl Mountable for DevFs {
+    fn kind(&self) -> &'static str {
+        "dev"
+    }
+
+    fn open(&self, path: &Path) -> io::Result<vfs::Entry> {
+        traits::FileSystem::open(self, path).map(vfs::Entry::from_fs)
+    }
+}
@@ -0,0 +1,315 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::{self, Vec};
+use core::fmt::Write as _;
+
+use shim::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use shim::ioerr;
+use shim::path::{Component, Path};
+
+use fat32::traits;
+use fat32::vfat::{Attributes, Metadata, Timestamp};
+use pi::atags::Atags;
+use pi::interrupt::Interrupt;
+use pi::mbox::MailBox;
+
+use crate::fs::vfs::{self, Mountable};
+use crate::mutex::Mutex;
+use crate::{clock, ALLOCATOR, FILESYSTEM, IRQ, SCHEDULER};
+
+/// A node of the file system: the root directory or one of its files.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Node {
+    Root,
+    Atags,
+    Board,
+    FatCache,
+    Interrupts,
+    MemInfo,
+    Processes,
+    Uptime,
+}
+
+/// The files of the root directory, by name.
+const FILES: [(&str, Node); 7] = [
+    ("atags", Node::Atags),
+    ("board", Node::Board),
+    ("fatcache", Node::FatCache),
+    ("interrupts", Node::Interrupts),
+    ("meminfo", Node::MemInfo),
+    ("processes", Node::Processes),
+    ("uptime", Node::Uptime),
+];
+
+impl Node {
+    /// Returns the current contents of the file `self`.
+    fn contents(self) -> String {
+        let mut s = String::new();
+        // Writing to a `String` cannot fail.
+        let _ = match self {
+            Node::Root => Ok(()),
+            Node::Atags => Atags::get().map(|atag| writeln!(s, "{:?}", atag)).collect(),
+            Node::Board => board(&mut s),
+            Node::FatCache => fat_cache(&mut s),
+            Node::Interrupts => interrupts(&mut s),
+            Node::MemInfo => meminfo(&mut s),
+            Node::Processes => processes(&mut s),
+            Node::Uptime => {
+                let uptime = pi::timer::current_time();
+                writeln!(s, "{}.{:02}", uptime.as_secs(), uptime.subsec_millis() / 10)
+            }
+        };
+        s
+    }
+}
+
+fn board(s: &mut String) -> core::fmt::Result {
+    let info = match MailBox::new().board_info() {
+        Ok(info) => info,
+        Err(()) => return writeln!(s, "mailbox call failed"),
+    };
+
+    let mac = info.mac_address;
+    writeln!(s, "model:             {:#x}", info.model)?;
+    writeln!(s, "revision:          {:#x}", info.revision)?;
+    writeln!(s, "serial:            {:016x}", info.serial)?;
+    writeln!(s, "firmware revision: {:#x}", info.firmware_revision)?;
+    writeln!(s, "mac address:       {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+             mac[0], mac[1], mac[2], mac[3], mac[4], mac[5])?;
+    writeln!(s, "arm memory:        {:#010x} {} KiB", info.arm_memory.0, info.arm_memory.1 / 1024)?;
+    writeln!(s, "vc memory:         {:#010x} {} KiB", info.vc_memory.0, info.vc_memory.1 / 1024)
+}
+
+fn fat_cache(s: &mut String) -> core::fmt::Result {
+    writeln!(s, "{:<16} {:>8} {:>8} {:>10} {:>10}", "PATH", "CACHED", "DIRTY", "HITS", "MISSES")?;
+    for mount in FILESYSTEM.mounts() {
+        if let Some(stats) = mount.fs().cache_stats() {
+            writeln!(s, "{:<16} {:>8} {:>8} {:>10} {:>10}",
+                     mount.path().display(), stats.cached, stats.dirty, stats.hits, stats.misses)?;
+        }
+    }
+    Ok(())
+}
+
+fn interrupts(s: &mut String) -> core::fmt::Result {
+    for (index, count) in IRQ.counts().iter().enumerate() {
+        writeln!(s, "{:<8} {}", format!("{:?}", Interrupt::from_index(index)), count)?;
+    }
+    Ok(())
+}
+
+fn meminfo(s: &mut String) -> core::fmt::Result {
+    match ALLOCATOR.usage() {
+        Some(usage) => {
+            writeln!(s, "total: {} KiB", usage.total / 1024)?;
+            writeln!(s, "used:  {} KiB", usage.used / 1024)?;
+            writeln!(s, "free:  {} KiB", (usage.total - usage.used) / 1024)
+        }
+        None => writeln!(s, "allocator does not report its usage"),
+    }
+}
+
+fn processes(s: &mut String) -> core::fmt::Result {
+    writeln!(s, "{:>5} {:>5} {:<8} {:>4} {:>5} {}", "ID", "PID", "STATE", "NICE", "LEVEL", "STACK")?;
+    for thread in SCHEDULER.threads().iter() {
+        let process = thread.process.map_or(String::from("-"), |id| format!("{}", id));
+        let stack = thread.stack_top.map_or(String::from("-"), |top| format!("{:#x}", top.as_usize()));
+        writeln!(
+            s,
+            "{:>5} {:>5} {:<8} {:>4} {:>5} {}",
+            thread.id, process, thread.state, thread.nice, thread.level, stack
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the metadata of every node: read-only and stamped with the
+/// current time, as the contents are made when first used.
+fn metadata(node: Node) -> Metadata {
+    let directory = if node == Node::Root { 0x10 } else { 0 };
+    let now = Timestamp::from_unix(clock::now());
+    Metadata { attr: Attributes::from(0x01 | directory), ctime: now, atime: now, mtime: now }
+}
+
+/// A read-only file system of synthetic files describing kernel state:
+///
+///   * `atags`: the ATAG list
+///   * `board`: board information from the VideoCore mailbox
+///   * `fatcache`: sector cache statistics of every mounted FAT volume
+///   * `interrupts`: the number of times each IRQ was handled
+///   * `meminfo`: memory usage of the kernel allocator
+///   * `processes`: the scheduler's threads, with the process of each
+///   * `uptime`: seconds since the system timer started
+#[derive(Debug)]
+pub struct ProcFs;
+
+/// A file of `ProcFs`. Its contents are made when it is first read, sought
+/// or sized, so a file shows the state of the kernel at that time, and its
+/// size is theirs. Making them only then keeps it out of the file system
+/// lock that opening holds.
+#[derive(Debug)]
+pub struct File {
+    node: Node,
+    contents: Mutex<Option<Cursor<Vec<u8>>>>,
+}
+
+impl File {
+    fn new(node: Node) -> File {
+        File { node, contents: Mutex::new(None) }
+    }
+
+    /// Calls `f` with the contents of the file, making them if needed.
+    fn with_contents<R>(&self, f: impl FnOnce(&mut Cursor<Vec<u8>>) -> R) -> R {
+        let node = self.node;
+        f(self.contents.lock().get_or_insert_with(|| Cursor::new(node.contents().into_bytes())))
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.with_contents(|contents| contents.read(buf))
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        ioerr!(PermissionDenied, "read-only file system")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.with_contents(|contents| contents.seek(pos))
+    }
+}
+
+impl traits::File for File {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.with_contents(|contents| contents.get_ref().len() as u64)
+    }
+}
+
+/// The root directory of `ProcFs`.
+#[derive(Debug)]
+pub struct Dir;
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = vec::IntoIter<Entry>;
+
+    fn entries(&self) -> io::Result<Self::Iter> {
+        Ok(FILES.iter().map(|&(name, node)| Entry::new(name, node)).collect::<Vec<_>>().into_iter())
+    }
+}
+
+#[derive(Debug)]
+enum Inner {
+    File(File),
+    Dir(Dir),
+}
+
+/// An entry of `ProcFs`.
+#[derive(Debug)]
+pub struct Entry {
+    name: &'static str,
+    metadata: Metadata,
+    inner: Inner,
+}
+
+impl Entry {
+    fn new(name: &'static str, node: Node) -> Entry {
+        let inner = match node {
+            Node::Root => Inner::Dir(Dir),
+            _ => Inner::File(File::new(node)),
+        };
+        Entry { name, metadata: metadata(node), inner }
+    }
+}
+
+impl traits::Entry for Entry {
+    type File = File;
+    type Dir = Dir;
+    type Metadata = Metadata;
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        match self.inner {
+            Inner::File(ref file) => Some(file),
+            Inner::Dir(_) => None,
+        }
+    }
+
+    fn as_dir(&self) -> Option<&Dir> {
+        match self.inner {
+            Inner::Dir(ref dir) => Some(dir),
+            Inner::File(_) => None,
+        }
+    }
+
+    fn into_file(self) -> Option<File> {
+        match self.inner {
+            Inner::File(file) => Some(file),
+            Inner::Dir(_) => None,
+        }
+    }
+
+    fn into_dir(self) -> Option<Dir> {
+        match self.inner {
+            Inner::Dir(dir) => Some(dir),
+            Inner::File(_) => None,
+        }
+    }
+}
+
+impl<'a> traits::FileSystem for &'a ProcFs {
+    type File = File;
+    type Dir = Dir;
+    type Entry = Entry;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Entry> {
+        let path = fat32::path::normalize(path)?;
+        let mut names = path.components().filter_map(|component| match component {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        });
+
+        match (names.next(), names.next()) {
+            (None, _) => Ok(Entry::new("/", Node::Root)),
+            (Some(name), rest) => {
+                let &(name, node) = match FILES.iter().find(|&&(file, _)| name.to_str() == Some(file)) {
+                    Some(file) => file,
+                    None => return ioerr!(NotFound, "no such file"),
+                };
+                match rest {
+                    Some(_) => ioerr!(InvalidInput, "not a directory"),
+                    None => Ok(Entry::new(name, node)),
+                }
+            }
+        }
+    }
+}
+
+impl Mountable for ProcFs {
+    fn kind(&self) -> &'static str {
+        "proc"
+    }
+
+    fn open(&self, path: &Path) -> io::Result<vfs::Entry> {
+        traits::FileSystem::open(self, path).map(vfs::Entry::from_fs)
+    }
+}
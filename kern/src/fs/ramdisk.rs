@@ -0,0 +1,144 @@
+use alloc::vec::Vec;
+use core::cmp;
+use core::fmt;
+use core::ops::Range;
+
+use shim::io;
+use shim::ioerr;
+use shim::newioerr;
+
+use fat32::traits::BlockDevice;
+use pi::atags::Atags;
+use xmodem::Xmodem;
+
+use crate::param::cmdline_option;
+
+const SECTOR_SIZE: usize = 512;
+
+/// A block device held in memory. Its contents are lost on reset.
+pub struct RamDisk(Vec<u8>);
+
+impl RamDisk {
+    /// Returns a zeroed RAM disk of `size` bytes, rounded down to a whole
+    /// number of sectors.
+    pub fn new(size: usize) -> RamDisk {
+        let mut data = Vec::new();
+        data.resize(size / SECTOR_SIZE * SECTOR_SIZE, 0);
+        RamDisk(data)
+    }
+
+    /// Returns the size, in bytes, of the RAM disk to create by default: the
+    /// `ramdisk=<MiB>` option of the kernel command line if given, otherwise
+    /// an eighth of the memory reported by the memory ATAG. Sizes are capped
+    /// at half of memory.
+    ///
+    /// Returns `None` if there is no memory ATAG.
+    pub fn default_size() -> Option<usize> {
+        let memory = Atags::get().filter_map(|atag| atag.mem()).next()?.size as usize;
+
+        let size = match cmdline_option("ramdisk").and_then(|mib| mib.parse::<usize>().ok()) {
+            Some(mib) => mib << 20,
+            None => memory / 8,
+        };
+        Some(cmp::min(size, memory / 2))
+    }
+
+    /// Returns the size of the disk in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Fills the disk from sector 0 with the contents of `image`, leaving the
+    /// rest of it untouched. Returns the number of bytes read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if `image` holds more than the disk,
+    /// or any error from reading `image`.
+    pub fn load<R: io::Read>(&mut self, mut image: R) -> io::Result<usize> {
+        let mut read = 0;
+        loop {
+            let n = if read < self.0.len() {
+                image.read(&mut self.0[read..])
+            } else {
+                image.read(&mut [0u8; 1])
+            };
+
+            match n {
+                Ok(0) => return Ok(read),
+                Ok(_) if read == self.0.len() => return ioerr!(InvalidData, "image larger than RAM disk"),
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Fills the disk from sector 0 with an image sent over `uart` with the
+    /// XMODEM protocol. Returns the number of bytes received, a multiple of
+    /// 128.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `WriteZero` if the image is larger than the disk,
+    /// or any error from the transfer; `TimedOut` if `uart` has a read timeout
+    /// and the sender did not start in time.
+    pub fn receive<T: io::Read + io::Write>(&mut self, uart: T) -> io::Result<usize> {
+        Xmodem::receive(uart, io::Cursor::new(&mut self.0[..]))
+    }
+
+    /// Returns the byte range of the disk holding the `count` sectors from
+    /// sector `start`.
+    fn range(&self, start: u64, count: u64) -> io::Result<Range<usize>> {
+        let end = start.checked_add(count)
+            .filter(|&end| end <= (self.0.len() / SECTOR_SIZE) as u64)
+            .ok_or(newioerr!(InvalidInput, "sector beyond end of RAM disk"))?;
+        Ok(start as usize * SECTOR_SIZE..end as usize * SECTOR_SIZE)
+    }
+}
+
+impl fmt::Debug for RamDisk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RamDisk({} bytes)", self.0.len())
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_sectors(n, 1, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        self.write_sectors(n, 1, buf)
+    }
+
+    /// Copies `count` sectors starting at `start` into `buf`, or as much of
+    /// them as `buf` holds.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if the sectors extend past the end
+    /// of the disk.
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let range = self.range(start, count)?;
+        let len = cmp::min(range.len(), buf.len());
+        buf[..len].copy_from_slice(&self.0[range.start..range.start + len]);
+        Ok(len)
+    }
+
+    /// Copies `buf` over `count` sectors starting at `start`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if the sectors extend past the end
+    /// of the disk and `UnexpectedEof` if `buf` does not cover them.
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        let range = self.range(start, count)?;
+        if buf.len() < range.len() {
+            return ioerr!(UnexpectedEof, "buf must cover every sector written");
+        }
+        let len = range.len();
+        self.0[range].copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
@@ -1,45 +1,11 @@
-use core::time::Duration;
 use shim::io;
-use shim::ioerr;
 
 use fat32::traits::BlockDevice;
-
-extern "C" {
-    /// A global representing the last SD controller error that occured.
-    static sd_err: i64;
-
-    /// Initializes the SD card controller.
-    ///
-    /// Returns 0 if initialization is successful. If initialization fails,
-    /// returns -1 if a timeout occured, or -2 if an error sending commands to
-    /// the SD controller occured.
-    fn sd_init() -> i32;
-
-    /// Reads sector `n` (512 bytes) from the SD card and writes it to `buffer`.
-    /// It is undefined behavior if `buffer` does not point to at least 512
-    /// bytes of memory. Also, the caller of this function should make sure that
-    /// `buffer` is at least 4-byte aligned.
-    ///
-    /// On success, returns the number of bytes read: a positive number.
-    ///
-    /// On error, returns 0. The true error code is stored in the `sd_err`
-    /// global. `sd_err` will be set to -1 if a timeout occured or -2 if an
-    /// error sending commands to the SD controller occured. Other error codes
-    /// are also possible but defined only as being less than zero.
-    fn sd_readsector(n: i32, buffer: *mut u8) -> i32;
-}
-
-// FIXME: Define a `#[no_mangle]` `wait_micros` function for use by `libsd`.
-// The `wait_micros` C signature is: `void wait_micros(unsigned int);`
-
-#[no_mangle]
-pub fn wait_micros(micros: u32) {
-    pi::timer::spin_sleep(Duration::from_micros(micros as u64))
-}
+use pi::sdhci::Sdhci;
 
 /// A handle to an SD card controller.
 #[derive(Debug)]
-pub struct Sd;
+pub struct Sd(Sdhci);
 
 impl Sd {
     /// Initializes the SD card controller and returns a handle to it.
@@ -48,13 +14,29 @@ impl Sd {
     /// with atomic memory access, but we can't use it yet since we haven't
     /// written the memory management unit (MMU).
     pub unsafe fn new() -> Result<Sd, io::Error> {
-        match sd_init() {
-            0 => Ok(Sd),
-            -1 => Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
-            -2 => Err(io::Error::new(io::ErrorKind::Other, "error sending commands to sd controller")),
-            _ => Err(io::Error::new(io::ErrorKind::Other, "???????")),
-        }
+        Ok(Sd(Sdhci::new()?))
     }
+
+    /// Returns the capacity of the card in sectors.
+    pub fn sectors(&self) -> u64 {
+        self.0.blocks()
+    }
+
+    /// Returns a mutable reference to the underlying controller, e.g. to
+    /// switch its transfer mode.
+    pub fn controller(&mut self) -> &mut Sdhci {
+        &mut self.0
+    }
+}
+
+/// Returns an `InvalidInput` error unless `len` bytes can hold `count`
+/// sectors.
+fn check_len(len: usize, count: u64) -> io::Result<usize> {
+    let bytes = count as usize * Sdhci::BLOCK_SIZE;
+    if len < bytes {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "buf too small"));
+    }
+    Ok(bytes)
 }
 
 impl BlockDevice for Sd {
@@ -64,30 +46,46 @@ impl BlockDevice for Sd {
     /// # Errors
     ///
     /// An I/O error of kind `InvalidInput` is returned if `buf.len() < 512` or
-    /// `n > 2^31 - 1` (the maximum value for an `i32`).
+    /// `n` is beyond the card's addressable range.
     ///
     /// An error of kind `TimedOut` is returned if a timeout occurs while
-    /// reading from the SD card.
+    /// reading from the SD card, and `InvalidData` if the transfer was
+    /// corrupted.
     ///
     /// An error of kind `Other` is returned for all other errors.
     fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
-        if buf.len() < 512 || n > core::i32::MAX as u64{
-            return Err(io::Error::new(io::ErrorKind::InvalidInput, "buf too small or n too large"));
-        }
-        let read = unsafe { sd_readsector(n as i32, buf.as_mut_ptr()) } as usize;
-        if read <= 0 {
-            let err_code = unsafe { sd_err };
-            Err(match err_code {
-                -1 => io::Error::new(io::ErrorKind::TimedOut, "timed out"),
-                -2 => io::Error::new(io::ErrorKind::Other, "error sending commands to sd controller"),
-                _ => io::Error::new(io::ErrorKind::Other, "???????"),
-            })
-        } else {
-            Ok(read)
+        let bytes = check_len(buf.len(), 1)?;
+        self.0.read_blocks(n, &mut buf[..bytes])
+    }
+
+    /// Writes `buf` to sector `n` of the SD card. On success, the number of
+    /// bytes written is returned.
+    ///
+    /// # Errors
+    ///
+    /// As for `read_sector()`.
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let bytes = check_len(buf.len(), 1)?;
+        self.0.write_blocks(n, &buf[..bytes])
+    }
+
+    /// Reads `count` sectors starting at `start` with a single multi-block
+    /// (CMD18) transfer. Reading no sectors does nothing.
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = check_len(buf.len(), count)?;
+        if bytes == 0 {
+            return Ok(0);
         }
+        self.0.read_blocks(start, &mut buf[..bytes])
     }
 
-    fn write_sector(&mut self, _n: u64, _buf: &[u8]) -> io::Result<usize> {
-        unimplemented!("SD card and file system are read only")
+    /// Writes `count` sectors starting at `start` with a single multi-block
+    /// (CMD25) transfer. Writing no sectors does nothing.
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        let bytes = check_len(buf.len(), count)?;
+        if bytes == 0 {
+            return Ok(0);
+        }
+        self.0.write_blocks(start, &buf[..bytes])
     }
 }
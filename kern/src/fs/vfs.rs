@@ -0,0 +1,527 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use shim::io::{self, Read, Seek, SeekFrom, Write};
+use shim::ioerr;
+use shim::path::{Component, Path, PathBuf};
+
+use fat32::check::Report;
+use fat32::traits;
+use fat32::vfat::{Attributes, CacheStats, Date, Metadata, StatFs, Time, Timestamp};
+
+/// Object-safe counterpart of `traits::File`, implemented by every file of a
+/// `fat32::traits` file system.
+pub trait VfsFile: Read + Write + Seek + Send {
+    /// Writes any buffered data to disk.
+    fn sync(&mut self) -> io::Result<()>;
+
+    /// Returns the size of the file in bytes.
+    fn size(&self) -> u64;
+
+    /// Performs the device-specific operation `request` on the file.
+    fn control(&mut self, request: u32, data: &mut [u8]) -> io::Result<usize>;
+}
+
+impl<F: traits::File + Send> VfsFile for F {
+    fn sync(&mut self) -> io::Result<()> {
+        traits::File::sync(self)
+    }
+
+    fn size(&self) -> u64 {
+        traits::File::size(self)
+    }
+
+    fn control(&mut self, request: u32, data: &mut [u8]) -> io::Result<usize> {
+        traits::File::control(self, request, data)
+    }
+}
+
+/// Object-safe counterpart of `traits::Dir`.
+pub trait VfsDir: Send {
+    /// Returns an iterator over the entries in this directory.
+    fn entries(&self) -> io::Result<Box<dyn Iterator<Item = Entry> + Send>>;
+}
+
+/// A directory of a `fat32::traits` file system whose entries are converted
+/// to VFS entries as they are read.
+struct FsDir<D>(D);
+
+impl<D, E> VfsDir for FsDir<D>
+where
+    D: traits::Dir<Entry = E> + Send + 'static,
+    D::Iter: Send + 'static,
+    E: traits::Entry<Dir = D> + 'static,
+    E::File: Send + 'static,
+{
+    fn entries(&self) -> io::Result<Box<dyn Iterator<Item = Entry> + Send>> {
+        Ok(Box::new(self.0.entries()?.map(Entry::from_fs)))
+    }
+}
+
+/// Returns `time` as a FAT timestamp, or the zero timestamp if FAT cannot
+/// represent its year.
+fn timestamp<T: traits::Timestamp>(time: T) -> Timestamp {
+    if time.year() < 1980 || time.year() > 2107 {
+        return Timestamp::default();
+    }
+
+    Timestamp {
+        date: Date::new(time.year(), time.month(), time.day()),
+        time: Time::new(time.hour(), time.minute(), time.second()),
+        tenths: time.second() % 2 * 100,
+    }
+}
+
+/// Returns `metadata` of any file system as FAT metadata, the metadata of
+/// every VFS entry.
+fn metadata<M: traits::Metadata>(metadata: &M, dir: bool) -> Metadata {
+    let attr = metadata.read_only() as u8 | (metadata.hidden() as u8) << 1 | (dir as u8) << 4;
+    Metadata {
+        attr: Attributes::from(attr),
+        ctime: timestamp(metadata.created()),
+        atime: timestamp(metadata.accessed()),
+        mtime: timestamp(metadata.modified()),
+    }
+}
+
+/// An open file of a mounted file system.
+pub struct File(Box<dyn VfsFile>);
+
+impl File {
+    /// Wraps a file of a `fat32::traits` file system.
+    pub fn from_fs<F: traits::File + Send + 'static>(file: F) -> File {
+        File(Box::new(file))
+    }
+}
+
+impl fmt::Debug for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "File({} bytes)", self.0.size())
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl traits::File for File {
+    fn sync(&mut self) -> io::Result<()> {
+        self.0.sync()
+    }
+
+    fn size(&self) -> u64 {
+        self.0.size()
+    }
+
+    fn control(&mut self, request: u32, data: &mut [u8]) -> io::Result<usize> {
+        self.0.control(request, data)
+    }
+}
+
+/// An open directory of a mounted file system. Listing it shows the file
+/// systems mounted on its children in place of whatever they cover.
+pub struct Dir {
+    inner: Box<dyn VfsDir>,
+    mounts: Vec<(String, MountedFs)>,
+}
+
+impl Dir {
+    /// Wraps a directory of a `fat32::traits` file system.
+    pub fn from_fs<D, E>(dir: D) -> Dir
+    where
+        D: traits::Dir<Entry = E> + Send + 'static,
+        D::Iter: Send + 'static,
+        E: traits::Entry<Dir = D> + 'static,
+        E::File: Send + 'static,
+    {
+        Dir { inner: Box::new(FsDir(dir)), mounts: Vec::new() }
+    }
+}
+
+impl fmt::Debug for Dir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.mounts.iter().map(|(name, _)| name)).finish()
+    }
+}
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = Box<dyn Iterator<Item = Entry> + Send>;
+
+    fn entries(&self) -> io::Result<Self::Iter> {
+        let mounts = self.mounts.clone();
+        let covered = self.inner.entries()?
+            .filter(move |entry| !mounts.iter().any(|(name, _)| name.eq_ignore_ascii_case(&entry.name)));
+
+        let mount_points = self.mounts.iter()
+            .filter_map(|(name, fs)| fs.0.open(Path::new("/")).ok().map(|entry| entry.named(name)))
+            .collect::<Vec<_>>();
+        Ok(Box::new(covered.chain(mount_points)))
+    }
+}
+
+enum Node {
+    File(File),
+    Dir(Dir),
+}
+
+/// An entry of a mounted file system.
+pub struct Entry {
+    name: String,
+    metadata: Metadata,
+    node: Node,
+}
+
+impl Entry {
+    /// Wraps an entry of a `fat32::traits` file system.
+    pub fn from_fs<E>(entry: E) -> Entry
+    where
+        E: traits::Entry + 'static,
+        E::File: Send + 'static,
+        E::Dir: traits::Dir<Entry = E> + Send + 'static,
+        <E::Dir as traits::Dir>::Iter: Send + 'static,
+    {
+        let name = String::from(entry.name());
+        let metadata = metadata(entry.metadata(), entry.is_dir());
+        let node = if entry.is_dir() {
+            Node::Dir(Dir::from_fs(entry.into_dir().expect("entry is a directory")))
+        } else {
+            Node::File(File::from_fs(entry.into_file().expect("entry is a file")))
+        };
+        Entry { name, metadata, node }
+    }
+
+    /// Returns `self` renamed to `name`, as when shown at a mount point.
+    fn named(mut self, name: &str) -> Entry {
+        self.name = String::from(name);
+        self
+    }
+}
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Entry({})", self.name)
+    }
+}
+
+impl traits::Entry for Entry {
+    type File = File;
+    type Dir = Dir;
+    type Metadata = Metadata;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        match self.node {
+            Node::File(ref file) => Some(file),
+            Node::Dir(_) => None,
+        }
+    }
+
+    fn as_dir(&self) -> Option<&Dir> {
+        match self.node {
+            Node::Dir(ref dir) => Some(dir),
+            Node::File(_) => None,
+        }
+    }
+
+    fn into_file(self) -> Option<File> {
+        match self.node {
+            Node::File(file) => Some(file),
+            Node::Dir(_) => None,
+        }
+    }
+
+    fn into_dir(self) -> Option<Dir> {
+        match self.node {
+            Node::Dir(dir) => Some(dir),
+            Node::File(_) => None,
+        }
+    }
+}
+
+/// A file system that can be mounted in a `MountTable`. Paths passed to its
+/// methods are absolute, normalized and relative to its own root.
+pub trait Mountable {
+    /// A short name for the kind of file system, e.g. `vfat`.
+    fn kind(&self) -> &'static str;
+
+    /// Opens the entry at `path`, with the errors of `traits::FileSystem`.
+    fn open(&self, path: &Path) -> io::Result<Entry>;
+
+    /// Creates an empty file at `path`. The default refuses with
+    /// `PermissionDenied`.
+    fn create_file(&self, _path: &Path) -> io::Result<File> {
+        ioerr!(PermissionDenied, "read-only file system")
+    }
+
+    /// Creates an empty directory at `path`. The default refuses with
+    /// `PermissionDenied`.
+    fn create_dir(&self, _path: &Path) -> io::Result<Dir> {
+        ioerr!(PermissionDenied, "read-only file system")
+    }
+
+    /// Removes the file or empty directory at `path`. The default refuses
+    /// with `PermissionDenied`.
+    fn remove(&self, _path: &Path) -> io::Result<()> {
+        ioerr!(PermissionDenied, "read-only file system")
+    }
+
+    /// Returns the space usage of the file system, if it has any.
+    fn statfs(&self) -> io::Result<StatFs> {
+        ioerr!(Other, "operation not supported")
+    }
+
+    /// Checks the consistency of the file system. See `fat32::check`.
+    fn check(&self, _repair: bool) -> io::Result<Report> {
+        ioerr!(Other, "operation not supported")
+    }
+
+    /// Returns the statistics of the file system's sector cache, if it has
+    /// one.
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+
+    /// Writes any buffered data to the underlying device.
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The first sector of its device the file system occupies and the
+    /// number of sectors it spans, if it lives on a block device.
+    fn device_range(&self) -> Option<(u64, u64)> {
+        None
+    }
+
+    /// Whether files or directories of the file system are still open, in
+    /// which case it may not be unmounted.
+    fn busy(&self) -> bool {
+        false
+    }
+}
+
+/// A file system in the mount table, shared with open directories that list
+/// its mount point.
+#[derive(Clone)]
+struct MountedFs(Rc<dyn Mountable>);
+
+// Unsound for the reasons given for `PiVFatHandle`, which holds as well for
+// the other file systems the kernel mounts.
+unsafe impl Send for MountedFs {}
+unsafe impl Sync for MountedFs {}
+
+/// A file system mounted at a path.
+#[derive(Clone)]
+pub struct Mount {
+    path: PathBuf,
+    source: String,
+    fs: MountedFs,
+}
+
+impl Mount {
+    /// The absolute, normalized path the file system is mounted at.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// What was mounted, e.g. `sd:1` for the second partition of the SD card.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The mounted file system.
+    pub fn fs(&self) -> &dyn Mountable {
+        &*self.fs.0
+    }
+}
+
+impl fmt::Debug for Mount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} on {} type {}", self.source, self.path.display(), self.fs.0.kind())
+    }
+}
+
+/// Returns the number of components of `path`, `/` included.
+fn depth(path: &Path) -> usize {
+    path.components().count()
+}
+
+/// File systems mounted at paths of a single tree. Paths passed to its
+/// methods must be absolute and normalized.
+pub struct MountTable(Vec<Mount>);
+
+impl MountTable {
+    /// Returns an empty mount table.
+    pub fn new() -> MountTable {
+        MountTable(Vec::new())
+    }
+
+    /// Returns the mounts, ordered by path.
+    pub fn mounts(&self) -> &[Mount] {
+        &self.0
+    }
+
+    /// Returns the mount at exactly `path`, if any.
+    pub fn get(&self, path: &Path) -> Option<&Mount> {
+        self.0.iter().find(|mount| mount.path == path)
+    }
+
+    /// Returns the mount holding `path`, the one at its longest prefix, and
+    /// `path` relative to the root of that file system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `NotFound` if no file system holds `path`.
+    pub fn resolve(&self, path: &Path) -> io::Result<(&Mount, PathBuf)> {
+        let mount = match self.0.iter()
+            .filter(|mount| path.starts_with(&mount.path))
+            .max_by_key(|mount| depth(&mount.path)) {
+            Some(mount) => mount,
+            None => return ioerr!(NotFound, "no file system mounted"),
+        };
+
+        let relative = path.strip_prefix(&mount.path).expect("mount is a prefix");
+        Ok((mount, Path::new("/").join(relative)))
+    }
+
+    /// Opens the entry at `path` in whichever file system holds it.
+    /// Directories list the file systems mounted on their children.
+    pub fn open(&self, path: &Path) -> io::Result<Entry> {
+        let (mount, relative) = self.resolve(path)?;
+        let mut entry = mount.fs.0.open(&relative)?;
+        if let Some(Component::Normal(name)) = path.components().last() {
+            if relative == Path::new("/") {
+                entry = entry.named(&name.to_string_lossy());
+            }
+        }
+
+        if let Node::Dir(ref mut dir) = entry.node {
+            dir.mounts = self.0.iter()
+                .filter(|mount| mount.path.parent() == Some(path))
+                .filter_map(|mount| mount.path.file_name().map(|name| (name, mount)))
+                .map(|(name, mount)| (name.to_string_lossy().into_owned(), mount.fs.clone()))
+                .collect();
+        }
+        Ok(entry)
+    }
+
+    /// Creates an empty file at `path` in whichever file system holds it.
+    pub fn create_file(&self, path: &Path) -> io::Result<File> {
+        let (mount, relative) = self.unmounted(path)?;
+        mount.fs.0.create_file(&relative)
+    }
+
+    /// Creates an empty directory at `path` in whichever file system holds
+    /// it.
+    pub fn create_dir(&self, path: &Path) -> io::Result<Dir> {
+        let (mount, relative) = self.unmounted(path)?;
+        mount.fs.0.create_dir(&relative)
+    }
+
+    /// Removes the file or empty directory at `path` in whichever file system
+    /// holds it.
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        let (mount, relative) = self.unmounted(path)?;
+        mount.fs.0.remove(&relative)
+    }
+
+    /// As `resolve()`, but fails with `Other` if a file system is mounted at
+    /// `path`.
+    fn unmounted(&self, path: &Path) -> io::Result<(&Mount, PathBuf)> {
+        match self.get(path) {
+            Some(_) => ioerr!(Other, "path is a mount point"),
+            None => self.resolve(path),
+        }
+    }
+
+    /// Mounts `fs` at `path`, which may not exist yet but whose parent must
+    /// be a directory. `source` names what is mounted in listings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `AlreadyExists` if a file system is mounted at
+    /// `path` and of `InvalidInput` if `path` or its parent is not a
+    /// directory.
+    pub fn mount(&mut self, path: &Path, source: &str, fs: Rc<dyn Mountable>) -> io::Result<()> {
+        if self.get(path).is_some() {
+            return ioerr!(AlreadyExists, "a file system is mounted there");
+        }
+
+        if let Some(parent) = path.parent() {
+            match self.open(path) {
+                Ok(ref entry) if traits::Entry::is_dir(entry) => (),
+                Ok(_) => return ioerr!(InvalidInput, "not a directory"),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                    if !traits::Entry::is_dir(&self.open(parent)?) {
+                        return ioerr!(InvalidInput, "not a directory");
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let index = self.0.iter().position(|mount| mount.path.as_path() > path).unwrap_or(self.0.len());
+        self.0.insert(index, Mount { path: path.to_path_buf(), source: String::from(source), fs: MountedFs(fs) });
+        Ok(())
+    }
+
+    /// Flushes and removes the file system mounted at `path`, returning its
+    /// mount.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `NotFound` if nothing is mounted at `path`, of
+    /// `Other` if another file system is mounted below it or it is still in
+    /// use, or any error from flushing it. The file system stays mounted in
+    /// these cases.
+    pub fn unmount(&mut self, path: &Path) -> io::Result<Mount> {
+        if self.0.iter().any(|mount| mount.path != path && mount.path.starts_with(path)) {
+            return ioerr!(Other, "file systems are mounted below it");
+        }
+        self.detach(path)
+    }
+
+    /// As `unmount()`, but leaves the file systems mounted below `path` in
+    /// place, e.g. to replace the one at `path`.
+    pub fn detach(&mut self, path: &Path) -> io::Result<Mount> {
+        let index = match self.0.iter().position(|mount| mount.path == path) {
+            Some(index) => index,
+            None => return ioerr!(NotFound, "nothing mounted there"),
+        };
+
+        let fs = &self.0[index].fs.0;
+        if Rc::strong_count(fs) > 1 || fs.busy() {
+            return ioerr!(Other, "file system is busy");
+        }
+        fs.flush()?;
+        Ok(self.0.remove(index))
+    }
+}
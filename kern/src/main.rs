@@ -15,6 +15,7 @@ mod init;
 extern crate alloc;
 
 pub mod allocator;
+pub mod clock;
 pub mod console;
 pub mod fs;
 pub mod mutex;
@@ -28,7 +29,7 @@ use console::{kprintln, kprint};
 
 use allocator::Allocator;
 use fs::FileSystem;
-use process::GlobalScheduler;
+use process::{GlobalScheduler, Process};
 use traps::irq::Irq;
 use vm::VMManager;
 
@@ -51,10 +52,13 @@ fn kmain() -> ! {
         kprintln!("{:#?}", atag);
     }
 
+    clock::initialize();
     unsafe {
         ALLOCATOR.initialize();
         FILESYSTEM.initialize();
     }
+    VMM.initialize();
+    VMM.setup();
 
     /*
     use alloc::vec::Vec;
@@ -69,7 +73,35 @@ fn kmain() -> ! {
     //kprintln!("{:#?}", fb.mailbox.mailbox);
     spin_sleep(Duration::from_secs(2));
     fb.show_picture();
+    if let Err(e) = FILESYSTEM.mount_devices(Some(fb), "/dev") {
+        kprintln!("error mounting /dev: {}", e);
+    }
+
+    IRQ.initialize();
+    unsafe {
+        SCHEDULER.initialize();
+    }
+    let (shell, thread) = Process::kernel(start_shell).expect("failed to create the shell process");
+    SCHEDULER.add(shell, thread).expect("failed to add the shell process");
+    SCHEDULER.spawn_kernel(sync, 0).expect("failed to start the sync thread");
+    SCHEDULER.start();
+}
+
+/// A kernel thread that writes the file systems' cached data back to the
+/// disk every `SYNC_PERIOD`, so that little is lost if the board loses
+/// power.
+extern "C" fn sync(_: u64) -> ! {
+    loop {
+        let _ = kernel_api::syscall::sleep(param::SYNC_PERIOD);
+        if let Err(e) = FILESYSTEM.sync() {
+            kprintln!("sync: {}", e);
+        }
+    }
+}
 
+/// The kernel shell, run as the first process so that it can start and wait
+/// for user programs.
+extern "C" fn start_shell() -> ! {
     kprintln!("Welcome to cs3210!");
-    shell::shell("> ", &mut fb);
+    shell::shell("> ");
 }
@@ -4,6 +4,7 @@ use shim::{const_assert_eq, const_assert_size};
 const_assert_size!(usize, 64 / 8);
 
 use core::time::Duration;
+use pi::atags::Atags;
 pub use pi::common::*;
 
 pub const PAGE_ALIGN: usize = 16;
@@ -23,6 +24,36 @@ pub const USER_MAX_VM_SIZE: usize = 0x4000_0000;
 const_assert_eq!(USER_IMG_BASE.wrapping_add(USER_MAX_VM_SIZE), 0);
 pub const KERN_STACK_BASE: usize = 0x80_000;
 
-/// The `tick` time.
-// FIXME: When you're ready, change this to something more reasonable.
-pub const TICK: Duration = Duration::from_secs(2);
+/// The default time slice: how long a process runs before it is preempted,
+/// unless it has been demoted for using up its time slices. The kernel
+/// command line option `slice=<milliseconds>` overrides it.
+pub const DEFAULT_SLICE: Duration = Duration::from_millis(10);
+
+/// How often the kernel's `sync` thread writes cached file system data back
+/// to the disk.
+pub const SYNC_PERIOD: Duration = Duration::from_secs(5);
+
+/// How many exit statuses of children a process keeps until it waits for
+/// them. Past this, the status of the child that exited first is dropped, so
+/// that a process which never waits does not collect them without bound.
+pub const MAX_EXITED_CHILDREN: usize = 64;
+
+/// How often the kernel shell checks the console for input while it waits
+/// for a line, sleeping in between so that other threads run.
+pub const CONSOLE_POLL_PERIOD: Duration = Duration::from_millis(20);
+
+/// Returns the value of the last `name=value` option on the kernel command
+/// line, if any.
+pub fn cmdline_option(name: &str) -> Option<&'static str> {
+    Atags::get()
+        .filter_map(|atag| atag.cmd())
+        .flat_map(|cmd| cmd.split_whitespace())
+        .filter_map(|arg| {
+            if arg.len() > name.len() && arg.starts_with(name) && arg.as_bytes()[name.len()] == b'=' {
+                Some(&arg[name.len() + 1..])
+            } else {
+                None
+            }
+        })
+        .last()
+}
@@ -1,10 +1,18 @@
+mod args;
+mod elf;
+mod fd;
+mod futex;
+mod mlfq;
 mod process;
 mod scheduler;
 mod stack;
 mod state;
+mod thread;
+mod timers;
 
-pub use self::process::{Id, Process};
-pub use self::scheduler::GlobalScheduler;
+pub use self::fd::{release, stat, Descriptor, FdTable, FileGuard};
+pub use self::process::{Child, Id, Process};
+pub use self::scheduler::{GlobalScheduler, ThreadInfo};
 pub use self::stack::Stack;
 pub use self::state::State;
-pub use crate::param::TICK;
+pub use self::thread::Thread;
@@ -0,0 +1,81 @@
+use alloc::string::String;
+
+use kernel_api::{OsError, OsResult};
+
+#[cfg(test)]
+mod tests;
+
+/// Where `build` placed the argument block on a new process's stack, as the
+/// process receives it: `sp` in `sp`, and `argc`, `argv` and `envp` in `x0`
+/// to `x2`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ArgBlock {
+    /// The initial stack pointer, at `argc`. It is 16-byte aligned.
+    pub sp: u64,
+    /// The number of arguments.
+    pub argc: u64,
+    /// The address of the null-terminated array of pointers to the
+    /// arguments.
+    pub argv: u64,
+    /// The address of the null-terminated array of pointers to the
+    /// environment's `KEY=value` strings.
+    pub envp: u64,
+}
+
+/// Rounds `addr` down to a multiple of 16.
+fn align_down(addr: u64) -> u64 {
+    addr & !0xf
+}
+
+/// Copies `bytes` to the virtual address `*addr` in `stack`, which starts at
+/// the virtual address `bottom`, and advances `*addr` past them.
+fn put(stack: &mut [u8], bottom: u64, addr: &mut u64, bytes: &[u8]) {
+    let offset = (*addr - bottom) as usize;
+    stack[offset..offset + bytes.len()].copy_from_slice(bytes);
+    *addr += bytes.len() as u64;
+}
+
+impl ArgBlock {
+    /// Writes the argument block of a process with arguments `args` and
+    /// environment `env` to the top of `stack`, the bytes of its stack that end
+    /// at the virtual address `top`.
+    ///
+    /// From `sp` upwards the block holds `argc`, then `argv` and `envp`, each a
+    /// null-terminated array of pointers, then the strings they point to, each
+    /// null-terminated, up to `top`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if the block does not fit in `stack`, or if a
+    /// string contains a null byte.
+    pub fn build(stack: &mut [u8], top: u64, args: &[String], env: &[String]) -> OsResult<ArgBlock> {
+        let strings = args.iter().chain(env.iter());
+        if strings.clone().any(|string| string.contains('\0')) {
+            return Err(OsError::InvalidArgument);
+        }
+
+        let strings_size = strings.map(|string| string.len() as u64 + 1).sum::<u64>();
+        let pointers_size = (args.len() as u64 + env.len() as u64 + 3) * 8;
+        let bottom = top - stack.len() as u64;
+        let strings_base = align_down(top.checked_sub(strings_size).ok_or(OsError::InvalidArgument)?);
+        let sp = align_down(strings_base.checked_sub(pointers_size).ok_or(OsError::InvalidArgument)?);
+        if sp < bottom {
+            return Err(OsError::InvalidArgument);
+        }
+
+        let mut pointer = sp;
+        let mut string = strings_base;
+        put(stack, bottom, &mut pointer, &(args.len() as u64).to_le_bytes());
+        for strings in &[args, env] {
+            for s in strings.iter() {
+                put(stack, bottom, &mut pointer, &string.to_le_bytes());
+                put(stack, bottom, &mut string, s.as_bytes());
+                put(stack, bottom, &mut string, &[0]);
+            }
+            put(stack, bottom, &mut pointer, &0u64.to_le_bytes());
+        }
+
+        let argv = sp + 8;
+        Ok(ArgBlock { sp, argc: args.len() as u64, argv, envp: argv + (args.len() as u64 + 1) * 8 })
+    }
+}
@@ -0,0 +1,91 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::process::args::ArgBlock;
+use kernel_api::OsError;
+
+const TOP: u64 = 0x1_0000;
+
+fn strings(strings: &[&str]) -> Vec<String> {
+    strings.iter().map(|&string| String::from(string)).collect()
+}
+
+/// A stack of `size` bytes ending at `TOP`.
+struct Stack(Vec<u8>);
+
+impl Stack {
+    fn new(size: usize) -> Stack {
+        Stack(vec![0xaa; size])
+    }
+
+    fn bottom(&self) -> u64 {
+        TOP - self.0.len() as u64
+    }
+
+    fn u64_at(&self, addr: u64) -> u64 {
+        let offset = (addr - self.bottom()) as usize;
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.0[offset..offset + 8]);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn str_at(&self, addr: u64) -> &str {
+        let offset = (addr - self.bottom()) as usize;
+        let len = self.0[offset..].iter().position(|&byte| byte == 0).expect("null-terminated");
+        core::str::from_utf8(&self.0[offset..offset + len]).expect("UTF-8")
+    }
+
+    /// Returns the strings of the null-terminated array of pointers at `addr`.
+    fn strings_at(&self, mut addr: u64) -> Vec<&str> {
+        let mut strings = Vec::new();
+        while self.u64_at(addr) != 0 {
+            strings.push(self.str_at(self.u64_at(addr)));
+            addr += 8;
+        }
+        strings
+    }
+}
+
+#[test]
+fn test_block() {
+    let mut stack = Stack::new(256);
+    let block = ArgBlock::build(&mut stack.0, TOP, &strings(&["fib", "20"]), &strings(&["PATH=/bin"])).expect("fits");
+
+    assert_eq!(block.sp % 16, 0);
+    assert_eq!(block.argc, 2);
+    assert_eq!(block.argv, block.sp + 8);
+    assert_eq!(block.envp, block.argv + 3 * 8);
+    assert_eq!(stack.u64_at(block.sp), 2);
+    assert_eq!(stack.strings_at(block.argv), vec!["fib", "20"]);
+    assert_eq!(stack.strings_at(block.envp), vec!["PATH=/bin"]);
+
+    // The strings are packed right below `TOP`, and the pointers below them.
+    let strings_size = "fib\0".len() + "20\0".len() + "PATH=/bin\0".len();
+    assert_eq!(stack.u64_at(block.argv), (TOP - strings_size as u64) & !0xf);
+    assert!(block.envp + 2 * 8 <= stack.u64_at(block.argv));
+}
+
+#[test]
+fn test_empty() {
+    let mut stack = Stack::new(32);
+    let block = ArgBlock::build(&mut stack.0, TOP, &[], &[]).expect("fits");
+    assert_eq!(block, ArgBlock { sp: TOP - 32, argc: 0, argv: TOP - 24, envp: TOP - 16 });
+    assert_eq!(stack.u64_at(block.sp), 0);
+    assert!(stack.strings_at(block.argv).is_empty());
+    assert!(stack.strings_at(block.envp).is_empty());
+}
+
+#[test]
+fn test_too_big() {
+    let args = strings(&["a"; 8]);
+    let size = ArgBlock::build(&mut Stack::new(4096).0, TOP, &args, &[]).map(|block| TOP - block.sp).expect("fits");
+    assert_eq!(ArgBlock::build(&mut Stack::new(size as usize).0, TOP, &args, &[]).map(|block| block.sp), Ok(TOP - size));
+    assert_eq!(ArgBlock::build(&mut Stack::new(size as usize - 1).0, TOP, &args, &[]), Err(OsError::InvalidArgument));
+}
+
+#[test]
+fn test_null_byte() {
+    let mut stack = Stack::new(256);
+    assert_eq!(ArgBlock::build(&mut stack.0, TOP, &strings(&["a\0b"]), &[]), Err(OsError::InvalidArgument));
+    assert_eq!(ArgBlock::build(&mut stack.0, TOP, &[], &strings(&["A=\0"])), Err(OsError::InvalidArgument));
+}
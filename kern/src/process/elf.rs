@@ -0,0 +1,182 @@
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+use crate::param::{PAGE_MASK, PAGE_SIZE, USER_IMG_BASE, USER_STACK_BASE};
+use kernel_api::{OsError, OsResult};
+
+#[cfg(test)]
+mod tests;
+
+/// The size of an ELF64 file header.
+const EHDR_SIZE: usize = 64;
+/// The size of an ELF64 program header.
+const PHDR_SIZE: usize = 56;
+
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_AARCH64: u16 = 183;
+
+/// Program header type of a segment to be loaded into memory.
+const PT_LOAD: u32 = 1;
+
+/// Segment flag: the segment is executable.
+pub const PF_X: u32 = 1 << 0;
+/// Segment flag: the segment is writable.
+pub const PF_W: u32 = 1 << 1;
+
+/// A `PT_LOAD` segment of an ELF file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Segment {
+    /// The virtual address the segment is loaded at.
+    pub vaddr: u64,
+    /// The offset of the segment's bytes in the file.
+    pub offset: u64,
+    /// The number of bytes of the segment stored in the file.
+    pub file_size: u64,
+    /// The number of bytes of the segment in memory. Bytes past `file_size`
+    /// (the `.bss`) are zero.
+    pub mem_size: u64,
+    /// The segment's `PF_*` flags.
+    pub flags: u32,
+}
+
+impl Segment {
+    /// Returns the virtual address one past the end of the segment.
+    pub fn end(&self) -> u64 {
+        self.vaddr + self.mem_size
+    }
+}
+
+/// A parsed and validated ELF64 executable for AArch64.
+#[derive(Debug)]
+pub struct Elf<'a> {
+    data: &'a [u8],
+    entry: u64,
+    segments: Vec<Segment>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Returns `data[offset..offset + len]`, or `None` if that is out of bounds.
+fn range(data: &[u8], offset: u64, len: u64) -> Option<&[u8]> {
+    let end = offset.checked_add(len)?;
+    if end > data.len() as u64 {
+        return None;
+    }
+    Some(&data[offset as usize..end as usize])
+}
+
+impl<'a> Elf<'a> {
+    /// Parses the ELF file in `data`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `data` is not a little-endian ELF64
+    /// executable for AArch64, if a header or segment lies outside of `data`,
+    /// if a segment lies outside of the user image (below `USER_IMG_BASE` or
+    /// on the stack page), if segments overlap, or if the entry point is not
+    /// in an executable segment.
+    pub fn parse(data: &'a [u8]) -> OsResult<Elf<'a>> {
+        let ehdr = range(data, 0, EHDR_SIZE as u64).ok_or(OsError::InvalidArgument)?;
+        if &ehdr[0..4] != ELF_MAGIC
+            || ehdr[4] != ELFCLASS64
+            || ehdr[5] != ELFDATA2LSB
+            || ehdr[6] != EV_CURRENT
+            || u16_at(ehdr, 16) != ET_EXEC
+            || u16_at(ehdr, 18) != EM_AARCH64
+            || u16_at(ehdr, 54) as usize != PHDR_SIZE
+        {
+            return Err(OsError::InvalidArgument);
+        }
+
+        let entry = u64_at(ehdr, 24);
+        let phoff = u64_at(ehdr, 32);
+        let phnum = u16_at(ehdr, 56) as u64;
+        let phdrs = range(data, phoff, phnum * PHDR_SIZE as u64).ok_or(OsError::InvalidArgument)?;
+
+        let mut segments = Vec::new();
+        for phdr in phdrs.chunks(PHDR_SIZE) {
+            if u32_at(phdr, 0) != PT_LOAD {
+                continue;
+            }
+
+            let segment = Segment {
+                flags: u32_at(phdr, 4),
+                offset: u64_at(phdr, 8),
+                vaddr: u64_at(phdr, 16),
+                file_size: u64_at(phdr, 32),
+                mem_size: u64_at(phdr, 40),
+            };
+
+            let in_image = segment.vaddr >= USER_IMG_BASE as u64
+                && segment.vaddr <= USER_STACK_BASE as u64
+                && segment.mem_size <= USER_STACK_BASE as u64 - segment.vaddr;
+            if segment.file_size > segment.mem_size
+                || !in_image
+                || range(data, segment.offset, segment.file_size).is_none()
+            {
+                return Err(OsError::InvalidArgument);
+            }
+            segments.push(segment);
+        }
+
+        segments.sort_by_key(|segment| segment.vaddr);
+        if segments.windows(2).any(|pair| pair[0].end() > pair[1].vaddr) {
+            return Err(OsError::InvalidArgument);
+        }
+
+        let executable = segments.iter().any(|segment| {
+            segment.flags & PF_X != 0 && segment.vaddr <= entry && entry < segment.end()
+        });
+        if !executable {
+            return Err(OsError::InvalidArgument);
+        }
+
+        Ok(Elf { data, entry, segments })
+    }
+
+    /// Returns the virtual address execution starts at.
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// Returns the `PT_LOAD` segments, sorted by virtual address.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns the bytes of `segment` stored in the file.
+    pub fn data(&self, segment: &Segment) -> &'a [u8] {
+        range(self.data, segment.offset, segment.file_size).expect("segment checked by parse")
+    }
+
+    /// Returns each page that a segment occupies, in ascending order, along
+    /// with the union of the flags of the segments that share it.
+    pub fn pages(&self) -> Vec<(u64, u32)> {
+        let mut pages: Vec<(u64, u32)> = Vec::new();
+        for segment in self.segments.iter().filter(|segment| segment.mem_size > 0) {
+            let mut page = segment.vaddr & PAGE_MASK as u64;
+            while page < segment.end() {
+                match pages.last_mut() {
+                    Some(last) if last.0 == page => last.1 |= segment.flags,
+                    _ => pages.push((page, segment.flags)),
+                }
+                page += PAGE_SIZE as u64;
+            }
+        }
+        pages
+    }
+}
@@ -0,0 +1,111 @@
+use crate::param::{PAGE_SIZE, USER_IMG_BASE};
+use crate::process::elf::{Elf, Segment, PF_W, PF_X};
+use kernel_api::OsError;
+
+const PF_R: u32 = 1 << 2;
+const BASE: u64 = USER_IMG_BASE as u64;
+const PAGE: u64 = PAGE_SIZE as u64;
+
+/// Offset of the first program header in every fixture.
+const PHDRS: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+macro fixture($name:expr) {
+    &include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/../ext/elf/", $name))[..]
+}
+
+/// Returns `hello.elf` with the little-endian `value` written at `offset`.
+fn patched(offset: usize, value: u64, size: usize) -> Vec<u8> {
+    let mut data = fixture!("hello.elf").to_vec();
+    data[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+    data
+}
+
+/// Returns `hello.elf` with field `field` of program header `index` set to
+/// `value`.
+fn patched_phdr(index: usize, field: usize, value: u64) -> Vec<u8> {
+    patched(PHDRS + index * PHDR_SIZE + field, value, 8)
+}
+
+#[test]
+fn test_hello() {
+    let elf = Elf::parse(fixture!("hello.elf")).expect("valid ELF");
+    assert_eq!(elf.entry(), BASE + 0x100);
+    assert_eq!(elf.segments(), &[
+        Segment { vaddr: BASE, offset: 0, file_size: 0x10c, mem_size: 0x10c, flags: PF_R | PF_X },
+        Segment { vaddr: BASE + 0x10c, offset: 0x10c, file_size: 8, mem_size: 0x40, flags: PF_R | PF_W },
+    ][..]);
+
+    let code = elf.data(&elf.segments()[0]);
+    assert_eq!(&code[0..4], b"\x7fELF");
+    assert_eq!(&code[0x100..], &[0x40, 0x05, 0x80, 0xd2, 0x61, 0x00, 0x00, 0xd4, 0x00, 0x00, 0x00, 0x14]);
+    assert_eq!(elf.data(&elf.segments()[1]), &0x1122334455667788u64.to_le_bytes());
+
+    // Both segments share the first page, which must be mapped with the
+    // permissions of each.
+    assert_eq!(elf.pages(), vec![(BASE, PF_R | PF_W | PF_X)]);
+}
+
+#[test]
+fn test_bss_pages() {
+    let elf = Elf::parse(fixture!("bss.elf")).expect("valid ELF");
+    assert_eq!(elf.segments().len(), 2);
+    assert_eq!(elf.segments()[1].end(), BASE + PAGE - 0x10 + 0x20000);
+    assert_eq!(elf.data(&elf.segments()[1]), b"data");
+    assert_eq!(elf.pages(), vec![
+        (BASE, PF_R | PF_W | PF_X),
+        (BASE + PAGE, PF_R | PF_W),
+        (BASE + 2 * PAGE, PF_R | PF_W),
+    ]);
+}
+
+#[test]
+fn test_wrong_format() {
+    for data in &[
+        fixture!("x86_64.elf"),
+        fixture!("elf32.elf"),
+        &b"#!/bin/sh\necho not an ELF file, but long enough for a header..\n"[..],
+        &patched(5, 2, 1)[..],
+        &patched(16, 3, 2)[..],
+        &patched(54, 64, 2)[..],
+    ] {
+        assert_eq!(Elf::parse(data).unwrap_err(), OsError::InvalidArgument);
+    }
+}
+
+#[test]
+fn test_truncated() {
+    let hello = fixture!("hello.elf");
+    for &len in &[0, 4, 63, PHDRS + PHDR_SIZE, hello.len() - 1] {
+        assert_eq!(Elf::parse(&hello[..len]).unwrap_err(), OsError::InvalidArgument, "len {}", len);
+    }
+    assert_eq!(Elf::parse(&patched_phdr(1, 8, 0x1000)).unwrap_err(), OsError::InvalidArgument);
+    assert_eq!(Elf::parse(&patched_phdr(1, 8, core::u64::MAX)).unwrap_err(), OsError::InvalidArgument);
+}
+
+#[test]
+fn test_bad_segments() {
+    for data in &[
+        // Below the user image.
+        patched_phdr(1, 16, 0x80000),
+        // Into the stack page, and wrapping around the address space.
+        patched_phdr(1, 40, 0x4000_0000),
+        patched_phdr(1, 40, core::u64::MAX),
+        // More bytes in the file than in memory.
+        patched_phdr(1, 32, 0x41),
+        // Overlapping the code segment.
+        patched_phdr(1, 16, BASE + 0x100),
+        // The entry point outside of the executable segment.
+        patched(24, BASE + 0x10c, 8),
+        patched(24, BASE - 4, 8),
+    ] {
+        assert_eq!(Elf::parse(data).unwrap_err(), OsError::InvalidArgument);
+    }
+}
+
+#[test]
+fn test_ignores_other_segments() {
+    // As a `PT_LOAD`, the `PT_GNU_STACK` header would be below the image.
+    assert!(Elf::parse(&patched(PHDRS + 2 * PHDR_SIZE, 1, 4)).is_err());
+    assert!(Elf::parse(fixture!("hello.elf")).is_ok());
+}
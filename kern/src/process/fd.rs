@@ -0,0 +1,192 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use fat32::traits::{Dir as _, Entry as _, File as _, Metadata as _};
+use kernel_api::{Fd, OsError, OsResult, Stat};
+
+use crate::fs::devfs;
+use crate::fs::vfs;
+use crate::mutex::{Mutex, MutexGuard};
+
+/// The most descriptors a process may hold open at once.
+pub const MAX_FDS: usize = 64;
+
+/// An open file or directory of a process.
+pub enum Descriptor {
+    /// A file or device, read and written at its own offset.
+    File(vfs::File),
+    /// A directory, whose remaining entries are returned by `readdir`.
+    Dir(Box<dyn Iterator<Item = vfs::Entry> + Send>),
+}
+
+impl Descriptor {
+    /// Returns a descriptor for `entry`: the file itself, or an iterator over
+    /// the entries of the directory.
+    pub fn open(entry: vfs::Entry) -> OsResult<Descriptor> {
+        if entry.is_dir() {
+            let dir = entry.into_dir().expect("entry is a directory");
+            Ok(Descriptor::Dir(dir.entries()?))
+        } else {
+            Ok(Descriptor::File(entry.into_file().expect("entry is a file")))
+        }
+    }
+}
+
+impl fmt::Debug for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Descriptor::File(ref file) => write!(f, "File({:?})", file),
+            Descriptor::Dir(_) => write!(f, "Dir"),
+        }
+    }
+}
+
+/// Returns the status of `entry`.
+pub fn stat(entry: &vfs::Entry) -> Stat {
+    let metadata = entry.metadata();
+    Stat {
+        size: entry.as_file().map_or(0, |file| file.size()),
+        modified: metadata.modified().to_unix().as_secs(),
+        is_dir: entry.is_dir(),
+        read_only: metadata.read_only(),
+    }
+}
+
+/// A locked file of a descriptor table, as returned by `FileGuard::lock()`.
+pub struct FileGuard<'a>(MutexGuard<'a, Descriptor>);
+
+impl<'a> FileGuard<'a> {
+    /// Locks `descriptor`, as returned by `FdTable::get()`, and returns its
+    /// file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `IsADirectory` if the descriptor is a directory.
+    pub fn lock(descriptor: &'a Mutex<Descriptor>) -> OsResult<FileGuard<'a>> {
+        let descriptor = descriptor.lock();
+        match *descriptor {
+            Descriptor::File(_) => Ok(FileGuard(descriptor)),
+            Descriptor::Dir(_) => Err(OsError::IsADirectory),
+        }
+    }
+}
+
+impl<'a> Deref for FileGuard<'a> {
+    type Target = vfs::File;
+
+    fn deref(&self) -> &vfs::File {
+        match *self.0 {
+            Descriptor::File(ref file) => file,
+            Descriptor::Dir(_) => unreachable!("guard of a directory"),
+        }
+    }
+}
+
+impl<'a> DerefMut for FileGuard<'a> {
+    fn deref_mut(&mut self) -> &mut vfs::File {
+        match *self.0 {
+            Descriptor::File(ref mut file) => file,
+            Descriptor::Dir(_) => unreachable!("guard of a directory"),
+        }
+    }
+}
+
+/// The descriptor table of a process, indexed by `Fd`. Descriptors 0, 1 and
+/// 2 start out open on the console.
+///
+/// A forked child gets a copy of the table whose descriptors are shared with
+/// the parent's: both see the same file offsets, and a descriptor is only
+/// synced once the last table holding it closes it.
+#[derive(Debug)]
+pub struct FdTable(Vec<Option<Arc<Mutex<Descriptor>>>>);
+
+impl FdTable {
+    /// Returns a table with the console open as standard input, output and
+    /// error.
+    pub fn new() -> FdTable {
+        let mut table = FdTable(Vec::new());
+        for _ in 0..3 {
+            table.0.push(Some(Arc::new(Mutex::new(Descriptor::File(devfs::console())))));
+        }
+        table
+    }
+
+    /// Returns a copy of this table that shares its descriptors, for a
+    /// forked child.
+    pub fn fork(&self) -> FdTable {
+        FdTable(self.0.clone())
+    }
+
+    /// Adds `descriptor` at the lowest free index and returns that index.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TooManyFiles` if `MAX_FDS` descriptors are open.
+    pub fn insert(&mut self, descriptor: Descriptor) -> OsResult<Fd> {
+        let descriptor = Arc::new(Mutex::new(descriptor));
+        match self.0.iter().position(Option::is_none) {
+            Some(index) => {
+                self.0[index] = Some(descriptor);
+                Ok(Fd(index as u64))
+            }
+            None if self.0.len() < MAX_FDS => {
+                self.0.push(Some(descriptor));
+                Ok(Fd(self.0.len() as u64 - 1))
+            }
+            None => Err(OsError::TooManyFiles),
+        }
+    }
+
+    /// Returns the descriptor `fd`, shared with the table, so that it can be
+    /// used once the table is no longer borrowed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BadDescriptor` if `fd` is not open.
+    pub fn get(&self, fd: Fd) -> OsResult<Arc<Mutex<Descriptor>>> {
+        self.0.get(fd.0 as usize).and_then(Option::clone).ok_or(OsError::BadDescriptor)
+    }
+
+    /// Closes `fd` and returns its descriptor, which the caller passes to
+    /// `release()` to sync it if no other table shares it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BadDescriptor` if `fd` is not open.
+    pub fn remove(&mut self, fd: Fd) -> OsResult<Arc<Mutex<Descriptor>>> {
+        let descriptor = self.0.get_mut(fd.0 as usize).and_then(Option::take).ok_or(OsError::BadDescriptor)?;
+        while let Some(None) = self.0.last() {
+            self.0.pop();
+        }
+        Ok(descriptor)
+    }
+}
+
+/// Drops a table's reference to `descriptor`, syncing it if it is a file that
+/// no other table shares.
+///
+/// # Errors
+///
+/// Returns any error from syncing the file.
+pub fn release(descriptor: Arc<Mutex<Descriptor>>) -> OsResult<()> {
+    if Arc::strong_count(&descriptor) > 1 {
+        return Ok(());
+    }
+    let mut guard = descriptor.lock();
+    match *guard {
+        Descriptor::File(ref mut file) => Ok(file.sync()?),
+        Descriptor::Dir(_) => Ok(()),
+    }
+}
+
+impl Drop for FdTable {
+    fn drop(&mut self) {
+        // Files still open when a process exits are synced, as by `close`.
+        for descriptor in self.0.drain(..).flatten() {
+            let _ = release(descriptor);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use alloc::collections::vec_deque::VecDeque;
+use alloc::vec::Vec;
+
+use crate::process::Id;
+
+#[cfg(test)]
+mod tests;
+
+/// The threads blocked in `futex_wait`, each on a futex word identified by
+/// its physical address, so that threads sharing a page wait on the same
+/// word whatever its virtual address in each. Threads are woken in the order
+/// they blocked.
+#[derive(Debug, Default)]
+pub struct FutexQueue(VecDeque<(u64, Id)>);
+
+impl FutexQueue {
+    /// Returns an empty queue.
+    pub fn new() -> FutexQueue {
+        FutexQueue(VecDeque::new())
+    }
+
+    /// Blocks thread `id` on the futex word at physical address `key`, after
+    /// the threads already blocked on it.
+    pub fn wait(&mut self, key: u64, id: Id) {
+        self.0.push_back((key, id));
+    }
+
+    /// Removes up to `n` threads blocked on the futex word at physical
+    /// address `key` and returns them, in the order they blocked.
+    pub fn wake(&mut self, key: u64, n: usize) -> Vec<Id> {
+        let mut woken = Vec::new();
+        self.0.retain(|&(other, id)| {
+            if other == key && woken.len() < n {
+                woken.push(id);
+                false
+            } else {
+                true
+            }
+        });
+        woken
+    }
+
+    /// Removes thread `id` from the queue, e.g. once its wait timed out.
+    /// Returns `false` if it was not blocked.
+    pub fn cancel(&mut self, id: Id) -> bool {
+        let len = self.0.len();
+        self.0.retain(|&(_, other)| other != id);
+        self.0.len() != len
+    }
+}
@@ -0,0 +1,33 @@
+use crate::process::futex::FutexQueue;
+
+#[test]
+fn test_empty() {
+    let mut futexes = FutexQueue::new();
+    assert!(futexes.wake(0x1000, 1).is_empty());
+    assert!(!futexes.cancel(0));
+}
+
+#[test]
+fn test_wake_in_order() {
+    let mut futexes = FutexQueue::new();
+    futexes.wait(0x1000, 0);
+    futexes.wait(0x2000, 1);
+    futexes.wait(0x1000, 2);
+    futexes.wait(0x1000, 3);
+
+    assert_eq!(futexes.wake(0x1000, 2), vec![0, 2]);
+    assert_eq!(futexes.wake(0x1000, 2), vec![3]);
+    assert!(futexes.wake(0x1000, 1).is_empty());
+    assert_eq!(futexes.wake(0x2000, 0), vec![]);
+    assert_eq!(futexes.wake(0x2000, usize::max_value()), vec![1]);
+}
+
+#[test]
+fn test_cancel() {
+    let mut futexes = FutexQueue::new();
+    futexes.wait(0x1000, 0);
+    futexes.wait(0x1000, 1);
+    assert!(futexes.cancel(0));
+    assert!(!futexes.cancel(0));
+    assert_eq!(futexes.wake(0x1000, 2), vec![1]);
+}
@@ -0,0 +1,241 @@
+use alloc::collections::vec_deque::VecDeque;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::process::Id;
+use kernel_api::{NICE_MAX, NICE_MIN};
+
+#[cfg(test)]
+mod tests;
+
+/// The number of run queues. Threads in lower queues are scheduled first.
+pub const LEVELS: usize = 8;
+
+/// The most levels a thread is demoted below the level of its nice value
+/// for using up its time slices.
+const MAX_PENALTY: usize = 4;
+
+/// How often every demotion is forgiven, so that CPU-bound threads are not
+/// starved by interactive ones.
+pub const BOOST_PERIOD: Duration = Duration::from_secs(1);
+
+/// The run queue of threads with nice value `nice` and no penalty: 0 for
+/// nice values from -20 to -11, up to 3 for nice values from 10 to 19.
+fn base_level(nice: i32) -> usize {
+    ((nice - NICE_MIN) / 10) as usize
+}
+
+/// A thread known to the scheduling policy.
+#[derive(Debug)]
+struct Entry {
+    id: Id,
+    nice: i32,
+    /// The number of levels the thread is demoted by.
+    penalty: usize,
+    /// How much of its current time slice the thread has used.
+    used: Duration,
+}
+
+impl Entry {
+    /// Returns the run queue of the thread.
+    fn level(&self) -> usize {
+        core::cmp::min(base_level(self.nice) + self.penalty, LEVELS - 1)
+    }
+
+    /// Returns the time slice of the thread, given the base time slice
+    /// `slice`: demoted threads run less often, but for longer.
+    fn quantum(&self, slice: Duration) -> Duration {
+        slice * (self.penalty as u32 + 1)
+    }
+}
+
+/// A multilevel feedback queue: the scheduling policy of `Scheduler`.
+///
+/// Threads run in order of their run queue, round-robin within one. A
+/// thread starts in the queue of its nice value, is demoted by a queue each
+/// time it uses up its time slice and promoted by one each time it blocks
+/// before doing so; every `BOOST_PERIOD`, every thread returns to the queue
+/// of its nice value.
+///
+/// The policy does not read the clock: every method that depends on time
+/// takes the current time, `now`.
+#[derive(Debug)]
+pub struct Mlfq {
+    slice: Duration,
+    queues: Vec<VecDeque<Id>>,
+    entries: Vec<Entry>,
+    /// The thread picked last, and when, until it is stopped.
+    running: Option<(Id, Duration)>,
+    last_boost: Duration,
+}
+
+impl Mlfq {
+    /// Returns an empty policy whose threads without penalty run for
+    /// `slice` at a time.
+    pub fn new(slice: Duration) -> Mlfq {
+        Mlfq {
+            slice,
+            queues: (0..LEVELS).map(|_| VecDeque::new()).collect(),
+            entries: Vec::new(),
+            running: None,
+            last_boost: Duration::from_secs(0),
+        }
+    }
+
+    fn entry(&self, id: Id) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    fn entry_mut(&mut self, id: Id) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.id == id)
+    }
+
+    /// Removes `id` from its run queue, if it is queued.
+    fn dequeue(&mut self, id: Id) {
+        for queue in self.queues.iter_mut() {
+            if let Some(index) = queue.iter().position(|&queued| queued == id) {
+                queue.remove(index);
+            }
+        }
+    }
+
+    /// Adds the thread `id` with nice value `nice` to the back of its run
+    /// queue.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `nice` is outside of `NICE_MIN..=NICE_MAX`.
+    pub fn add(&mut self, id: Id, nice: i32) {
+        assert!((NICE_MIN..=NICE_MAX).contains(&nice), "nice value {} out of range", nice);
+        let entry = Entry { id, nice, penalty: 0, used: Duration::from_secs(0) };
+        self.queues[entry.level()].push_back(id);
+        self.entries.push(entry);
+    }
+
+    /// Forgets the thread `id`.
+    pub fn remove(&mut self, id: Id) {
+        self.dequeue(id);
+        self.entries.retain(|entry| entry.id != id);
+        if self.running.map(|(running, _)| running) == Some(id) {
+            self.running = None;
+        }
+    }
+
+    /// Returns the nice value of the thread `id`.
+    pub fn nice(&self, id: Id) -> Option<i32> {
+        self.entry(id).map(|entry| entry.nice)
+    }
+
+    /// Returns the run queue of the thread `id`.
+    pub fn level(&self, id: Id) -> Option<usize> {
+        self.entry(id).map(Entry::level)
+    }
+
+    /// Sets the nice value of the thread `id`, moving it to the back of its
+    /// new run queue if it is queued. Returns `false` if there is no such
+    /// thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `nice` is outside of `NICE_MIN..=NICE_MAX`.
+    pub fn set_nice(&mut self, id: Id, nice: i32) -> bool {
+        assert!((NICE_MIN..=NICE_MAX).contains(&nice), "nice value {} out of range", nice);
+        let level = match self.entry_mut(id) {
+            Some(entry) => {
+                entry.nice = nice;
+                entry.level()
+            }
+            None => return false,
+        };
+        if self.queues.iter().any(|queue| queue.contains(&id)) {
+            self.dequeue(id);
+            self.queues[level].push_back(id);
+        }
+        true
+    }
+
+    /// Returns every thread to the run queue of its nice value, keeping the
+    /// order of threads within each queue.
+    fn boost(&mut self, now: Duration) {
+        let queued: Vec<Id> = self.queues.iter_mut().flat_map(|queue| queue.drain(..)).collect();
+        for entry in self.entries.iter_mut() {
+            entry.penalty = 0;
+            entry.used = Duration::from_secs(0);
+        }
+        for id in queued {
+            let level = self.entry(id).expect("queued thread has an entry").level();
+            self.queues[level].push_back(id);
+        }
+        self.last_boost = now;
+    }
+
+    /// Picks the thread to run at `now`: the first for which `ready`
+    /// returns `true`, in order of run queue and then of arrival. The thread
+    /// is taken off its queue until it is stopped with `stop()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thread picked last has not been stopped.
+    pub fn pick<F: FnMut(Id) -> bool>(&mut self, now: Duration, mut ready: F) -> Option<Id> {
+        assert!(self.running.is_none(), "thread {:?} still running", self.running);
+        if now - self.last_boost >= BOOST_PERIOD {
+            self.boost(now);
+        }
+
+        for queue in self.queues.iter_mut() {
+            if let Some(index) = queue.iter().position(|&id| ready(id)) {
+                let id = queue.remove(index).expect("index in bounds");
+                self.running = Some((id, now));
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    /// Returns `true` if the running thread has used up its time slice at
+    /// `now`.
+    pub fn expired(&self, now: Duration) -> bool {
+        match self.running {
+            Some((id, start)) => {
+                let entry = self.entry(id).expect("running thread has an entry");
+                entry.used + (now - start) >= entry.quantum(self.slice)
+            }
+            None => false,
+        }
+    }
+
+    /// Returns when the running thread's time slice is over.
+    pub fn deadline(&self) -> Option<Duration> {
+        let (id, start) = self.running?;
+        let entry = self.entry(id).expect("running thread has an entry");
+        Some(start + entry.quantum(self.slice) - entry.used)
+    }
+
+    /// Stops the running thread at `now`, because its time slice is over,
+    /// because it `blocked` waiting for an event, or for no reason of its own,
+    /// and puts it at the back of its run queue. A thread is demoted if it
+    /// used up its time slice, and promoted if it blocked before doing so;
+    /// either way, it gets a new time slice.
+    ///
+    /// Does nothing if no thread is running.
+    pub fn stop(&mut self, now: Duration, blocked: bool) {
+        let (id, start) = match self.running.take() {
+            Some(running) => running,
+            None => return,
+        };
+
+        let slice = self.slice;
+        let entry = self.entry_mut(id).expect("running thread has an entry");
+        entry.used += now - start;
+        if entry.used >= entry.quantum(slice) {
+            entry.penalty = core::cmp::min(entry.penalty + 1, MAX_PENALTY);
+            entry.used = Duration::from_secs(0);
+        } else if blocked {
+            entry.penalty = entry.penalty.saturating_sub(1);
+            entry.used = Duration::from_secs(0);
+        }
+
+        let level = entry.level();
+        self.queues[level].push_back(id);
+    }
+}
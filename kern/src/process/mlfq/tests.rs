@@ -0,0 +1,183 @@
+use core::time::Duration;
+
+use crate::process::mlfq::{Mlfq, BOOST_PERIOD, LEVELS};
+
+const SLICE: Duration = Duration::from_millis(10);
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+fn ready(_: u64) -> bool {
+    true
+}
+
+/// Runs whatever `mlfq` picks at `*now` until its time slice is over, and
+/// returns its ID.
+fn run_slice(mlfq: &mut Mlfq, now: &mut Duration) -> u64 {
+    let id = mlfq.pick(*now, ready).expect("a thread to run");
+    while !mlfq.expired(*now) {
+        *now += SLICE;
+    }
+    mlfq.stop(*now, false);
+    id
+}
+
+#[test]
+fn test_round_robin() {
+    let mut mlfq = Mlfq::new(SLICE);
+    for id in 0..3 {
+        mlfq.add(id, 0);
+    }
+
+    // Preempted before their time slices are over, threads keep their
+    // level and take turns.
+    let mut order = vec![];
+    for i in 0..6 {
+        order.push(mlfq.pick(ms(i), ready).unwrap());
+        mlfq.stop(ms(i) + ms(1), false);
+    }
+    assert_eq!(order, vec![0, 1, 2, 0, 1, 2]);
+    assert_eq!(mlfq.level(0), Some(2));
+}
+
+#[test]
+fn test_skips_waiting() {
+    let mut mlfq = Mlfq::new(SLICE);
+    for id in 0..3 {
+        mlfq.add(id, 0);
+    }
+    assert_eq!(mlfq.pick(ms(0), |id| id == 2), Some(2));
+    mlfq.stop(ms(1), false);
+    assert_eq!(mlfq.pick(ms(1), |id| id == 2), Some(2));
+    mlfq.stop(ms(2), false);
+    assert_eq!(mlfq.pick(ms(2), |_| false), None);
+    assert_eq!(mlfq.pick(ms(2), ready), Some(0));
+}
+
+#[test]
+fn test_demotes_cpu_hogs() {
+    let mut mlfq = Mlfq::new(SLICE);
+    mlfq.add(0, 0);
+    let mut now = ms(0);
+
+    // Each time slice used up demotes the thread, and doubles, then
+    // triples, ... its next time slice.
+    for (level, slices) in (2..7).zip(1..) {
+        assert_eq!(mlfq.level(0), Some(level));
+        let start = now;
+        assert_eq!(run_slice(&mut mlfq, &mut now), 0);
+        assert_eq!(now - start, SLICE * slices);
+    }
+
+    // Down to a limit.
+    assert_eq!(mlfq.level(0), Some(6));
+    run_slice(&mut mlfq, &mut now);
+    assert_eq!(mlfq.level(0), Some(6));
+}
+
+#[test]
+fn test_promotes_interactive() {
+    let mut mlfq = Mlfq::new(SLICE);
+    mlfq.add(0, 0);
+    mlfq.add(1, 0);
+    let mut now = ms(0);
+
+    // Both use up a time slice.
+    assert_eq!(run_slice(&mut mlfq, &mut now), 0);
+    assert_eq!(run_slice(&mut mlfq, &mut now), 1);
+    assert_eq!(mlfq.level(0), Some(3));
+
+    // 1 is behind 0 in the queue, but blocking promotes it.
+    assert_eq!(mlfq.pick(now, |id| id == 1), Some(1));
+    mlfq.stop(now + ms(1), true);
+    assert_eq!(mlfq.level(1), Some(2));
+    assert_eq!(mlfq.pick(now + ms(1), ready), Some(1));
+    mlfq.stop(now + ms(2), false);
+
+    // 0 runs once 1 is waiting.
+    assert_eq!(mlfq.pick(now + ms(2), |id| id == 0), Some(0));
+}
+
+#[test]
+fn test_boost() {
+    let mut mlfq = Mlfq::new(SLICE);
+    mlfq.add(0, 0);
+    let mut now = ms(0);
+    run_slice(&mut mlfq, &mut now);
+    run_slice(&mut mlfq, &mut now);
+    assert_eq!(mlfq.level(0), Some(4));
+
+    // Until the boost, 1 always goes first.
+    mlfq.add(1, 0);
+    assert_eq!(mlfq.pick(BOOST_PERIOD - ms(2), ready), Some(1));
+    mlfq.stop(BOOST_PERIOD - ms(1), false);
+    assert_eq!(mlfq.pick(BOOST_PERIOD - ms(1), ready), Some(1));
+    mlfq.stop(BOOST_PERIOD, false);
+
+    // The boost puts 0 back in the queue of 1, behind it, with its first
+    // time slice.
+    assert_eq!(mlfq.pick(BOOST_PERIOD, ready), Some(1));
+    assert_eq!(mlfq.level(0), Some(2));
+    mlfq.stop(BOOST_PERIOD + ms(1), false);
+    assert_eq!(mlfq.pick(BOOST_PERIOD + ms(1), ready), Some(0));
+    assert!(!mlfq.expired(BOOST_PERIOD + ms(1) + SLICE - ms(1)));
+    assert!(mlfq.expired(BOOST_PERIOD + ms(1) + SLICE));
+}
+
+#[test]
+fn test_nice() {
+    let mut mlfq = Mlfq::new(SLICE);
+    mlfq.add(0, 19);
+    mlfq.add(1, 0);
+    mlfq.add(2, -20);
+    assert_eq!(mlfq.level(0), Some(3));
+    assert_eq!(mlfq.level(2), Some(0));
+    assert_eq!(mlfq.nice(0), Some(19));
+
+    assert_eq!(mlfq.pick(ms(0), ready), Some(2));
+    mlfq.stop(ms(1), false);
+    assert_eq!(mlfq.pick(ms(1), |id| id != 2), Some(1));
+    mlfq.stop(ms(2), false);
+
+    // Renicing requeues the thread.
+    assert!(mlfq.set_nice(0, -10));
+    assert_eq!(mlfq.level(0), Some(1));
+    assert_eq!(mlfq.pick(ms(2), |id| id != 2), Some(0));
+    mlfq.stop(ms(3), false);
+
+    assert!(!mlfq.set_nice(3, 0));
+    assert_eq!(mlfq.level(3), None);
+}
+
+#[test]
+fn test_nice_and_penalty_bounded() {
+    let mut mlfq = Mlfq::new(SLICE);
+    mlfq.add(0, 19);
+    let mut now = ms(0);
+    for _ in 0..10 {
+        run_slice(&mut mlfq, &mut now);
+    }
+    assert_eq!(mlfq.level(0), Some(LEVELS - 1));
+}
+
+#[test]
+fn test_remove() {
+    let mut mlfq = Mlfq::new(SLICE);
+    mlfq.add(0, 0);
+    mlfq.add(1, 0);
+    assert_eq!(mlfq.pick(ms(0), ready), Some(0));
+    mlfq.remove(0);
+    mlfq.stop(ms(1), false);
+    assert_eq!(mlfq.pick(ms(1), ready), Some(1));
+    mlfq.stop(ms(2), false);
+    mlfq.remove(1);
+    assert_eq!(mlfq.pick(ms(2), ready), None);
+    assert_eq!(mlfq.nice(1), None);
+}
+
+#[test]
+#[should_panic]
+fn test_nice_out_of_range() {
+    Mlfq::new(SLICE).add(0, 20);
+}
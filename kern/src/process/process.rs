@@ -1,11 +1,17 @@
 use alloc::boxed::Box;
-use shim::io;
-use shim::path::Path;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use shim::io::{self, Read};
+use shim::path::{Path, PathBuf};
 
 use aarch64;
+use fat32::traits::{Entry, File, FileSystem};
 
 use crate::param::*;
-use crate::process::{Stack, State};
+use crate::process::args::ArgBlock;
+use crate::process::elf::{Elf, Segment, PF_W, PF_X};
+use crate::process::{FdTable, Thread};
 use crate::traps::TrapFrame;
 use crate::vm::*;
 use kernel_api::{OsError, OsResult};
@@ -13,98 +19,265 @@ use kernel_api::{OsError, OsResult};
 /// Type alias for the type of a process ID.
 pub type Id = u64;
 
-/// A structure that represents the complete state of a process.
+/// A child of a process, which the process can `wait` for, or a thread it
+/// spawned, which it can `thread_join`.
+#[derive(Debug, Copy, Clone)]
+pub struct Child {
+    /// The child's process ID, or the thread's ID.
+    pub id: Id,
+    /// The child's or thread's exit status, once it has exited.
+    pub status: Option<i32>,
+}
+
+/// The state shared by the threads of a process: its address space, open
+/// files and family. The threads themselves are scheduled separately; see
+/// `Thread`.
 #[derive(Debug)]
 pub struct Process {
-    /// The saved trap frame of a process.
-    pub context: Box<TrapFrame>,
-    /// The memory allocation used for the process's stack.
-    pub stack: Stack,
+    /// The process's ID, the ID of its first thread.
+    pub id: Id,
     /// The page table describing the Virtual Memory of the process
-    // pub vmap: Box<UserPageTable>,
-    /// The scheduling state of the process.
-    pub state: State,
+    pub vmap: Box<UserPageTable>,
+    /// The files and directories the process has open.
+    pub files: FdTable,
+    /// The working directory, against which relative paths are resolved.
+    pub cwd: PathBuf,
+    /// The command-line arguments the process was started with, the first
+    /// being the name it was started by.
+    pub args: Vec<String>,
+    /// The environment the process was started with, as `KEY=value` strings.
+    pub env: Vec<String>,
+    /// The process that spawned this one, while it is alive.
+    pub parent: Option<Id>,
+    /// The children of this process that it has not yet waited for.
+    pub children: Vec<Child>,
+    /// The threads this process spawned that it has not yet joined.
+    pub threads: Vec<Child>,
 }
 
 impl Process {
-    /// Creates a new process with a zeroed `TrapFrame` (the default), a zeroed
-    /// stack of the default size, and a state of `Ready`.
-    ///
-    /// If enough memory could not be allocated to start the process, returns
-    /// `None`. Otherwise returns `Some` of the new `Process`.
+    /// Creates a new process with ID 0 until it is scheduled, an empty
+    /// address space, the console open as descriptors 0 to 2, `/` as the
+    /// working directory, and no arguments, environment, parent, children or
+    /// threads.
     pub fn new() -> OsResult<Process> {
         Ok(Process {
-            context: Box::new(TrapFrame::default()),
-            stack: Stack::new().ok_or(OsError::NoMemory)?,
-            state: State::Ready,
+            id: 0,
+            vmap: Box::new(UserPageTable::new()),
+            files: FdTable::new(),
+            cwd: PathBuf::from("/"),
+            args: Vec::new(),
+            env: Vec::new(),
+            parent: None,
+            children: Vec::new(),
+            threads: Vec::new(),
         })
+    }
 
+    /// Creates a process whose first thread runs `entry` in the kernel, as a
+    /// kernel thread does (see `Thread::kernel()`), but with a process of its
+    /// own: it can start and `wait` for children.
+    pub fn kernel(entry: extern "C" fn() -> !) -> OsResult<(Process, Thread)> {
+        let p = Process::new()?;
+        let mut thread = Thread::kernel(entry as u64, 0)?;
+        thread.context.ttbr1 = p.vmap.get_baddr().as_u64();
+        Ok((p, thread))
     }
 
-    /// Load a program stored in the given path by calling `do_load()` method.
-    /// Set trapframe `context` corresponding to the its page table.
-    /// `sp` - the address of stack top
-    /// `elr` - the address of image base.
-    /// `ttbr0` - the base address of kernel page table
-    /// `ttbr1` - the base address of user page table
-    /// `spsr` - `F`, `A`, `D` bit should be set.
-    ///
-    /// Returns Os Error if do_load fails.
-    pub fn load<P: AsRef<Path>>(pn: P) -> OsResult<Process> {
+    /// Returns a thread of this process that runs `entry` in user space, on
+    /// the stack whose top is `sp`, with `arg` in `x0`.
+    pub fn thread(&self, entry: u64, arg: u64, sp: u64) -> Thread {
         use crate::VMM;
 
-        let mut p = Process::do_load(pn)?;
+        let mut thread = Thread::new();
+        thread.context.elr = entry;
+        thread.context.sp = sp;
+        thread.context.xs[0] = arg;
+        thread.context.ttbr0 = VMM.get_baddr().as_u64();
+        thread.context.ttbr1 = self.vmap.get_baddr().as_u64();
+        thread.context.spsr = aarch64::SPSR_EL1::F | aarch64::SPSR_EL1::A | aarch64::SPSR_EL1::D;
+        thread.process = Some(self.id);
+        thread
+    }
+
+    /// Load a program stored in the given path by calling `do_load()` method,
+    /// and returns the process with its first thread, which runs the program
+    /// in user space from its entry point, on the process's stack.
+    ///
+    /// `args` and `env` are kept as the process's command-line arguments and
+    /// environment, and passed to it in an argument block on its stack:
+    /// `sp` points at the block, and `x0` to `x2` hold `argc`, `argv` and
+    /// `envp`, as the arguments of its entry point.
+    ///
+    /// Returns Os Error if do_load fails, or `InvalidArgument` if the
+    /// arguments and environment do not fit on the stack.
+    pub fn load<P: AsRef<Path>>(pn: P, args: &[&str], env: &[&str]) -> OsResult<(Process, Thread)> {
+        let (mut p, entry) = Process::do_load(pn)?;
+        p.args = args.iter().map(|&arg| String::from(arg)).collect();
+        p.env = env.iter().map(|&var| String::from(var)).collect();
+
+        let top = Process::get_stack_top();
+        let stack = p.vmap.page(Process::get_stack_base()).expect("stack page is mapped");
+        let block = ArgBlock::build(&mut stack[..top.as_usize() - USER_STACK_BASE], top.as_u64(), &p.args, &p.env)?;
+        let mut thread = p.thread(entry, block.argc, block.sp);
+        thread.context.xs[1] = block.argv;
+        thread.context.xs[2] = block.envp;
+
+        Ok((p, thread))
+    }
+
+    /// Returns a copy of this process as its thread's system call with trap
+    /// frame `tf` left it, to run as its child with a copy of that thread
+    /// alone. The child shares this process's pages copy-on-write and its
+    /// open descriptors, and returns 0 from the call.
+    ///
+    /// Returns `InvalidArgument` if this is a kernel process, whose stack is
+    /// not in its address space, and `NoMemory` if its pages cannot be shared
+    /// once more.
+    pub fn fork(&mut self, tf: &TrapFrame) -> OsResult<(Process, Thread)> {
+        if tf.spsr & aarch64::SPSR_EL1::M != 0 {
+            return Err(OsError::InvalidArgument);
+        }
 
-        //FIXME: Set trapframe for the process.
+        let mut child = Process::new()?;
+        *child.vmap = self.vmap.fork().ok_or(OsError::NoMemory)?;
+        child.files = self.files.fork();
+        child.cwd = self.cwd.clone();
+        child.args = self.args.clone();
+        child.env = self.env.clone();
+        child.parent = Some(self.id);
 
-        Ok(p)
+        let mut thread = Thread::new();
+        *thread.context = *tf;
+        thread.context.ttbr1 = child.vmap.get_baddr().as_u64();
+        thread.context.xs[0] = 0;
+        thread.context.xs[7] = OsError::Ok as u64;
+        Ok((child, thread))
     }
 
-    /// Creates a process and open a file with given path.
-    /// Allocates one page for stack with read/write permission, and N pages with read/write/execute
-    /// permission to load file's contents.
-    fn do_load<P: AsRef<Path>>(pn: P) -> OsResult<Process> {
-        unimplemented!();
+    /// Creates a process and loads the ELF executable at the given path into
+    /// it. Allocates one page for stack with read/write permission, and maps
+    /// the pages of each `PT_LOAD` segment with the permissions of the
+    /// segments on it. Returns the process and the entry point of the
+    /// executable.
+    ///
+    /// Returns `InvalidArgument` if the file is not an ELF64 executable for
+    /// AArch64 that fits in the user image, and `NoMemory` if its pages cannot
+    /// be allocated.
+    fn do_load<P: AsRef<Path>>(pn: P) -> OsResult<(Process, u64)> {
+        use crate::FILESYSTEM;
+
+        let mut file = FILESYSTEM.open(pn.as_ref())?.into_file().ok_or(OsError::IsADirectory)?;
+        // Nothing larger can fit in the user image, so don't read it.
+        if file.size() > USER_MAX_VM_SIZE as u64 {
+            return Err(OsError::InvalidArgument);
+        }
+        let mut data = vec![0; file.size() as usize];
+        file.read_exact(&mut data)?;
+        let elf = Elf::parse(&data)?;
+
+        let mut process = Process::new()?;
+        process.vmap.alloc(Process::get_stack_base(), PagePerm::RW).ok_or(OsError::NoMemory)?;
+        for &(page, flags) in elf.pages().iter() {
+            let perm = match (flags & PF_W != 0, flags & PF_X != 0) {
+                (true, true) => PagePerm::RWX,
+                (true, false) => PagePerm::RW,
+                (false, true) => PagePerm::RX,
+                (false, false) => PagePerm::RO,
+            };
+            process.vmap.alloc(VirtualAddr::from(page), perm).ok_or(OsError::NoMemory)?;
+        }
+        for segment in elf.segments() {
+            process.load_segment(segment, elf.data(segment));
+        }
+
+        Ok((process, elf.entry()))
+    }
+
+    /// Copies `data`, the bytes of `segment` in its file, to the segment's
+    /// pages, and zeroes the rest of the segment.
+    fn load_segment(&mut self, segment: &Segment, data: &[u8]) {
+        let mut va = segment.vaddr as usize;
+        let end = segment.end() as usize;
+        while va < end {
+            let base = va & PAGE_MASK;
+            let page = self.vmap.page(VirtualAddr::from(base)).expect("segment page is mapped");
+            let chunk = &mut page[va - base..core::cmp::min(end - base, PAGE_SIZE)];
+
+            let start = va - segment.vaddr as usize;
+            let copied = data.len().saturating_sub(start).min(chunk.len());
+            chunk[..copied].copy_from_slice(&data[start..start + copied]);
+            for byte in chunk[copied..].iter_mut() {
+                *byte = 0;
+            }
+            va += chunk.len();
+        }
     }
 
     /// Returns the highest `VirtualAddr` that is supported by this system.
     pub fn get_max_va() -> VirtualAddr {
-        unimplemented!();
+        VirtualAddr::from(USER_IMG_BASE.wrapping_add(USER_MAX_VM_SIZE).wrapping_sub(1))
     }
 
     /// Returns the `VirtualAddr` represents the base address of the user
     /// memory space.
     pub fn get_image_base() -> VirtualAddr {
-        unimplemented!();
+        VirtualAddr::from(USER_IMG_BASE)
     }
 
     /// Returns the `VirtualAddr` represents the base address of the user
     /// process's stack.
     pub fn get_stack_base() -> VirtualAddr {
-        unimplemented!();
+        VirtualAddr::from(USER_STACK_BASE)
     }
 
     /// Returns the `VirtualAddr` represents the top of the user process's
     /// stack.
     pub fn get_stack_top() -> VirtualAddr {
-        unimplemented!();
+        VirtualAddr::from(Process::get_max_va().as_usize() & !(PAGE_ALIGN - 1))
     }
 
-    /// Returns `true` if this process is ready to be scheduled.
-    ///
-    /// This functions returns `true` only if one of the following holds:
-    ///
-    ///   * The state is currently `Ready`.
-    ///
-    ///   * An event being waited for has arrived.
-    ///
-    ///     If the process is currently waiting, the corresponding event
-    ///     function is polled to determine if the event being waiting for has
-    ///     occured. If it has, the state is switched to `Ready` and this
-    ///     function returns `true`.
-    ///
-    /// Returns `false` in all other cases.
-    pub fn is_ready(&mut self) -> bool {
-        unimplemented!("Process::is_ready()")
+    /// Returns `true` if `id` is a child of this process that has not been
+    /// waited for.
+    pub fn has_child(&self, id: Id) -> bool {
+        self.children.iter().any(|child| child.id == id)
+    }
+
+    /// Records that the child `id` exited with `status`, dropping the status
+    /// of the child that exited first if more than `MAX_EXITED_CHILDREN`
+    /// are then kept.
+    pub fn child_exited(&mut self, id: Id, status: i32) {
+        let index = match self.children.iter().position(|child| child.id == id) {
+            Some(index) => index,
+            None => return,
+        };
+        // Exited children are kept in the order they exited, last at the end.
+        self.children.remove(index);
+        self.children.push(Child { id, status: Some(status) });
+
+        if self.children.iter().filter(|child| child.status.is_some()).count() > MAX_EXITED_CHILDREN {
+            let first = self.children.iter().position(|child| child.status.is_some()).expect("an exited child");
+            self.children.remove(first);
+        }
+    }
+
+    /// Forgets the child `id` and returns its exit status if it has exited.
+    /// Returns `None` if it is still running.
+    pub fn reap(&mut self, id: Id) -> Option<i32> {
+        let index = self.children.iter().position(|child| child.id == id && child.status.is_some())?;
+        self.children.remove(index).status
+    }
+
+    /// Returns `true` if `id` is a thread this process spawned that has not
+    /// been joined.
+    pub fn has_thread(&self, id: Id) -> bool {
+        self.threads.iter().any(|thread| thread.id == id)
+    }
+
+    /// Forgets the thread `id` and returns its exit status if it has exited.
+    /// Returns `None` if it is still running.
+    pub fn join(&mut self, id: Id) -> Option<i32> {
+        let index = self.threads.iter().position(|thread| thread.id == id && thread.status.is_some())?;
+        self.threads.remove(index).status
     }
 }
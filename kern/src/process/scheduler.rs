@@ -1,14 +1,40 @@
 use alloc::boxed::Box;
-use alloc::collections::vec_deque::VecDeque;
+use alloc::vec::Vec;
 use core::fmt;
+use core::time::Duration;
 
 use aarch64::*;
+use pi::interrupt::{Controller, Interrupt};
+use pi::timer;
 
 use crate::mutex::Mutex;
-use crate::param::{PAGE_MASK, PAGE_SIZE, TICK, USER_IMG_BASE};
-use crate::process::{Id, Process, State};
+use crate::param::{cmdline_option, DEFAULT_SLICE, PAGE_MASK, PAGE_SIZE, USER_IMG_BASE};
+use crate::process::futex::FutexQueue;
+use crate::process::mlfq::Mlfq;
+use crate::process::timers::TimerQueue;
+use crate::process::{Child, Id, Process, State, Thread};
 use crate::traps::TrapFrame;
-use crate::VMM;
+use crate::vm::PhysicalAddr;
+use crate::{IRQ, SCHEDULER, VMM};
+use kernel_api::{OsError, OsResult, NICE_MAX, NICE_MIN};
+
+/// A summary of a queued thread, as returned by
+/// `GlobalScheduler::threads()`.
+#[derive(Debug, Copy, Clone)]
+pub struct ThreadInfo {
+    /// The thread's ID.
+    pub id: Id,
+    /// The ID of the thread's process, or `None` for a kernel thread.
+    pub process: Option<Id>,
+    /// The name of the thread's scheduling state, e.g. `ready`.
+    pub state: &'static str,
+    /// The thread's nice value.
+    pub nice: i32,
+    /// The thread's run queue, from 0, scheduled first.
+    pub level: usize,
+    /// The top of the thread's kernel stack, if it runs in the kernel.
+    pub stack_top: Option<PhysicalAddr>,
+}
 
 /// Process scheduler for the entire machine.
 #[derive(Debug)]
@@ -31,47 +57,224 @@ impl GlobalScheduler {
     }
 
 
-    /// Adds a process to the scheduler's queue and returns that process's ID.
-    /// For more details, see the documentation on `Scheduler::add()`.
-    pub fn add(&self, process: Process) -> Option<Id> {
-        self.critical(move |scheduler| scheduler.add(process))
+    /// Returns a summary of every thread, in order of ID. The list is empty
+    /// if the scheduler is not yet initialized.
+    pub fn threads(&self) -> Vec<ThreadInfo> {
+        match self.0.lock().as_ref() {
+            Some(scheduler) => scheduler.threads.iter()
+                .map(|thread| {
+                    let id = thread.context.tpidr;
+                    ThreadInfo {
+                        id,
+                        process: thread.process,
+                        state: thread.state.name(),
+                        nice: scheduler.mlfq.nice(id).expect("thread is scheduled"),
+                        level: scheduler.mlfq.level(id).expect("thread is scheduled"),
+                        stack_top: thread.stack.as_ref().map(|stack| stack.top()),
+                    }
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Calls `f` with the process of the running thread whose trap frame is
+    /// `tf`, as from a system call, and returns its result.
+    ///
+    /// `f` runs with the scheduler locked, so it should only take what it
+    /// needs from the process, e.g. a descriptor or the working directory,
+    /// and leave file system I/O to the caller: some files, like those of
+    /// `/proc`, lock the scheduler themselves.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if no such thread is running, or if it is a
+    /// kernel thread, which has no process.
+    pub fn with_current<F, R>(&self, tf: &TrapFrame, f: F) -> OsResult<R>
+    where
+        F: FnOnce(&mut Process) -> OsResult<R>,
+    {
+        self.critical(|scheduler| f(scheduler.current(tf).ok_or(OsError::InvalidArgument)?))
+    }
+
+    /// Adds a process and its first thread to the scheduler's queue and
+    /// returns that process's ID. For more details, see the documentation on
+    /// `Scheduler::add()`.
+    pub fn add(&self, process: Process, thread: Thread) -> Option<Id> {
+        self.critical(move |scheduler| scheduler.add(process, thread))
+    }
+
+    /// Adds `thread`, a new thread of the process of the running thread whose
+    /// trap frame is `tf`, to the scheduler's queue with the running thread's
+    /// nice value, and returns its ID. For more details, see the
+    /// documentation on `Scheduler::spawn()`.
+    pub fn spawn(&self, tf: &TrapFrame, thread: Thread) -> Option<Id> {
+        self.critical(move |scheduler| {
+            let nice = scheduler.mlfq.nice(tf.tpidr).unwrap_or(0);
+            scheduler.spawn(thread, nice)
+        })
+    }
+
+    /// Starts a kernel thread that runs `entry` with `arg`, as described by
+    /// `Thread::kernel()`, and returns its ID.
+    pub fn spawn_kernel(&self, entry: extern "C" fn(u64) -> !, arg: u64) -> OsResult<Id> {
+        let thread = Thread::kernel(entry as u64, arg)?;
+        self.critical(move |scheduler| scheduler.spawn(thread, 0)).ok_or(OsError::NoMemory)
     }
 
     /// Performs a context switch using `tf` by setting the state of the current
-    /// process to `new_state`, saving `tf` into the current process, and
-    /// restoring the next process's trap frame into `tf`. For more details, see
+    /// thread to `new_state`, saving `tf` into the current thread, and
+    /// restoring the next thread's trap frame into `tf`. For more details, see
     /// the documentation on `Scheduler::schedule_out()` and `Scheduler::switch_to()`.
     pub fn switch(&self, new_state: State, tf: &mut TrapFrame) -> Id {
         self.critical(|scheduler| scheduler.schedule_out(new_state, tf));
         self.switch_to(tf)
     }
 
+    /// Performs a context switch using `tf` to the next thread to run, and
+    /// programs the timer for the next event: the end of the thread's time
+    /// slice or the next wakeup, whichever is nearer. Until a thread is
+    /// ready to run, the core sleeps until the next interrupt: the timer is
+    /// only programmed for wakeups, if any.
     pub fn switch_to(&self, tf: &mut TrapFrame) -> Id {
         loop {
-            let rtn = self.critical(|scheduler| scheduler.switch_to(tf));
+            let rtn = self.critical(|scheduler| {
+                let now = timer::current_time();
+                scheduler.wake(now);
+                let id = scheduler.switch_to(tf);
+                scheduler.arm_timer(now);
+                id
+            });
             if let Some(id) = rtn {
                 return id;
             }
-            aarch64::wfe();
+            // IRQs are masked here, but a pending IRQ still ends the `wfi`.
+            aarch64::wfi();
+        }
+    }
+
+    /// Puts the running thread whose trap frame is `tf` to sleep for `span`,
+    /// and switches to the next thread using `tf`. The thread's system call
+    /// returns once it is woken, as `Thread::wake()` describes.
+    pub fn sleep(&self, span: Duration, tf: &mut TrapFrame) -> Id {
+        let now = timer::current_time();
+        self.critical(|scheduler| {
+            scheduler.timers.add(now + span, tf.tpidr);
+            scheduler.schedule_out(State::Sleeping(now), tf)
+        });
+        self.switch_to(tf)
+    }
+
+    /// Blocks the running thread whose trap frame is `tf` on the futex word at
+    /// physical address `key`, until `futex_wake()` wakes it or, if given,
+    /// `timeout` is over, and switches to the next thread using `tf`. The
+    /// thread's system call returns as `Thread::unblock()` or
+    /// `Thread::wake()` describes.
+    ///
+    /// The caller must have checked the futex word: nothing may have run
+    /// since, so that no wakeup is missed.
+    pub fn futex_wait(&self, tf: &mut TrapFrame, key: u64, timeout: Option<Duration>) -> Id {
+        let now = timer::current_time();
+        self.critical(|scheduler| {
+            scheduler.futexes.wait(key, tf.tpidr);
+            if let Some(timeout) = timeout {
+                scheduler.timers.add(now + timeout, tf.tpidr);
+            }
+            scheduler.schedule_out(State::Blocked, tf)
+        });
+        self.switch_to(tf)
+    }
+
+    /// Wakes up to `n` threads blocked on the futex word at physical address
+    /// `key`, in the order they blocked, and returns how many were woken.
+    pub fn futex_wake(&self, key: u64, n: usize) -> usize {
+        self.critical(|scheduler| {
+            let woken = scheduler.futexes.wake(key, n);
+            for &id in woken.iter() {
+                scheduler.timers.cancel(id);
+                if let Some(thread) = scheduler.find_thread(id) {
+                    thread.unblock();
+                }
+            }
+            woken.len()
+        })
+    }
+
+    /// Sets the nice value of thread `id` on behalf of the running thread
+    /// whose trap frame is `tf`. For more details, see the documentation on
+    /// `Scheduler::renice()`.
+    pub fn renice(&self, tf: &TrapFrame, id: Id, nice: i32, relative: bool) -> OsResult<i32> {
+        self.critical(|scheduler| scheduler.renice(tf.tpidr, id, nice, relative))
+    }
+
+    /// Handles a timer interrupt taken with `tf`: wakes the threads whose
+    /// sleep or futex wait is over, and switches to the next thread if the running one has
+    /// used up its time slice. Either way, programs the timer for the next
+    /// event.
+    pub fn tick(&self, tf: &mut TrapFrame) {
+        let now = timer::current_time();
+        let expired = self.critical(|scheduler| {
+            scheduler.wake(now);
+            let expired = scheduler.mlfq.expired(now);
+            if !expired {
+                scheduler.arm_timer(now);
+            }
+            expired
+        });
+        if expired {
+            self.switch(State::Ready, tf);
         }
     }
 
-    /// Kills currently running process and returns that process's ID.
-    /// For more details, see the documentaion on `Scheduler::kill()`.
+    /// Kills the process of the currently running thread with exit status
+    /// `status` and returns that process's ID. For more details, see the
+    /// documentaion on `Scheduler::kill()`.
+    #[must_use]
+    pub fn kill(&self, tf: &mut TrapFrame, status: i32) -> Option<Id> {
+        self.critical(|scheduler| scheduler.kill(tf, status))
+    }
+
+    /// Ends the currently running thread with exit status `status` and
+    /// returns that thread's ID. For more details, see the documentaion on
+    /// `Scheduler::exit_thread()`.
     #[must_use]
-    pub fn kill(&self, tf: &mut TrapFrame) -> Option<Id> {
-        self.critical(|scheduler| scheduler.kill(tf))
+    pub fn exit_thread(&self, tf: &mut TrapFrame, status: i32) -> Option<Id> {
+        self.critical(|scheduler| scheduler.exit_thread(tf, status))
     }
 
-    /// Starts executing processes in user space using timer interrupt based
-    /// preemptive scheduling. This method should not return under normal conditions.
+    /// Starts executing threads using timer interrupt based preemptive
+    /// scheduling. This method should not return under normal conditions.
     pub fn start(&self) -> ! {
-        unimplemented!("GlobalScheduler::start()")
+        Controller::new().enable(Interrupt::Timer1);
+
+        let mut tf = TrapFrame::default();
+        self.switch_to(&mut tf);
+
+        // Restore the first thread's context from `tf` as an exception
+        // return would. The kernel stack below `tf` is free from then on.
+        unsafe {
+            asm!("mov sp, $0
+                  bl context_restore
+                  ldp x28, x29, [sp], #16
+                  ldp lr, xzr, [sp], #16
+                  eret"
+                 :: "r"(&tf as *const TrapFrame)
+                 :: "volatile");
+        }
+        unreachable!("eret returned");
     }
 
-    /// Initializes the scheduler and add userspace processes to the Scheduler
+    /// Initializes the scheduler and registers the timer interrupt handler.
+    ///
+    /// The time slice is `DEFAULT_SLICE`, unless the kernel command line sets
+    /// `slice=<milliseconds>`.
     pub unsafe fn initialize(&self) {
-        unimplemented!("GlobalScheduler::initialize()")
+        let slice = cmdline_option("slice")
+            .and_then(|ms| ms.parse().ok())
+            .filter(|&ms| ms > 0)
+            .map_or(DEFAULT_SLICE, Duration::from_millis);
+        *self.0.lock() = Some(Scheduler::new(slice));
+        IRQ.register(Interrupt::Timer1, Box::new(|tf: &mut TrapFrame| SCHEDULER.tick(tf)));
     }
 
     // The following method may be useful for testing Phase 3:
@@ -92,56 +295,306 @@ impl GlobalScheduler {
     // }
 }
 
+/// The threads and processes of the machine. Threads are scheduled by a
+/// multilevel feedback queue (see `Mlfq`) whose time slices are measured
+/// with the system timer; processes hold what their threads share.
 #[derive(Debug)]
 pub struct Scheduler {
-    processes: VecDeque<Process>,
+    threads: Vec<Thread>,
+    processes: Vec<Process>,
+    mlfq: Mlfq,
+    timers: TimerQueue,
+    futexes: FutexQueue,
     last_id: Option<Id>,
 }
 
 impl Scheduler {
-    /// Returns a new `Scheduler` with an empty queue.
-    fn new() -> Scheduler {
-        unimplemented!("Scheduler::new()")
+    /// Returns a new `Scheduler` with no threads, whose threads run for
+    /// `slice` at a time before they are demoted.
+    fn new(slice: Duration) -> Scheduler {
+        Scheduler {
+            threads: Vec::new(),
+            processes: Vec::new(),
+            mlfq: Mlfq::new(slice),
+            timers: TimerQueue::new(),
+            futexes: FutexQueue::new(),
+            last_id: None,
+        }
+    }
+
+    /// Wakes the threads whose sleep or futex wait is over at `now`.
+    fn wake(&mut self, now: Duration) {
+        for id in self.timers.expire(now) {
+            self.futexes.cancel(id);
+            if let Some(thread) = self.find_thread(id) {
+                thread.wake(now);
+            }
+        }
+    }
+
+    /// Programs timer 1 for the next event after `now`: the end of the
+    /// running thread's time slice or the next wakeup, whichever is nearer.
+    /// Cancels the timer if there is neither.
+    fn arm_timer(&self, now: Duration) {
+        let next = match (self.mlfq.deadline(), self.timers.next()) {
+            (Some(slice_end), Some(wakeup)) => Some(core::cmp::min(slice_end, wakeup)),
+            (slice_end, wakeup) => slice_end.or(wakeup),
+        };
+        match next {
+            Some(next) => timer::tick_in(next.checked_sub(now).unwrap_or(Duration::from_secs(0))),
+            None => timer::cancel_tick(),
+        }
+    }
+
+    /// Allocates a new thread or process ID. Returns `None` if they have run
+    /// out.
+    fn next_id(&mut self) -> Option<Id> {
+        let id = match self.last_id {
+            Some(id) => id.checked_add(1)?,
+            None => 0,
+        };
+        self.last_id = Some(id);
+        Some(id)
     }
 
-    /// Adds a process to the scheduler's queue and returns that process's ID if
-    /// a new process can be scheduled. The process ID is newly allocated for
-    /// the process and saved in its `trap_frame`. If no further processes can
-    /// be scheduled, returns `None`.
+    /// Queues `thread` with ID `id` and nice value `nice`, saving the ID in
+    /// its trap frame.
+    fn queue(&mut self, id: Id, mut thread: Thread, nice: i32) {
+        thread.context.tpidr = id;
+        self.mlfq.add(id, nice);
+        self.threads.push(thread);
+    }
+
+    /// Adds a process and its first thread to the scheduler's queue and
+    /// returns that process's ID if a new process can be scheduled. The
+    /// process ID is newly allocated for the process, and is also its
+    /// thread's ID, saved in the thread's `trap_frame`. If no further
+    /// processes can be scheduled, returns `None`. If the process has a
+    /// parent, it becomes a child of the parent and its thread inherits the
+    /// nice value of the parent's first thread.
     ///
     /// It is the caller's responsibility to ensure that the first time `switch`
-    /// is called, that process is executing on the CPU.
-    fn add(&mut self, mut process: Process) -> Option<Id> {
-        unimplemented!("Scheduler::add()")
+    /// is called, that thread is executing on the CPU.
+    fn add(&mut self, mut process: Process, mut thread: Thread) -> Option<Id> {
+        let id = self.next_id()?;
+        process.id = id;
+        thread.process = Some(id);
+        if let Some(parent) = process.parent.and_then(|parent| self.find(parent)) {
+            parent.children.push(Child { id, status: None });
+        }
+        let nice = process.parent.and_then(|parent| self.mlfq.nice(parent)).unwrap_or(0);
+        self.processes.push(process);
+        self.queue(id, thread, nice);
+        Some(id)
+    }
+
+    /// Adds `thread` to the scheduler's queue with nice value `nice` and
+    /// returns its newly allocated ID, or `None` if no further threads can be
+    /// scheduled. A thread of a process is recorded in the process, which can
+    /// join it.
+    fn spawn(&mut self, thread: Thread, nice: i32) -> Option<Id> {
+        let id = self.next_id()?;
+        if let Some(process) = thread.process.and_then(|process| self.find(process)) {
+            process.threads.push(Child { id, status: None });
+        }
+        self.queue(id, thread, nice);
+        Some(id)
+    }
+
+    /// Returns the process with ID `id`.
+    fn find(&mut self, id: Id) -> Option<&mut Process> {
+        self.processes.iter_mut().find(|process| process.id == id)
+    }
+
+    /// Returns the thread with ID `id`.
+    fn find_thread(&mut self, id: Id) -> Option<&mut Thread> {
+        self.threads.iter_mut().find(|thread| thread.context.tpidr == id)
+    }
+
+    /// Returns the ID of the process of thread `id`, if there is such a
+    /// thread and it is not a kernel thread.
+    fn process_of(&self, id: Id) -> Option<Id> {
+        self.threads.iter().find(|thread| thread.context.tpidr == id)?.process
+    }
+
+    /// Returns the process of the running thread whose ID is saved in `tf`.
+    fn current(&mut self, tf: &TrapFrame) -> Option<&mut Process> {
+        let id = self.threads.iter().find(|thread| runs(thread, tf))?.process?;
+        self.find(id)
+    }
+
+    /// Sets the nice value of thread `id` on behalf of thread `caller`: to
+    /// `nice`, or if `relative`, to its current nice value plus `nice`, kept
+    /// within `NICE_MIN..=NICE_MAX`. Returns the new nice value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoEntry` if there is no thread `id`, `NoAccess` if it is
+    /// neither `caller` nor a thread of the caller's process or of a child of
+    /// it, and `InvalidArgument` if `nice` is out of range and not `relative`.
+    fn renice(&mut self, caller: Id, id: Id, nice: i32, relative: bool) -> OsResult<i32> {
+        let current = self.mlfq.nice(id).ok_or(OsError::NoEntry)?;
+        let process = self.process_of(id);
+        let parent = process.and_then(|process| self.processes.iter().find(|p| p.id == process)?.parent);
+        let own = self.process_of(caller);
+        if id != caller && (own.is_none() || (process != own && parent != own)) {
+            return Err(OsError::NoAccess);
+        }
+
+        let nice = if relative {
+            current.saturating_add(nice).max(NICE_MIN).min(NICE_MAX)
+        } else if (NICE_MIN..=NICE_MAX).contains(&nice) {
+            nice
+        } else {
+            return Err(OsError::InvalidArgument);
+        };
+        self.mlfq.set_nice(id, nice);
+        Ok(nice)
     }
 
-    /// Finds the currently running process, sets the current process's state
+    /// Finds the currently running thread, sets the current thread's state
     /// to `new_state`, prepares the context switch on `tf` by saving `tf`
-    /// into the current process, and push the current process back to the
-    /// end of `processes` queue.
+    /// into the current thread, and puts the current thread back in its run
+    /// queue, demoted or promoted as `Mlfq::stop()` describes.
     ///
-    /// If the `processes` queue is empty or there is no current process,
-    /// returns `false`. Otherwise, returns `true`.
+    /// If there is no current thread, returns `false`. Otherwise, returns
+    /// `true`.
     fn schedule_out(&mut self, new_state: State, tf: &mut TrapFrame) -> bool {
-        unimplemented!("Scheduler::schedule_out()")
+        let thread = match self.threads.iter_mut().find(|thread| runs(thread, tf)) {
+            Some(thread) => thread,
+            None => return false,
+        };
+
+        let blocked = match new_state {
+            State::Waiting(_) | State::Sleeping(_) | State::Blocked => true,
+            _ => false,
+        };
+        thread.state = new_state;
+        *thread.context = *tf;
+        self.mlfq.stop(timer::current_time(), blocked);
+        true
     }
 
-    /// Finds the next process to switch to, brings the next process to the
-    /// front of the `processes` queue, changes the next process's state to
-    /// `Running`, and performs context switch by restoring the next process`s
-    /// trap frame into `tf`.
+    /// Finds the next thread to switch to, as picked by the multilevel
+    /// feedback queue among those that are ready, changes the next thread's
+    /// state to `Running`, and performs context switch by restoring the next
+    /// thread`s trap frame into `tf`.
     ///
-    /// If there is no process to switch to, returns `None`. Otherwise, returns
-    /// `Some` of the next process`s process ID.
+    /// If there is no thread to switch to, returns `None`. Otherwise, returns
+    /// `Some` of the next thread`s ID.
     fn switch_to(&mut self, tf: &mut TrapFrame) -> Option<Id> {
-        unimplemented!("Scheduler::switch_to()")
+        let threads = &mut self.threads;
+        let processes = &mut self.processes;
+        let id = self.mlfq.pick(timer::current_time(), |id| {
+            match threads.iter_mut().find(|thread| thread.context.tpidr == id) {
+                Some(thread) => {
+                    let process = match thread.process {
+                        Some(process) => processes.iter_mut().find(|p| p.id == process),
+                        None => None,
+                    };
+                    thread.is_ready(process)
+                }
+                None => false,
+            }
+        })?;
+
+        let thread = self.find_thread(id).expect("picked thread exists");
+        thread.state = State::Running;
+        *tf = *thread.context;
+        Some(id)
+    }
+
+    /// Removes the thread `id` from the queue, and from the timer and futex
+    /// queues if it is in them, and returns it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such thread.
+    fn remove_thread(&mut self, id: Id) -> Thread {
+        let index = self.threads.iter().position(|thread| thread.context.tpidr == id).expect("thread exists");
+        self.mlfq.remove(id);
+        self.timers.cancel(id);
+        self.futexes.cancel(id);
+        self.threads.remove(index)
+    }
+
+    /// Drops process `id`, which has no threads left, with exit status
+    /// `status`.
+    ///
+    /// Only the exit status outlives the process: it is kept by the parent
+    /// until the parent reaps it with `wait`, or dropped with the parent or
+    /// as `Process::child_exited()` describes. The process's own children are
+    /// orphaned, and the statuses it kept for those that exited are dropped
+    /// with it.
+    fn exit(&mut self, id: Id, status: i32) {
+        let process = match self.processes.iter().position(|process| process.id == id) {
+            Some(index) => self.processes.remove(index),
+            None => return,
+        };
+
+        if let Some(parent) = process.parent.and_then(|parent| self.find(parent)) {
+            parent.child_exited(id, status);
+        }
+        for orphan in self.processes.iter_mut().filter(|other| other.parent == Some(id)) {
+            orphan.parent = None;
+        }
     }
 
-    /// Kills currently running process by scheduling out the current process
-    /// as `Dead` state. Removes the dead process from the queue, drop the
-    /// dead process's instance, and returns the dead process's process ID.
-    fn kill(&mut self, tf: &mut TrapFrame) -> Option<Id> {
-        unimplemented!("Scheduler::kill()")
+    /// Kills the process of the currently running thread by scheduling out
+    /// the current thread as `Dead` state. Removes every thread of the
+    /// process from the queue, drops the process's instance with exit status
+    /// `status` as `exit()` describes, and returns the dead process's ID. A
+    /// kernel thread has no process: it is killed alone, and its ID returned.
+    fn kill(&mut self, tf: &mut TrapFrame, status: i32) -> Option<Id> {
+        if !self.schedule_out(State::Dead, tf) {
+            return None;
+        }
+        let process = match self.remove_thread(tf.tpidr).process {
+            Some(process) => process,
+            None => return Some(tf.tpidr),
+        };
+
+        let threads: Vec<Id> = self.threads.iter()
+            .filter(|thread| thread.process == Some(process))
+            .map(|thread| thread.context.tpidr)
+            .collect();
+        for id in threads {
+            self.remove_thread(id);
+        }
+        self.exit(process, status);
+        Some(process)
+    }
+
+    /// Ends the currently running thread by scheduling it out as `Dead`
+    /// state, removes it from the queue, and returns its ID.
+    ///
+    /// The exit status `status` is kept by the thread's process until it
+    /// joins the thread. If it was the last thread of its process, the
+    /// process exits with `status` instead, as `exit()` describes.
+    fn exit_thread(&mut self, tf: &mut TrapFrame, status: i32) -> Option<Id> {
+        if !self.schedule_out(State::Dead, tf) {
+            return None;
+        }
+        let id = tf.tpidr;
+        let process = match self.remove_thread(id).process {
+            Some(process) => process,
+            None => return Some(id),
+        };
+
+        if !self.threads.iter().any(|thread| thread.process == Some(process)) {
+            self.exit(process, status);
+        } else if let Some(thread) = self.find(process).and_then(|p| p.threads.iter_mut().find(|t| t.id == id)) {
+            thread.status = Some(status);
+        }
+        Some(id)
+    }
+}
+
+/// Returns `true` if `thread` is running with the trap frame `tf`.
+fn runs(thread: &Thread, tf: &TrapFrame) -> bool {
+    match thread.state {
+        State::Running => thread.context.tpidr == tf.tpidr,
+        _ => false,
     }
 }
 
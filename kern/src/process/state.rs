@@ -1,34 +1,58 @@
 use core::fmt;
+use core::time::Duration;
 
 use alloc::boxed::Box;
 
 use crate::process::Process;
+use crate::traps::TrapFrame;
 
-/// Type of a function used to determine if a process is ready to be scheduled
-/// again. The scheduler calls this function when it is the process's turn to
-/// execute. If the function returns `true`, the process is scheduled. If it
-/// returns `false`, the process is not scheduled, and this function will be
-/// called on the next time slice.
-pub type EventPollFn = Box<dyn FnMut(&mut Process) -> bool + Send>;
+/// Type of a function used to determine if a thread is ready to be scheduled
+/// again. The scheduler calls this function with the thread's process and
+/// saved trap frame when it is the thread's turn to execute. If the function
+/// returns `true`, the thread is scheduled. If it returns `false`, the thread
+/// is not scheduled, and this function will be called on the next time slice.
+pub type EventPollFn = Box<dyn FnMut(&mut Process, &mut TrapFrame) -> bool + Send>;
 
-/// The scheduling state of a process.
+/// The scheduling state of a thread.
 pub enum State {
-    /// The process is ready to be scheduled.
+    /// The thread is ready to be scheduled.
     Ready,
-    /// The process is waiting on an event to occur before it can be scheduled.
+    /// The thread is waiting on an event to occur before it can be scheduled.
     Waiting(EventPollFn),
-    /// The process is currently running.
+    /// The thread is in `sleep` since the given time, until its wakeup in
+    /// the scheduler's timer queue.
+    Sleeping(Duration),
+    /// The thread is blocked in `futex_wait`, until it is woken from the
+    /// scheduler's futex queue or its timeout in the timer queue is over.
+    Blocked,
+    /// The thread is currently running.
     Running,
-    /// The process is currently dead (ready to be reclaimed).
+    /// The thread is currently dead (ready to be reclaimed).
     Dead,
 }
 
+impl State {
+    /// Returns the name of the state, e.g. `ready`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            State::Ready => "ready",
+            State::Waiting(_) => "waiting",
+            State::Sleeping(_) => "sleeping",
+            State::Blocked => "blocked",
+            State::Running => "running",
+            State::Dead => "dead",
+        }
+    }
+}
+
 impl fmt::Debug for State {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             State::Ready => write!(f, "State::Ready"),
             State::Running => write!(f, "State::Running"),
             State::Waiting(_) => write!(f, "State::Waiting"),
+            State::Sleeping(since) => write!(f, "State::Sleeping({:?})", since),
+            State::Blocked => write!(f, "State::Blocked"),
             State::Dead => write!(f, "State::Dead"),
         }
     }
@@ -0,0 +1,116 @@
+use alloc::boxed::Box;
+use core::mem;
+use core::time::Duration;
+
+use aarch64;
+
+use crate::process::{Id, Process, Stack, State};
+use crate::traps::TrapFrame;
+use kernel_api::{OsError, OsResult};
+
+/// A thread of execution: the unit the scheduler runs.
+///
+/// A thread of a process runs in the process's address space, with its
+/// descriptors and working directory; its ID is saved in its trap frame's
+/// `tpidr`. A process's first thread has the process's ID. A kernel thread
+/// belongs to no process, and has no user address space.
+#[derive(Debug)]
+pub struct Thread {
+    /// The saved trap frame of the thread.
+    pub context: Box<TrapFrame>,
+    /// The stack of a thread that runs in the kernel. Threads in user space
+    /// run on a stack in their process's address space instead.
+    pub stack: Option<Stack>,
+    /// The scheduling state of the thread.
+    pub state: State,
+    /// The ID of the process the thread belongs to, or `None` for a kernel
+    /// thread.
+    pub process: Option<Id>,
+}
+
+impl Thread {
+    /// Creates a thread with a zeroed `TrapFrame`, no stack, a state of
+    /// `Ready`, and no process.
+    pub fn new() -> Thread {
+        Thread { context: Box::new(TrapFrame::default()), stack: None, state: State::Ready, process: None }
+    }
+
+    /// Creates a kernel thread that runs `entry` with `arg` in `x0`, at EL1
+    /// but on its own stack (`SP_EL0`), so that exceptions taken from it
+    /// still use the kernel stack. IRQs are masked: kernel code is not
+    /// preempted, and only gives up the CPU by making a system call, e.g.
+    /// `sleep`.
+    ///
+    /// The thread has no user address space: `ttbr1` holds the kernel's page
+    /// table, whose upper half it never uses.
+    pub fn kernel(entry: u64, arg: u64) -> OsResult<Thread> {
+        use aarch64::SPSR_EL1;
+        use crate::VMM;
+
+        let mut thread = Thread::new();
+        let stack = Stack::new().ok_or(OsError::NoMemory)?;
+        thread.context.elr = entry;
+        thread.context.sp = stack.top().as_u64();
+        thread.context.xs[0] = arg;
+        thread.context.ttbr0 = VMM.get_baddr().as_u64();
+        thread.context.ttbr1 = VMM.get_baddr().as_u64();
+        thread.context.spsr = (SPSR_EL1::M & 0b0100) | SPSR_EL1::F | SPSR_EL1::I | SPSR_EL1::A | SPSR_EL1::D;
+        thread.stack = Some(stack);
+        Ok(thread)
+    }
+
+    /// Wakes the thread from `sleep` or from `futex_wait` at `now`, its
+    /// deadline: it becomes ready, and its system call returns the time it
+    /// slept, or that its wait timed out. Does nothing if the thread is
+    /// neither sleeping nor blocked.
+    pub fn wake(&mut self, now: Duration) {
+        match self.state {
+            State::Sleeping(since) => {
+                self.context.xs[0] = (now - since).as_millis() as u64;
+                self.context.xs[7] = OsError::Ok as u64;
+            }
+            State::Blocked => self.context.xs[7] = OsError::TimedOut as u64,
+            _ => return,
+        }
+        self.state = State::Ready;
+    }
+
+    /// Wakes the thread from `futex_wait` by a `futex_wake`: it becomes
+    /// ready, and its system call returns successfully. Does nothing if the
+    /// thread is not blocked.
+    pub fn unblock(&mut self) {
+        if let State::Blocked = self.state {
+            self.context.xs[7] = OsError::Ok as u64;
+            self.state = State::Ready;
+        }
+    }
+
+    /// Returns `true` if this thread is ready to be scheduled. `process` is
+    /// the thread's process, if it has one.
+    ///
+    /// This functions returns `true` only if one of the following holds:
+    ///
+    ///   * The state is currently `Ready`.
+    ///
+    ///   * An event being waited for has arrived.
+    ///
+    ///     If the thread is currently waiting, the corresponding event
+    ///     function is polled with `process` to determine if the event being
+    ///     waiting for has occured. If it has, the state is switched to
+    ///     `Ready` and this function returns `true`. A kernel thread has no
+    ///     process to poll with, so it never stops waiting.
+    ///
+    /// Returns `false` in all other cases.
+    pub fn is_ready(&mut self, process: Option<&mut Process>) -> bool {
+        let mut state = mem::replace(&mut self.state, State::Ready);
+        let ready = match (&mut state, process) {
+            (State::Ready, _) => true,
+            (State::Waiting(poll), Some(process)) => poll(process, &mut self.context),
+            _ => false,
+        };
+        if !ready {
+            self.state = state;
+        }
+        ready
+    }
+}
@@ -0,0 +1,44 @@
+use alloc::collections::vec_deque::VecDeque;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::process::Id;
+
+#[cfg(test)]
+mod tests;
+
+/// The pending wakeups of sleeping threads, and the timeouts of threads
+/// blocked on a futex, in order of deadline.
+#[derive(Debug, Default)]
+pub struct TimerQueue(VecDeque<(Duration, Id)>);
+
+impl TimerQueue {
+    /// Returns an empty queue.
+    pub fn new() -> TimerQueue {
+        TimerQueue(VecDeque::new())
+    }
+
+    /// Adds a wakeup of thread `id` at `deadline`, after the wakeups with
+    /// the same deadline.
+    pub fn add(&mut self, deadline: Duration, id: Id) {
+        let index = self.0.iter().position(|&(other, _)| other > deadline).unwrap_or(self.0.len());
+        self.0.insert(index, (deadline, id));
+    }
+
+    /// Returns the nearest deadline.
+    pub fn next(&self) -> Option<Duration> {
+        self.0.front().map(|&(deadline, _)| deadline)
+    }
+
+    /// Removes the wakeups due at `now` and returns their threads, in order
+    /// of deadline.
+    pub fn expire(&mut self, now: Duration) -> Vec<Id> {
+        let due = self.0.iter().take_while(|&&(deadline, _)| deadline <= now).count();
+        self.0.drain(..due).map(|(_, id)| id).collect()
+    }
+
+    /// Removes the wakeups of thread `id`, e.g. once it was woken otherwise.
+    pub fn cancel(&mut self, id: Id) {
+        self.0.retain(|&(_, other)| other != id);
+    }
+}
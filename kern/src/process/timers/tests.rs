@@ -0,0 +1,55 @@
+use core::time::Duration;
+
+use crate::process::timers::TimerQueue;
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[test]
+fn test_empty() {
+    let mut timers = TimerQueue::new();
+    assert_eq!(timers.next(), None);
+    assert!(timers.expire(ms(1000)).is_empty());
+}
+
+#[test]
+fn test_order() {
+    let mut timers = TimerQueue::new();
+    timers.add(ms(30), 0);
+    timers.add(ms(10), 1);
+    timers.add(ms(20), 2);
+    timers.add(ms(10), 3);
+    assert_eq!(timers.next(), Some(ms(10)));
+
+    // Due exactly at the deadline, and in order of insertion on ties.
+    assert!(timers.expire(ms(9)).is_empty());
+    assert_eq!(timers.expire(ms(10)), vec![1, 3]);
+    assert_eq!(timers.next(), Some(ms(20)));
+    assert_eq!(timers.expire(ms(100)), vec![2, 0]);
+    assert_eq!(timers.next(), None);
+}
+
+#[test]
+fn test_add_after_expire() {
+    let mut timers = TimerQueue::new();
+    timers.add(ms(10), 0);
+    timers.add(ms(50), 1);
+    assert_eq!(timers.expire(ms(20)), vec![0]);
+    timers.add(ms(30), 0);
+    assert_eq!(timers.next(), Some(ms(30)));
+    assert_eq!(timers.expire(ms(50)), vec![0, 1]);
+}
+
+#[test]
+fn test_cancel() {
+    let mut timers = TimerQueue::new();
+    timers.add(ms(10), 0);
+    timers.add(ms(20), 1);
+    timers.add(ms(30), 0);
+    timers.cancel(0);
+    assert_eq!(timers.next(), Some(ms(20)));
+    assert_eq!(timers.expire(ms(100)), vec![1]);
+    timers.cancel(2);
+    assert_eq!(timers.next(), None);
+}
@@ -4,14 +4,25 @@ use shim::path::{Path, PathBuf};
 use stack_vec::StackVec;
 
 use pi::atags::Atags;
-use pi::fb::FrameBuffer;
 
 use fat32::traits::FileSystem;
-use fat32::traits::{Dir, Entry};
+use fat32::traits::{Dir, Entry, File};
 
+use crate::clock;
 use crate::console::{kprint, kprintln, CONSOLE};
+use crate::fs::ramdisk::RamDisk;
+use crate::param;
+use crate::process::Process;
 use crate::ALLOCATOR;
 use crate::FILESYSTEM;
+use crate::SCHEDULER;
+
+/// How many times `ramdisk -x` waits for a sender before giving up.
+const RECEIVE_ATTEMPTS: usize = 80;
+
+/// The environment of the programs the shell runs. `PATH` names where it
+/// looks up commands without a `/`.
+const ENV: &[&str] = &["PATH=/bin"];
 
 /// Error type for `Command` parse failures.
 #[derive(Debug)]
@@ -51,14 +62,25 @@ impl<'a> Command<'a> {
         self.args[0]
     }
 
-    fn extract_path(&self) -> Option<PathBuf> {
+    fn extract_path(&self) -> Option<&str> {
         if self.args.len() < 2 {
             return None
         }
         if self.args[self.args.len() - 1] == "-a" {
             return None
         }
-        Some(PathBuf::from(self.args[self.args.len() - 1]))
+        Some(self.args[self.args.len() - 1])
+    }
+
+    /// Resolves `path` against `working_dir`, reporting a failure.
+    fn resolve(&self, working_dir: &PathBuf, path: &str) -> Option<PathBuf> {
+        match fat32::path::resolve(working_dir, path) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                kprintln!("{}: {}: {}", self.path(), path, e);
+                None
+            }
+        }
     }
 
     fn ls(&self, working_dir: &PathBuf) -> Result<(), ()> {
@@ -74,36 +96,36 @@ impl<'a> Command<'a> {
             }
         }
 
-        let mut path = working_dir.clone();
-        let arg_path = self.extract_path();
-        if let Some(p) = arg_path {
-            path.push(p);
-        }
+        let path = match self.extract_path() {
+            Some(path) => self.resolve(working_dir, path).ok_or(())?,
+            None => working_dir.clone(),
+        };
 
-
-        let mut entries = FILESYSTEM
-            .open_dir(path.clone())
-            .expect("directory")
-            .entries()
-            .expect("entries interator")
-            .collect::<Vec<_>>();
+        let entries = FILESYSTEM.open_dir(&path).and_then(|dir| dir.entries());
+        let mut entries = match entries {
+            Ok(entries) => entries.collect::<Vec<_>>(),
+            Err(e) => {
+                kprintln!("ls: {}: {}", path.display(), e);
+                return Err(());
+            }
+        };
         entries.sort_by(|a, b| a.name().cmp(b.name()));
         for entry in &entries {
-            let atime = entry.metadata().atime;
+            let mtime = entry.metadata().mtime;
             let hidden = entry.metadata().attr.hidden() && !show_hidden;
             if hidden {
                 continue;
             }
             let is_dir_str = if entry.is_dir() { &"d" } else { &"" };
-            let size_str = if let Some(f) = entry.as_file() { f.size } else { 0 };
+            let size_str = if let Some(f) = entry.as_file() { f.size() } else { 0 };
             kprintln!("{0: <1} {1:02}/{2:02}/{3} {4:02}:{5:02}:{6:02} {7: <10} {8: <10}",
                       is_dir_str,
-                      atime.month(),
-                      atime.day(),
-                      atime.year(),
-                      atime.hour(),
-                      atime.minute(),
-                      atime.second(),
+                      mtime.month(),
+                      mtime.day(),
+                      mtime.year(),
+                      mtime.hour(),
+                      mtime.minute(),
+                      mtime.second(),
                       size_str,
                       entry.name())
 
@@ -113,7 +135,355 @@ impl<'a> Command<'a> {
 
     }
 
-    fn exec(&self, working_dir: &mut PathBuf, mut fb: &mut FrameBuffer) -> Result<(), ()> {
+    fn cd(&self, working_dir: &mut PathBuf) {
+        let path = match self.args.get(1) {
+            Some(arg) => match self.resolve(working_dir, arg) {
+                Some(path) => path,
+                None => return,
+            },
+            None => PathBuf::from("/"),
+        };
+
+        match FILESYSTEM.open_dir(&path) {
+            Ok(_) => *working_dir = path,
+            Err(e) => kprintln!("cd: {}: {}", path.display(), e),
+        }
+    }
+
+    fn cat(&self, working_dir: &PathBuf) {
+        use shim::io::Read;
+
+        for arg in &self.args[1..] {
+            let path = match self.resolve(working_dir, arg) {
+                Some(path) => path,
+                None => continue,
+            };
+
+            let mut contents = alloc::vec::Vec::new();
+            let read = FILESYSTEM.open_file(&path).and_then(|mut file| file.read_to_end(&mut contents));
+            match read {
+                Ok(_) => kprint!("{}", alloc::string::String::from_utf8_lossy(&contents)),
+                Err(e) => kprintln!("cat: {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    fn show(&self) {
+        use core::{mem, slice};
+        use shim::io::Write;
+        use pi::data::IMAGE;
+
+        let image = unsafe { slice::from_raw_parts(IMAGE.as_ptr() as *const u8, mem::size_of_val(&IMAGE)) };
+        if let Err(e) = FILESYSTEM.open_file("/dev/fb0").and_then(|mut fb| fb.write_all(image)) {
+            kprintln!("show: /dev/fb0: {}", e);
+        }
+    }
+
+    fn fsck(&self, working_dir: &PathBuf) {
+        let mut repair = false;
+        let mut path = working_dir.clone();
+        for &arg in &self.args[1..] {
+            match arg {
+                "-r" => repair = true,
+                _ => match self.resolve(working_dir, arg) {
+                    Some(resolved) => path = resolved,
+                    None => return,
+                },
+            }
+        }
+
+        match FILESYSTEM.check(&path, repair) {
+            Ok(report) => {
+                for problem in &report.problems {
+                    kprintln!("{}", problem);
+                }
+                kprintln!("{}", report);
+            }
+            Err(e) => kprintln!("fsck: {}: {}", path.display(), e),
+        }
+    }
+
+    fn date(&self) {
+        use core::time::Duration;
+        use fat32::traits::Timestamp;
+
+        if let Some(arg) = self.args.get(1) {
+            match arg.parse() {
+                Ok(secs) => clock::set(Duration::from_secs(secs)),
+                Err(_) => {
+                    kprintln!("usage: date [seconds since the Unix epoch]");
+                    return;
+                }
+            }
+        }
+
+        let now = fat32::vfat::Timestamp::from_unix(clock::now());
+        kprintln!("{}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+                  now.year(), now.month(), now.day(), now.hour(), now.minute(), now.second());
+    }
+
+    fn df(&self, working_dir: &PathBuf) {
+        use alloc::vec::Vec;
+
+        let mut paths = Vec::new();
+        match self.args.get(1) {
+            Some(arg) => match self.resolve(working_dir, arg) {
+                Some(path) => paths.push(path),
+                None => return,
+            },
+            None => paths.extend(FILESYSTEM.mounts().iter().map(|mount| mount.path().to_path_buf())),
+        }
+
+        kprintln!("{:>10} {:>10} {:>10} {:>5} {:>8}  {}", "KiB", "Used", "Free", "Use%", "Cluster", "Path");
+        for path in &paths {
+            match FILESYSTEM.statfs(path) {
+                Ok(stat) => kprintln!("{:>10} {:>10} {:>10} {:>4}% {:>8}  {}",
+                                      stat.total_bytes / 1024,
+                                      stat.used_bytes / 1024,
+                                      stat.free_bytes / 1024,
+                                      stat.used_bytes * 100 / stat.total_bytes.max(1),
+                                      stat.cluster_size,
+                                      path.display()),
+                // Synthetic file systems have no space to report.
+                Err(_) if self.args.len() == 1 => (),
+                Err(e) => kprintln!("df: {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    fn mkfs(&self) {
+        use fat32::format::Options;
+
+        let mut args = self.args[1..].iter();
+        let mut size = None;
+        let mut options = Options::new(0);
+        while let Some(&arg) = args.next() {
+            match arg {
+                "-m" => options.mbr = true,
+                "-L" => match args.next() {
+                    Some(label) => options = options.label(label),
+                    None => {
+                        kprintln!("mkfs: -L requires a label");
+                        return;
+                    }
+                },
+                _ => match arg.parse::<u64>() {
+                    Ok(mib) => size = Some(mib),
+                    Err(_) => {
+                        kprintln!("mkfs: unknown argument '{}'", arg);
+                        return;
+                    }
+                },
+            }
+        }
+
+        let size = match size {
+            Some(size) => size,
+            None => {
+                kprintln!("usage: mkfs <size in MiB> [-m] [-L label]");
+                return;
+            }
+        };
+        options.sectors = size * 2048;
+
+        match FILESYSTEM.format(&options) {
+            Ok(layout) => kprintln!("{:?}: {} clusters of {} bytes", layout.fat_type, layout.clusters,
+                                    layout.sectors_per_cluster as u32 * layout.bytes_per_sector as u32),
+            Err(e) => kprintln!("mkfs: {}", e),
+        }
+    }
+
+    fn ramdisk(&self, working_dir: &PathBuf) {
+        let mut args = self.args[1..].iter();
+        let (mut size, mut image, mut receive, mut mount_point) = (None, None, false, None);
+        while let Some(&arg) = args.next() {
+            match arg {
+                "-s" => match args.next().and_then(|mib| mib.parse::<usize>().ok()) {
+                    Some(mib) => size = Some(mib << 20),
+                    None => {
+                        kprintln!("ramdisk: -s requires a size in MiB");
+                        return;
+                    }
+                },
+                "-l" => match args.next() {
+                    Some(path) => image = Some(path),
+                    None => {
+                        kprintln!("ramdisk: -l requires an image");
+                        return;
+                    }
+                },
+                "-x" => receive = true,
+                _ if mount_point.is_none() && !arg.starts_with('-') => mount_point = Some(arg),
+                _ => {
+                    kprintln!("usage: ramdisk [-s <size in MiB>] [-l <image> | -x] [mount point]");
+                    return;
+                }
+            }
+        }
+
+        let mount_point = match self.resolve(working_dir, mount_point.unwrap_or("/ram")) {
+            Some(path) => path,
+            None => return,
+        };
+
+        let mut file = match image {
+            Some(path) => {
+                let path = match self.resolve(working_dir, path) {
+                    Some(path) => path,
+                    None => return,
+                };
+                match FILESYSTEM.open_file(&path) {
+                    Ok(file) => Some(file),
+                    Err(e) => {
+                        kprintln!("ramdisk: {}: {}", path.display(), e);
+                        return;
+                    }
+                }
+            }
+            None => None,
+        };
+
+        // A preloaded image sets the size unless one is given.
+        let size = match (size, &file) {
+            (Some(size), _) => size,
+            (None, Some(file)) => file.size() as usize,
+            (None, None) => match RamDisk::default_size() {
+                Some(size) => size,
+                None => {
+                    kprintln!("ramdisk: memory size unknown; use -s");
+                    return;
+                }
+            },
+        };
+
+        let mut disk = RamDisk::new(size);
+        let filled = match file.take() {
+            Some(mut file) => disk.load(&mut file).map(|_| ()),
+            None if receive => {
+                kprintln!("ramdisk: send an image of at most {} KiB with XMODEM", disk.len() / 1024);
+                receive_image(&mut disk).map(|_| ())
+            }
+            None => {
+                let options = fat32::format::Options::new((disk.len() / 512) as u64);
+                fat32::format::format(&mut disk, &options).map(|_| ())
+            }
+        };
+
+        match filled.and_then(|_| FILESYSTEM.mount_device(disk, "ram", &mount_point)) {
+            Ok(()) => kprintln!("mounted a {} KiB RAM disk on {}", size / 1024, mount_point.display()),
+            Err(e) => kprintln!("ramdisk: {}", e),
+        }
+    }
+
+    fn mounts(&self) {
+        for mount in FILESYSTEM.mounts() {
+            kprintln!("{} on {} type {}", mount.source(), mount.path().display(), mount.fs().kind());
+        }
+    }
+
+    fn mount(&self, working_dir: &PathBuf) {
+        use alloc::rc::Rc;
+        use fat32::partition::Selector;
+        use crate::fs::procfs::ProcFs;
+
+        let (source, path) = match (self.args.len(), self.args.get(1), self.args.get(2)) {
+            (1, _, _) => {
+                self.mounts();
+                return;
+            }
+            (3, Some(&source), Some(&path)) => (source, path),
+            _ => {
+                kprintln!("usage: mount [<sd[:<partition index | GUID>] | proc | dev> <path>]");
+                return;
+            }
+        };
+
+        let path = match self.resolve(working_dir, path) {
+            Some(path) => path,
+            None => return,
+        };
+
+        let mounted = match source {
+            "proc" => FILESYSTEM.mount("proc", Rc::new(ProcFs), &path),
+            "dev" => FILESYSTEM.mount_devices(None, &path),
+            "sd" => FILESYSTEM.mount_sd(None, &path),
+            _ if source.starts_with("sd:") => match source[3..].parse::<Selector>() {
+                Ok(selector) => FILESYSTEM.mount_sd(Some(selector), &path),
+                Err(_) => {
+                    kprintln!("mount: {}: not a partition index or GUID", &source[3..]);
+                    return;
+                }
+            },
+            _ => {
+                kprintln!("mount: {}: unknown source", source);
+                return;
+            }
+        };
+
+        if let Err(e) = mounted {
+            kprintln!("mount: {}: {}", path.display(), e);
+        }
+    }
+
+    fn umount(&self, working_dir: &mut PathBuf) {
+        let path = match self.args.get(1) {
+            Some(arg) if self.args.len() == 2 => match self.resolve(working_dir, arg) {
+                Some(path) => path,
+                None => return,
+            },
+            _ => {
+                kprintln!("usage: umount <path>");
+                return;
+            }
+        };
+
+        match FILESYSTEM.unmount(&path) {
+            // Leave a working directory that no longer exists.
+            Ok(()) if working_dir.starts_with(&path) => *working_dir = PathBuf::from("/"),
+            Ok(()) => (),
+            Err(e) => kprintln!("umount: {}: {}", path.display(), e),
+        }
+    }
+
+    /// Runs the program named by this command's path as a child process,
+    /// passing it all the arguments as typed, path first, and `ENV` as its
+    /// environment. Waits for it to exit and prints its exit status. Paths
+    /// without a `/` are looked up in `/bin`.
+    fn run(&self, working_dir: &PathBuf) {
+        use kernel_api::syscall::{getpid, wait};
+        use kernel_api::OsError;
+
+        let path = if self.path().contains('/') {
+            match self.resolve(working_dir, self.path()) {
+                Some(path) => path,
+                None => return,
+            }
+        } else {
+            Path::new("/bin").join(self.path())
+        };
+
+        let (mut process, thread) = match Process::load(&path, &self.args[..], ENV) {
+            Ok(loaded) => loaded,
+            Err(OsError::NoEntry) => {
+                kprintln!("{}: command not found", self.path());
+                return;
+            }
+            Err(e) => {
+                kprintln!("{}: {:?}", path.display(), e);
+                return;
+            }
+        };
+        process.cwd = working_dir.clone();
+        process.parent = Some(getpid());
+
+        let status = SCHEDULER.add(process, thread).ok_or(OsError::NoMemory).and_then(wait);
+        match status {
+            Ok(status) => kprintln!("{}: exited with status {}", self.path(), status),
+            Err(e) => kprintln!("{}: {:?}", path.display(), e),
+        }
+    }
+
+    fn exec(&self, working_dir: &mut PathBuf) -> Result<(), ()> {
         match self.path() {
             "echo" => {
                 for arg in self.args[1..self.args.len() - 1].iter() {
@@ -122,19 +492,57 @@ impl<'a> Command<'a> {
                 kprintln!("{}", self.args[self.args.len() - 1]);
             },
             "ls" => {self.ls(&working_dir);},
-            "show" => {fb.show_picture();},
-            _ => kprintln!("{}: command not found", self.path()),
+            "cd" => self.cd(working_dir),
+            "pwd" => kprintln!("{}", working_dir.display()),
+            "cat" => self.cat(&working_dir),
+            "show" => self.show(),
+            "fsck" => self.fsck(&working_dir),
+            "df" => self.df(&working_dir),
+            "date" => self.date(),
+            "mkfs" => self.mkfs(),
+            "ramdisk" => self.ramdisk(&working_dir),
+            "mount" => self.mount(&working_dir),
+            "umount" => self.umount(working_dir),
+            "mounts" => self.mounts(),
+            _ => self.run(&working_dir),
         }
         Ok(())
     }
 }
 
+/// Receives an image into `disk` over the console with XMODEM, waiting for
+/// the sender to start for a minute at most.
+fn receive_image(disk: &mut RamDisk) -> io::Result<usize> {
+    use core::time::Duration;
+
+    let mut console = CONSOLE.lock();
+    console.set_read_timeout(Some(Duration::from_millis(750)));
+    let mut attempts = 1;
+    let result = loop {
+        match disk.receive(&mut *console) {
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut && attempts < RECEIVE_ATTEMPTS => attempts += 1,
+            result => break result,
+        }
+    };
+    console.set_read_timeout(None);
+    result
+}
+
+/// Reads a byte from the console, sleeping while none is ready so that the
+/// rest of the system keeps running.
+fn read_byte() -> u8 {
+    while !CONSOLE.lock().has_byte() {
+        let _ = kernel_api::syscall::sleep(param::CONSOLE_POLL_PERIOD);
+    }
+    CONSOLE.lock().read_byte()
+}
+
 fn readline(buf: &mut [u8]) -> &str {
     use core::str;
     let mut line: StackVec<u8> = StackVec::new(buf);
 
     loop {
-        match CONSOLE.lock().read_byte() {
+        match read_byte() {
             0x08 | 0x7F if !line.is_empty() => {
                 kprint!("\u{8} \u{8}");
                 line.pop();
@@ -155,12 +563,12 @@ fn readline(buf: &mut [u8]) -> &str {
 
 /// Starts a shell using `prefix` as the prefix for each line. This function
 /// never returns.
-pub fn shell(prefix: &str, mut fb: &mut FrameBuffer) -> ! {
+pub fn shell(prefix: &str) -> ! {
     let mut working_dir = PathBuf::from("/");
     loop {
         kprint!("{}", prefix);
         match Command::parse(readline(&mut [0u8; 512]), &mut [""; 64]) {
-            Ok(c) => c.exec(&mut working_dir, &mut fb).unwrap(),
+            Ok(c) => c.exec(&mut working_dir).unwrap(),
             Err(Error::TooManyArgs) => kprintln!("error: too many args"),
             Err(_) => (),
         }
@@ -7,8 +7,12 @@ pub use self::frame::TrapFrame;
 
 use pi::interrupt::{Controller, Interrupt};
 
-use self::syndrome::Syndrome;
+use self::syndrome::{Fault, Syndrome};
 use self::syscall::handle_syscall;
+use crate::console::kprintln;
+use crate::param::USER_IMG_BASE;
+use crate::vm::VirtualAddr;
+use crate::{IRQ, SCHEDULER};
 
 #[repr(u16)]
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -35,11 +39,47 @@ pub struct Info {
     kind: Kind,
 }
 
+/// Handles a permission fault of the current process, whose trap frame is
+/// `tf`, on a write to a page it shares copy-on-write by giving it its own
+/// copy; the write is retried on return. Returns `false` if the fault was
+/// anything else.
+fn copy_on_write(tf: &TrapFrame) -> bool {
+    let far = unsafe { aarch64::FAR_EL1.get() } as usize;
+    far >= USER_IMG_BASE
+        && SCHEDULER.with_current(tf, |process| Ok(process.vmap.make_writable(VirtualAddr::from(far)))).unwrap_or(false)
+}
+
 /// This function is called when an exception occurs. The `info` parameter
 /// specifies the source and kind of exception that has occurred. The `esr` is
 /// the value of the exception syndrome register. Finally, `tf` is a pointer to
 /// the trap frame for the exception.
 #[no_mangle]
 pub extern "C" fn handle_exception(info: Info, esr: u32, tf: &mut TrapFrame) {
-    unimplemented!("handle_exception");
+    match info.kind {
+        Kind::Synchronous => match Syndrome::from(esr) {
+            Syndrome::Svc(num) => handle_syscall(num, tf),
+            Syndrome::Brk(num) => {
+                kprintln!("brk {} at {:#x}", num, tf.elr);
+                tf.elr += 4;
+            }
+            Syndrome::DataAbort { kind: Fault::Permission, .. }
+                if info.source == Source::LowerAArch64 && copy_on_write(tf) => {}
+            syndrome if info.source == Source::LowerAArch64 => {
+                let far = unsafe { aarch64::FAR_EL1.get() };
+                kprintln!("thread {} killed with its process: {:?} at {:#x} (far {:#x})", tf.tpidr, syndrome, tf.elr, far);
+                let _ = SCHEDULER.kill(tf, kernel_api::EXIT_KILLED);
+                SCHEDULER.switch_to(tf);
+            }
+            syndrome => panic!("unhandled exception {:?}: {:?} at {:#x}", info, syndrome, tf.elr),
+        },
+        Kind::Irq => {
+            let controller = Controller::new();
+            for &int in Interrupt::iter() {
+                if controller.is_pending(int) {
+                    IRQ.invoke(int, tf);
+                }
+            }
+        }
+        Kind::Fiq | Kind::SError => kprintln!("unhandled exception {:?}: esr {:#x}", info, esr),
+    }
 }
@@ -1,8 +1,40 @@
 use core::fmt;
 
+/// The state of a process saved on exception entry by `context_save` and
+/// restored by `context_restore`, in the order they push it.
 #[repr(C)]
-#[derive(Default, Copy, Clone, Debug)]
+#[derive(Default, Copy, Clone)]
 pub struct TrapFrame {
-    // FIXME: Fill me in.
+    /// The address to return to: `ELR_EL1`.
+    pub elr: u64,
+    /// The saved program status: `SPSR_EL1`.
+    pub spsr: u64,
+    /// The EL0 stack pointer: `SP_EL0`.
+    pub sp: u64,
+    /// The EL0 thread ID register, holding the process ID.
+    pub tpidr: u64,
+    /// The base address of the kernel page table.
+    pub ttbr0: u64,
+    /// The base address of the user page table.
+    pub ttbr1: u64,
+    /// The SIMD/FP registers `q0` to `q31`.
+    pub qs: [u128; 32],
+    /// The general purpose registers `x0` to `x30`.
+    pub xs: [u64; 31],
+    /// Padding keeping the frame 16-byte aligned, pushed as `xzr`.
+    pub xzr: u64,
 }
 
+impl fmt::Debug for TrapFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TrapFrame")
+            .field("elr", &format_args!("{:#x}", self.elr))
+            .field("spsr", &format_args!("{:#x}", self.spsr))
+            .field("sp", &format_args!("{:#x}", self.sp))
+            .field("tpidr", &self.tpidr)
+            .field("ttbr0", &format_args!("{:#x}", self.ttbr0))
+            .field("ttbr1", &format_args!("{:#x}", self.ttbr1))
+            .field("xs", &self.xs)
+            .finish()
+    }
+}
@@ -7,26 +7,46 @@ use crate::traps::TrapFrame;
 pub type IrqHandler = Box<dyn FnMut(&mut TrapFrame) + Send>;
 pub type IrqHandlers = [Option<IrqHandler>; Interrupt::MAX];
 
-pub struct Irq(Mutex<Option<IrqHandlers>>);
+pub struct Irq {
+    handlers: Mutex<Option<IrqHandlers>>,
+    /// The number of times each interrupt was invoked, by `Interrupt` index.
+    counts: Mutex<[u64; Interrupt::MAX]>,
+}
 
 impl Irq {
     pub const fn uninitialized() -> Irq {
-        Irq(Mutex::new(None))
+        Irq {
+            handlers: Mutex::new(None),
+            counts: Mutex::new([0; Interrupt::MAX]),
+        }
     }
 
     pub fn initialize(&self) {
-        *self.0.lock() = Some([None, None, None, None, None, None, None, None]);
+        *self.handlers.lock() = Some([None, None, None, None, None, None, None, None]);
     }
 
     /// Register an irq handler for an interrupt.
     /// The caller should assure that `initialize()` has been called before calling this function.
     pub fn register(&self, int: Interrupt, handler: IrqHandler) {
-        unimplemented!("Irq::register()")
+        let mut handlers = self.handlers.lock();
+        handlers.as_mut().expect("irq uninitialized")[Interrupt::to_index(int)] = Some(handler);
     }
 
     /// Executes an irq handler for the givven interrupt.
     /// The caller should assure that `initialize()` has been called before calling this function.
     pub fn invoke(&self, int: Interrupt, tf: &mut TrapFrame) {
-        unimplemented!("Irq::register()")
+        let index = Interrupt::to_index(int);
+        self.counts.lock()[index] += 1;
+
+        let mut handlers = self.handlers.lock();
+        if let Some(handler) = handlers.as_mut().expect("irq uninitialized")[index].as_mut() {
+            handler(tf);
+        }
+    }
+
+    /// Returns the number of times each interrupt was invoked, by `Interrupt`
+    /// index.
+    pub fn counts(&self) -> [u64; Interrupt::MAX] {
+        *self.counts.lock()
     }
 }
@@ -12,8 +12,23 @@ pub enum Fault {
 }
 
 impl From<u32> for Fault {
+    /// Decodes the fault status code in the low six bits of an abort's ISS
+    /// (ref: D13.2.37).
     fn from(val: u32) -> Fault {
-        unimplemented!("From<u32> for Fault")
+        use self::Fault::*;
+
+        let code = (val & 0b11_1111) as u8;
+        match code {
+            0b10_0001 => Alignment,
+            0b11_0000 => TlbConflict,
+            _ => match code >> 2 {
+                0b0000 => AddressSize,
+                0b0001 => Translation,
+                0b0010 => AccessFlag,
+                0b0011 => Permission,
+                _ => Other(code),
+            },
+        }
     }
 }
 
@@ -45,6 +60,34 @@ impl From<u32> for Syndrome {
     fn from(esr: u32) -> Syndrome {
         use self::Syndrome::*;
 
-        unimplemented!("From<u32> for Syndrome")
+        let iss = esr & 0x1FF_FFFF;
+        let abort = |esr: u32| (Fault::from(esr), (esr & 0b11) as u8);
+        match ESR_EL1::get_value(esr as u64, ESR_EL1::EC) {
+            0b00_0000 => Unknown,
+            0b00_0001 => WfiWfe,
+            0b00_0111 => SimdFp,
+            0b00_1110 => IllegalExecutionState,
+            0b01_0101 => Svc(iss as u16),
+            0b01_0110 => Hvc(iss as u16),
+            0b01_0111 => Smc(iss as u16),
+            0b01_1000 => MsrMrsSystem,
+            0b10_0000 | 0b10_0001 => {
+                let (kind, level) = abort(esr);
+                InstructionAbort { kind, level }
+            }
+            0b10_0010 => PCAlignmentFault,
+            0b10_0100 | 0b10_0101 => {
+                let (kind, level) = abort(esr);
+                DataAbort { kind, level }
+            }
+            0b10_0110 => SpAlignmentFault,
+            0b10_1000 | 0b10_1100 => TrappedFpu,
+            0b10_1111 => SError,
+            0b11_0000 | 0b11_0001 => Breakpoint,
+            0b11_0010 | 0b11_0011 => Step,
+            0b11_0100 | 0b11_0101 => Watchpoint,
+            0b11_1100 => Brk(iss as u16),
+            _ => Other(esr),
+        }
     }
 }
@@ -1,12 +1,142 @@
 use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
 use core::time::Duration;
 
+use shim::io::{self, Read, Seek, SeekFrom, Write};
+use shim::path::PathBuf;
+
+use fat32::traits::{Entry, FileSystem};
+
 use crate::console::CONSOLE;
-use crate::process::State;
+use crate::param::{PAGE_MASK, PAGE_SIZE, USER_IMG_BASE};
+use crate::process::{self, Descriptor, FileGuard, Process, State};
 use crate::traps::TrapFrame;
-use crate::SCHEDULER;
+use crate::vm::{UserPageTable, VirtualAddr};
+use crate::{FILESYSTEM, SCHEDULER};
 use kernel_api::*;
 
+/// Sets the return value of a system call in `tf`: the value in `x0` and the
+/// status in `x7`.
+fn ret(tf: &mut TrapFrame, result: OsResult<u64>) {
+    match result {
+        Ok(value) => {
+            tf.xs[0] = value;
+            tf.xs[7] = OsError::Ok as u64;
+        }
+        Err(e) => tf.xs[7] = e as u64,
+    }
+}
+
+/// Returns the address of each page spanned by the `len` bytes at `ptr`,
+/// where `len` is not 0 and the range does not wrap around.
+fn pages(ptr: u64, len: u64) -> impl Iterator<Item = VirtualAddr> {
+    let first = ptr & PAGE_MASK as u64;
+    let last = (ptr + (len - 1)) & PAGE_MASK as u64;
+    (0..=(last - first) / PAGE_SIZE as u64).map(move |i| VirtualAddr::from(first + i * PAGE_SIZE as u64))
+}
+
+/// Returns `len` bytes of user memory at `ptr`, in the address space `vmap`.
+///
+/// # Errors
+///
+/// Returns `BadAddress` if the range is not in the user address space, or
+/// not all mapped.
+unsafe fn user_slice<'a>(vmap: &UserPageTable, ptr: u64, len: u64) -> OsResult<&'a mut [u8]> {
+    if len == 0 {
+        return Ok(&mut []);
+    }
+    // The user address space runs from `USER_IMG_BASE` to the top of memory.
+    if ptr < USER_IMG_BASE as u64 || len > 0u64.wrapping_sub(ptr) || !pages(ptr, len).all(|page| vmap.is_mapped(page)) {
+        return Err(OsError::BadAddress);
+    }
+    Ok(core::slice::from_raw_parts_mut(ptr as *mut u8, len as usize))
+}
+
+/// Returns `len` bytes of user memory at `ptr`, in the address space `vmap`,
+/// for the kernel to write to. Pages the process shares copy-on-write are
+/// copied first, as a write by the process itself would.
+///
+/// # Errors
+///
+/// Returns `BadAddress` if the range is not in the user address space, or
+/// not all writable by the process.
+unsafe fn user_buf<'a>(vmap: &mut UserPageTable, ptr: u64, len: u64) -> OsResult<&'a mut [u8]> {
+    let buf = user_slice(vmap, ptr, len)?;
+    if len > 0 && !pages(ptr, len).all(|page| vmap.make_writable(page)) {
+        return Err(OsError::BadAddress);
+    }
+    Ok(buf)
+}
+
+/// Returns the UTF-8 string of `len` bytes of user memory at `ptr`.
+///
+/// # Errors
+///
+/// Returns `BadAddress` if the range is not in the user address space and
+/// `InvalidArgument` if it is not UTF-8.
+unsafe fn user_str<'a>(vmap: &UserPageTable, ptr: u64, len: u64) -> OsResult<&'a str> {
+    core::str::from_utf8(user_slice(vmap, ptr, len)?).map_err(|_| OsError::InvalidArgument)
+}
+
+/// Returns the `argc` strings at `argv`, each given by its address and
+/// length as in `kernel_api::syscall::spawn`.
+///
+/// # Errors
+///
+/// Returns `InvalidArgument` if there are more than `ARG_MAX`.
+unsafe fn user_args<'a>(vmap: &UserPageTable, argv: u64, argc: u64) -> OsResult<Vec<&'a str>> {
+    if argc > ARG_MAX as u64 {
+        return Err(OsError::InvalidArgument);
+    }
+    let argv = user_slice(vmap, argv, argc * 16)?;
+    argv.chunks(16)
+        .map(|arg| {
+            let ptr = (arg.as_ptr() as *const u64).read_unaligned();
+            let len = (arg.as_ptr() as *const u64).add(1).read_unaligned();
+            user_str(vmap, ptr, len)
+        })
+        .collect()
+}
+
+/// Returns the physical address of the futex word at `addr`, the key of its
+/// waiters, and the value it holds. The word's page is made writable first,
+/// so that the key does not change when the page is copied on a later write.
+///
+/// # Errors
+///
+/// Returns `InvalidArgument` if `addr` is not 4-byte aligned, and
+/// `BadAddress` if the word is not writable by the process.
+unsafe fn futex_word(vmap: &mut UserPageTable, addr: u64) -> OsResult<(u64, u32)> {
+    if addr % 4 != 0 {
+        return Err(OsError::InvalidArgument);
+    }
+    let word = user_buf(vmap, addr, 4)?;
+    let key = vmap.translate(VirtualAddr::from(addr)).expect("futex word is mapped");
+    Ok((key.as_u64(), (word.as_ptr() as *const u32).read_volatile()))
+}
+
+/// Returns the path of `len` bytes at `ptr` in the memory of the process
+/// whose thread's trap frame is `tf`, resolved against its working directory.
+///
+/// # Errors
+///
+/// As for `user_str()`, or any error from resolving the path.
+fn user_path(tf: &TrapFrame, ptr: u64, len: u64) -> OsResult<PathBuf> {
+    SCHEDULER.with_current(tf, |process| {
+        Ok(fat32::path::resolve(&process.cwd, unsafe { user_str(&process.vmap, ptr, len)? })?)
+    })
+}
+
+/// Copies `value` to user memory at `ptr`.
+unsafe fn copy_out<T: Copy>(vmap: &mut UserPageTable, ptr: u64, value: T) -> OsResult<()> {
+    let bytes = user_buf(vmap, ptr, mem::size_of::<T>() as u64)?;
+    (bytes.as_mut_ptr() as *mut T).write_unaligned(value);
+    Ok(())
+}
+
 /// Sleep for `ms` milliseconds.
 ///
 /// This system call takes one parameter: the number of milliseconds to sleep.
@@ -15,7 +145,7 @@ use kernel_api::*;
 /// parameter: the approximate true elapsed time from when `sleep` was called to
 /// when `sleep` returned.
 pub fn sys_sleep(ms: u32, tf: &mut TrapFrame) {
-    unimplemented!("sys_sleep()");
+    SCHEDULER.sleep(Duration::from_millis(ms as u64), tf);
 }
 
 /// Returns current time.
@@ -27,23 +157,34 @@ pub fn sys_sleep(ms: u32, tf: &mut TrapFrame) {
 ///  - current time as seconds
 ///  - fractional part of the current time, in nanoseconds.
 pub fn sys_time(tf: &mut TrapFrame) {
-    unimplemented!("sys_time()");
+    let now = pi::timer::current_time();
+    ret(tf, Ok(now.as_secs()));
+    tf.xs[1] = now.subsec_nanos() as u64;
 }
 
-/// Kills current process.
+/// Kills current process, with all of its threads.
 ///
-/// This system call does not take paramer and does not return any value.
-pub fn sys_exit(tf: &mut TrapFrame) {
-    unimplemented!("sys_exit()");
+/// This system call takes one parameter: the exit status, which is passed to
+/// the parent's `wait`. It does not return.
+pub fn sys_exit(status: i32, tf: &mut TrapFrame) {
+    let _ = SCHEDULER.kill(tf, status);
+    SCHEDULER.switch_to(tf);
 }
 
-/// Write to console.
+/// Writes to an open file.
 ///
-/// This system call takes one parameter: a u8 character to print.
+/// This system call takes three parameters: the descriptor, and the address
+/// and length of the bytes to write.
 ///
-/// It only returns the usual status value.
-pub fn sys_write(b: u8, tf: &mut TrapFrame) {
-    unimplemented!("sys_write()");
+/// In addition to the usual status value, this system call returns one
+/// parameter: the number of bytes written.
+pub fn sys_write(fd: u64, buf: u64, len: u64, tf: &mut TrapFrame) {
+    let result = SCHEDULER.with_current(tf, |process| {
+        let buf = unsafe { user_slice(&process.vmap, buf, len)? };
+        Ok((buf, process.files.get(Fd(fd))?))
+    });
+    let result = result.and_then(|(buf, descriptor)| Ok(FileGuard::lock(&descriptor)?.write(buf)? as u64));
+    ret(tf, result);
 }
 
 /// Returns current process's ID.
@@ -53,10 +194,439 @@ pub fn sys_write(b: u8, tf: &mut TrapFrame) {
 /// In addition to the usual status value, this system call returns a
 /// parameter: the current process's ID.
 pub fn sys_getpid(tf: &mut TrapFrame) {
-    unimplemented!("sys_getpid()");
+    let id = SCHEDULER.with_current(tf, |process| Ok(process.id));
+    ret(tf, id);
+}
+
+/// Returns current thread's ID.
+///
+/// This system call does not take parameter.
+///
+/// In addition to the usual status value, this system call returns a
+/// parameter: the current thread's ID.
+pub fn sys_gettid(tf: &mut TrapFrame) {
+    let id = tf.tpidr;
+    ret(tf, Ok(id));
+}
+
+/// Opens a file or directory.
+///
+/// This system call takes three parameters: the address and length of the
+/// path, relative to the working directory unless absolute, and the flags,
+/// a combination of `O_CREATE` and `O_APPEND`.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the new descriptor, the lowest one free.
+pub fn sys_open(path: u64, len: u64, flags: u64, tf: &mut TrapFrame) {
+    let descriptor = user_path(tf, path, len).and_then(|path| {
+        let mut descriptor = match FILESYSTEM.open(&path) {
+            Ok(entry) => Descriptor::open(entry)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound && flags & O_CREATE != 0 => {
+                Descriptor::File(FILESYSTEM.create_file(&path)?)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if let (Descriptor::File(ref mut file), true) = (&mut descriptor, flags & O_APPEND != 0) {
+            file.seek(SeekFrom::End(0))?;
+        }
+        Ok(descriptor)
+    });
+    let result = descriptor.and_then(|descriptor| {
+        SCHEDULER.with_current(tf, |process| process.files.insert(descriptor).map(|fd| fd.0))
+    });
+    ret(tf, result);
+}
+
+/// Closes a descriptor.
+///
+/// This system call takes one parameter: the descriptor.
+///
+/// It only returns the usual status value.
+pub fn sys_close(fd: u64, tf: &mut TrapFrame) {
+    let descriptor = SCHEDULER.with_current(tf, |process| process.files.remove(Fd(fd)));
+    ret(tf, descriptor.and_then(process::release).map(|()| 0));
+}
+
+/// Reads from an open file.
+///
+/// This system call takes three parameters: the descriptor, and the address
+/// and length of the buffer to read into.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the number of bytes read, 0 at the end of the file. Reading the
+/// console blocks until at least one byte has arrived.
+pub fn sys_read(fd: u64, buf: u64, len: u64, tf: &mut TrapFrame) {
+    let result = SCHEDULER.with_current(tf, |process| {
+        let buf = unsafe { user_buf(&mut process.vmap, buf, len)? };
+        Ok((buf, process.files.get(Fd(fd))?))
+    });
+    let result = result.and_then(|(buf, descriptor)| match FileGuard::lock(&descriptor)?.read(buf) {
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+        read => Ok(Some(read? as u64)),
+    });
+
+    match result {
+        Ok(Some(read)) => ret(tf, Ok(read)),
+        Ok(None) => {
+            // Only the console blocks. Once it has input, the thread returns
+            // to the `svc` instruction and so makes the call again.
+            tf.elr -= 4;
+            let poll = Box::new(|_: &mut Process, _: &mut TrapFrame| CONSOLE.lock().has_byte());
+            SCHEDULER.switch(State::Waiting(poll), tf);
+        }
+        Err(e) => ret(tf, Err(e)),
+    }
+}
+
+/// Moves the offset of an open file.
+///
+/// This system call takes three parameters: the descriptor, the offset, and
+/// what it is relative to: `SEEK_START`, `SEEK_CURRENT` or `SEEK_END`.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the new offset from the start of the file.
+pub fn sys_seek(fd: u64, offset: u64, whence: u64, tf: &mut TrapFrame) {
+    let pos = match whence {
+        SEEK_START => SeekFrom::Start(offset),
+        SEEK_CURRENT => SeekFrom::Current(offset as i64),
+        SEEK_END => SeekFrom::End(offset as i64),
+        _ => return ret(tf, Err(OsError::InvalidArgument)),
+    };
+    let descriptor = SCHEDULER.with_current(tf, |process| process.files.get(Fd(fd)));
+    let result = descriptor.and_then(|descriptor| Ok(FileGuard::lock(&descriptor)?.seek(pos)?));
+    ret(tf, result);
+}
+
+/// Returns the status of a file or directory.
+///
+/// This system call takes three parameters: the address and length of the
+/// path, and the address of the `Stat` to fill in.
+///
+/// It only returns the usual status value.
+pub fn sys_stat(path: u64, len: u64, stat: u64, tf: &mut TrapFrame) {
+    let result = user_path(tf, path, len)
+        .and_then(|path| Ok(process::stat(&FILESYSTEM.open(&path)?)))
+        .and_then(|value| SCHEDULER.with_current(tf, |process| unsafe { copy_out(&mut process.vmap, stat, value) }));
+    ret(tf, result.map(|()| 0));
+}
+
+/// Reads the next entry of an open directory.
+///
+/// This system call takes two parameters: the descriptor and the address of
+/// the `DirEntry` to fill in.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: 1 if an entry was read, or 0 if there are no more entries.
+pub fn sys_readdir(fd: u64, entry: u64, tf: &mut TrapFrame) {
+    let descriptor = SCHEDULER.with_current(tf, |process| process.files.get(Fd(fd)));
+    let next = descriptor.and_then(|descriptor| {
+        let mut descriptor = descriptor.lock();
+        match *descriptor {
+            Descriptor::Dir(ref mut entries) => Ok(entries.next()),
+            Descriptor::File(_) => Err(OsError::NotADirectory),
+        }
+    });
+    let result = next.and_then(|next| match next {
+        Some(next) => {
+            let value = DirEntry::new(next.name(), process::stat(&next));
+            SCHEDULER.with_current(tf, |process| unsafe { copy_out(&mut process.vmap, entry, value) }).map(|()| 1)
+        }
+        None => Ok(0),
+    });
+    ret(tf, result);
+}
+
+/// Returns the working directory.
+///
+/// This system call takes two parameters: the address and length of the
+/// buffer to write the path into.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the length of the path. The status is `InvalidArgument` if the
+/// buffer is too small.
+pub fn sys_getcwd(buf: u64, len: u64, tf: &mut TrapFrame) {
+    let result = SCHEDULER.with_current(tf, |process| {
+        let cwd = process.cwd.to_str().ok_or(OsError::InvalidArgument)?;
+        let buf = unsafe { user_buf(&mut process.vmap, buf, len)? };
+        if buf.len() < cwd.len() {
+            return Err(OsError::InvalidArgument);
+        }
+        buf[..cwd.len()].copy_from_slice(cwd.as_bytes());
+        Ok(cwd.len() as u64)
+    });
+    ret(tf, result);
+}
+
+/// Changes the working directory.
+///
+/// This system call takes two parameters: the address and length of the
+/// path, relative to the working directory unless absolute.
+///
+/// It only returns the usual status value. The status is `NotADirectory` if
+/// the path names a file.
+pub fn sys_chdir(path: u64, len: u64, tf: &mut TrapFrame) {
+    let path = user_path(tf, path, len).and_then(|path| {
+        if !FILESYSTEM.open(&path)?.is_dir() {
+            return Err(OsError::NotADirectory);
+        }
+        Ok(path)
+    });
+    let result = path.and_then(|path| {
+        SCHEDULER.with_current(tf, |process| {
+            process.cwd = path;
+            Ok(0)
+        })
+    });
+    ret(tf, result);
+}
+
+/// Starts a child process.
+///
+/// This system call takes four parameters: the address and length of the
+/// path of an ELF executable, relative to the working directory unless
+/// absolute, and the address and count of the arguments, each an address and
+/// length. The child gets the path as given as its first argument, followed
+/// by these, and inherits the environment and the working directory.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the child's process ID.
+pub fn sys_spawn(path: u64, len: u64, argv: u64, argc: u64, tf: &mut TrapFrame) {
+    let spawn = SCHEDULER.with_current(tf, |process| {
+        let name = unsafe { user_str(&process.vmap, path, len)? };
+        let path = fat32::path::resolve(&process.cwd, name)?;
+        let mut args = vec![String::from(name)];
+        args.extend(unsafe { user_args(&process.vmap, argv, argc)? }.into_iter().map(String::from));
+        Ok((path, args, process.env.clone(), process.cwd.clone(), process.id))
+    });
+    // The executable is read with the scheduler unlocked.
+    let child = spawn.and_then(|(path, args, env, cwd, parent)| {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let env: Vec<&str> = env.iter().map(String::as_str).collect();
+        let (mut child, thread) = Process::load(&path, &args, &env)?;
+        child.cwd = cwd;
+        child.parent = Some(parent);
+        Ok((child, thread))
+    });
+    let result = child.and_then(|(child, thread)| SCHEDULER.add(child, thread).ok_or(OsError::NoMemory));
+    ret(tf, result);
+}
+
+/// Waits for a child process to exit.
+///
+/// This system call takes one parameter: the child's process ID.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the child's exit status, `EXIT_KILLED` if the kernel killed it.
+/// The status is `NoChild` if the process is not a child of the caller, was
+/// already waited for, or exited so long before that its status was dropped;
+/// see `MAX_EXITED_CHILDREN`.
+pub fn sys_wait(id: u64, tf: &mut TrapFrame) {
+    let reaped = SCHEDULER.with_current(tf, |process| {
+        if process.has_child(id) {
+            Ok(process.reap(id))
+        } else {
+            Err(OsError::NoChild)
+        }
+    });
+
+    match reaped {
+        Ok(Some(status)) => ret(tf, Ok(status as u64)),
+        Ok(None) => {
+            let poll = Box::new(move |process: &mut Process, context: &mut TrapFrame| match process.reap(id) {
+                Some(status) => {
+                    ret(context, Ok(status as u64));
+                    true
+                }
+                None if !process.has_child(id) => {
+                    ret(context, Err(OsError::NoChild));
+                    true
+                }
+                None => false,
+            });
+            SCHEDULER.switch(State::Waiting(poll), tf);
+        }
+        Err(e) => ret(tf, Err(e)),
+    }
+}
+
+/// Starts a child process running a copy of the current one.
+///
+/// This system call does not take parameter. The child shares the parent's
+/// memory copy-on-write, its open descriptors and its working directory.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the child's process ID in the parent, and 0 in the child.
+pub fn sys_fork(tf: &mut TrapFrame) {
+    let child = SCHEDULER.with_current(tf, |process| process.fork(tf));
+    let result = child.and_then(|(child, thread)| SCHEDULER.add(child, thread).ok_or(OsError::NoMemory));
+    ret(tf, result);
+}
+
+/// Sets the nice value of a thread.
+///
+/// This system call takes three parameters: the thread ID, which must be the
+/// caller's or that of a thread of its process or of one of its children, the
+/// nice value, and 1 if the nice
+/// value is to be added to the current one, kept within
+/// `NICE_MIN..=NICE_MAX`, or 0 if it replaces it.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the new nice value.
+pub fn sys_nice(id: u64, nice: i32, relative: bool, tf: &mut TrapFrame) {
+    let result = SCHEDULER.renice(tf, id, nice, relative);
+    ret(tf, result.map(|nice| nice as u64));
+}
+
+/// Starts a thread of the current process.
+///
+/// This system call takes three parameters: the address the thread starts
+/// at, the argument it gets in `x0`, and the top of its stack, 16-byte
+/// aligned. The thread shares the process's memory, descriptors and working
+/// directory. The status is `BadAddress` if the entry point or the stack is
+/// not mapped, and `InvalidArgument` if the caller runs in the kernel.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the thread's ID.
+pub fn sys_thread_spawn(entry: u64, arg: u64, stack: u64, tf: &mut TrapFrame) {
+    let thread = SCHEDULER.with_current(tf, |process| {
+        if tf.spsr & aarch64::SPSR_EL1::M != 0 {
+            return Err(OsError::InvalidArgument);
+        }
+        if stack & 0xf != 0 {
+            return Err(OsError::InvalidArgument);
+        }
+        unsafe {
+            user_slice(&process.vmap, entry, 4)?;
+            user_slice(&process.vmap, stack.wrapping_sub(16), 16)?;
+        }
+        Ok(process.thread(entry, arg, stack))
+    });
+    let result = thread.and_then(|thread| SCHEDULER.spawn(tf, thread).ok_or(OsError::NoMemory));
+    ret(tf, result);
+}
+
+/// Waits for a thread of the current process to exit.
+///
+/// This system call takes one parameter: the thread's ID.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the thread's exit status. The status is `NoEntry` if the thread
+/// was not spawned by the caller's process, or was already joined, and
+/// `InvalidArgument` if it is the caller itself.
+pub fn sys_thread_join(id: u64, tf: &mut TrapFrame) {
+    let caller = tf.tpidr;
+    let joined = SCHEDULER.with_current(tf, |process| {
+        if id == caller {
+            Err(OsError::InvalidArgument)
+        } else if process.has_thread(id) {
+            Ok(process.join(id))
+        } else {
+            Err(OsError::NoEntry)
+        }
+    });
+
+    match joined {
+        Ok(Some(status)) => ret(tf, Ok(status as u64)),
+        Ok(None) => {
+            let poll = Box::new(move |process: &mut Process, context: &mut TrapFrame| match process.join(id) {
+                Some(status) => {
+                    ret(context, Ok(status as u64));
+                    true
+                }
+                None => false,
+            });
+            SCHEDULER.switch(State::Waiting(poll), tf);
+        }
+        Err(e) => ret(tf, Err(e)),
+    }
+}
+
+/// Ends the current thread.
+///
+/// This system call takes one parameter: the exit status, which is passed to
+/// the process's `thread_join`, or to the parent's `wait` if this was the
+/// process's last thread. It does not return.
+pub fn sys_thread_exit(status: i32, tf: &mut TrapFrame) {
+    let _ = SCHEDULER.exit_thread(tf, status);
+    SCHEDULER.switch_to(tf);
+}
+
+/// Blocks on a futex word until a `futex_wake` on it.
+///
+/// This system call takes three parameters: the address of the futex word, a
+/// 4-byte aligned `u32`, the value it is expected to hold, and a timeout in
+/// milliseconds, or `u64::MAX` for none. Threads sharing the word's page
+/// wait on the same word, whatever its address in their process.
+///
+/// It only returns the usual status value. The status is `WouldBlock`,
+/// without blocking, if the word does not hold the expected value, and
+/// `TimedOut` if the timeout is over first.
+pub fn sys_futex_wait(addr: u64, expected: u32, timeout: u64, tf: &mut TrapFrame) {
+    let key = SCHEDULER.with_current(tf, |process| {
+        let (key, value) = unsafe { futex_word(&mut process.vmap, addr)? };
+        if value == expected {
+            Ok(key)
+        } else {
+            Err(OsError::WouldBlock)
+        }
+    });
+
+    match key {
+        Ok(key) => {
+            let timeout = match timeout {
+                core::u64::MAX => None,
+                ms => Some(Duration::from_millis(ms)),
+            };
+            SCHEDULER.futex_wait(tf, key, timeout);
+        }
+        Err(e) => ret(tf, Err(e)),
+    }
+}
+
+/// Wakes threads blocked on a futex word.
+///
+/// This system call takes two parameters: the address of the futex word and
+/// the most threads to wake, which are woken in the order they blocked.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the number of threads woken.
+pub fn sys_futex_wake(addr: u64, n: u64, tf: &mut TrapFrame) {
+    let key = SCHEDULER.with_current(tf, |process| unsafe { futex_word(&mut process.vmap, addr) });
+    let result = key.map(|(key, _)| SCHEDULER.futex_wake(key, n as usize) as u64);
+    ret(tf, result);
 }
 
 pub fn handle_syscall(num: u16, tf: &mut TrapFrame) {
     use crate::console::kprintln;
-    unimplemented!("handle_syscall()")
+
+    let (x0, x1, x2, x3) = (tf.xs[0], tf.xs[1], tf.xs[2], tf.xs[3]);
+    match num as usize {
+        NR_SLEEP => sys_sleep(x0 as u32, tf),
+        NR_TIME => sys_time(tf),
+        NR_EXIT => sys_exit(x0 as i32, tf),
+        NR_WRITE => sys_write(x0, x1, x2, tf),
+        NR_GETPID => sys_getpid(tf),
+        NR_OPEN => sys_open(x0, x1, x2, tf),
+        NR_CLOSE => sys_close(x0, tf),
+        NR_READ => sys_read(x0, x1, x2, tf),
+        NR_SEEK => sys_seek(x0, x1, x2, tf),
+        NR_STAT => sys_stat(x0, x1, x2, tf),
+        NR_READDIR => sys_readdir(x0, x1, tf),
+        NR_GETCWD => sys_getcwd(x0, x1, tf),
+        NR_CHDIR => sys_chdir(x0, x1, tf),
+        NR_SPAWN => sys_spawn(x0, x1, x2, x3, tf),
+        NR_WAIT => sys_wait(x0, tf),
+        NR_FORK => sys_fork(tf),
+        NR_NICE => sys_nice(x0, x1 as i32, x2 != 0, tf),
+        NR_THREAD_SPAWN => sys_thread_spawn(x0, x1, x2, tf),
+        NR_THREAD_JOIN => sys_thread_join(x0, tf),
+        NR_THREAD_EXIT => sys_thread_exit(x0 as i32, tf),
+        NR_GETTID => sys_gettid(tf),
+        NR_FUTEX_WAIT => sys_futex_wait(x0, x1 as u32, x2, tf),
+        NR_FUTEX_WAKE => sys_futex_wake(x0, x1, tf),
+        _ => {
+            kprintln!("thread {}: unknown system call {}", tf.tpidr, num);
+            ret(tf, Err(OsError::Unknown));
+        }
+    }
 }
@@ -7,6 +7,7 @@ use alloc::fmt;
 use core::alloc::{GlobalAlloc, Layout};
 
 use crate::allocator;
+use crate::mutex::Mutex;
 use crate::param::*;
 use crate::vm::{PhysicalAddr, VirtualAddr};
 use crate::ALLOCATOR;
@@ -14,6 +15,41 @@ use crate::ALLOCATOR;
 use aarch64::vmsa::*;
 use shim::const_assert_size;
 
+/// The number of user page tables mapping each page of physical memory, by
+/// page number. Pages that `fork` shares copy-on-write count more than one.
+static PAGE_REFS: Mutex<[u16; IO_BASE / PAGE_SIZE]> = Mutex::new([0; IO_BASE / PAGE_SIZE]);
+
+/// Returns the number of user page tables mapping the page at `addr`.
+fn page_refs(addr: PhysicalAddr) -> u16 {
+    PAGE_REFS.lock()[addr.as_usize() / PAGE_SIZE]
+}
+
+/// Records one more user page table mapping the page at `addr`. Returns
+/// `false`, recording nothing, if the count is at its maximum. A page
+/// nothing maps yet can always be shared.
+fn share_page(addr: PhysicalAddr) -> bool {
+    let mut refs = PAGE_REFS.lock();
+    let count = &mut refs[addr.as_usize() / PAGE_SIZE];
+    match count.checked_add(1) {
+        Some(new) => {
+            *count = new;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Records one user page table fewer mapping the page at `addr`, and frees the
+/// page if that was the last.
+fn release_page(mut addr: PhysicalAddr) {
+    let mut refs = PAGE_REFS.lock();
+    let count = &mut refs[addr.as_usize() / PAGE_SIZE];
+    *count -= 1;
+    if *count == 0 {
+        unsafe { ALLOCATOR.dealloc(addr.as_mut_ptr(), Page::layout()) };
+    }
+}
+
 #[repr(C)]
 pub struct Page([u8; PAGE_SIZE]);
 const_assert_size!(Page, PAGE_SIZE);
@@ -37,12 +73,12 @@ const_assert_size!(L2PageTable, PAGE_SIZE);
 impl L2PageTable {
     /// Returns a new `L2PageTable`
     fn new() -> L2PageTable {
-        unimplemented!("L2PageTable::new()")
+        L2PageTable { entries: [RawL2Entry::new(0); 8192] }
     }
 
     /// Returns a `PhysicalAddr` of the pagetable.
     pub fn as_ptr(&self) -> PhysicalAddr {
-        unimplemented!("L2PageTable::as_ptr()")
+        PhysicalAddr::from(self as *const L2PageTable)
     }
 }
 
@@ -52,18 +88,22 @@ pub struct L3Entry(RawL3Entry);
 impl L3Entry {
     /// Returns a new `L3Entry`.
     fn new() -> L3Entry {
-        unimplemented!("L3Entry::new()")
+        L3Entry(RawL3Entry::new(0))
     }
 
     /// Returns `true` if the L3Entry is valid and `false` otherwise.
     fn is_valid(&self) -> bool {
-        unimplemented!("L3Entry::is_valid()")
+        self.0.get_value(RawL3Entry::VALID) == EntryValid::Valid
     }
 
     /// Extracts `ADDR` field of the L3Entry and returns as a `PhysicalAddr`
     /// if valid. Otherwise, return `None`.
     fn get_page_addr(&self) -> Option<PhysicalAddr> {
-        unimplemented!("LeEntry::get_page_add()")
+        if self.is_valid() {
+            Some(PhysicalAddr::from(self.0.get_masked(RawL3Entry::ADDR)))
+        } else {
+            None
+        }
     }
 }
 
@@ -77,12 +117,12 @@ const_assert_size!(L3PageTable, PAGE_SIZE);
 impl L3PageTable {
     /// Returns a new `L3PageTable`.
     fn new() -> L3PageTable {
-        unimplemented!("L3PageTable::new()")
+        L3PageTable { entries: [L3Entry::new(); 8192] }
     }
 
     /// Returns a `PhysicalAddr` of the pagetable.
     pub fn as_ptr(&self) -> PhysicalAddr {
-        unimplemented!("L3PageTable::as_ptr()")
+        PhysicalAddr::from(self as *const L3PageTable)
     }
 }
 
@@ -97,7 +137,22 @@ impl PageTable {
     /// Returns a new `Box` containing `PageTable`.
     /// Entries in L2PageTable should be initialized properly before return.
     fn new(perm: u64) -> Box<PageTable> {
-        unimplemented!("PageTable::new()")
+        let mut table = Box::new(PageTable {
+            l2: L2PageTable::new(),
+            l3: [L3PageTable::new(), L3PageTable::new()],
+        });
+
+        for (entry, l3) in table.l2.entries.iter_mut().zip(table.l3.iter()) {
+            entry
+                .set_masked(l3.as_ptr().as_u64(), RawL2Entry::ADDR)
+                .set_value(1, RawL2Entry::AF)
+                .set_value(EntrySh::ISh, RawL2Entry::SH)
+                .set_value(perm, RawL2Entry::AP)
+                .set_value(EntryAttr::Mem, RawL2Entry::ATTR)
+                .set_value(EntryType::Table, RawL2Entry::TYPE)
+                .set_value(EntryValid::Valid, RawL2Entry::VALID);
+        }
+        table
     }
 
     /// Returns the (L2index, L3index) extracted from the given virtual address.
@@ -109,35 +164,71 @@ impl PageTable {
     /// Panics if the virtual address is not properly aligned to page size.
     /// Panics if extracted L2index exceeds the number of L3PageTable.
     fn locate(va: VirtualAddr) -> (usize, usize) {
-        unimplemented!("PageTable::localte()")
+        let va = va.as_usize();
+        if va % PAGE_SIZE != 0 {
+            panic!("virtual address {:#x} is not page aligned", va);
+        }
+
+        let l2_index = (va >> 29) & 0x1fff;
+        let l3_index = (va >> 16) & 0x1fff;
+        if l2_index >= 2 {
+            panic!("virtual address {:#x} is out of range", va);
+        }
+        (l2_index, l3_index)
+    }
+
+    /// Returns the L3entry indicated by the given virtual address.
+    fn entry(&self, va: VirtualAddr) -> &L3Entry {
+        let (l2_index, l3_index) = PageTable::locate(va);
+        &self.l3[l2_index].entries[l3_index]
+    }
+
+    /// Returns the L3entry indicated by the given virtual address, mutably.
+    fn entry_mut(&mut self, va: VirtualAddr) -> &mut L3Entry {
+        let (l2_index, l3_index) = PageTable::locate(va);
+        &mut self.l3[l2_index].entries[l3_index]
     }
 
     /// Returns `true` if the L3entry indicated by the given virtual address is valid.
     /// Otherwise, `false` is returned.
     pub fn is_valid(&self, va: VirtualAddr) -> bool {
-        unimplemented!("PageTable::is_valid()")
+        self.entry(va).is_valid()
     }
 
     /// Returns `true` if the L3entry indicated by the given virtual address is invalid.
     /// Otherwise, `true` is returned.
     pub fn is_invalid(&self, va: VirtualAddr) -> bool {
-        unimplemented!("PageTable::is_invalid()")
+        !self.is_valid(va)
+    }
+
+    /// Returns the address of the page that the given virtual address
+    /// translates to, or `None` if the L3entry is invalid.
+    pub fn get_page_addr(&self, va: VirtualAddr) -> Option<PhysicalAddr> {
+        self.entry(va).get_page_addr()
     }
 
     /// Set the given RawL3Entry `entry` to the L3Entry indicated by the given virtual
     /// address.
     pub fn set_entry(&mut self, va: VirtualAddr, entry: RawL3Entry) -> &mut Self {
-        unimplemented!("PageTable::set_entry()")
+        *self.entry_mut(va) = L3Entry(entry);
+        self
     }
 
     /// Returns a base address of the pagetable. The returned `PhysicalAddr` value
     /// will point the start address of the L2PageTable.
     pub fn get_baddr(&self) -> PhysicalAddr {
-        unimplemented!("PageTable::get_baddr()")
+        self.l2.as_ptr()
     }
 }
 
-// FIXME: Implement `IntoIterator` for `&PageTable`.
+impl<'a> IntoIterator for &'a PageTable {
+    type Item = &'a L3Entry;
+    type IntoIter = Chain<Iter<'a, L3Entry>, Iter<'a, L3Entry>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.l3[0].entries.iter().chain(self.l3[1].entries.iter())
+    }
+}
 
 pub struct KernPageTable(Box<PageTable>);
 
@@ -150,15 +241,46 @@ impl KernPageTable {
     /// Each L3 entry should have correct value for lower attributes[10:0] as well
     /// as address[47:16]. Refer to the definition of `RawL3Entry` in `vmsa.rs` for
     /// more details.
+    ///
+    /// Memory between the end of ARM RAM and `IO_BASE`, which the GPU owns and
+    /// shares with us for the framebuffer, is mapped non-cacheable.
     pub fn new() -> KernPageTable {
-        unimplemented!("KernPageTable::new()")
+        let mut table = PageTable::new(EntryPerm::KERN_RW);
+        let (_, mem_end) = allocator::memory_map().expect("failed to find memory map");
+
+        let mut addr = 0;
+        while addr < IO_BASE_END {
+            let (attr, sh) = if addr >= IO_BASE {
+                (EntryAttr::Dev, EntrySh::OSh)
+            } else if addr + PAGE_SIZE > mem_end {
+                (EntryAttr::Nc, EntrySh::OSh)
+            } else {
+                (EntryAttr::Mem, EntrySh::ISh)
+            };
+
+            let mut entry = RawL3Entry::new(0);
+            entry
+                .set_masked(addr as u64, RawL3Entry::ADDR)
+                .set_value(1, RawL3Entry::AF)
+                .set_value(sh, RawL3Entry::SH)
+                .set_value(EntryPerm::KERN_RW, RawL3Entry::AP)
+                .set_value(attr, RawL3Entry::ATTR)
+                .set_value(PageType::Page, RawL3Entry::TYPE)
+                .set_value(EntryValid::Valid, RawL3Entry::VALID);
+            table.set_entry(VirtualAddr::from(addr), entry);
+            addr += PAGE_SIZE;
+        }
+        KernPageTable(table)
     }
 }
 
+/// The permissions of a page in a user address space.
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum PagePerm {
     RW,
     RO,
     RWX,
+    RX,
 }
 
 pub struct UserPageTable(Box<PageTable>);
@@ -167,21 +289,163 @@ impl UserPageTable {
     /// Returns a new `UserPageTable` containing a `PageTable` created with
     /// `USER_RW` permission.
     pub fn new() -> UserPageTable {
-        unimplemented!("UserPageTable::new()")
+        UserPageTable(PageTable::new(EntryPerm::USER_RW))
     }
 
-    /// Allocates a page and set an L3 entry translates given virtual address to the
-    /// physical address of the allocated page. Returns the allocated page.
+    /// Returns the offset of the given user virtual address from
+    /// `USER_IMG_BASE`, which is what `PageTable` indexes by.
+    ///
+    /// # Panics
+    /// Panics if the virtual address is lower than `USER_IMG_BASE`.
+    fn offset(va: VirtualAddr) -> VirtualAddr {
+        if va.as_usize() < USER_IMG_BASE {
+            panic!("virtual address {:?} is below USER_IMG_BASE", va);
+        }
+        va - VirtualAddr::from(USER_IMG_BASE)
+    }
+
+    /// Allocates a zeroed page and set an L3 entry translates given virtual
+    /// address to the physical address of the allocated page with permission
+    /// `perm`. Returns the allocated page, or `None` if the allocator fails to
+    /// allocate one.
     ///
     /// # Panics
     /// Panics if the virtual address is lower than `USER_IMG_BASE`.
     /// Panics if the virtual address has already been allocated.
-    /// Panics if allocator fails to allocate a page.
+    pub fn alloc(&mut self, va: VirtualAddr, perm: PagePerm) -> Option<&mut [u8]> {
+        let offset = UserPageTable::offset(va);
+        if self.is_valid(offset) {
+            panic!("virtual address {:?} is already allocated", va);
+        }
+
+        let page = unsafe { ALLOCATOR.alloc(Page::layout()) };
+        if page.is_null() {
+            return None;
+        }
+        unsafe { page.write_bytes(0, PAGE_SIZE) };
+        share_page(PhysicalAddr::from(page));
+
+        let (ap, executable) = match perm {
+            PagePerm::RW => (EntryPerm::USER_RW, false),
+            PagePerm::RO => (EntryPerm::USER_RO, false),
+            PagePerm::RWX => (EntryPerm::USER_RW, true),
+            PagePerm::RX => (EntryPerm::USER_RO, true),
+        };
+
+        let mut entry = RawL3Entry::new(0);
+        entry
+            .set_masked(page as u64, RawL3Entry::ADDR)
+            .set_value(!executable as u64, RawL3Entry::UXN)
+            .set_value(1, RawL3Entry::PXN)
+            .set_value(1, RawL3Entry::AF)
+            .set_value(EntrySh::ISh, RawL3Entry::SH)
+            .set_value(ap, RawL3Entry::AP)
+            .set_value(EntryAttr::Mem, RawL3Entry::ATTR)
+            .set_value(PageType::Page, RawL3Entry::TYPE)
+            .set_value(EntryValid::Valid, RawL3Entry::VALID);
+        self.set_entry(offset, entry);
+
+        Some(unsafe { core::slice::from_raw_parts_mut(page, PAGE_SIZE) })
+    }
+
+    /// Returns the page that the given virtual address is mapped to, or `None`
+    /// if it has not been allocated.
     ///
-    /// TODO. use Result<T> and make it failurable
-    /// TODO. use perm properly
-    pub fn alloc(&mut self, va: VirtualAddr, _perm: PagePerm) -> &mut [u8] {
-        unimplemented!("alloc()");
+    /// # Panics
+    /// Panics if the virtual address is lower than `USER_IMG_BASE`.
+    pub fn page(&mut self, va: VirtualAddr) -> Option<&mut [u8]> {
+        let mut addr = self.get_page_addr(UserPageTable::offset(va))?;
+        Some(unsafe { core::slice::from_raw_parts_mut(addr.as_mut_ptr(), PAGE_SIZE) })
+    }
+
+    /// Returns `true` if the page containing the given virtual address is
+    /// allocated.
+    ///
+    /// # Panics
+    /// Panics if the virtual address is lower than `USER_IMG_BASE`.
+    pub fn is_mapped(&self, va: VirtualAddr) -> bool {
+        let page = VirtualAddr::from(va.as_usize() & PAGE_MASK);
+        self.is_valid(UserPageTable::offset(page))
+    }
+
+    /// Returns the physical address the given virtual address is mapped to,
+    /// or `None` if its page has not been allocated.
+    ///
+    /// # Panics
+    /// Panics if the virtual address is lower than `USER_IMG_BASE`.
+    pub fn translate(&self, va: VirtualAddr) -> Option<PhysicalAddr> {
+        let page = VirtualAddr::from(va.as_usize() & PAGE_MASK);
+        let addr = self.get_page_addr(UserPageTable::offset(page))?;
+        Some(PhysicalAddr::from(addr.as_usize() + (va.as_usize() & !PAGE_MASK)))
+    }
+
+    /// Returns a copy of this address space that shares every page with it
+    /// copy-on-write: writable pages become read-only in both page tables,
+    /// until `make_writable` gives the first to write its own copy. Returns
+    /// `None` if a page is already mapped by as many page tables as can be
+    /// counted.
+    pub fn fork(&mut self) -> Option<UserPageTable> {
+        let mut child = UserPageTable::new();
+        for (l3, child_l3) in self.0.l3.iter_mut().zip(child.0.l3.iter_mut()) {
+            for (entry, child_entry) in l3.entries.iter_mut().zip(child_l3.entries.iter_mut()) {
+                let addr = match entry.get_page_addr() {
+                    Some(addr) => addr,
+                    None => continue,
+                };
+                if entry.0.get_value(RawL3Entry::AP) == EntryPerm::USER_RW {
+                    entry.0.set_value(EntryPerm::USER_RO, RawL3Entry::AP).set_value(1, RawL3Entry::COW);
+                }
+                if !share_page(addr) {
+                    return None;
+                }
+                *child_entry = *entry;
+            }
+        }
+
+        // Writes through the parent's cached translations must fault now.
+        unsafe { aarch64::tlb_invalidate_all() };
+        Some(child)
+    }
+
+    /// Makes the page containing the given virtual address writable, if it
+    /// was writable before `fork` shared it copy-on-write: the page is copied,
+    /// or kept if no other page table maps it any longer. Returns `true` if
+    /// the page is now writable, or `false` if it is not allocated, is
+    /// read-only, or could not be copied.
+    ///
+    /// # Panics
+    /// Panics if the virtual address is lower than `USER_IMG_BASE`.
+    pub fn make_writable(&mut self, va: VirtualAddr) -> bool {
+        let page = VirtualAddr::from(va.as_usize() & PAGE_MASK);
+        let offset = UserPageTable::offset(page);
+        let mut entry = *self.entry_mut(offset);
+
+        let mut addr = match entry.get_page_addr() {
+            Some(addr) => addr,
+            None => return false,
+        };
+        if entry.0.get_value(RawL3Entry::AP) == EntryPerm::USER_RW {
+            return true;
+        }
+        if entry.0.get_value(RawL3Entry::COW) == 0 {
+            return false;
+        }
+
+        if page_refs(addr) > 1 {
+            let copy = unsafe { ALLOCATOR.alloc(Page::layout()) };
+            if copy.is_null() {
+                return false;
+            }
+            unsafe { copy.copy_from_nonoverlapping(addr.as_mut_ptr(), PAGE_SIZE) };
+            share_page(PhysicalAddr::from(copy));
+            release_page(addr);
+            entry.0.set_masked(copy as u64, RawL3Entry::ADDR);
+        }
+        entry.0.set_value(EntryPerm::USER_RW, RawL3Entry::AP).set_value(0, RawL3Entry::COW);
+        self.set_entry(offset, entry.0);
+
+        unsafe { aarch64::tlb_invalidate_va(page.as_u64()) };
+        true
     }
 }
 
@@ -213,5 +477,21 @@ impl DerefMut for UserPageTable {
     }
 }
 
-// FIXME: Implement `Drop` for `UserPageTable`.
-// FIXME: Implement `fmt::Debug` as you need.
+impl Drop for UserPageTable {
+    fn drop(&mut self) {
+        for entry in self.0.into_iter() {
+            if let Some(addr) = entry.get_page_addr() {
+                release_page(addr);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for UserPageTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UserPageTable")
+            .field("baddr", &self.get_baddr())
+            .field("pages", &self.0.into_iter().filter(|entry| entry.is_valid()).count())
+            .finish()
+    }
+}
@@ -55,6 +55,31 @@ pub unsafe fn cli() {
          : "volatile");
 }
 
+/// Invalidate the TLB entries translating virtual address `va` at EL1&0 on
+/// every core, and wait for the invalidation to complete.
+#[inline(always)]
+pub unsafe fn tlb_invalidate_va(va: u64) {
+    asm!("dsb ishst
+          tlbi vaae1is, $0
+          dsb ish
+          isb"
+         :
+         : "r"((va >> 12) & ((1 << 44) - 1))
+         :
+         : "volatile");
+}
+
+/// Invalidate every TLB entry at EL1&0 on every core, and wait for the
+/// invalidation to complete.
+#[inline(always)]
+pub unsafe fn tlb_invalidate_all() {
+    asm!("dsb ishst
+          tlbi vmalle1is
+          dsb ish
+          isb"
+         :::: "volatile");
+}
+
 /// Break with an immeidate
 #[macro_export]
 macro_rules! brk {
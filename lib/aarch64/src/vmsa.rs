@@ -58,6 +58,9 @@ defbit!(RawL2Entry, [
 ]);
 
 defbit!(RawL3Entry, [
+    COW   [55-55],
+    UXN   [54-54],
+    PXN   [53-53],
     ADDR  [47-16],
 
     AF    [10-10],
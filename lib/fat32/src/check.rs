@@ -0,0 +1,566 @@
+//! A consistency checker for FAT volumes in the spirit of `fsck.vfat`.
+//!
+//! `check()` walks every directory reachable from the root and verifies:
+//!
+//!   * that every copy of the FAT matches the first,
+//!   * that long file name entries carry valid sequence numbers and the
+//!     checksum of the 8.3 entry they belong to,
+//!   * that every cluster chain is well formed and owned by exactly one entry,
+//!   * that a file's size agrees with the length of its cluster chain,
+//!   * that no allocated cluster is unreachable,
+//!   * that the free cluster count recorded in FSInfo, if any, is right.
+//!
+//! When asked to repair, the first FAT is taken as authoritative and problems
+//! are fixed the way `fsck.vfat` would: stray LFN entries are deleted, broken
+//! or cross-linked chains are truncated, sizes and chains are made to agree,
+//! lost clusters are freed, and the free count is recomputed. Repairs are
+//! written through the sector cache and flushed before `check()` returns.
+
+use core::fmt;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use shim::io;
+
+use crate::util::VecExt;
+use crate::vfat::{Cluster, Position, VFat, VFatDirEntry, VFatHandle, VFatRegularDirEntry};
+
+const FAT_ENTRY_MASK: u32 = 0x0FFFFFFF;
+const FAT_BAD: u32 = 0x0FFFFFF7;
+const FAT_EOC: u32 = 0x0FFFFFFF;
+
+const LFN_LAST: u8 = 0x40;
+const LFN_SEQUENCE: u8 = 0x1F;
+
+/// A single inconsistency found on the volume.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// `sectors` sectors of FAT copy `copy` differ from the first FAT.
+    FatMismatch { copy: u8, sectors: u32 },
+    /// A run of LFN entries before `path` has missing or out of order
+    /// sequence numbers, or is not followed by an 8.3 entry.
+    BadLfnSequence { path: String },
+    /// The LFN entries of `path` carry a checksum that does not match its 8.3
+    /// name.
+    BadLfnChecksum { path: String },
+    /// The chain of `path` runs into `cluster`, which is free, bad, out of
+    /// range, or already part of the same chain.
+    BadChain { path: String, cluster: u32 },
+    /// The chain of `path` runs into `cluster`, which belongs to `other`.
+    CrossLinked { path: String, other: String, cluster: u32 },
+    /// The size of `path` does not agree with the `clusters` in its chain.
+    SizeMismatch { path: String, size: u32, clusters: u32 },
+    /// `count` clusters are allocated but not reachable from any entry.
+    LostClusters { count: u32 },
+    /// FSInfo records `recorded` free clusters where the FAT has `actual`.
+    FreeCountMismatch { recorded: u32, actual: u32 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Problem::*;
+
+        match self {
+            FatMismatch { copy, sectors } => write!(f, "FAT {} differs from FAT 0 in {} sectors", copy, sectors),
+            BadLfnSequence { path } => write!(f, "{}: bad long file name sequence", path),
+            BadLfnChecksum { path } => write!(f, "{}: long file name checksum mismatch", path),
+            BadChain { path, cluster } => write!(f, "{}: broken cluster chain at cluster {}", path, cluster),
+            CrossLinked { path, other, cluster } => {
+                write!(f, "{}: cross-linked with {} at cluster {}", path, other, cluster)
+            }
+            SizeMismatch { path, size, clusters } => {
+                write!(f, "{}: size {} does not match chain of {} clusters", path, size, clusters)
+            }
+            LostClusters { count } => write!(f, "{} lost clusters", count),
+            FreeCountMismatch { recorded, actual } => {
+                write!(f, "free cluster count is {} but should be {}", recorded, actual)
+            }
+        }
+    }
+}
+
+/// The outcome of a call to `check()`.
+#[derive(Debug, Default, Clone)]
+pub struct Report {
+    /// Every problem found, in the order it was found.
+    pub problems: Vec<Problem>,
+    /// Whether the problems were repaired.
+    pub repaired: bool,
+    /// The number of directories visited, including the root.
+    pub directories: u32,
+    /// The number of files visited.
+    pub files: u32,
+    /// The number of clusters reachable from the root.
+    pub used_clusters: u32,
+    /// The number of data clusters on the volume.
+    pub total_clusters: u32,
+}
+
+impl Report {
+    /// Returns `true` if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} directories, {} files, {}/{} clusters, {} problems{}",
+            self.directories,
+            self.files,
+            self.used_clusters,
+            self.total_clusters,
+            self.problems.len(),
+            if self.repaired && !self.is_clean() { " (repaired)" } else { "" }
+        )
+    }
+}
+
+/// Checks the consistency of the volume behind `vfat`, repairing any problem
+/// found if `repair` is `true`.
+///
+/// # Errors
+///
+/// Returns an error if reading from or writing to the device fails. Problems
+/// with the volume itself are reported in the returned `Report`.
+pub fn check<HANDLE: VFatHandle>(vfat: &HANDLE, repair: bool) -> io::Result<Report> {
+    vfat.lock(|vfat| {
+        let report = Checker::new(vfat, repair).run();
+        // Repairs may rewrite or delete entries whose names are cached.
+        vfat.forget_all_lookups();
+        report
+    })
+}
+
+/// A directory waiting to be scanned.
+struct PendingDir {
+    path: usize,
+    sectors: Vec<u64>,
+}
+
+/// A run of LFN entries waiting for its 8.3 entry.
+#[derive(Default)]
+struct LfnRun {
+    positions: Vec<Position>,
+    name: Vec<u16>,
+    checksum: u8,
+    next_sequence: u8,
+    valid: bool,
+}
+
+impl LfnRun {
+    fn push(&mut self, position: Position, sequence: u8, checksum: u8, chars: &[u16; 13]) {
+        let n = sequence & LFN_SEQUENCE;
+        let in_order = if sequence & LFN_LAST != 0 {
+            self.positions.is_empty()
+        } else {
+            !self.positions.is_empty() && n == self.next_sequence && checksum == self.checksum
+        };
+
+        if self.positions.is_empty() {
+            self.name.resize(n as usize * 13, 0);
+            self.checksum = checksum;
+            self.valid = true;
+        }
+        self.valid &= in_order && n != 0;
+        if self.valid {
+            let start = (n as usize - 1) * 13;
+            self.name[start..start + 13].copy_from_slice(chars);
+        }
+        self.next_sequence = n.wrapping_sub(1);
+        self.positions.push(position);
+    }
+
+    fn is_complete(&self) -> bool {
+        self.valid && self.next_sequence == 0
+    }
+
+    fn name(&self) -> Option<String> {
+        let len = self.name.iter().position(|&c| c == 0x0000 || c == 0xFFFF).unwrap_or(self.name.len());
+        String::from_utf16(&self.name[..len]).ok()
+    }
+}
+
+struct Checker<'a, HANDLE: VFatHandle> {
+    vfat: &'a mut VFat<HANDLE>,
+    repair: bool,
+    /// The entries of the first FAT, kept in sync with any repairs.
+    fat: Vec<u32>,
+    /// For every cluster, `1 +` the index in `paths` of its owner, or `0`.
+    owners: Vec<u32>,
+    paths: Vec<String>,
+    report: Report,
+}
+
+impl<'a, HANDLE: VFatHandle> Checker<'a, HANDLE> {
+    fn new(vfat: &'a mut VFat<HANDLE>, repair: bool) -> Checker<'a, HANDLE> {
+        let clusters = vfat.cluster_count() as usize + 2;
+        Checker {
+            vfat: vfat,
+            repair: repair,
+            fat: Vec::new(),
+            owners: vec![0; clusters],
+            paths: Vec::new(),
+            report: Report::default(),
+        }
+    }
+
+    fn run(mut self) -> io::Result<Report> {
+        self.report.total_clusters = self.vfat.cluster_count();
+        self.report.repaired = self.repair;
+
+        self.load_fat()?;
+        self.compare_fats()?;
+        self.walk()?;
+        self.find_lost_clusters()?;
+        self.check_free_count();
+
+        self.report.used_clusters = self.owners.iter().filter(|&&owner| owner != 0).count() as u32;
+        if self.repair {
+            self.vfat.flush()?;
+        }
+        Ok(self.report)
+    }
+
+    fn load_fat(&mut self) -> io::Result<()> {
+        let (_, sectors) = self.vfat.fat_layout();
+        let fat_bytes = sectors as usize * self.vfat.bytes_per_sector() as usize;
+        let entries = fat_bytes * 8 / self.vfat.fat_type().entry_bits();
+
+        for cluster in 0..::core::cmp::min(entries, self.owners.len()) {
+            let entry = self.vfat.fat_entry(Cluster::from(cluster as u32))?;
+            self.fat.push(entry.0 & FAT_ENTRY_MASK);
+        }
+
+        // A FAT too small for the data region: treat the missing entries as bad.
+        self.fat.resize(self.owners.len(), FAT_BAD);
+        Ok(())
+    }
+
+    fn compare_fats(&mut self) -> io::Result<()> {
+        let (copies, sectors) = self.vfat.fat_layout();
+
+        for copy in 1..copies {
+            let mut differing = 0;
+            for n in 0..sectors {
+                let first = self.vfat.fat_sector(0, n)?.to_vec();
+                if self.vfat.fat_sector(copy, n)? != &first[..] {
+                    differing += 1;
+                    if self.repair {
+                        self.vfat.set_fat_sector(copy, n, &first)?;
+                    }
+                }
+            }
+
+            if differing > 0 {
+                self.report.problems.push(Problem::FatMismatch { copy: copy, sectors: differing });
+            }
+        }
+        Ok(())
+    }
+
+    fn set_fat_entry(&mut self, cluster: Cluster, value: u32) -> io::Result<()> {
+        self.fat[cluster.get() as usize] = value;
+        self.vfat.set_fat_entry(cluster, value)
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && (cluster as usize) < self.fat.len()
+    }
+
+    /// Follows the chain beginning at `start`, claiming every cluster for
+    /// `path`. Stops at the end of the chain or at the first cluster that
+    /// cannot be part of it, returning the clusters claimed and whether the
+    /// chain was broken.
+    fn claim_chain(&mut self, path: usize, start: Cluster) -> (Vec<Cluster>, bool) {
+        let mut chain = Vec::new();
+        let mut cluster = start.get();
+
+        loop {
+            if !self.is_valid_cluster(cluster) {
+                let path = self.paths[path].clone();
+                self.report.problems.push(Problem::BadChain { path: path, cluster: cluster });
+                return (chain, true);
+            }
+
+            let owner = self.owners[cluster as usize];
+            if owner == path as u32 + 1 {
+                let path = self.paths[path].clone();
+                self.report.problems.push(Problem::BadChain { path: path, cluster: cluster });
+                return (chain, true);
+            } else if owner != 0 {
+                let (path, other) = (self.paths[path].clone(), self.paths[owner as usize - 1].clone());
+                self.report.problems.push(Problem::CrossLinked { path: path, other: other, cluster: cluster });
+                return (chain, true);
+            }
+
+            match self.fat[cluster as usize] {
+                0 | 1 | 0x0FFFFFF0..=FAT_BAD => {
+                    let path = self.paths[path].clone();
+                    self.report.problems.push(Problem::BadChain { path: path, cluster: cluster });
+                    return (chain, true);
+                }
+                next => {
+                    self.owners[cluster as usize] = path as u32 + 1;
+                    chain.push(Cluster::from(cluster));
+                    if next >= 0x0FFFFFF8 {
+                        return (chain, false);
+                    }
+                    cluster = next;
+                }
+            }
+        }
+    }
+
+    /// Cuts `chain` down to its first `keep` clusters, freeing the rest. The
+    /// caller is responsible for the directory entry when `keep` is `0`.
+    fn truncate_chain(&mut self, chain: &mut Vec<Cluster>, keep: usize) -> io::Result<()> {
+        for &cluster in &chain[keep..] {
+            self.owners[cluster.get() as usize] = 0;
+            self.set_fat_entry(cluster, 0)?;
+        }
+        chain.truncate(keep);
+        if let Some(&last) = chain.last() {
+            self.set_fat_entry(last, FAT_EOC)?;
+        }
+        Ok(())
+    }
+
+    fn write_entry(&mut self, position: Position, entry: &VFatRegularDirEntry) -> io::Result<()> {
+        self.vfat.write_sector_bytes(position.sector, position.offset, entry.as_bytes())
+    }
+
+    fn delete_entries(&mut self, positions: &[Position]) -> io::Result<()> {
+        for position in positions {
+            self.vfat.write_sector_bytes(position.sector, position.offset, &[0xE5])?;
+        }
+        Ok(())
+    }
+
+    /// Returns the sectors of the clusters in `chain`, in order.
+    fn chain_sectors(&self, chain: &[Cluster]) -> Vec<u64> {
+        let sectors_per_cluster = self.vfat.sectors_per_cluster() as u64;
+        chain.iter()
+            .flat_map(|&cluster| {
+                let start = self.vfat.cluster_start_sector(cluster);
+                start..start + sectors_per_cluster
+            })
+            .collect()
+    }
+
+    fn walk(&mut self) -> io::Result<()> {
+        self.paths.push(String::from("/"));
+        self.report.directories += 1;
+
+        let sectors = match self.vfat.root_dir_region() {
+            Some((start, count)) => (start..start + count).collect(),
+            None => {
+                let root = self.vfat.root_cluster();
+                let (chain, _) = self.claim_chain(0, root);
+                self.chain_sectors(&chain)
+            }
+        };
+
+        let mut pending = vec![PendingDir { path: 0, sectors: sectors }];
+        while let Some(dir) = pending.pop() {
+            self.scan_dir(&dir, &mut pending)?;
+        }
+        Ok(())
+    }
+
+    fn scan_dir(&mut self, dir: &PendingDir, pending: &mut Vec<PendingDir>) -> io::Result<()> {
+        let sector_size = self.vfat.bytes_per_sector() as usize;
+        let mut buf = vec![0u8; sector_size * dir.sectors.len()];
+        for (i, &sector) in dir.sectors.iter().enumerate() {
+            self.vfat.read_sector(sector, &mut buf[i * sector_size..])?;
+        }
+
+        let entries: Vec<VFatDirEntry> = unsafe { buf.cast() };
+        let mut lfn = LfnRun::default();
+
+        for (i, entry) in entries.iter().enumerate() {
+            let position = Position {
+                sector: dir.sectors[i * 32 / sector_size],
+                offset: i * 32 % sector_size,
+            };
+
+            match entry.marker() {
+                0 => break,
+                0xE5 => {
+                    self.end_lfn_run(dir.path, &mut lfn, None)?;
+                    continue;
+                }
+                _ => (),
+            }
+
+            if entry.is_lfn() {
+                let long_filename = entry.long_filename();
+                let sequence = long_filename.sequence();
+                if sequence & LFN_LAST != 0 {
+                    self.end_lfn_run(dir.path, &mut lfn, None)?;
+                }
+                lfn.push(position, sequence, long_filename.checksum(), &long_filename.name_chars());
+                continue;
+            }
+
+            let regular = entry.regular();
+            let name = self.end_lfn_run(dir.path, &mut lfn, Some(&regular))?;
+            let attr = regular.attributes();
+            if attr.volume_id() || name == "." || name == ".." {
+                continue;
+            }
+
+            let path = self.child_path(dir.path, &name);
+            if attr.directory() {
+                self.report.directories += 1;
+                if let Some(chain) = self.check_dir(path, position, regular)? {
+                    let sectors = self.chain_sectors(&chain);
+                    pending.push(PendingDir { path: path, sectors: sectors });
+                }
+            } else {
+                self.report.files += 1;
+                self.check_file(path, position, regular)?;
+            }
+        }
+
+        // Trailing LFN entries with no 8.3 entry to belong to.
+        self.end_lfn_run(dir.path, &mut lfn, None)
+            .map(|_| ())
+    }
+
+    /// Finishes the pending LFN run, checking it against `entry`, the 8.3
+    /// entry that ended it, if any. Returns the name of `entry`.
+    fn end_lfn_run(&mut self, dir: usize, lfn: &mut LfnRun, entry: Option<&VFatRegularDirEntry>) -> io::Result<String> {
+        let run = ::core::mem::replace(lfn, LfnRun::default());
+        let short_name = entry.map(|entry| entry.short_name()).unwrap_or_default();
+        if run.positions.is_empty() {
+            return Ok(short_name);
+        }
+
+        let problem = match entry {
+            Some(entry) if run.is_complete() => {
+                if run.checksum == entry.short_name_checksum() {
+                    return Ok(run.name().unwrap_or(short_name));
+                }
+                Problem::BadLfnChecksum { path: self.child_path_name(dir, &short_name) }
+            }
+            _ => Problem::BadLfnSequence { path: self.child_path_name(dir, &short_name) },
+        };
+
+        self.report.problems.push(problem);
+        if self.repair {
+            self.delete_entries(&run.positions)?;
+        }
+        Ok(short_name)
+    }
+
+    fn child_path_name(&self, dir: usize, name: &str) -> String {
+        let mut path = self.paths[dir].clone();
+        if !path.ends_with('/') {
+            path.push('/');
+        }
+        path.push_str(name);
+        path
+    }
+
+    fn child_path(&mut self, dir: usize, name: &str) -> usize {
+        let path = self.child_path_name(dir, name);
+        self.paths.push(path);
+        self.paths.len() - 1
+    }
+
+    /// Claims the chain of directory `path`, returning it if it should be
+    /// scanned.
+    fn check_dir(&mut self, path: usize, position: Position, entry: VFatRegularDirEntry) -> io::Result<Option<Vec<Cluster>>> {
+        let (mut chain, broken) = self.claim_chain(path, entry.cluster_number());
+        if broken && self.repair {
+            if chain.is_empty() {
+                // Nothing left of the directory: drop its entry.
+                self.delete_entries(&[position])?;
+                return Ok(None);
+            }
+            let keep = chain.len();
+            self.truncate_chain(&mut chain, keep)?;
+        }
+        Ok(Some(chain))
+    }
+
+    fn check_file(&mut self, path: usize, position: Position, mut entry: VFatRegularDirEntry) -> io::Result<()> {
+        let cluster_size = self.vfat.cluster_size() as u64;
+        let size = entry.file_size();
+
+        let mut chain = if entry.cluster_number().get() == 0 {
+            Vec::new()
+        } else {
+            let (mut chain, broken) = self.claim_chain(path, entry.cluster_number());
+            if broken && self.repair {
+                let keep = chain.len();
+                self.truncate_chain(&mut chain, keep)?;
+                if chain.is_empty() {
+                    entry.set_cluster_number(Cluster::from(0));
+                    self.write_entry(position, &entry)?;
+                }
+            }
+            chain
+        };
+
+        let needed = ((size as u64 + cluster_size - 1) / cluster_size) as usize;
+        if needed == chain.len() {
+            return Ok(());
+        }
+
+        self.report.problems.push(Problem::SizeMismatch {
+            path: self.paths[path].clone(),
+            size: size,
+            clusters: chain.len() as u32,
+        });
+
+        if self.repair {
+            if chain.len() > needed {
+                self.truncate_chain(&mut chain, needed)?;
+                if needed == 0 {
+                    entry.set_cluster_number(Cluster::from(0));
+                }
+            } else {
+                entry.set_file_size((chain.len() as u64 * cluster_size) as u32);
+            }
+            self.write_entry(position, &entry)?;
+        }
+        Ok(())
+    }
+
+    fn find_lost_clusters(&mut self) -> io::Result<()> {
+        let lost: Vec<Cluster> = (2..self.fat.len())
+            .filter(|&c| self.fat[c] != 0 && self.fat[c] != FAT_BAD && self.owners[c] == 0)
+            .map(|c| Cluster::from(c as u32))
+            .collect();
+
+        if lost.is_empty() {
+            return Ok(());
+        }
+
+        self.report.problems.push(Problem::LostClusters { count: lost.len() as u32 });
+        if self.repair {
+            for cluster in lost {
+                self.set_fat_entry(cluster, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_free_count(&mut self) {
+        let actual = self.fat[2..].iter().filter(|&&entry| entry == 0).count() as u32;
+        match self.vfat.recorded_free_clusters() {
+            Some(recorded) if recorded != actual => {
+                self.report.problems.push(Problem::FreeCountMismatch { recorded: recorded, actual: actual });
+                if self.repair {
+                    self.vfat.set_free_clusters(actual);
+                }
+            }
+            _ => (),
+        }
+    }
+}
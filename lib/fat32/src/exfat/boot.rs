@@ -0,0 +1,114 @@
+use core::{fmt, mem};
+use shim::const_assert_size;
+
+use crate::traits::BlockDevice;
+use crate::vfat::Error;
+
+/// The main boot sector of an exFAT volume.
+#[repr(C, packed)]
+pub struct BootSector {
+    jmp: [u8; 3],
+    fs_name: [u8; 8],
+    must_be_zero: [u8; 53],
+    partition_offset: u64,
+    pub volume_length: u64,
+    pub fat_offset: u32,
+    pub fat_length: u32,
+    pub cluster_heap_offset: u32,
+    pub cluster_count: u32,
+    pub root_cluster: u32,
+    volume_serial: u32,
+    fs_revision: u16,
+    volume_flags: u16,
+    bytes_per_sector_shift: u8,
+    sectors_per_cluster_shift: u8,
+    pub fats: u8,
+    drive_select: u8,
+    percent_in_use: u8,
+    reserved: [u8; 7],
+    boot_code: [u8; 390],
+    boot_signature: u16,
+}
+
+const_assert_size!(BootSector, 512);
+
+/// Offsets of the boot sector fields that may change while the volume is in
+/// use and so are excluded from the boot region checksum.
+const VOLATILE_FIELDS: [usize; 3] = [106, 107, 112];
+
+impl BootSector {
+    /// Reads the exFAT boot sector from sector `sector` of device `device`.
+    ///
+    /// # Errors
+    ///
+    /// If the file system name is not `EXFAT`, the boot signature is invalid,
+    /// or the sector and cluster sizes are out of range, returns an error of
+    /// `BadSignature`.
+    pub fn from<T: BlockDevice>(mut device: T, sector: u64) -> Result<BootSector, Error> {
+        let mut buf = [0u8; 512];
+        device.read_sector(sector, &mut buf).map_err(|e| { Error::Io(e) })?;
+        let boot = unsafe { mem::transmute::<[u8; 512], BootSector>(buf) };
+
+        if &boot.fs_name != b"EXFAT   " || boot.boot_signature != 0xAA55 {
+            return Err(Error::BadSignature);
+        }
+
+        let (bps_shift, spc_shift) = (boot.bytes_per_sector_shift, boot.sectors_per_cluster_shift);
+        if bps_shift < 9 || bps_shift > 12 || bps_shift + spc_shift > 25 {
+            return Err(Error::BadSignature);
+        }
+
+        Ok(boot)
+    }
+
+    pub fn bytes_per_sector(&self) -> u64 {
+        1 << self.bytes_per_sector_shift
+    }
+
+    pub fn sectors_per_cluster(&self) -> u64 {
+        1 << self.sectors_per_cluster_shift
+    }
+
+    /// Verifies the boot region checksum. `sectors` holds the first 12
+    /// sectors of the volume: the 11 sectors covered by the checksum followed
+    /// by the checksum sector, which repeats the checksum throughout.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BadChecksum` if the checksum sector does not match.
+    pub fn verify_checksum(&self, sectors: &[u8]) -> Result<(), Error> {
+        let bytes_per_sector = self.bytes_per_sector() as usize;
+        let (region, checksum_sector) = sectors[..12 * bytes_per_sector].split_at(11 * bytes_per_sector);
+
+        let checksum = region.iter()
+            .enumerate()
+            .filter(|(i, _)| !VOLATILE_FIELDS.contains(i))
+            .fold(0u32, |sum, (_, &b)| sum.rotate_right(1).wrapping_add(b as u32));
+
+        let matches = checksum_sector.chunks(4)
+            .all(|c| (c[0] as u32 | (c[1] as u32) << 8 | (c[2] as u32) << 16 | (c[3] as u32) << 24) == checksum);
+
+        if matches { Ok(()) } else { Err(Error::BadChecksum) }
+    }
+}
+
+impl fmt::Debug for BootSector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BootSector")
+            .field("partition_offset", &{ self.partition_offset })
+            .field("volume_length", &{ self.volume_length })
+            .field("fat_offset", &{ self.fat_offset })
+            .field("fat_length", &{ self.fat_length })
+            .field("cluster_heap_offset", &{ self.cluster_heap_offset })
+            .field("cluster_count", &{ self.cluster_count })
+            .field("root_cluster", &{ self.root_cluster })
+            .field("volume_serial", &{ self.volume_serial })
+            .field("fs_revision", &{ self.fs_revision })
+            .field("volume_flags", &{ self.volume_flags })
+            .field("bytes_per_sector_shift", &self.bytes_per_sector_shift)
+            .field("sectors_per_cluster_shift", &self.sectors_per_cluster_shift)
+            .field("fats", &self.fats)
+            .field("percent_in_use", &self.percent_in_use)
+            .finish()
+    }
+}
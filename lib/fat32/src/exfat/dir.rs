@@ -0,0 +1,338 @@
+use alloc::string::String;
+use alloc::vec::{IntoIter, Vec};
+
+use shim::const_assert_size;
+use shim::ffi::OsStr;
+use shim::io;
+
+use crate::exfat::{Entry, ExFatHandle, File};
+use crate::traits;
+use crate::util::VecExt;
+use crate::vfat::{Attributes, Date, Metadata, Time, Timestamp};
+
+pub(crate) const ENTRY_END: u8 = 0x00;
+pub(crate) const ENTRY_BITMAP: u8 = 0x81;
+pub(crate) const ENTRY_UPCASE: u8 = 0x82;
+pub(crate) const ENTRY_FILE: u8 = 0x85;
+pub(crate) const ENTRY_STREAM: u8 = 0xC0;
+pub(crate) const ENTRY_NAME: u8 = 0xC1;
+
+/// `general_secondary_flags` bit marking data whose clusters are contiguous
+/// and not recorded in the FAT.
+const NO_FAT_CHAIN: u8 = 0x02;
+
+const NAME_CHARS_PER_ENTRY: usize = 15;
+
+#[derive(Debug)]
+pub struct Dir<HANDLE: ExFatHandle> {
+    pub exfat: HANDLE,
+    pub name: String,
+    pub metadata: Metadata,
+    pub first_cluster: u32,
+    /// Whether the directory's clusters are contiguous (`NoFatChain`).
+    pub contiguous: bool,
+    /// The directory's size; `None` for the root directory.
+    pub size: Option<u64>,
+    /// The hash of the up-cased name, from the stream extension entry.
+    pub name_hash: u16,
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct ExFatFileEntry {
+    entry_type: u8,
+    secondary_count: u8,
+    set_checksum: u16,
+    file_attributes: u16,
+    reserved1: u16,
+    create_timestamp: u32,
+    modified_timestamp: u32,
+    accessed_timestamp: u32,
+    create_10ms: u8,
+    modified_10ms: u8,
+    create_utc_offset: u8,
+    modified_utc_offset: u8,
+    accessed_utc_offset: u8,
+    reserved2: [u8; 7],
+}
+
+const_assert_size!(ExFatFileEntry, 32);
+
+fn timestamp(raw: u32, tenths: u8) -> Timestamp {
+    Timestamp {
+        date: Date::from((raw >> 16) as u16),
+        time: Time::from(raw as u16),
+        tenths: tenths,
+    }
+}
+
+impl ExFatFileEntry {
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            attr: Attributes::from(self.file_attributes as u8),
+            ctime: timestamp(self.create_timestamp, self.create_10ms),
+            atime: timestamp(self.accessed_timestamp, 0),
+            mtime: timestamp(self.modified_timestamp, self.modified_10ms),
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct ExFatStreamEntry {
+    entry_type: u8,
+    flags: u8,
+    reserved1: u8,
+    name_length: u8,
+    name_hash: u16,
+    reserved2: u16,
+    valid_data_length: u64,
+    reserved3: u32,
+    first_cluster: u32,
+    data_length: u64,
+}
+
+const_assert_size!(ExFatStreamEntry, 32);
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct ExFatNameEntry {
+    entry_type: u8,
+    flags: u8,
+    name: [u16; NAME_CHARS_PER_ENTRY],
+}
+
+const_assert_size!(ExFatNameEntry, 32);
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct ExFatBitmapEntry {
+    entry_type: u8,
+    flags: u8,
+    reserved: [u8; 18],
+    first_cluster: u32,
+    data_length: u64,
+}
+
+const_assert_size!(ExFatBitmapEntry, 32);
+
+impl ExFatBitmapEntry {
+    pub fn first_cluster(&self) -> u32 {
+        self.first_cluster
+    }
+
+    pub fn data_length(&self) -> u64 {
+        self.data_length
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct ExFatUpcaseEntry {
+    entry_type: u8,
+    reserved1: [u8; 3],
+    checksum: u32,
+    reserved2: [u8; 12],
+    first_cluster: u32,
+    data_length: u64,
+}
+
+const_assert_size!(ExFatUpcaseEntry, 32);
+
+impl ExFatUpcaseEntry {
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    pub fn first_cluster(&self) -> u32 {
+        self.first_cluster
+    }
+
+    pub fn data_length(&self) -> u64 {
+        self.data_length
+    }
+}
+
+pub union RawEntry {
+    raw: [u8; 32],
+    file: ExFatFileEntry,
+    stream: ExFatStreamEntry,
+    name: ExFatNameEntry,
+    bitmap: ExFatBitmapEntry,
+    upcase: ExFatUpcaseEntry,
+}
+
+const_assert_size!(RawEntry, 32);
+
+impl RawEntry {
+    /// Returns the entry type. `0` marks the end of the directory; types
+    /// without the high bit set are unused or deleted entries.
+    pub fn entry_type(&self) -> u8 {
+        unsafe { self.raw[0] }
+    }
+
+    pub fn bitmap(&self) -> ExFatBitmapEntry {
+        unsafe { self.bitmap }
+    }
+
+    pub fn upcase(&self) -> ExFatUpcaseEntry {
+        unsafe { self.upcase }
+    }
+}
+
+/// Returns the checksum of the entry set `set`, which excludes the checksum
+/// field of the primary entry.
+fn set_checksum(set: &[RawEntry]) -> u16 {
+    let mut checksum = 0u16;
+    for (i, entry) in set.iter().enumerate() {
+        for (j, &b) in unsafe { entry.raw }.iter().enumerate() {
+            if i == 0 && (j == 2 || j == 3) {
+                continue;
+            }
+            checksum = checksum.rotate_right(1).wrapping_add(b as u16);
+        }
+    }
+    checksum
+}
+
+impl<HANDLE: ExFatHandle> Dir<HANDLE> {
+    /// Finds the entry named `name` in `self` and returns it. Comparison is
+    /// case-insensitive according to the volume's up-case table.
+    ///
+    /// # Errors
+    ///
+    /// If no entry with name `name` exists in `self`, an error of `NotFound` is
+    /// returned.
+    ///
+    /// If `name` contains invalid UTF-8 characters, an error of `InvalidInput`
+    /// is returned.
+    pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry<HANDLE>> {
+        use traits::Dir;
+        use traits::Entry;
+
+        let name: Vec<u16> = name.as_ref()
+            .to_str()
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "name contains invalid UTF-8 characters"))?
+            .encode_utf16()
+            .collect();
+
+        let upcase = self.exfat.lock(|exfat| exfat.upcase_table().clone());
+        let hash = upcase.name_hash(&name);
+
+        self.entries()?
+            .find(|entry| {
+                entry.name_hash() == hash
+                    && upcase.eq_ignore_case(&entry.name().encode_utf16().collect::<Vec<_>>(), &name)
+            })
+            .ok_or(io::Error::new(io::ErrorKind::NotFound, "name not found"))
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+pub struct DirEntryIter<HANDLE: ExFatHandle> {
+    entries: IntoIter<RawEntry>,
+    exfat: HANDLE,
+}
+
+impl<HANDLE: ExFatHandle> DirEntryIter<HANDLE> {
+    /// Builds an entry from the file entry set `set`. Returns `None` if the
+    /// set is malformed or fails its checksum.
+    fn entry_from_set(&self, set: &[RawEntry]) -> Option<Entry<HANDLE>> {
+        let file = unsafe { set[0].file };
+        if set.len() < 2 || set.len() != file.secondary_count as usize + 1 {
+            return None;
+        }
+        if set_checksum(set) != file.set_checksum {
+            return None;
+        }
+
+        if set[1].entry_type() != ENTRY_STREAM {
+            return None;
+        }
+        let stream = unsafe { set[1].stream };
+
+        let mut name = Vec::new();
+        for entry in &set[2..] {
+            if entry.entry_type() != ENTRY_NAME {
+                return None;
+            }
+            name.extend_from_slice(&unsafe { entry.name.name });
+        }
+        if name.len() < stream.name_length as usize {
+            return None;
+        }
+        let name = String::from_utf16(&name[..stream.name_length as usize]).ok()?;
+
+        let metadata = file.metadata();
+        let contiguous = stream.flags & NO_FAT_CHAIN != 0;
+        Some(if metadata.attr.directory() {
+            Entry::EntryDir(Dir {
+                exfat: self.exfat.clone(),
+                name: name,
+                metadata: metadata,
+                first_cluster: stream.first_cluster,
+                contiguous: contiguous,
+                size: Some(stream.data_length),
+                name_hash: stream.name_hash,
+            })
+        } else {
+            Entry::EntryFile(File {
+                exfat: self.exfat.clone(),
+                name: name,
+                metadata: metadata,
+                first_cluster: stream.first_cluster,
+                contiguous: contiguous,
+                size: stream.data_length,
+                valid_size: stream.valid_data_length,
+                name_hash: stream.name_hash,
+                cursor: 0,
+            })
+        })
+    }
+}
+
+impl<HANDLE: ExFatHandle> Iterator for DirEntryIter<HANDLE> {
+    type Item = Entry<HANDLE>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(entry) = self.entries.next() {
+            match entry.entry_type() {
+                ENTRY_END => return None,
+                ENTRY_FILE => (),
+                // Critical, benign, unused and stray secondary entries.
+                _ => continue,
+            }
+
+            let secondary_count = unsafe { entry.file }.secondary_count as usize;
+            let mut set = vec![entry];
+            set.extend(self.entries.by_ref().take(secondary_count));
+            if let Some(entry) = self.entry_from_set(&set) {
+                return Some(entry);
+            }
+        }
+        None
+    }
+}
+
+impl<HANDLE: ExFatHandle> traits::Dir for Dir<HANDLE> {
+    type Entry = Entry<HANDLE>;
+
+    type Iter = DirEntryIter<HANDLE>;
+
+    fn entries(&self) -> io::Result<Self::Iter> {
+        let mut buf = Vec::new();
+        self.exfat.lock(|exfat| exfat.read_dir(self.first_cluster, self.contiguous, self.size, &mut buf))?;
+
+        Ok(DirEntryIter {
+            entries: unsafe { buf.cast() }.into_iter(),
+            exfat: self.exfat.clone(),
+        })
+    }
+}
@@ -0,0 +1,67 @@
+use crate::exfat::{Dir, ExFatHandle, File};
+use crate::traits;
+use crate::vfat::Metadata;
+
+#[derive(Debug)]
+pub enum Entry<HANDLE: ExFatHandle> {
+    EntryFile(File<HANDLE>),
+    EntryDir(Dir<HANDLE>),
+}
+
+impl<HANDLE: ExFatHandle> Entry<HANDLE> {
+    /// Returns the hash of the up-cased name recorded on disk.
+    pub fn name_hash(&self) -> u16 {
+        match self {
+            &Entry::EntryFile(ref f) => f.name_hash,
+            &Entry::EntryDir(ref d) => d.name_hash,
+        }
+    }
+}
+
+impl<HANDLE: ExFatHandle> traits::Entry for Entry<HANDLE> {
+    type File = File<HANDLE>;
+    type Dir = Dir<HANDLE>;
+    type Metadata = Metadata;
+
+    fn name(&self) -> &str {
+        match self {
+            &Entry::EntryFile(ref f) => &f.name(),
+            &Entry::EntryDir(ref d) => &d.name(),
+        }
+    }
+
+    fn metadata(&self) -> &Self::Metadata {
+        match self {
+            &Entry::EntryFile(ref f) => &f.metadata(),
+            &Entry::EntryDir(ref d) => &d.metadata(),
+        }
+    }
+
+    fn as_file(&self) -> Option<&Self::File> {
+        match self {
+            Entry::EntryFile(ref f) => Some(f),
+            _ => None,
+        }
+    }
+
+    fn as_dir(&self) -> Option<&Self::Dir> {
+        match self {
+            Entry::EntryDir(ref d) => Some(d),
+            _ => None,
+        }
+    }
+
+    fn into_file(self) -> Option<Self::File> {
+        match self {
+            Entry::EntryFile(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    fn into_dir(self) -> Option<Self::Dir> {
+        match self {
+            Entry::EntryDir(d) => Some(d),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use core::cmp;
+use core::fmt::Debug;
+use core::marker::PhantomData;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use shim::io;
+use shim::ioerr;
+use shim::path::{Component, Path};
+
+use crate::exfat::{BootSector, Dir, Entry, File, UpcaseTable};
+use crate::exfat::dir::{RawEntry, ENTRY_BITMAP, ENTRY_END, ENTRY_UPCASE};
+use crate::partition;
+use crate::traits::{BlockDevice, FileSystem};
+use crate::util::VecExt;
+use crate::vfat::{CachedPartition, Error, Metadata, Partition};
+
+/// A generic trait that handles a critical section as a closure
+pub trait ExFatHandle: Clone + Debug + Send + Sync {
+    fn new(val: ExFat<Self>) -> Self;
+    fn lock<R>(&self, f: impl FnOnce(&mut ExFat<Self>) -> R) -> R;
+}
+
+/// The file system of an exFAT volume. `traits::FileSystem` is implemented
+/// for `&Volume` rather than for the handle itself, which would overlap with
+/// the implementation for `VFatHandle`s.
+#[derive(Debug, Clone)]
+pub struct Volume<HANDLE: ExFatHandle>(pub HANDLE);
+
+#[derive(Debug)]
+pub struct ExFat<HANDLE: ExFatHandle> {
+    phantom: PhantomData<HANDLE>,
+    device: CachedPartition,
+    bytes_per_sector: u64,
+    sectors_per_cluster: u64,
+    fat_start_sector: u64,
+    cluster_heap_start: u64,
+    cluster_count: u32,
+    root_cluster: u32,
+    bitmap: Vec<u8>,
+    upcase: UpcaseTable,
+}
+
+const FAT_BAD: u32 = 0xFFFFFFF7;
+const FAT_EOC: u32 = 0xFFFFFFFF;
+
+impl<HANDLE: ExFatHandle> ExFat<HANDLE> {
+    /// Mounts the first exFAT partition of `device`. The boot region checksum
+    /// is verified and the allocation bitmap and up-case table are loaded
+    /// from the root directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if no partition holds an exFAT volume, `BadChecksum`
+    /// if the boot region or up-case table is corrupt, and `Io` for device
+    /// errors or a malformed root directory.
+    pub fn from<T>(mut device: T) -> Result<Volume<HANDLE>, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        let partitions = partition::read_partitions(&mut device)?;
+
+        let mut found = None;
+        for part in partitions.iter().filter(|part| part.may_be_exfat()) {
+            match BootSector::from(&mut device, part.start) {
+                Ok(boot) => {
+                    found = Some((part.start, boot));
+                    break;
+                }
+                Err(Error::BadSignature) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        let (start, boot) = found.ok_or(Error::NotFound)?;
+
+        let mut device = CachedPartition::new(device, Partition {
+            start: start,
+            num_sectors: boot.volume_length,
+            sector_size: boot.bytes_per_sector(),
+        });
+
+        let bytes_per_sector = boot.bytes_per_sector() as usize;
+        let mut region = vec![0u8; 12 * bytes_per_sector];
+        device.read_sectors(0, 12, &mut region)?;
+        boot.verify_checksum(&region)?;
+
+        let mut exfat = ExFat {
+            phantom: PhantomData,
+            device: device,
+            bytes_per_sector: boot.bytes_per_sector(),
+            sectors_per_cluster: boot.sectors_per_cluster(),
+            fat_start_sector: boot.fat_offset as u64,
+            cluster_heap_start: boot.cluster_heap_offset as u64,
+            cluster_count: boot.cluster_count,
+            root_cluster: boot.root_cluster,
+            bitmap: Vec::new(),
+            upcase: UpcaseTable::identity(),
+        };
+        exfat.load_metadata()?;
+
+        Ok(Volume(HANDLE::new(exfat)))
+    }
+
+    /// Loads the allocation bitmap and up-case table named by the critical
+    /// entries of the root directory.
+    fn load_metadata(&mut self) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        self.read_dir(self.root_cluster, false, None, &mut buf)?;
+        let entries: Vec<RawEntry> = unsafe { buf.cast() };
+
+        let (mut bitmap, mut upcase) = (None, None);
+        for entry in entries.iter() {
+            match entry.entry_type() {
+                ENTRY_END => break,
+                // With two FATs there are two bitmaps; the first belongs to
+                // the first FAT.
+                ENTRY_BITMAP if bitmap.is_none() => bitmap = Some(entry.bitmap()),
+                ENTRY_UPCASE if upcase.is_none() => upcase = Some(entry.upcase()),
+                _ => (),
+            }
+        }
+
+        let bitmap = bitmap.ok_or(Error::NotFound)?;
+        let mut data = vec![0; bitmap.data_length() as usize];
+        self.read_data(bitmap.first_cluster(), false, 0, &mut data)?;
+        self.bitmap = data;
+
+        let upcase = upcase.ok_or(Error::NotFound)?;
+        let mut data = vec![0; upcase.data_length() as usize];
+        self.read_data(upcase.first_cluster(), false, 0, &mut data)?;
+        self.upcase = UpcaseTable::from(&data, upcase.checksum())?;
+        Ok(())
+    }
+
+    fn cluster_size(&self) -> u64 {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster - 2 < self.cluster_count
+    }
+
+    /// Returns the FAT entry for `cluster`.
+    fn fat_entry(&mut self, cluster: u32) -> io::Result<u32> {
+        let offset = cluster as u64 * 4;
+        let sector = self.device.get(self.fat_start_sector + offset / self.bytes_per_sector)?;
+        let i = (offset % self.bytes_per_sector) as usize;
+        Ok(sector[i] as u32 | (sector[i + 1] as u32) << 8 | (sector[i + 2] as u32) << 16 | (sector[i + 3] as u32) << 24)
+    }
+
+    /// Returns the cluster that follows `cluster` in its data. Data marked
+    /// `NoFatChain` is `contiguous` and does not consult the FAT.
+    fn next_cluster(&mut self, cluster: u32, contiguous: bool) -> io::Result<u32> {
+        let next = if contiguous { cluster + 1 } else { self.fat_entry(cluster)? };
+        match next {
+            FAT_EOC => ioerr!(UnexpectedEof, "end of cluster chain"),
+            FAT_BAD => ioerr!(InvalidData, "bad cluster in chain"),
+            next if self.is_valid_cluster(next) => Ok(next),
+            _ => ioerr!(InvalidData, "invalid cluster in chain"),
+        }
+    }
+
+    fn read_cluster(&mut self, cluster: u32, buf: &mut [u8]) -> io::Result<usize> {
+        let sector = self.cluster_heap_start + (cluster - 2) as u64 * self.sectors_per_cluster;
+        self.device.read_sectors(sector, self.sectors_per_cluster, buf)
+    }
+
+    /// Reads `buf.len()` bytes at byte `offset` of the data that begins at
+    /// `first_cluster`. The caller is responsible for staying within the
+    /// data's length.
+    pub(crate) fn read_data(&mut self, first_cluster: u32, contiguous: bool, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.is_valid_cluster(first_cluster) {
+            return ioerr!(InvalidData, "invalid first cluster");
+        }
+
+        let cluster_size = self.cluster_size();
+        let mut cluster = first_cluster;
+        if contiguous {
+            cluster += (offset / cluster_size) as u32;
+            if !self.is_valid_cluster(cluster) {
+                return ioerr!(InvalidData, "data extends past the cluster heap");
+            }
+        } else {
+            for _ in 0..offset / cluster_size {
+                cluster = self.next_cluster(cluster, false)?;
+            }
+        }
+
+        let mut scratch = Vec::new();
+        let mut pos = (offset % cluster_size) as usize;
+        let mut read = 0;
+        loop {
+            let len = cmp::min(cluster_size as usize - pos, buf.len() - read);
+            if len == cluster_size as usize {
+                self.read_cluster(cluster, &mut buf[read..read + len])?;
+            } else {
+                scratch.resize(cluster_size as usize, 0);
+                self.read_cluster(cluster, &mut scratch)?;
+                buf[read..read + len].copy_from_slice(&scratch[pos..pos + len]);
+            }
+
+            read += len;
+            if read == buf.len() {
+                return Ok(read);
+            }
+            pos = 0;
+            cluster = self.next_cluster(cluster, contiguous)?;
+        }
+    }
+
+    /// Appends the contents of the directory that begins at `first_cluster`
+    /// to `buf`. The root directory has no stream extension and so no known
+    /// `size`; it is read until the end of its chain.
+    pub(crate) fn read_dir(&mut self, first_cluster: u32, contiguous: bool, size: Option<u64>, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let start = buf.len();
+        if let Some(size) = size {
+            buf.resize(start + size as usize, 0);
+            return self.read_data(first_cluster, contiguous, 0, &mut buf[start..]);
+        }
+
+        if !self.is_valid_cluster(first_cluster) {
+            return ioerr!(InvalidData, "invalid first cluster");
+        }
+
+        let cluster_size = self.cluster_size() as usize;
+        let mut cluster = first_cluster;
+        loop {
+            let end = buf.len();
+            buf.resize(end + cluster_size, 0);
+            self.read_cluster(cluster, &mut buf[end..])?;
+            match self.next_cluster(cluster, contiguous) {
+                Ok(next) => cluster = next,
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(buf.len() - start),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub(crate) fn upcase_table(&self) -> &UpcaseTable {
+        &self.upcase
+    }
+
+    /// Returns `true` if `cluster` is marked as allocated in the allocation
+    /// bitmap.
+    pub fn is_allocated(&self, cluster: u32) -> bool {
+        if !self.is_valid_cluster(cluster) {
+            return false;
+        }
+        let index = (cluster - 2) as usize;
+        self.bitmap.get(index / 8).map_or(false, |byte| byte & (1 << (index % 8)) != 0)
+    }
+
+    /// Returns the number of clusters the allocation bitmap marks as free.
+    pub fn free_clusters(&self) -> u32 {
+        (2..self.cluster_count + 2).filter(|&cluster| !self.is_allocated(cluster)).count() as u32
+    }
+
+    /// Returns the number of clusters in the cluster heap.
+    pub fn cluster_count(&self) -> u32 {
+        self.cluster_count
+    }
+}
+
+impl<'a, HANDLE: ExFatHandle> FileSystem for &'a Volume<HANDLE> {
+    type File = File<HANDLE>;
+    type Dir = Dir<HANDLE>;
+    type Entry = Entry<HANDLE>;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        use crate::traits::Entry as EntryTrait;
+
+        let path = crate::path::normalize(path)?;
+        let mut entry = Entry::EntryDir(Dir {
+            exfat: self.0.clone(),
+            name: String::from("/"),
+            metadata: Metadata::default(),
+            first_cluster: self.0.lock(|exfat| exfat.root_cluster),
+            contiguous: false,
+            size: None,
+            name_hash: 0,
+        });
+        for component in path.components() {
+            if let Component::Normal(name) = component {
+                entry = entry.as_dir()
+                    .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "not a directory"))?
+                    .find(name)?;
+            }
+        }
+        Ok(entry)
+    }
+}
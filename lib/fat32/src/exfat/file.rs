@@ -0,0 +1,99 @@
+use alloc::string::String;
+
+use shim::io::{self, SeekFrom};
+use shim::ioerr;
+
+use crate::exfat::ExFatHandle;
+use crate::traits;
+use crate::vfat::Metadata;
+
+#[derive(Debug)]
+pub struct File<HANDLE: ExFatHandle> {
+    pub exfat: HANDLE,
+    pub name: String,
+    pub metadata: Metadata,
+    pub first_cluster: u32,
+    /// Whether the file's clusters are contiguous (`NoFatChain`).
+    pub contiguous: bool,
+    pub size: u64,
+    /// Bytes past `valid_size` have never been written and read as zero.
+    pub valid_size: u64,
+    /// The hash of the up-cased name, from the stream extension entry.
+    pub name_hash: u16,
+    pub cursor: u64,
+}
+
+impl<HANDLE: ExFatHandle> File<HANDLE> {
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<HANDLE: ExFatHandle> io::Write for File<HANDLE> {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        ioerr!(PermissionDenied, "exFAT volumes are read-only")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<HANDLE: ExFatHandle> io::Read for File<HANDLE> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = ::core::cmp::min(buf.len() as u64, self.size.saturating_sub(self.cursor)) as usize;
+        let valid = ::core::cmp::min(len as u64, self.valid_size.saturating_sub(self.cursor)) as usize;
+
+        let (first_cluster, contiguous, cursor) = (self.first_cluster, self.contiguous, self.cursor);
+        self.exfat.lock(|exfat| exfat.read_data(first_cluster, contiguous, cursor, &mut buf[..valid]))?;
+        for byte in buf[valid..len].iter_mut() {
+            *byte = 0;
+        }
+
+        self.cursor += len as u64;
+        Ok(len)
+    }
+}
+
+impl<HANDLE: ExFatHandle> io::Seek for File<HANDLE> {
+    /// Seek to offset `pos` in the file.
+    ///
+    /// A seek to the end of the file is allowed. A seek _beyond_ the end of the
+    /// file returns an `InvalidInput` error.
+    ///
+    /// If the seek operation completes successfully, this method returns the
+    /// new position from the start of the stream. That position can be used
+    /// later with SeekFrom::Start.
+    ///
+    /// # Errors
+    ///
+    /// Seeking before the start of a file or beyond the end of the file results
+    /// in an `InvalidInput` error.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let cursor = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.size as i64 + offset,
+            SeekFrom::Current(offset) => self.cursor as i64 + offset,
+        };
+
+        if cursor < 0 || cursor as u64 > self.size {
+            return ioerr!(InvalidInput, "seek out of bounds");
+        }
+        self.cursor = cursor as u64;
+        Ok(self.cursor)
+    }
+}
+
+impl<HANDLE: ExFatHandle> traits::File for File<HANDLE> {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+}
@@ -0,0 +1,66 @@
+use alloc::vec::Vec;
+
+use crate::vfat::Error;
+
+/// The up-case table of an exFAT volume. File names are compared, and their
+/// hashes computed, after mapping every character through this table.
+#[derive(Debug, Clone, Default)]
+pub struct UpcaseTable(Vec<u16>);
+
+impl UpcaseTable {
+    /// Returns a table that maps every character to itself.
+    pub fn identity() -> UpcaseTable {
+        UpcaseTable(Vec::new())
+    }
+
+    /// Decompresses the on-disk table `data`. A `0xFFFF` entry is followed by
+    /// the number of characters that map to themselves.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BadChecksum` if `data` does not match `checksum`.
+    pub fn from(data: &[u8], checksum: u32) -> Result<UpcaseTable, Error> {
+        let actual = data.iter().fold(0u32, |sum, &b| sum.rotate_right(1).wrapping_add(b as u32));
+        if actual != checksum {
+            return Err(Error::BadChecksum);
+        }
+
+        let mut table = Vec::new();
+        let mut entries = data.chunks(2).map(|c| c[0] as u16 | (c.get(1).cloned().unwrap_or(0) as u16) << 8);
+        while let Some(entry) = entries.next() {
+            if entry == 0xFFFF {
+                let run = entries.next().unwrap_or(0) as usize;
+                let start = table.len();
+                table.extend((start..start + run).map(|c| c as u16));
+            } else {
+                table.push(entry);
+            }
+        }
+
+        Ok(UpcaseTable(table))
+    }
+
+    /// Returns the up-case form of `c`. Characters beyond the table map to
+    /// themselves.
+    pub fn upcase(&self, c: u16) -> u16 {
+        self.0.get(c as usize).cloned().unwrap_or(c)
+    }
+
+    /// Returns `true` if `a` and `b` are equal once up-cased.
+    pub fn eq_ignore_case(&self, a: &[u16], b: &[u16]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(&x, &y)| self.upcase(x) == self.upcase(y))
+    }
+
+    /// Returns the hash of the up-cased `name`, as stored in stream extension
+    /// entries.
+    pub fn name_hash(&self, name: &[u16]) -> u16 {
+        let mut hash = 0u16;
+        for &c in name {
+            let c = self.upcase(c);
+            for &b in [c as u8, (c >> 8) as u8].iter() {
+                hash = hash.rotate_right(1).wrapping_add(b as u16);
+            }
+        }
+        hash
+    }
+}
@@ -0,0 +1,277 @@
+use core::cmp;
+
+use shim::io;
+use shim::ioerr;
+
+use crate::traits::BlockDevice;
+use crate::vfat::{Attributes, BiosParameterBlock, Cluster, FatType, FsInfo, VFatRegularDirEntry};
+
+/// The first cluster number that is reserved in FAT32 entries.
+const MAX_CLUSTERS: u64 = 0x0FFFFFF5;
+
+/// The number of entries in the fixed root directory of FAT12/16 volumes.
+const ROOT_DIR_ENTRIES: u16 = 512;
+
+/// Partitions written with an MBR start on a 1 MiB boundary.
+const PARTITION_ALIGNMENT: u64 = 2048;
+
+/// Sectors zeroed per write while clearing the FATs.
+const ZERO_CHUNK_SECTORS: u64 = 64;
+
+/// Options for `format()`.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The number of sectors of the device to use.
+    pub sectors: u64,
+    /// Write an MBR with a single partition covering the device rather than
+    /// placing the volume at sector 0.
+    pub mbr: bool,
+    /// Sectors per cluster; a power of two. Chosen from the volume size when
+    /// `None`.
+    pub sectors_per_cluster: Option<u8>,
+    /// The volume label, padded with spaces.
+    pub label: [u8; 11],
+    pub volume_id: u32,
+}
+
+impl Options {
+    /// Returns the default options for a device of `sectors` sectors.
+    pub fn new(sectors: u64) -> Options {
+        Options {
+            sectors: sectors,
+            mbr: false,
+            sectors_per_cluster: None,
+            label: *b"NO NAME    ",
+            volume_id: 0,
+        }
+    }
+
+    /// Sets the volume label to `label`, truncated to 11 characters and
+    /// upper-cased.
+    pub fn label(mut self, label: &str) -> Options {
+        self.label = [b' '; 11];
+        for (i, b) in label.bytes().take(11).enumerate() {
+            self.label[i] = b.to_ascii_uppercase();
+        }
+        self
+    }
+}
+
+/// The layout of a volume created by `format()`. Sector numbers other than
+/// `start` are relative to the start of the volume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// The device sector holding the volume's boot sector.
+    pub start: u64,
+    /// The number of sectors in the volume.
+    pub sectors: u64,
+    /// The FAT type, which follows from the number of clusters.
+    pub fat_type: FatType,
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub fats: u8,
+    pub sectors_per_fat: u32,
+    /// The number of entries in the fixed root directory; `0` on FAT32.
+    pub root_dir_entries: u16,
+    /// The FSInfo sector; `0` on FAT12/16, which have none.
+    pub fsinfo_sector: u16,
+    /// The backup boot sector; `0` on FAT12/16, which have none.
+    pub backup_boot_sector: u16,
+    /// The number of data clusters.
+    pub clusters: u32,
+}
+
+impl Layout {
+    /// Returns the first sector of the fixed root directory on FAT12/16, and
+    /// of the data region on FAT32.
+    fn root_dir_start_sector(&self) -> u64 {
+        self.reserved_sectors as u64 + self.fats as u64 * self.sectors_per_fat as u64
+    }
+
+    fn data_start_sector(&self) -> u64 {
+        let bytes_per_sector = self.bytes_per_sector as u64;
+        let root_dir_sectors = (self.root_dir_entries as u64 * 32 + bytes_per_sector - 1) / bytes_per_sector;
+        self.root_dir_start_sector() + root_dir_sectors
+    }
+}
+
+/// Returns the default cluster size, in sectors of 512 bytes, for a volume
+/// of `sectors` such sectors, following the table Microsoft's tools use.
+fn default_sectors_per_cluster(sectors: u64) -> u8 {
+    match sectors {
+        0..=532_480 => 1,
+        532_481..=16_777_216 => 8,
+        16_777_217..=33_554_432 => 16,
+        33_554_433..=67_108_864 => 32,
+        _ => 64,
+    }
+}
+
+/// Computes the layout of a volume of `sectors` sectors at `start`: FAT32 if
+/// it has enough clusters, and otherwise FAT16 or, smaller still, FAT12.
+fn layout(start: u64, sectors: u64, bytes_per_sector: u16, sectors_per_cluster: u8) -> io::Result<Layout> {
+    if sectors_per_cluster == 0 || !sectors_per_cluster.is_power_of_two() {
+        return ioerr!(InvalidInput, "sectors per cluster must be a power of two");
+    }
+    if sectors_per_cluster as u64 * bytes_per_sector as u64 > 32768 {
+        return ioerr!(InvalidInput, "clusters larger than 32 KiB");
+    }
+    if start + sectors > u32::max_value() as u64 {
+        return ioerr!(InvalidInput, "volume too large for FAT32");
+    }
+
+    // Each type's FATs take a different share of the volume, so the cluster
+    // count decides between them only once the layout is computed.
+    for &fat_type in [FatType::Fat32, FatType::Fat16, FatType::Fat12].iter() {
+        let layout = layout_as(fat_type, start, sectors, bytes_per_sector, sectors_per_cluster);
+        if layout.clusters == 0 || FatType::from_cluster_count(layout.clusters) != fat_type {
+            continue;
+        }
+        if layout.clusters as u64 + 2 > MAX_CLUSTERS {
+            return ioerr!(InvalidInput, "too many clusters for FAT32; use larger clusters");
+        }
+        return Ok(layout);
+    }
+    ioerr!(InvalidInput, "volume too small, or no FAT type fits it with this cluster size")
+}
+
+/// Computes the layout of a volume of `fat_type`, whatever its number of
+/// clusters.
+fn layout_as(fat_type: FatType, start: u64, sectors: u64, bytes_per_sector: u16, sectors_per_cluster: u8) -> Layout {
+    let fat32 = fat_type == FatType::Fat32;
+    let mut layout = Layout {
+        start: start,
+        sectors: sectors,
+        fat_type: fat_type,
+        bytes_per_sector: bytes_per_sector,
+        sectors_per_cluster: sectors_per_cluster,
+        reserved_sectors: if fat32 { 32 } else { 1 },
+        fats: 2,
+        sectors_per_fat: 1,
+        root_dir_entries: if fat32 { 0 } else { ROOT_DIR_ENTRIES },
+        fsinfo_sector: if fat32 { 1 } else { 0 },
+        backup_boot_sector: if fat32 { 6 } else { 0 },
+        clusters: 0,
+    };
+
+    // Growing the FATs shrinks the data region, so iterate to a fixed point.
+    loop {
+        let data_sectors = sectors.saturating_sub(layout.data_start_sector());
+        let clusters = data_sectors / sectors_per_cluster as u64;
+        let fat_bytes = ((clusters + 2) * fat_type.entry_bits() as u64 + 7) / 8;
+        let needed = (fat_bytes + bytes_per_sector as u64 - 1) / bytes_per_sector as u64;
+        if needed <= layout.sectors_per_fat as u64 {
+            layout.clusters = clusters as u32;
+            return layout;
+        }
+        layout.sectors_per_fat = needed as u32;
+    }
+}
+
+/// Writes zeroes to `count` sectors of `device` starting at `start`.
+fn zero_sectors<T: BlockDevice>(device: &mut T, start: u64, count: u64) -> io::Result<()> {
+    let sector_size = device.sector_size() as usize;
+    let zeroes = vec![0u8; ZERO_CHUNK_SECTORS as usize * sector_size];
+
+    let mut done = 0;
+    while done < count {
+        let n = cmp::min(ZERO_CHUNK_SECTORS, count - done);
+        device.write_sectors(start + done, n, &zeroes[..n as usize * sector_size])?;
+        done += n;
+    }
+    Ok(())
+}
+
+fn put32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Creates an empty FAT volume on `device` as described by `options` and
+/// returns its layout. The volume is FAT32 if it has at least 65525
+/// clusters, and FAT16 or FAT12 otherwise.
+///
+/// On FAT32, the boot sector is written with a backup copy, as is the FSInfo
+/// sector, and the root directory is given cluster 2; on FAT12/16, the root
+/// directory is the region that follows the FATs. Both FATs are cleared.
+/// With `options.mbr`, sector 0 receives an MBR whose only partition, of a
+/// type matching the FAT type, holds the volume.
+///
+/// # Errors
+///
+/// Returns `InvalidInput` if the device's sector size is not 512, clusters
+/// would exceed 32 KiB, the volume is too small for any FAT type, or it
+/// would have too many clusters for FAT32. I/O errors from `device` are
+/// returned as is.
+pub fn format<T: BlockDevice>(mut device: T, options: &Options) -> io::Result<Layout> {
+    let sector_size = device.sector_size();
+    if sector_size != 512 {
+        return ioerr!(InvalidInput, "only 512-byte sectors are supported");
+    }
+
+    let start = if options.mbr { PARTITION_ALIGNMENT } else { 0 };
+    let sectors = options.sectors.saturating_sub(start);
+    let sectors_per_cluster = options.sectors_per_cluster.unwrap_or_else(|| default_sectors_per_cluster(sectors));
+    let layout = layout(start, sectors, sector_size as u16, sectors_per_cluster)?;
+
+    // Reserved region, FATs and the root directory, a region on FAT12/16
+    // and a cluster on FAT32.
+    let fat32 = layout.fat_type == FatType::Fat32;
+    let root_start = start + layout.root_dir_start_sector();
+    zero_sectors(&mut device, start, layout.data_start_sector())?;
+    if fat32 {
+        zero_sectors(&mut device, root_start, sectors_per_cluster as u64)?;
+    }
+
+    let ebpb = BiosParameterBlock::new(&layout, options.volume_id, options.label);
+    device.write_sector(start, ebpb.as_bytes())?;
+    if fat32 {
+        // Only the root directory's cluster is in use.
+        let fsinfo = FsInfo::new(Some(layout.clusters - 1), 3);
+        device.write_sector(start + layout.backup_boot_sector as u64, ebpb.as_bytes())?;
+        for &boot in [0, layout.backup_boot_sector as u64].iter() {
+            device.write_sector(start + boot + layout.fsinfo_sector as u64, fsinfo.as_bytes())?;
+        }
+    }
+
+    // Entries 0 and 1 are reserved; on FAT32, entry 2 ends the root
+    // directory's chain.
+    let mut fat = [0u8; 512];
+    match layout.fat_type {
+        FatType::Fat12 => fat[..3].copy_from_slice(&[0xF8, 0xFF, 0xFF]),
+        FatType::Fat16 => fat[..4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]),
+        FatType::Fat32 => {
+            put32(&mut fat, 0, 0x0FFFFFF8);
+            put32(&mut fat, 4, 0x0FFFFFFF);
+            put32(&mut fat, 8, 0x0FFFFFFF);
+        }
+    }
+    for copy in 0..layout.fats as u64 {
+        device.write_sector(start + layout.reserved_sectors as u64 + copy * layout.sectors_per_fat as u64, &fat)?;
+    }
+
+    if options.label != *b"NO NAME    " {
+        let entry = VFatRegularDirEntry::new(options.label, Attributes::from(0x08), Cluster::from(0), 0);
+        let mut root = [0u8; 512];
+        root[..32].copy_from_slice(entry.as_bytes());
+        device.write_sector(root_start, &root)?;
+    }
+
+    if options.mbr {
+        let mut mbr = [0u8; 512];
+        let entry = 446;
+        mbr[entry + 1..entry + 4].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+        mbr[entry + 4] = match layout.fat_type {
+            FatType::Fat12 => 0x01,
+            FatType::Fat16 => 0x06,
+            FatType::Fat32 => 0x0C,
+        };
+        mbr[entry + 5..entry + 8].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+        put32(&mut mbr, entry + 8, start as u32);
+        put32(&mut mbr, entry + 12, sectors as u32);
+        mbr[510..512].copy_from_slice(&[0x55, 0xAA]);
+        device.write_sector(0, &mbr)?;
+    }
+
+    Ok(layout)
+}
@@ -0,0 +1,279 @@
+use core::{fmt, mem};
+use core::str::FromStr;
+
+use alloc::vec::Vec;
+
+use shim::const_assert_size;
+use shim::io;
+
+use crate::traits::BlockDevice;
+
+/// A globally unique identifier as stored on disk: the first three fields
+/// are little-endian, the last two big-endian.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub struct Guid(pub [u8; 16]);
+
+const_assert_size!(Guid, 16);
+
+impl Guid {
+    /// The partition type of Microsoft basic data partitions, which hold FAT,
+    /// exFAT and NTFS volumes.
+    pub const BASIC_DATA: Guid = Guid([
+        0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44,
+        0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7,
+    ]);
+
+    /// The partition type of EFI system partitions, which hold FAT volumes.
+    pub const EFI_SYSTEM: Guid = Guid([
+        0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11,
+        0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B,
+    ]);
+
+    pub fn is_nil(&self) -> bool {
+        self.0.iter().all(|&b| b == 0)
+    }
+}
+
+/// The largest partition entry array a header may describe, in bytes, which
+/// bounds the memory `GptHeader::entries()` allocates.
+const MAX_ENTRIES_LEN: u64 = 1 << 20;
+
+/// The order in which the bytes of a GUID appear in its textual form.
+const TEXT_ORDER: [usize; 16] = [3, 2, 1, 0, 5, 4, 7, 6, 8, 9, 10, 11, 12, 13, 14, 15];
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, &byte) in TEXT_ORDER.iter().enumerate() {
+            if i == 4 || i == 6 || i == 8 || i == 10 {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02X}", self.0[byte])?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Guid({})", self)
+    }
+}
+
+impl FromStr for Guid {
+    type Err = ();
+
+    /// Parses a GUID of the form `EBD0A0A2-B9E5-4433-87C0-68B6B72699C7`,
+    /// ignoring case.
+    fn from_str(s: &str) -> Result<Guid, ()> {
+        let s = s.as_bytes();
+        if s.len() != 36 || [8, 13, 18, 23].iter().any(|&i| s[i] != b'-') {
+            return Err(());
+        }
+
+        let mut digits = s.iter().filter(|&&c| c != b'-');
+        let mut guid = Guid::default();
+        for &byte in TEXT_ORDER.iter() {
+            let mut value = 0;
+            for _ in 0..2 {
+                let digit = (*digits.next().ok_or(())? as char).to_digit(16).ok_or(())?;
+                value = value << 4 | digit as u8;
+            }
+            guid.0[byte] = value;
+        }
+        Ok(guid)
+    }
+}
+
+/// The header of a GUID partition table (GPT).
+#[repr(C, packed)]
+pub struct GptHeader {
+    signature: [u8; 8],
+    revision: u32,
+    header_size: u32,
+    header_crc32: u32,
+    reserved: u32,
+    pub current_lba: u64,
+    pub backup_lba: u64,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub disk_guid: Guid,
+    pub entries_lba: u64,
+    pub num_entries: u32,
+    pub entry_size: u32,
+    entries_crc32: u32,
+    padding: [u8; 420],
+}
+
+const_assert_size!(GptHeader, 512);
+
+impl fmt::Debug for GptHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GptHeader")
+            .field("revision", &{ self.revision })
+            .field("current_lba", &{ self.current_lba })
+            .field("backup_lba", &{ self.backup_lba })
+            .field("disk_guid", &{ self.disk_guid })
+            .field("entries_lba", &{ self.entries_lba })
+            .field("num_entries", &{ self.num_entries })
+            .field("entry_size", &{ self.entry_size })
+            .finish()
+    }
+}
+
+/// An entry of the GPT partition entry array.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct GptEntry {
+    pub type_guid: Guid,
+    pub guid: Guid,
+    pub first_lba: u64,
+    pub last_lba: u64,
+    pub attributes: u64,
+    name: [u16; 36],
+}
+
+const_assert_size!(GptEntry, 128);
+
+impl GptEntry {
+    /// Returns `true` if the entry does not describe a partition.
+    pub fn is_empty(&self) -> bool {
+        self.type_guid.is_nil()
+    }
+
+    pub fn num_sectors(&self) -> u64 {
+        (self.last_lba + 1).saturating_sub(self.first_lba)
+    }
+}
+
+impl fmt::Debug for GptEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GptEntry")
+            .field("type_guid", &{ self.type_guid })
+            .field("guid", &{ self.guid })
+            .field("first_lba", &{ self.first_lba })
+            .field("last_lba", &{ self.last_lba })
+            .field("attributes", &{ self.attributes })
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// There was an I/O error while reading the partition table.
+    Io(io::Error),
+    /// The header's magic signature was invalid.
+    BadSignature,
+    /// The header's size, entry size or number of entries is out of range.
+    BadHeader,
+    /// The header failed its CRC32 check.
+    BadHeaderChecksum,
+    /// The partition entry array failed its CRC32 check.
+    BadEntriesChecksum,
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+/// Returns the CRC32 (IEEE 802.3) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+impl GptHeader {
+    /// Reads and validates the GPT header at sector `lba` of `device`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BadSignature` if the signature is not `EFI PART`, `BadHeader`
+    /// if the header size or entry size is out of range or the entry array
+    /// is larger than `MAX_ENTRIES_LEN`,
+    /// and `BadHeaderChecksum` if the header fails its CRC32 check.
+    pub fn from<T: BlockDevice>(mut device: T, lba: u64) -> Result<GptHeader, Error> {
+        let mut buf = [0u8; 512];
+        device.read_sector(lba, &mut buf)?;
+        let header = unsafe { mem::transmute::<[u8; 512], GptHeader>(buf) };
+
+        if &header.signature != b"EFI PART" {
+            return Err(Error::BadSignature);
+        }
+
+        let header_size = header.header_size as usize;
+        let (entry_size, num_entries) = (header.entry_size, header.num_entries);
+        if header_size < 92 || header_size > buf.len()
+            || (entry_size as usize) < mem::size_of::<GptEntry>()
+            || entry_size as u64 * num_entries as u64 > MAX_ENTRIES_LEN
+        {
+            return Err(Error::BadHeader);
+        }
+
+        // The checksum covers the header with its checksum field zeroed.
+        buf[16..20].copy_from_slice(&[0; 4]);
+        if crc32(&buf[..header_size]) != header.header_crc32 {
+            return Err(Error::BadHeaderChecksum);
+        }
+
+        Ok(header)
+    }
+
+    /// Reads the partition entry array described by `self`, including empty
+    /// entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BadEntriesChecksum` if the array fails its CRC32 check.
+    pub fn entries<T: BlockDevice>(&self, mut device: T) -> Result<Vec<GptEntry>, Error> {
+        let len = self.num_entries as usize * self.entry_size as usize;
+        let sector_size = device.sector_size() as usize;
+        let mut buf = vec![0u8; (len + sector_size - 1) / sector_size * sector_size];
+        device.read_sectors(self.entries_lba, (buf.len() / sector_size) as u64, &mut buf)?;
+
+        if crc32(&buf[..len]) != self.entries_crc32 {
+            return Err(Error::BadEntriesChecksum);
+        }
+
+        Ok(buf[..len].chunks(self.entry_size as usize)
+            .map(|raw| {
+                let mut entry = [0u8; 128];
+                entry.copy_from_slice(&raw[..128]);
+                unsafe { mem::transmute::<[u8; 128], GptEntry>(entry) }
+            })
+            .collect())
+    }
+}
+
+/// Reads the partition entries of the GUID partition table of `device`.
+/// `last_lba` is the last sector covered by the protective MBR partition,
+/// where the backup header is expected if the primary header is unreadable.
+///
+/// The primary header and array are used if both are valid; otherwise the
+/// backup header and its array are tried.
+///
+/// # Errors
+///
+/// If neither copy of the table is valid, returns the error found in the
+/// primary copy.
+pub fn read_entries<T: BlockDevice>(mut device: T, last_lba: u64) -> Result<Vec<GptEntry>, Error> {
+    let (error, backup_lba) = match GptHeader::from(&mut device, 1) {
+        Ok(header) => match header.entries(&mut device) {
+            Ok(entries) => return Ok(entries),
+            Err(e) => (e, header.backup_lba),
+        },
+        Err(e) => (e, last_lba),
+    };
+
+    match GptHeader::from(&mut device, backup_lba) {
+        Ok(ref header) if header.current_lba == backup_lba => header.entries(&mut device).map_err(|_| error),
+        _ => Err(error),
+    }
+}
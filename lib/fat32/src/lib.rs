@@ -15,6 +15,12 @@ mod mbr;
 mod tests;
 mod util;
 
+pub mod check;
+pub mod exfat;
+pub mod format;
+pub mod gpt;
+pub mod partition;
+pub mod path;
 pub mod traits;
 pub mod vfat;
 
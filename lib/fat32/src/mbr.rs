@@ -36,9 +36,49 @@ pub struct PartitionEntry {
 }
 
 impl PartitionEntry {
+    pub fn partition_type(&self) -> u8 {
+        self.partition_type
+    }
+
+    /// Returns `true` if the slot does not describe a partition.
+    pub fn is_empty(&self) -> bool {
+        self.partition_type == 0 || self.total_sectors == 0
+    }
+
+    /// Returns `true` if the partition is an extended partition, whose first
+    /// sector holds an extended boot record (EBR) chaining logical partitions.
+    pub fn is_extended(&self) -> bool {
+        match self.partition_type {
+            0x05 | 0x0F | 0x85 => true,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the partition is the protective partition of a GUID
+    /// partition table.
+    pub fn is_protective(&self) -> bool {
+        self.partition_type == 0xEE
+    }
+
     pub fn is_fat32(&self) -> bool {
         self.partition_type == 0xB || self.partition_type == 0xC
     }
+
+    /// Returns `true` if the partition type is any of the FAT12, FAT16 or
+    /// FAT32 types. The FAT type itself is decided by the volume, not by the
+    /// partition type.
+    pub fn is_fat(&self) -> bool {
+        match self.partition_type {
+            0x01 | 0x04 | 0x06 | 0x0E => true,
+            _ => self.is_fat32(),
+        }
+    }
+
+    /// Returns `true` if the partition type is the one used by exFAT. NTFS
+    /// shares the type; only the volume's boot sector tells them apart.
+    pub fn is_exfat(&self) -> bool {
+        self.partition_type == 0x07
+    }
 }
 
 impl fmt::Debug for PartitionEntry {
@@ -98,9 +138,19 @@ impl MasterBootRecord {
     /// Returns `UnknownBootIndicator(n)` if partition `n` contains an invalid
     /// boot indicator. Returns `Io(err)` if the I/O error `err` occured while
     /// reading the MBR.
-    pub fn from<T: BlockDevice>(mut device: T) -> Result<MasterBootRecord, Error> {
+    pub fn from<T: BlockDevice>(device: T) -> Result<MasterBootRecord, Error> {
+        MasterBootRecord::read(device, 0)
+    }
+
+    /// Reads and returns the boot record at sector `sector` of `device`.
+    /// Extended boot records (EBRs) share the MBR's layout.
+    ///
+    /// # Errors
+    ///
+    /// As for `from()`.
+    pub fn read<T: BlockDevice>(mut device: T, sector: u64) -> Result<MasterBootRecord, Error> {
         let mut buf = [0u8; 512];
-        device.read_sector(0, &mut buf).map_err(|e| { Error::Io(e) })?;
+        device.read_sector(sector, &mut buf).map_err(|e| { Error::Io(e) })?;
         let mbr = unsafe { mem::transmute::<[u8; 512], MasterBootRecord>(buf) };
 
         if mbr.signature != [0x55, 0xAA] {
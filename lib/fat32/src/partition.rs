@@ -0,0 +1,164 @@
+use core::fmt;
+use core::str::FromStr;
+
+use alloc::vec::Vec;
+
+use crate::gpt::{self, Guid};
+use crate::mbr::MasterBootRecord;
+use crate::traits::BlockDevice;
+use crate::vfat::Error;
+
+/// Longest chain of extended boot records followed, guarding against loops.
+const MAX_LOGICAL_PARTITIONS: usize = 128;
+
+/// What a partition table records about a partition's contents.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// An MBR partition, primary or logical, with its partition type.
+    Mbr(u8),
+    /// A GPT partition with its type and unique GUIDs.
+    Gpt { type_guid: Guid, guid: Guid },
+}
+
+/// A partition found on a device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PartitionInfo {
+    /// The partition's index. GPT partitions are numbered by their slot in
+    /// the entry array. MBR primary partitions are numbered 0 to 3 by their
+    /// slot, and logical partitions from 4 in the order of the EBR chain.
+    pub index: usize,
+    /// The partition's first sector.
+    pub start: u64,
+    pub num_sectors: u64,
+    pub kind: Kind,
+}
+
+impl PartitionInfo {
+    /// Returns the partition's unique GUID, if it is a GPT partition.
+    pub fn guid(&self) -> Option<Guid> {
+        match self.kind {
+            Kind::Gpt { guid, .. } => Some(guid),
+            Kind::Mbr(_) => None,
+        }
+    }
+
+    /// Returns `true` if the partition's type allows a FAT volume.
+    pub fn may_be_fat(&self) -> bool {
+        match self.kind {
+            Kind::Mbr(partition_type) => match partition_type {
+                0x01 | 0x04 | 0x06 | 0x0B | 0x0C | 0x0E => true,
+                _ => false,
+            },
+            Kind::Gpt { type_guid, .. } => type_guid == Guid::BASIC_DATA || type_guid == Guid::EFI_SYSTEM,
+        }
+    }
+
+    /// Returns `true` if the partition's type allows an exFAT volume.
+    pub fn may_be_exfat(&self) -> bool {
+        match self.kind {
+            Kind::Mbr(partition_type) => partition_type == 0x07,
+            Kind::Gpt { type_guid, .. } => type_guid == Guid::BASIC_DATA,
+        }
+    }
+
+    /// Returns `true` if `selector` refers to this partition.
+    pub fn matches(&self, selector: Selector) -> bool {
+        match selector {
+            Selector::Index(index) => self.index == index,
+            Selector::Guid(guid) => self.guid() == Some(guid),
+        }
+    }
+}
+
+/// Identifies a partition by index or, on GPT disks, by unique GUID.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Selector {
+    Index(usize),
+    Guid(Guid),
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::Index(index) => write!(f, "{}", index),
+            Selector::Guid(guid) => write!(f, "{}", guid),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = ();
+
+    /// Parses a decimal partition index or a GUID.
+    fn from_str(s: &str) -> Result<Selector, ()> {
+        match s.parse::<usize>() {
+            Ok(index) => Ok(Selector::Index(index)),
+            Err(_) => s.parse().map(Selector::Guid),
+        }
+    }
+}
+
+/// Reads the partition table of `device` and returns its partitions in index
+/// order. A protective MBR is followed by the GUID partition table it
+/// protects; otherwise the MBR's primary partitions and the logical
+/// partitions of its extended partition are returned.
+///
+/// # Errors
+///
+/// Returns `Mbr` if the MBR is invalid, `Gpt` if neither copy of the GUID
+/// partition table is valid, and `Io` for device errors.
+pub fn read_partitions<T: BlockDevice>(mut device: T) -> Result<Vec<PartitionInfo>, Error> {
+    let mbr = MasterBootRecord::from(&mut device)?;
+
+    if let Some(protective) = mbr.partition_table.iter().find(|part| part.is_protective()) {
+        let last_lba = protective.relative_sector as u64 + protective.total_sectors as u64 - 1;
+        let entries = gpt::read_entries(&mut device, last_lba)?;
+        return Ok(entries.iter()
+            .enumerate()
+            .filter(|(_, entry)| !entry.is_empty())
+            .map(|(index, entry)| PartitionInfo {
+                index: index,
+                start: entry.first_lba,
+                num_sectors: entry.num_sectors(),
+                kind: Kind::Gpt { type_guid: entry.type_guid, guid: entry.guid },
+            })
+            .collect());
+    }
+
+    let mut partitions: Vec<PartitionInfo> = mbr.partition_table.iter()
+        .enumerate()
+        .filter(|(_, part)| !part.is_empty() && !part.is_extended())
+        .map(|(index, part)| PartitionInfo {
+            index: index,
+            start: part.relative_sector as u64,
+            num_sectors: part.total_sectors as u64,
+            kind: Kind::Mbr(part.partition_type()),
+        })
+        .collect();
+
+    if let Some(extended) = mbr.partition_table.iter().find(|part| part.is_extended()) {
+        // Logical partitions start relative to their EBR; links to the next
+        // EBR are relative to the start of the extended partition.
+        let extended_start = extended.relative_sector as u64;
+        let mut ebr_sector = extended_start;
+        for index in 4..4 + MAX_LOGICAL_PARTITIONS {
+            let ebr = MasterBootRecord::read(&mut device, ebr_sector)?;
+            let (logical, next) = (&ebr.partition_table[0], &ebr.partition_table[1]);
+            if !logical.is_empty() {
+                partitions.push(PartitionInfo {
+                    index: index,
+                    start: ebr_sector + logical.relative_sector as u64,
+                    num_sectors: logical.total_sectors as u64,
+                    kind: Kind::Mbr(logical.partition_type()),
+                });
+            }
+
+            if next.is_empty() {
+                break;
+            }
+            ebr_sector = extended_start + next.relative_sector as u64;
+        }
+    }
+
+    Ok(partitions)
+}
@@ -0,0 +1,45 @@
+//! Resolution of paths for `traits::FileSystem` implementations and their
+//! users.
+
+use shim::io;
+use shim::ioerr;
+use shim::path::{Component, Path, PathBuf};
+
+/// Returns the absolute path `path` with its `.` components removed and each
+/// `..` component applied to the directory before it, as in `/a/./b/../c`
+/// becoming `/a/c`. `..` at the root stays at the root.
+///
+/// Resolution is purely lexical: `/file/..` is `/` whether or not `file` is
+/// a directory.
+///
+/// # Errors
+///
+/// Returns `InvalidInput` if `path` is not absolute.
+pub fn normalize<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
+    let path = path.as_ref();
+    if !path.is_absolute() {
+        return ioerr!(InvalidInput, "path must be absolute");
+    }
+
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => (),
+        }
+    }
+    Ok(normalized)
+}
+
+/// Resolves `path` against the working directory `cwd`, which must be
+/// absolute, and normalizes the result. An absolute `path` ignores `cwd`.
+///
+/// # Errors
+///
+/// Returns `InvalidInput` if neither `cwd` nor `path` is absolute.
+pub fn resolve<P: AsRef<Path>, Q: AsRef<Path>>(cwd: P, path: Q) -> io::Result<PathBuf> {
+    normalize(cwd.as_ref().join(path))
+}
@@ -7,6 +7,7 @@ use std::io::Cursor;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
+use crate::exfat;
 use crate::mbr;
 use crate::traits::*;
 use crate::vfat;
@@ -475,3 +476,1141 @@ fn shuffle_test() {
     let hash = hash_files_recursive_from(vfat, "/");
     assert_hash_eq!("mock 1 file hashes", hash, hash_for!("files-1"));
 }
+
+#[test]
+fn test_cursor_multi_sector_io() {
+    let mut data = vec![0u8; 512 * 8];
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = (i / 512) as u8;
+    }
+
+    let mut device = Cursor::new(data);
+    let mut buf = vec![0u8; 512 * 3];
+    assert_eq!(device.read_sectors(2, 3, &mut buf).unwrap(), 512 * 3);
+    for (i, chunk) in buf.chunks(512).enumerate() {
+        assert!(chunk.iter().all(|&b| b == (i + 2) as u8));
+    }
+
+    let new = vec![0xAAu8; 512 * 2];
+    assert_eq!(device.write_sectors(6, 2, &new).unwrap(), 512 * 2);
+    let mut buf = vec![0u8; 512 * 8];
+    assert_eq!(device.read_sectors(0, 8, &mut buf).unwrap(), 512 * 8);
+    assert!(buf[512 * 5..512 * 6].iter().all(|&b| b == 5));
+    assert!(buf[512 * 6..].iter().all(|&b| b == 0xAA));
+}
+
+#[test]
+fn test_cached_partition_multi_sector_io() {
+    use crate::vfat::{CachedPartition, Partition};
+
+    let mut data = vec![0u8; 512 * 16];
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = (i / 512) as u8;
+    }
+
+    // Logical sectors of 1024 bytes starting at physical sector 2.
+    let mut partition = CachedPartition::new(Cursor::new(data), Partition {
+        start: 2,
+        num_sectors: 7,
+        sector_size: 1024,
+    });
+
+    // Dirty a cached sector; multi-sector reads must observe it.
+    partition.write_sector(1, &[0xFFu8; 1024]).unwrap();
+
+    let mut buf = vec![0u8; 1024 * 3];
+    assert_eq!(partition.read_sectors(0, 3, &mut buf).unwrap(), 1024 * 3);
+    assert!(buf[..512].iter().all(|&b| b == 2));
+    assert!(buf[512..1024].iter().all(|&b| b == 3));
+    assert!(buf[1024..2048].iter().all(|&b| b == 0xFF));
+    assert!(buf[2048..2560].iter().all(|&b| b == 6));
+
+    // Only sector 1 was cached; the runs around it went to the device.
+    let stats = partition.stats();
+    assert_eq!((stats.cached, stats.dirty, stats.hits, stats.misses), (1, 1, 1, 3));
+    partition.flush().unwrap();
+    assert_eq!(partition.stats().dirty, 0);
+
+    // Reads past the end of the partition fail.
+    let mut buf = vec![0u8; 1024 * 2];
+    partition.read_sectors(6, 2, &mut buf).unwrap_err();
+}
+
+/// Builds a small image of type `fat_type`: an MBR with one partition at
+/// sector 1, two FATs, and one-sector clusters holding:
+///
+///   /HELLO.TXT          600 bytes, clusters 3 -> 4
+///   /SUB                cluster 5
+///   /SUB/long name.txt  empty, with one LFN entry
+///
+/// FAT32 images have 64 clusters, one-sector FATs, and the root directory in
+/// cluster 2; their FATs start at byte 0x600 and clusters 2 and 5 at 0xA00
+/// and 0x1000. FAT12 and FAT16 images have enough clusters to be of their
+/// type and a one-sector root directory region.
+fn tiny_image(fat_type: vfat::FatType) -> Vec<u8> {
+    use crate::vfat::FatType;
+
+    fn put16(data: &mut [u8], offset: usize, value: u16) {
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn dir_entry(data: &mut [u8], offset: usize, name: &[u8; 11], attr: u8, cluster: u32, size: u32) {
+        data[offset..offset + 11].copy_from_slice(name);
+        data[offset + 11] = attr;
+        put16(data, offset + 20, (cluster >> 16) as u16);
+        put16(data, offset + 26, cluster as u16);
+        put32(data, offset + 28, size);
+    }
+
+    const PART: usize = 512;
+    let (clusters, sectors_per_fat, root_entries) = match fat_type {
+        FatType::Fat12 => (64, 1, 16),
+        FatType::Fat16 => (4100, 17, 16),
+        FatType::Fat32 => (64, 1, 0),
+    };
+    let fat = |copy: usize| PART + (2 + copy * sectors_per_fat) * 512;
+    let root_region = fat(2);
+    let data_start = root_region + root_entries * 32;
+    let cluster = |n: usize| data_start + (n - 2) * 512;
+    let total_sectors = (data_start - PART) / 512 + clusters;
+
+    let mut data = vec![0u8; PART + total_sectors * 512];
+
+    // MBR
+    data[446 + 4] = match fat_type {
+        FatType::Fat12 => 0x01,
+        FatType::Fat16 => 0x06,
+        FatType::Fat32 => 0x0C,
+    };
+    put32(&mut data, 446 + 8, 1);
+    put32(&mut data, 446 + 12, total_sectors as u32);
+    data[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+    // BPB
+    put16(&mut data, PART + 11, 512);
+    data[PART + 13] = 1;
+    put16(&mut data, PART + 14, 2);
+    data[PART + 16] = 2;
+    put16(&mut data, PART + 17, root_entries as u16);
+    if fat_type == FatType::Fat32 {
+        put32(&mut data, PART + 32, total_sectors as u32);
+        put32(&mut data, PART + 36, sectors_per_fat as u32);
+        put32(&mut data, PART + 44, 2);
+    } else {
+        put16(&mut data, PART + 19, total_sectors as u16);
+        put16(&mut data, PART + 22, sectors_per_fat as u16);
+    }
+    data[PART + 510..PART + 512].copy_from_slice(&[0x55, 0xAA]);
+
+    // FATs
+    let root_dir = match fat_type {
+        FatType::Fat32 => cluster(2),
+        _ => root_region,
+    };
+    let mut entries = vec![(0, 0x0FFFFFF8), (1, 0x0FFFFFFF), (3, 4), (4, 0x0FFFFFFF), (5, 0x0FFFFFFF)];
+    if fat_type == FatType::Fat32 {
+        entries.push((2, 0x0FFFFFFF));
+    }
+    for copy in 0..2 {
+        for &(n, value) in &entries {
+            let offset = fat(copy) + fat_type.entry_offset(n);
+            fat_type.encode(n, value, &mut data[offset..offset + fat_type.entry_len()]);
+        }
+    }
+
+    // Root directory
+    dir_entry(&mut data, root_dir, b"HELLO   TXT", 0x20, 3, 600);
+    dir_entry(&mut data, root_dir + 32, b"SUB        ", 0x10, 5, 0);
+    for (i, byte) in data[cluster(3)..cluster(3) + 600].iter_mut().enumerate() {
+        *byte = (i % 251) as u8;
+    }
+
+    // /SUB
+    let sub = cluster(5);
+    dir_entry(&mut data, sub, b".          ", 0x10, 5, 0);
+    dir_entry(&mut data, sub + 32, b"..         ", 0x10, 0, 0);
+    let short_name = b"LONGNA~1TXT";
+    let checksum = short_name.iter().fold(0u8, |sum, &b| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b));
+    let lfn = sub + 64;
+    data[lfn] = 0x41;
+    data[lfn + 11] = 0x0F;
+    data[lfn + 13] = checksum;
+    let name: Vec<u16> = "long name.txt".encode_utf16().collect();
+    for (i, &offset) in [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30].iter().enumerate() {
+        put16(&mut data, lfn + offset, name[i]);
+    }
+    dir_entry(&mut data, sub + 96, short_name, 0x20, 0, 0);
+
+    data
+}
+
+#[test]
+fn test_fat_types() {
+    use crate::check;
+    use crate::vfat::FatType;
+
+    for &fat_type in [FatType::Fat12, FatType::Fat16, FatType::Fat32].iter() {
+        let vfat = VFat::<StdVFatHandle>::from(Cursor::new(tiny_image(fat_type)))
+            .expect("failed to initialize VFAT from image");
+        assert_eq!(vfat.lock(|vfat| vfat.fat_type()), fat_type);
+
+        let mut root = (&vfat).open_dir("/").expect("open root")
+            .entries().expect("root entries")
+            .map(|entry| entry.name().to_string())
+            .collect::<Vec<_>>();
+        root.sort();
+        assert_eq!(root, vec!["HELLO.TXT", "SUB"], "{:?}", fat_type);
+
+        let mut buf = Vec::new();
+        let mut hello = (&vfat).open_file("/HELLO.TXT").expect("open HELLO.TXT");
+        hello.read_to_end(&mut buf).expect("read HELLO.TXT");
+        assert_eq!(buf.len(), 600);
+        assert!(buf.iter().enumerate().all(|(i, &b)| b == (i % 251) as u8), "{:?}", fat_type);
+
+        let entry = (&vfat).open("/SUB/long name.txt").expect("open long name");
+        assert_eq!(entry.name(), "long name.txt");
+        assert_eq!(entry.as_file().expect("file").size(), 0);
+
+        // `..` in a child of the root refers to it as cluster 0.
+        let parent = (&vfat).open_dir("/SUB").expect("open SUB")
+            .find("..").expect("find ..")
+            .into_dir().expect("dir")
+            .entries().expect("parent entries")
+            .count();
+        assert_eq!(parent, 2, "{:?}", fat_type);
+
+        let report = check::check(&vfat, false).expect("check");
+        assert!(report.is_clean(), "{:?}: unexpected problems: {:?}", fat_type, report.problems);
+        assert_eq!((report.directories, report.files), (2, 2));
+    }
+}
+
+#[test]
+fn test_fat12_entries() {
+    use crate::vfat::FatType;
+
+    // The entries for clusters 2 and 3 share the byte at offset 4.
+    let mut raw = [0u8; 6];
+    FatType::Fat12.encode(2, 0xABC, &mut raw[3..5]);
+    FatType::Fat12.encode(3, 0x0FFFFFFF, &mut raw[4..6]);
+    assert_eq!(raw[3..], [0xBC, 0xFA, 0xFF]);
+    assert_eq!({ FatType::Fat12.decode(2, &raw[3..5]).0 }, 0xABC);
+    assert_eq!({ FatType::Fat12.decode(3, &raw[4..6]).0 }, 0x0FFFFFFF);
+    expect_variant!(FatType::Fat12.decode(3, &raw[4..6]).status(), vfat::Status::Eoc(_));
+
+    expect_variant!(FatType::Fat16.decode(0, &[0xF7, 0xFF]).status(), vfat::Status::Bad);
+    expect_variant!(FatType::Fat16.decode(0, &[0x34, 0x12]).status(), vfat::Status::Data(c) if c.get() == 0x1234);
+
+    assert_eq!(FatType::from_cluster_count(4084), FatType::Fat12);
+    assert_eq!(FatType::from_cluster_count(4085), FatType::Fat16);
+    assert_eq!(FatType::from_cluster_count(65525), FatType::Fat32);
+}
+
+#[test]
+fn test_check_clean() {
+    use crate::check;
+
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(tiny_image(vfat::FatType::Fat32)))
+        .expect("failed to initialize VFAT from image");
+
+    let report = check::check(&vfat, false).expect("check");
+    assert!(report.is_clean(), "unexpected problems: {:?}", report.problems);
+    assert_eq!(report.directories, 2);
+    assert_eq!(report.files, 2);
+    assert_eq!(report.used_clusters, 4);
+    assert_eq!(report.total_clusters, 64);
+
+    let entry = (&vfat).open("/SUB/long name.txt").expect("open long name");
+    assert_eq!(entry.name(), "long name.txt");
+}
+
+#[test]
+fn test_check_repair() {
+    use crate::check::{self, Problem};
+
+    let mut data = tiny_image(vfat::FatType::Fat32);
+    let (fat, root, sub) = (512 + 2 * 512, 512 + 4 * 512, 512 + 7 * 512);
+
+    // A lost cluster, recorded in the first FAT only.
+    data[fat + 10 * 4..fat + 11 * 4].copy_from_slice(&0x0FFFFFFFu32.to_le_bytes());
+    // HELLO.TXT claims more bytes than its chain holds.
+    data[root + 28..root + 32].copy_from_slice(&2000u32.to_le_bytes());
+    // The LFN entry no longer matches its 8.3 entry.
+    data[sub + 64 + 13] ^= 0xFF;
+    // LONGNA~1.TXT shares HELLO.TXT's second cluster.
+    data[sub + 96 + 26..sub + 96 + 28].copy_from_slice(&4u16.to_le_bytes());
+    data[sub + 96 + 28..sub + 96 + 32].copy_from_slice(&512u32.to_le_bytes());
+
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(data))
+        .expect("failed to initialize VFAT from image");
+
+    let report = check::check(&vfat, false).expect("check");
+    assert_eq!(report.problems, vec![
+        Problem::FatMismatch { copy: 1, sectors: 1 },
+        Problem::SizeMismatch { path: "/HELLO.TXT".into(), size: 2000, clusters: 2 },
+        Problem::BadLfnChecksum { path: "/SUB/LONGNA~1.TXT".into() },
+        Problem::CrossLinked {
+            path: "/SUB/LONGNA~1.TXT".into(),
+            other: "/HELLO.TXT".into(),
+            cluster: 4,
+        },
+        Problem::SizeMismatch { path: "/SUB/LONGNA~1.TXT".into(), size: 512, clusters: 0 },
+        Problem::LostClusters { count: 1 },
+    ]);
+
+    let report = check::check(&vfat, true).expect("check and repair");
+    assert_eq!(report.problems.len(), 6);
+    assert!(report.repaired);
+
+    let report = check::check(&vfat, false).expect("check after repair");
+    assert!(report.is_clean(), "unexpected problems: {:?}", report.problems);
+    assert_eq!(report.used_clusters, 4);
+
+    let mut hello = (&vfat).open_file("/HELLO.TXT").expect("open HELLO.TXT");
+    assert_eq!(hello.size(), 1024);
+    let mut buf = Vec::new();
+    hello.read_to_end(&mut buf).expect("read HELLO.TXT");
+    assert_eq!(buf.len(), 1024);
+}
+
+#[derive(Clone)]
+struct StdExFatHandle(Arc<Mutex<exfat::ExFat<Self>>>);
+
+impl Debug for StdExFatHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "StdExFatHandle")
+    }
+}
+
+impl exfat::ExFatHandle for StdExFatHandle {
+    fn new(val: exfat::ExFat<StdExFatHandle>) -> Self {
+        StdExFatHandle(Arc::new(Mutex::new(val)))
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut exfat::ExFat<StdExFatHandle>) -> R) -> R {
+        f(&mut self.0.lock().expect("all okay"))
+    }
+}
+
+/// Builds an exFAT image with 512-byte sectors and 1 KiB clusters:
+///
+///   cluster 2           allocation bitmap
+///   cluster 3           up-case table mapping `a`-`z` to `A`-`Z`
+///   clusters 4, 14      root directory
+///   clusters 6, 8, 7    /Hello.txt, 2500 bytes
+///   clusters 9-11       /contig.bin, NoFatChain, 3000 bytes of which 2000
+///                       are valid
+///   cluster 12          /Sub Dir, NoFatChain, whose entry set spans both
+///                       root clusters
+///   cluster 13          /Sub Dir/Nested File With A Long Name.dat, 10 bytes
+///
+/// The root also holds an entry set with a bad checksum. File data is the
+/// byte pattern `i % 251`.
+fn tiny_exfat_image() -> Vec<u8> {
+    fn put16(data: &mut [u8], offset: usize, value: u16) {
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put64(data: &mut [u8], offset: usize, value: u64) {
+        data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn upcase(c: u16) -> u16 {
+        if c >= 'a' as u16 && c <= 'z' as u16 { c - 32 } else { c }
+    }
+
+    /// Returns the file, stream extension and name entries for a file.
+    fn entry_set(name: &str, attr: u16, flags: u8, cluster: u32, valid: u64, size: u64) -> Vec<[u8; 32]> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let name_entries = (name.len() + 14) / 15;
+        let mut set = vec![[0u8; 32]; 2 + name_entries];
+
+        set[0][0] = 0x85;
+        set[0][1] = 1 + name_entries as u8;
+        put16(&mut set[0], 4, attr);
+        // Modified 2020-01-02 00:00:00.
+        put32(&mut set[0], 12, ((40 << 9) | (1 << 5) | 2) << 16);
+
+        let hash = name.iter().fold(0u16, |hash, &c| {
+            let c = upcase(c);
+            let hash = hash.rotate_right(1).wrapping_add(c & 0xFF);
+            hash.rotate_right(1).wrapping_add(c >> 8)
+        });
+        set[1][0] = 0xC0;
+        set[1][1] = 0x01 | flags;
+        set[1][3] = name.len() as u8;
+        put16(&mut set[1], 4, hash);
+        put64(&mut set[1], 8, valid);
+        put32(&mut set[1], 20, cluster);
+        put64(&mut set[1], 24, size);
+
+        for (i, chunk) in name.chunks(15).enumerate() {
+            set[2 + i][0] = 0xC1;
+            for (j, &c) in chunk.iter().enumerate() {
+                put16(&mut set[2 + i], 2 + j * 2, c);
+            }
+        }
+
+        let mut checksum = 0u16;
+        for (i, entry) in set.iter().enumerate() {
+            for (j, &b) in entry.iter().enumerate() {
+                if i != 0 || (j != 2 && j != 3) {
+                    checksum = checksum.rotate_right(1).wrapping_add(b as u16);
+                }
+            }
+        }
+        put16(&mut set[0], 2, checksum);
+        set
+    }
+
+    const PART: usize = 512;
+    const CLUSTERS: usize = 40;
+    let heap = 32;
+    let cluster = |n: usize| PART + (heap + (n - 2) * 2) * 512;
+    let total_sectors = heap + CLUSTERS * 2;
+    let mut data = vec![0u8; PART + total_sectors * 512];
+
+    // MBR
+    data[446 + 4] = 0x07;
+    put32(&mut data, 446 + 8, 1);
+    put32(&mut data, 446 + 12, total_sectors as u32);
+    data[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+    // Boot sector and boot region checksum
+    data[PART..PART + 3].copy_from_slice(&[0xEB, 0x76, 0x90]);
+    data[PART + 3..PART + 11].copy_from_slice(b"EXFAT   ");
+    put64(&mut data, PART + 64, 1);
+    put64(&mut data, PART + 72, total_sectors as u64);
+    put32(&mut data, PART + 80, 24);
+    put32(&mut data, PART + 84, 1);
+    put32(&mut data, PART + 88, heap as u32);
+    put32(&mut data, PART + 92, CLUSTERS as u32);
+    put32(&mut data, PART + 96, 4);
+    put16(&mut data, PART + 104, 0x0100);
+    data[PART + 108] = 9;
+    data[PART + 109] = 1;
+    data[PART + 110] = 1;
+    data[PART + 112] = 25;
+    data[PART + 510..PART + 512].copy_from_slice(&[0x55, 0xAA]);
+    let checksum = data[PART..PART + 11 * 512].iter()
+        .enumerate()
+        .filter(|&(i, _)| i != 106 && i != 107 && i != 112)
+        .fold(0u32, |sum, (_, &b)| sum.rotate_right(1).wrapping_add(b as u32));
+    for i in 0..128 {
+        put32(&mut data, PART + 11 * 512 + i * 4, checksum);
+    }
+
+    // FAT
+    let fat = PART + 24 * 512;
+    for &(n, value) in [(0, 0xFFFFFFF8), (1, 0xFFFFFFFF), (2, 0xFFFFFFFF), (3, 0xFFFFFFFF),
+                        (4, 14), (14, 0xFFFFFFFF), (6, 8), (8, 7), (7, 0xFFFFFFFF),
+                        (13, 0xFFFFFFFF)].iter() {
+        put32(&mut data, fat + n * 4, value);
+    }
+
+    // Allocation bitmap: clusters 2 through 14.
+    put16(&mut data, cluster(2), 0x1FFF);
+
+    // Up-case table
+    let mut table = vec![0xFFFF, 'a' as u16];
+    table.extend('A' as u16..'Z' as u16 + 1);
+    let table: Vec<u8> = table.iter().flat_map(|c| c.to_le_bytes().to_vec()).collect();
+    data[cluster(3)..cluster(3) + table.len()].copy_from_slice(&table);
+    let table_checksum = table.iter().fold(0u32, |sum, &b| sum.rotate_right(1).wrapping_add(b as u32));
+
+    // Root directory
+    let root_entry = |i: usize| if i < 32 { cluster(4) + i * 32 } else { cluster(14) + (i - 32) * 32 };
+    let mut root = vec![[0u8; 32]; 31];
+    root[0][0] = 0x81;
+    put32(&mut root[0], 20, 2);
+    put64(&mut root[0], 24, 5);
+    root[1][0] = 0x82;
+    put32(&mut root[1], 4, table_checksum);
+    put32(&mut root[1], 20, 3);
+    put64(&mut root[1], 24, table.len() as u64);
+    let hello = entry_set("Hello.txt", 0x20, 0, 6, 2500, 2500);
+    let contig = entry_set("contig.bin", 0x20, 0x02, 9, 2000, 3000);
+    root.splice(2..2 + hello.len() + contig.len(), hello.into_iter().chain(contig));
+    // Deleted entries pad the root so that the next set spans two clusters.
+    for entry in root.iter_mut().skip(8) {
+        entry[0] = 0x05;
+    }
+    root.extend(entry_set("Sub Dir", 0x10, 0x02, 12, 1024, 1024));
+    let mut corrupt = entry_set("Corrupt.txt", 0x20, 0, 13, 10, 10);
+    corrupt[2][2] ^= 0xFF;
+    root.extend(corrupt);
+    for (i, entry) in root.iter().enumerate() {
+        data[root_entry(i)..root_entry(i) + 32].copy_from_slice(entry);
+    }
+
+    // /Sub Dir
+    for (i, entry) in entry_set("Nested File With A Long Name.dat", 0x20, 0, 13, 10, 10).iter().enumerate() {
+        data[cluster(12) + i * 32..cluster(12) + (i + 1) * 32].copy_from_slice(entry);
+    }
+
+    // File data
+    let pattern = |len: usize| (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    for (chunk, &n) in pattern(2500).chunks(1024).zip([6, 8, 7].iter()) {
+        data[cluster(n)..cluster(n) + chunk.len()].copy_from_slice(chunk);
+    }
+    data[cluster(9)..cluster(9) + 2000].copy_from_slice(&pattern(2000));
+    // Stale bytes past the valid data length must read as zero.
+    for byte in data[cluster(9) + 2000..cluster(9) + 3000].iter_mut() {
+        *byte = 0xEE;
+    }
+    data[cluster(13)..cluster(13) + 10].copy_from_slice(&pattern(10));
+
+    data
+}
+
+#[test]
+fn test_exfat() {
+    let volume = exfat::ExFat::<StdExFatHandle>::from(Cursor::new(tiny_exfat_image()))
+        .expect("failed to initialize exFAT from image");
+    let pattern = |len: usize| (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+    let mut root = (&volume).open_dir("/").expect("open root")
+        .entries().expect("root entries")
+        .map(|entry| entry.name().to_string())
+        .collect::<Vec<_>>();
+    root.sort();
+    assert_eq!(root, vec!["Hello.txt", "Sub Dir", "contig.bin"]);
+
+    let mut buf = Vec::new();
+    let mut hello = (&volume).open_file("/HELLO.TXT").expect("open HELLO.TXT");
+    assert_eq!(hello.name(), "Hello.txt");
+    assert_eq!(hello.metadata().modified().year(), 2020);
+    hello.read_to_end(&mut buf).expect("read Hello.txt");
+    assert_eq!(buf, pattern(2500));
+
+    let mut contig = (&volume).open_file("/Contig.BIN").expect("open contig.bin");
+    buf.clear();
+    contig.read_to_end(&mut buf).expect("read contig.bin");
+    assert_eq!(&buf[..2000], &pattern(2000)[..]);
+    assert!(buf[2000..].iter().all(|&b| b == 0));
+    assert_eq!(buf.len(), 3000);
+
+    let mut window = [0xFFu8; 20];
+    contig.seek(io::SeekFrom::Start(1990)).expect("seek");
+    contig.read_exact(&mut window).expect("read across valid length");
+    assert_eq!(&window[..10], &pattern(2000)[1990..]);
+    assert_eq!(&window[10..], &[0u8; 10]);
+
+    let sub = (&volume).open("/sub dir").expect("open Sub Dir");
+    assert!(sub.is_dir());
+    assert_eq!(sub.metadata().modified().year(), 2020);
+
+    let mut nested = (&volume).open_file("/Sub Dir/nested file with a long name.DAT").expect("open nested");
+    assert_eq!(nested.name(), "Nested File With A Long Name.dat");
+    buf.clear();
+    nested.read_to_end(&mut buf).expect("read nested");
+    assert_eq!(buf, pattern(10));
+
+    expect_variant!((&volume).open("/Corrupt.txt"), Err(ref e) if e.kind() == io::ErrorKind::NotFound);
+    expect_variant!(nested.write(b"x"), Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied);
+
+    exfat::ExFatHandle::lock(&volume.0, |exfat| {
+        assert!(exfat.is_allocated(14));
+        assert!(!exfat.is_allocated(15));
+        assert_eq!(exfat.free_clusters(), 27);
+    });
+}
+
+#[test]
+fn test_exfat_bad_checksum() {
+    let mut data = tiny_exfat_image();
+    data[512 + 100] ^= 0xFF;
+    expect_variant!(exfat::ExFat::<StdExFatHandle>::from(Cursor::new(data)), Err(vfat::Error::BadChecksum));
+
+    // The volume flags are excluded from the checksum.
+    let mut data = tiny_exfat_image();
+    data[512 + 106] = 0x02;
+    exfat::ExFat::<StdExFatHandle>::from(Cursor::new(data)).expect("volume flags are volatile");
+}
+
+/// Builds a GPT disk whose entry array holds an 8-sector Linux partition at
+/// sector 34 (index 0) and the FAT32 volume of `tiny_image` at sector 64
+/// (index 2), with the backup array and header in the last two sectors.
+fn tiny_gpt_image() -> Vec<u8> {
+    use crate::gpt::Guid;
+
+    fn crc32(data: &[u8]) -> u32 {
+        !data.iter().fold(!0u32, |crc, &b| {
+            (0..8).fold(crc ^ b as u32, |crc, _| if crc & 1 != 0 { crc >> 1 ^ 0xEDB88320 } else { crc >> 1 })
+        })
+    }
+
+    let volume = tiny_image(vfat::FatType::Fat32)[512..].to_vec();
+    let sectors = 64 + volume.len() / 512 + 2;
+    let mut data = vec![0u8; sectors * 512];
+
+    // Protective MBR
+    data[446 + 4] = 0xEE;
+    data[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+    data[446 + 12..446 + 16].copy_from_slice(&(sectors as u32 - 1).to_le_bytes());
+    data[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+    // Entry array: four 128-byte entries.
+    let linux: Guid = "0FC63DAF-8483-4772-8E79-3D69D8477DE4".parse().unwrap();
+    let mut entries = vec![0u8; 512];
+    for &(slot, type_guid, guid, first, last) in [
+        (0, linux, 0x11, 34, 41),
+        (2, Guid::BASIC_DATA, 0x22, 64, 64 + volume.len() / 512 - 1),
+    ].iter() {
+        let entry = &mut entries[slot * 128..(slot + 1) * 128];
+        entry[0..16].copy_from_slice(&type_guid.0);
+        entry[16..32].copy_from_slice(&[guid; 16]);
+        entry[32..40].copy_from_slice(&(first as u64).to_le_bytes());
+        entry[40..48].copy_from_slice(&(last as u64).to_le_bytes());
+    }
+    let entries_crc = crc32(&entries);
+
+    let header = |current: usize, backup: usize, entries_lba: usize| {
+        let mut header = vec![0u8; 512];
+        header[0..8].copy_from_slice(b"EFI PART");
+        header[8..12].copy_from_slice(&0x00010000u32.to_le_bytes());
+        header[12..16].copy_from_slice(&92u32.to_le_bytes());
+        header[24..32].copy_from_slice(&(current as u64).to_le_bytes());
+        header[32..40].copy_from_slice(&(backup as u64).to_le_bytes());
+        header[40..48].copy_from_slice(&34u64.to_le_bytes());
+        header[48..56].copy_from_slice(&(sectors as u64 - 3).to_le_bytes());
+        header[72..80].copy_from_slice(&(entries_lba as u64).to_le_bytes());
+        header[80..84].copy_from_slice(&4u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let crc = crc32(&header[..92]);
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+        header
+    };
+
+    let last = sectors - 1;
+    data[512..1024].copy_from_slice(&header(1, last, 2));
+    data[1024..1536].copy_from_slice(&entries);
+    data[(last - 1) * 512..last * 512].copy_from_slice(&entries);
+    data[last * 512..].copy_from_slice(&header(last, 1, last - 1));
+    data[64 * 512..64 * 512 + volume.len()].copy_from_slice(&volume);
+
+    data
+}
+
+fn read_hello(vfat: &StdVFatHandle) -> Vec<u8> {
+    let mut buf = Vec::new();
+    vfat.open_file("/HELLO.TXT").expect("open HELLO.TXT")
+        .read_to_end(&mut buf).expect("read HELLO.TXT");
+    buf
+}
+
+#[test]
+fn test_gpt() {
+    use crate::gpt::Guid;
+    use crate::partition::{self, Kind, Selector};
+
+    let data = tiny_gpt_image();
+    let partitions = partition::read_partitions(&mut Cursor::new(data.clone())).expect("read partitions");
+    assert_eq!(partitions.iter().map(|p| (p.index, p.start)).collect::<Vec<_>>(), vec![(0, 34), (2, 64)]);
+    assert_eq!(partitions[1].kind, Kind::Gpt { type_guid: Guid::BASIC_DATA, guid: Guid([0x22; 16]) });
+
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(data.clone())).expect("mount first FAT partition");
+    assert_eq!(read_hello(&vfat).len(), 600);
+
+    let vfat = VFat::<StdVFatHandle>::from_partition(Cursor::new(data.clone()), Selector::Index(2))
+        .expect("mount partition 2");
+    assert_eq!(read_hello(&vfat).len(), 600);
+    let vfat = VFat::<StdVFatHandle>::from_partition(Cursor::new(data.clone()), Selector::Guid(Guid([0x22; 16])))
+        .expect("mount partition by GUID");
+    assert_eq!(read_hello(&vfat).len(), 600);
+
+    expect_variant!(VFat::<StdVFatHandle>::from_partition(Cursor::new(data.clone()), Selector::Index(0)),
+                    Err(vfat::Error::BadSignature));
+    expect_variant!(VFat::<StdVFatHandle>::from_partition(Cursor::new(data.clone()), Selector::Index(1)),
+                    Err(vfat::Error::NotFound));
+}
+
+#[test]
+fn test_gpt_backup() {
+    use crate::gpt;
+    use crate::partition;
+
+    // A corrupt primary header falls back to the backup.
+    let mut data = tiny_gpt_image();
+    data[512 + 40] ^= 0xFF;
+    let partitions = partition::read_partitions(&mut Cursor::new(data.clone())).expect("read backup");
+    assert_eq!(partitions.len(), 2);
+
+    // So does a corrupt primary entry array.
+    let mut data = tiny_gpt_image();
+    data[1024 + 32] ^= 0xFF;
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(data.clone())).expect("mount from backup");
+    assert_eq!(read_hello(&vfat).len(), 600);
+
+    // With both copies corrupt, the primary's error is reported.
+    let last = data.len() - 512;
+    data[last - 512 + 32] ^= 0xFF;
+    expect_variant!(partition::read_partitions(&mut Cursor::new(data)),
+                    Err(vfat::Error::Gpt(gpt::Error::BadEntriesChecksum)));
+}
+
+#[test]
+fn test_gpt_bad_header() {
+    use crate::gpt::{self, GptHeader};
+
+    fn crc32(data: &[u8]) -> u32 {
+        !data.iter().fold(!0u32, |crc, &b| {
+            (0..8).fold(crc ^ b as u32, |crc, _| if crc & 1 != 0 { crc >> 1 ^ 0xEDB88320 } else { crc >> 1 })
+        })
+    }
+
+    // Entry arrays too big to allocate are rejected before they are read.
+    let patched = |offset: usize, value: u32| {
+        let mut data = tiny_gpt_image();
+        data[512 + offset..512 + offset + 4].copy_from_slice(&value.to_le_bytes());
+        data[512 + 16..512 + 20].copy_from_slice(&[0; 4]);
+        let crc = crc32(&data[512..512 + 92]);
+        data[512 + 16..512 + 20].copy_from_slice(&crc.to_le_bytes());
+        data
+    };
+    for &(offset, value) in [(80, 0xFFFF_FFFFu32), (80, 8193), (84, 0x8000_0000), (84, 1 << 20)].iter() {
+        expect_variant!(GptHeader::from(&mut Cursor::new(patched(offset, value)), 1), Err(gpt::Error::BadHeader));
+    }
+
+    // More than the usual 128 entries is fine, as long as the array is not
+    // too big.
+    for &(offset, value) in [(80, 1024u32), (84, 1024)].iter() {
+        assert!(GptHeader::from(&mut Cursor::new(patched(offset, value)), 1).is_ok());
+    }
+}
+
+#[test]
+fn test_extended_mbr() {
+    use crate::partition::{self, Kind, Selector};
+
+    fn put_entry(data: &mut [u8], record: usize, slot: usize, partition_type: u8, start: u32, sectors: u32) {
+        let entry = record * 512 + 446 + slot * 16;
+        data[entry + 4] = partition_type;
+        data[entry + 8..entry + 12].copy_from_slice(&start.to_le_bytes());
+        data[entry + 12..entry + 16].copy_from_slice(&sectors.to_le_bytes());
+        data[record * 512 + 510..record * 512 + 512].copy_from_slice(&[0x55, 0xAA]);
+    }
+
+    // An extended partition at sector 1 chaining a 4-sector Linux partition
+    // (EBR at sector 1) and the FAT32 volume (EBR at sector 6).
+    let volume = tiny_image(vfat::FatType::Fat32)[512..].to_vec();
+    let volume_sectors = (volume.len() / 512) as u32;
+    let mut data = vec![0u8; (8 + volume_sectors as usize) * 512];
+    put_entry(&mut data, 0, 0, 0x0F, 1, 7 + volume_sectors);
+    put_entry(&mut data, 1, 0, 0x83, 1, 4);
+    put_entry(&mut data, 1, 1, 0x05, 5, 2 + volume_sectors);
+    put_entry(&mut data, 6, 0, 0x0C, 2, volume_sectors);
+    data[8 * 512..].copy_from_slice(&volume);
+
+    let partitions = partition::read_partitions(&mut Cursor::new(data.clone())).expect("read partitions");
+    assert_eq!(partitions.iter().map(|p| (p.index, p.start, p.kind)).collect::<Vec<_>>(),
+               vec![(4, 2, Kind::Mbr(0x83)), (5, 8, Kind::Mbr(0x0C))]);
+
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(data.clone())).expect("mount logical partition");
+    assert_eq!(read_hello(&vfat).len(), 600);
+    let vfat = VFat::<StdVFatHandle>::from_partition(Cursor::new(data), Selector::Index(5))
+        .expect("mount partition 5");
+    assert_eq!(read_hello(&vfat).len(), 600);
+}
+
+#[test]
+fn test_partition_selector() {
+    use crate::gpt::Guid;
+    use crate::partition::Selector;
+
+    assert_eq!("3".parse(), Ok(Selector::Index(3)));
+    let text = "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7";
+    assert_eq!(text.to_lowercase().parse(), Ok(Selector::Guid(Guid::BASIC_DATA)));
+    assert_eq!(Guid::BASIC_DATA.to_string(), text);
+    assert_eq!("EBD0A0A2B9E5-4433-87C0-68B6B72699C7-".parse::<Selector>(), Err(()));
+    assert_eq!("sda1".parse::<Selector>(), Err(()));
+}
+
+#[test]
+fn test_vfat_write() {
+    use crate::check;
+    use crate::vfat::FatType;
+
+    let pattern = |len: usize| (0..len).map(|i| (i * 7 % 256) as u8).collect::<Vec<_>>();
+
+    for &fat_type in [FatType::Fat12, FatType::Fat16, FatType::Fat32].iter() {
+        let vfat = VFat::<StdVFatHandle>::from(Cursor::new(tiny_image(fat_type)))
+            .expect("failed to initialize VFAT from image");
+
+        // A long name spanning two LFN entries, written across clusters.
+        let mut file = (&vfat).create_file("/A file with a long name.bin").expect("create file");
+        file.write_all(&pattern(1500)).expect("write");
+        file.seek(io::SeekFrom::Start(10)).expect("seek");
+        file.write_all(b"overwrite").expect("overwrite");
+        file.sync().expect("sync");
+
+        let mut expected = pattern(1500);
+        expected[10..19].copy_from_slice(b"overwrite");
+        let mut buf = Vec::new();
+        (&vfat).open_file("/a file with a long name.BIN").expect("reopen")
+            .read_to_end(&mut buf).expect("read back");
+        assert_eq!(buf, expected, "{:?}", fat_type);
+
+        expect_variant!((&vfat).create_file("/HELLO.TXT"), Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists);
+        expect_variant!((&vfat).create_file("/bad:name"), Err(ref e) if e.kind() == io::ErrorKind::InvalidInput);
+
+        // Enough entries to grow a subdirectory past its first cluster.
+        let dir = (&vfat).create_dir("/SUB/New Dir").expect("create dir");
+        for i in 0..20 {
+            dir.create_file(&format!("file number {}", i)).expect("create in dir");
+        }
+        let mut nested = (&vfat).create_file("/SUB/New Dir/nested.txt").expect("create nested");
+        nested.write_all(b"nested").expect("write nested");
+        let names = (&vfat).open_dir("/SUB/New Dir").expect("open dir")
+            .entries().expect("entries")
+            .map(|entry| entry.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), 23);
+        assert_eq!(&names[..2], &[".", ".."]);
+        assert_eq!(names[22], "nested.txt");
+
+        expect_variant!((&vfat).remove("/SUB/New Dir"), Err(ref e) if e.kind() == io::ErrorKind::Other);
+        let report = check::check(&vfat, false).expect("check");
+        assert!(report.is_clean(), "{:?}: unexpected problems: {:?}", fat_type, report.problems);
+        assert_eq!(report.files, 24);
+
+        for i in 0..20 {
+            (&vfat).remove(format!("/SUB/New Dir/file number {}", i)).expect("remove file");
+        }
+        (&vfat).remove("/SUB/New Dir/NESTED.TXT").expect("remove nested");
+        (&vfat).remove("/SUB/New Dir").expect("remove dir");
+        (&vfat).remove("/A file with a long name.bin").expect("remove long name");
+        expect_variant!((&vfat).open("/SUB/New Dir"), Err(ref e) if e.kind() == io::ErrorKind::NotFound);
+
+        let report = check::check(&vfat, false).expect("check after remove");
+        assert!(report.is_clean(), "{:?}: unexpected problems: {:?}", fat_type, report.problems);
+        // FAT12/16 root directories occupy no cluster.
+        let used = if fat_type == FatType::Fat32 { 4 } else { 3 };
+        assert_eq!((report.directories, report.files, report.used_clusters), (2, 2, used));
+    }
+}
+
+#[test]
+fn test_vfat_short_names() {
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(tiny_image(vfat::FatType::Fat32)))
+        .expect("failed to initialize VFAT from image");
+
+    for name in ["README.MD", "readme.md", "Readme.markdown", "read me.md"].iter() {
+        (&vfat).create_file(format!("/SUB/{}", name)).ok();
+    }
+    let names = (&vfat).open_dir("/SUB").expect("open SUB")
+        .entries().expect("entries")
+        .map(|entry| entry.name().to_string())
+        .collect::<Vec<_>>();
+    // `readme.md` collides with `README.MD` case-insensitively.
+    assert_eq!(names, vec![".", "..", "long name.txt", "README.MD", "Readme.markdown", "read me.md"]);
+
+    let check = crate::check::check(&vfat, false).expect("check");
+    assert!(check.is_clean(), "unexpected problems: {:?}", check.problems);
+}
+
+/// A `BlockDevice` over a vector that counts the sectors written to it.
+struct CountingDevice {
+    data: Vec<u8>,
+    writes: u64,
+}
+
+impl BlockDevice for CountingDevice {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let start = n as usize * 512;
+        buf[..512].copy_from_slice(&self.data[start..start + 512]);
+        Ok(512)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let start = n as usize * 512;
+        self.data[start..start + 512].copy_from_slice(&buf[..512]);
+        self.writes += 1;
+        Ok(512)
+    }
+}
+
+#[test]
+fn test_format() {
+    use crate::check;
+    use crate::format::{self, Options};
+
+    // 40 MiB: the smallest common size that still has enough clusters.
+    const SECTORS: u64 = 40 * 2048;
+
+    for &mbr in [false, true].iter() {
+        let mut device = CountingDevice { data: vec![0xAA; SECTORS as usize * 512], writes: 0 };
+        let options = Options { mbr: mbr, volume_id: 0x1234, ..Options::new(SECTORS) }.label("scratch");
+        let layout = format::format(&mut device, &options).expect("format");
+        assert_eq!(layout.start, if mbr { 2048 } else { 0 });
+        assert_eq!(layout.sectors_per_cluster, 1);
+        assert!(layout.clusters >= 65525);
+        assert!((layout.clusters as u64 + 2) * 4 <= layout.sectors_per_fat as u64 * 512);
+        // Only metadata is written, not the data region.
+        assert!(device.writes < 32 + 2 * layout.sectors_per_fat as u64 + 16);
+
+        let boot = layout.start as usize * 512;
+        let backup = boot + 6 * 512;
+        assert_eq!(device.data[boot..boot + 512], device.data[backup..backup + 512]);
+        assert_eq!(&device.data[boot + 512..boot + 516], &[0x52, 0x52, 0x61, 0x41]);
+
+        let vfat = VFat::<StdVFatHandle>::from(Cursor::new(device.data)).expect("mount formatted volume");
+        assert_eq!(vfat.lock(|vfat| vfat.fat_type()), vfat::FatType::Fat32);
+        let names = (&vfat).open_dir("/").expect("open root")
+            .entries().expect("root entries")
+            .map(|entry| entry.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["SCRATCH"]);
+
+        let mut file = (&vfat).create_file("/hello.txt").expect("create");
+        file.write_all(b"hello").expect("write");
+        let report = check::check(&vfat, false).expect("check");
+        assert!(report.is_clean(), "unexpected problems: {:?}", report.problems);
+        assert_eq!(report.used_clusters, 2);
+        assert_eq!(report.total_clusters, layout.clusters);
+    }
+
+    // Volumes with too few clusters for FAT32 get FAT16, or FAT12.
+    for &(sectors, fat_type) in [(20 * 2048, vfat::FatType::Fat16), (4096, vfat::FatType::Fat12)].iter() {
+        for &mbr in [false, true].iter() {
+            let mut data = Cursor::new(vec![0xAA; sectors as usize * 512]);
+            let options = Options { mbr: mbr, ..Options::new(sectors) }.label("small");
+            let layout = format::format(&mut data, &options).expect("format");
+            assert_eq!(layout.fat_type, fat_type);
+
+            let vfat = VFat::<StdVFatHandle>::from(data).expect("mount formatted volume");
+            assert_eq!(vfat.lock(|vfat| vfat.fat_type()), fat_type);
+            assert_eq!(vfat.lock(|vfat| vfat.cluster_count()), layout.clusters);
+            let names = (&vfat).open_dir("/").expect("open root")
+                .entries().expect("root entries")
+                .map(|entry| entry.name().to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(names, vec!["SMALL"]);
+
+            (&vfat).create_file("/hello.txt").expect("create").write_all(b"hello").expect("write");
+            let report = check::check(&vfat, false).expect("check");
+            assert!(report.is_clean(), "unexpected problems: {:?}", report.problems);
+            assert_eq!(report.used_clusters, 1);
+        }
+    }
+
+    let tiny = Options::new(16);
+    expect_variant!(format::format(&mut Cursor::new(vec![0; 16 * 512]), &tiny),
+                    Err(ref e) if e.kind() == io::ErrorKind::InvalidInput);
+}
+
+/// A `BlockDevice` whose contents outlive the `VFat` mounted on it.
+#[derive(Clone)]
+struct SharedImage(Arc<Mutex<Cursor<Vec<u8>>>>);
+
+impl BlockDevice for SharedImage {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().read_sector(n, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write_sector(n, buf)
+    }
+}
+
+#[test]
+fn test_fsinfo() {
+    use crate::check::{self, Problem};
+    use crate::format::{self, Options};
+
+    const SECTORS: u64 = 40 * 2048;
+    let fsinfo_field = |image: &SharedImage, offset: usize| {
+        let data = image.0.lock().unwrap();
+        let bytes = &data.get_ref()[512 + offset..512 + offset + 4];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+    let set_fsinfo_field = |image: &SharedImage, offset: usize, value: u32| {
+        image.0.lock().unwrap().get_mut()[512 + offset..512 + offset + 4].copy_from_slice(&value.to_le_bytes());
+    };
+
+    let mut data = Cursor::new(vec![0; SECTORS as usize * 512]);
+    let layout = format::format(&mut data, &Options::new(SECTORS)).expect("format");
+    let clusters = layout.clusters as u64;
+    let image = SharedImage(Arc::new(Mutex::new(data)));
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).expect("mount");
+    let stat = vfat.lock(|vfat| vfat.statfs()).expect("statfs");
+    assert_eq!(stat.cluster_size, 512);
+    assert_eq!(stat.total_bytes, clusters * 512);
+    assert_eq!(stat.free_bytes, (clusters - 1) * 512);
+    assert_eq!(stat.used_bytes, 512);
+
+    // Four clusters, allocated from the hint onwards: clusters 3 to 6.
+    let mut file = (&vfat).create_file("/data.bin").expect("create");
+    file.write_all(&[7; 2000]).expect("write");
+    file.sync().expect("sync");
+    vfat.lock(|vfat| vfat.flush()).expect("flush");
+    assert_eq!(fsinfo_field(&image, 488), clusters as u32 - 5);
+    assert_eq!(fsinfo_field(&image, 492), 7);
+    drop(file);
+    drop(vfat);
+
+    // A wrong count is trusted on mount but caught and fixed by the checker.
+    set_fsinfo_field(&image, 488, 5);
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).expect("remount");
+    assert_eq!(vfat.lock(|vfat| vfat.statfs()).expect("statfs").free_bytes, 5 * 512);
+    let report = check::check(&vfat, true).expect("check");
+    assert_eq!(report.problems, vec![Problem::FreeCountMismatch { recorded: 5, actual: clusters as u32 - 5 }]);
+    assert_eq!(fsinfo_field(&image, 488), clusters as u32 - 5);
+    drop(vfat);
+
+    // Without a valid FSInfo sector the FAT is counted instead.
+    set_fsinfo_field(&image, 0, 0);
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).expect("remount");
+    assert_eq!(vfat.lock(|vfat| vfat.statfs()).expect("statfs").free_bytes, (clusters - 5) * 512);
+    assert!(check::check(&vfat, false).expect("check").is_clean());
+}
+
+#[test]
+fn test_timestamps() {
+    use std::time::Duration;
+    use crate::vfat::{Date, Time, Timestamp as FatTimestamp};
+
+    let epoch = FatTimestamp::from_unix(Duration::from_secs(315_532_800));
+    assert_eq!((epoch.date.raw(), epoch.time.raw(), epoch.tenths), (0x0021, 0, 0));
+    assert_eq!(epoch.to_unix(), Duration::from_secs(315_532_800));
+
+    // 2000-02-29 12:34:57.78, a leap day and an odd second.
+    let time = Duration::new(951_827_697, 780_000_000);
+    let stamp = FatTimestamp::from_unix(time);
+    assert_eq!(stamp.date, Date::new(2000, 2, 29));
+    assert_eq!(stamp.time, Time::new(12, 34, 56));
+    assert_eq!(stamp.tenths, 178);
+    assert_eq!((stamp.year(), stamp.month(), stamp.day()), (2000, 2, 29));
+    assert_eq!((stamp.hour(), stamp.minute(), stamp.second()), (12, 34, 57));
+    assert_eq!(stamp.to_unix(), time);
+
+    // Times outside the FAT range are clamped to it.
+    assert_eq!(FatTimestamp::from_unix(Duration::from_secs(0)), epoch);
+    let last = FatTimestamp::from_unix(Duration::from_secs(u64::max_value()));
+    assert_eq!((last.year(), last.month(), last.day(), last.hour(), last.minute()), (2107, 12, 31, 23, 59));
+
+    fn written() -> Duration {
+        Duration::new(1_600_000_001, 250_000_000) // 2020-09-13 12:26:41.25
+    }
+    fn next_day() -> Duration {
+        Duration::from_secs(1_600_000_001 + 86400)
+    }
+
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(tiny_image(vfat::FatType::Fat32))).expect("mount");
+    vfat.lock(|vfat| vfat.set_clock(written));
+    let mut file = (&vfat).create_file("/stamped.txt").expect("create");
+    file.write_all(b"stamped").expect("write");
+    (&vfat).create_dir("/stamped dir").expect("create dir");
+
+    vfat.lock(|vfat| vfat.set_clock(next_day));
+    let mut buf = Vec::new();
+    (&vfat).open_file("/stamped.txt").expect("open").read_to_end(&mut buf).expect("read");
+
+    let metadata = (&vfat).open("/stamped.txt").expect("reopen").metadata().clone();
+    let created = metadata.created();
+    assert_eq!((created.year(), created.month(), created.day()), (2020, 9, 13));
+    assert_eq!((created.hour(), created.minute(), created.second()), (12, 26, 41));
+    assert_eq!(created.tenths, 125);
+    assert_eq!(metadata.modified().time, Time::new(12, 26, 40));
+    assert_eq!(metadata.accessed().date, Date::new(2020, 9, 14));
+
+    let dot = (&vfat).open("/stamped dir/.").expect("open .").metadata().clone();
+    assert_eq!(dot.created().date, Date::new(2020, 9, 13));
+}
+
+#[test]
+fn test_paths() {
+    use crate::path::{normalize, resolve};
+
+    assert_eq!(normalize("/a/./b/../c").unwrap(), Path::new("/a/c"));
+    assert_eq!(normalize("/../..").unwrap(), Path::new("/"));
+    assert_eq!(normalize("/a/b/").unwrap(), Path::new("/a/b"));
+    expect_variant!(normalize("a/b"), Err(ref e) if e.kind() == io::ErrorKind::InvalidInput);
+    assert_eq!(resolve("/SUB", "../HELLO.TXT").unwrap(), Path::new("/HELLO.TXT"));
+    assert_eq!(resolve("/SUB", "/x/./y").unwrap(), Path::new("/x/y"));
+    expect_variant!(resolve("SUB", "x"), Err(ref e) if e.kind() == io::ErrorKind::InvalidInput);
+
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(tiny_image(vfat::FatType::Fat32))).expect("mount");
+    assert_eq!((&vfat).open("/SUB/./../SUB/long name.txt").expect("open").name(), "long name.txt");
+    assert_eq!((&vfat).open("/SUB/..").expect("open parent").name(), "/");
+    expect_variant!((&vfat).open("SUB"), Err(ref e) if e.kind() == io::ErrorKind::InvalidInput);
+    expect_variant!((&vfat).open("/HELLO.TXT/x"), Err(ref e) if e.kind() == io::ErrorKind::InvalidInput);
+    expect_variant!((&vfat).open("/NOPE/x"), Err(ref e) if e.kind() == io::ErrorKind::NotFound);
+
+    (&vfat).create_file("/SUB/../new.txt").expect("create through ..");
+    (&vfat).open_file("/new.txt").expect("open created");
+    (&vfat).remove("/SUB/./../new.txt").expect("remove through .");
+    expect_variant!((&vfat).remove("/SUB/.."), Err(ref e) if e.kind() == io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_dir_streaming() {
+    let image = SharedImage(Arc::new(Mutex::new(Cursor::new(tiny_image(vfat::FatType::Fat32)))));
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).expect("mount");
+
+    // 16 entries fit in a cluster, so these span three clusters.
+    let dir = (&vfat).create_dir("/big").expect("create dir");
+    for i in 0..40 {
+        dir.create_file(&format!("F{}.TXT", i)).expect("create file");
+    }
+    let entries = dir.entries().expect("entries").collect::<Vec<_>>();
+    assert_eq!(entries.len(), 42);
+    assert_eq!(entries[41].name(), "F39.TXT");
+
+    // Positions are distinct and point at the entries on disk.
+    let positions = entries.iter().map(|entry| entry.position().expect("position")).collect::<Vec<_>>();
+    assert!(positions.windows(2).all(|pair| pair[0] != pair[1]));
+    vfat.lock(|vfat| vfat.flush()).expect("flush");
+    let position = positions[41];
+    let start = (1 + position.sector as usize) * 512 + position.offset;
+    assert_eq!(&image.0.lock().unwrap().get_ref()[start..start + 11], b"F39     TXT");
+    assert_eq!((&vfat).open("/").expect("open root").position(), None);
+
+    // Looking a name up again sees later writes to the entry.
+    let mut file = (&vfat).open_file("/BIG/f39.txt").expect("open");
+    assert_eq!(file.position(), Some(position));
+    file.write_all(b"streamed").expect("write");
+    assert_eq!((&vfat).open_file("/big/F39.TXT").expect("reopen").size(), 8);
+
+    // Removing a name forgets it, even if another takes its place.
+    (&vfat).remove("/big/F39.TXT").expect("remove");
+    expect_variant!((&vfat).open("/big/F39.TXT"), Err(ref e) if e.kind() == io::ErrorKind::NotFound);
+    (&vfat).create_file("/big/OTHER.TXT").expect("create in freed slot");
+    expect_variant!((&vfat).open("/big/F39.TXT"), Err(ref e) if e.kind() == io::ErrorKind::NotFound);
+    let other = (&vfat).open("/big/other.txt").expect("open other");
+    assert_eq!(other.position(), Some(position));
+
+    // A remembered slot whose name changed underneath is not trusted.
+    vfat.lock(|vfat| vfat.write_sector_bytes(position.sector, position.offset, b"RENAMED TXT")).expect("rename");
+    expect_variant!((&vfat).open("/big/OTHER.TXT"), Err(ref e) if e.kind() == io::ErrorKind::NotFound);
+    assert_eq!((&vfat).open("/big/renamed.txt").expect("open renamed").position(), Some(position));
+
+    let long = (&vfat).open("/SUB/long name.txt").expect("open long name").position().expect("position");
+    vfat.lock(|vfat| vfat.write_sector_bytes(long.sector, long.offset - 32 + 1, &[b's', 0])).expect("rename");
+    expect_variant!((&vfat).open("/SUB/long name.txt"), Err(ref e) if e.kind() == io::ErrorKind::NotFound);
+    (&vfat).open("/SUB/song name.txt").expect("open renamed long name");
+}
+
+#[test]
+fn test_lfn_sequence_zero() {
+    let mut data = tiny_image(vfat::FatType::Fat32);
+    // The LFN entry of /SUB/long name.txt, with its sequence number cleared.
+    data[0x1040] = 0x40;
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(data)).expect("mount");
+
+    let names = (&vfat).open_dir("/SUB").expect("open SUB")
+        .entries().expect("entries")
+        .map(|entry| entry.name().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec![".", "..", "LONGNA~1.TXT"]);
+}
@@ -56,9 +56,62 @@ pub trait BlockDevice: Send {
     /// error of `UnexpectedEof` if the length of `buf` is less than
     /// `self.sector_size()`.
     fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize>;
+
+    /// Reads `count` consecutive sectors starting at sector `start` into `buf`.
+    ///
+    /// `count * self.sector_size()` or `buf.len()` bytes, whichever is less,
+    /// are read into `buf`. The number of bytes read is returned.
+    ///
+    /// The default implementation calls `read_sector()` once per sector.
+    /// Devices that can transfer several sectors in one request should
+    /// override it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if seeking or reading from `self` fails.
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        for n in start..start + count {
+            if read >= buf.len() {
+                break;
+            }
+            read += self.read_sector(n, &mut buf[read..])?;
+        }
+        Ok(read)
+    }
+
+    /// Overwrites `count` consecutive sectors starting at sector `start` with
+    /// the contents of `buf`.
+    ///
+    /// `count * self.sector_size()` or `buf.len()` bytes, whichever is less,
+    /// are written. The number of bytes written is returned.
+    ///
+    /// The default implementation calls `write_sector()` once per sector.
+    /// Devices that can transfer several sectors in one request should
+    /// override it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if seeking or writing to `self` fails. Returns an
+    /// error of `UnexpectedEof` if the last sector to be written is only
+    /// partially covered by `buf`.
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        for n in start..start + count {
+            if written >= buf.len() {
+                break;
+            }
+            written += self.write_sector(n, &buf[written..])?;
+        }
+        Ok(written)
+    }
 }
 
 impl<'a, T: BlockDevice> BlockDevice for &'a mut T {
+    fn sector_size(&self) -> u64 {
+        (**self).sector_size()
+    }
+
     fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
         (*self).read_sector(n, buf)
     }
@@ -66,6 +119,14 @@ impl<'a, T: BlockDevice> BlockDevice for &'a mut T {
     fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
         (*self).write_sector(n, buf)
     }
+
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (*self).read_sectors(start, count, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        (*self).write_sectors(start, count, buf)
+    }
 }
 
 macro impl_for_read_write_seek($(<$($gen:tt),*>)* $T:path) {
@@ -87,11 +148,27 @@ macro impl_for_read_write_seek($(<$($gen:tt),*>)* $T:path) {
             self.write_all(&buf[..to_write])?;
             Ok(to_write)
         }
+
+        fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+            let sector_size = self.sector_size();
+            let to_read = ::core::cmp::min((count * sector_size) as usize, buf.len());
+            self.seek(io::SeekFrom::Start(start * sector_size))?;
+            self.read_exact(&mut buf[..to_read])?;
+            Ok(to_read)
+        }
+
+        fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+            let sector_size = self.sector_size();
+            let to_write = ::core::cmp::min((count * sector_size) as usize, buf.len());
+            self.seek(io::SeekFrom::Start(start * sector_size))?;
+            self.write_all(&buf[..to_write])?;
+            Ok(to_write)
+        }
     }
 }
 
 impl_for_read_write_seek!(<'a> shim::io::Cursor<&'a mut [u8]>);
 impl_for_read_write_seek!(shim::io::Cursor<Vec<u8>>);
 impl_for_read_write_seek!(shim::io::Cursor<Box<[u8]>>);
-#[cfg(test)]
+#[cfg(not(feature = "no_std"))]
 impl_for_read_write_seek!(::std::fs::File);
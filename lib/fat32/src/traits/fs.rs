@@ -9,6 +9,18 @@ pub trait File: io::Read + io::Write + io::Seek + Sized {
 
     /// Returns the size of the file in bytes.
     fn size(&self) -> u64;
+
+    /// Performs the device-specific operation `request` on the file, passing
+    /// it `data` and writing any result back to `data`. Returns the number of
+    /// bytes of `data` written.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation, for files that are not devices, returns an
+    /// error of `Other`.
+    fn control(&mut self, _request: u32, _data: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::Other, "not a device"))
+    }
 }
 
 /// Trait implemented by directories in a file system.
@@ -113,4 +125,38 @@ pub trait FileSystem: Sized {
             .into_dir()
             .ok_or(io::Error::new(io::ErrorKind::Other, "not a directory"))
     }
+
+    /// Creates an empty file at `path` and returns it. `path` must be
+    /// absolute and its parent must exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error kind of `AlreadyExists` if an entry exists at `path`.
+    /// File systems that cannot be written return `PermissionDenied`, which
+    /// is the default.
+    fn create_file<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::File> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "read-only file system"))
+    }
+
+    /// Creates an empty directory at `path` and returns it. `path` must be
+    /// absolute and its parent must exist.
+    ///
+    /// # Errors
+    ///
+    /// As for `create_file()`.
+    fn create_dir<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::Dir> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "read-only file system"))
+    }
+
+    /// Removes the file or empty directory at `path`. `path` must be
+    /// absolute.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error kind of `NotFound` if no entry exists at `path` and
+    /// `Other` if it is a directory that is not empty. File systems that
+    /// cannot be written return `PermissionDenied`, which is the default.
+    fn remove<P: AsRef<Path>>(self, _path: P) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "read-only file system"))
+    }
 }
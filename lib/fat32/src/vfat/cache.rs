@@ -25,6 +25,23 @@ pub struct CachedPartition {
     device: Box<dyn BlockDevice>,
     cache: HashMap<u64, CacheEntry>,
     partition: Partition,
+    hits: u64,
+    misses: u64,
+}
+
+/// Counters describing a `CachedPartition`, as returned by
+/// `CachedPartition::stats()`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of sectors held in memory.
+    pub cached: usize,
+    /// The number of cached sectors not yet written back.
+    pub dirty: usize,
+    /// Sector accesses served from memory.
+    pub hits: u64,
+    /// Sector accesses that went to the device, whether or not the sector was
+    /// then cached.
+    pub misses: u64,
 }
 
 impl CachedPartition {
@@ -54,6 +71,8 @@ impl CachedPartition {
             device: Box::new(device),
             cache: HashMap::new(),
             partition: partition,
+            hits: 0,
+            misses: 0,
         }
     }
 
@@ -77,21 +96,51 @@ impl CachedPartition {
         Some(physical_sector)
     }
 
+    /// Maps the run of `count` virtual sectors starting at `virt` to its first
+    /// physical sector. Returns `None` if any sector of the run is out of
+    /// range.
+    fn run_to_physical(&self, virt: u64, count: u64) -> Option<u64> {
+        self.virtual_to_physical(virt + count.saturating_sub(1))?;
+        self.virtual_to_physical(virt)
+    }
+
     fn read_sector_disk(&mut self, sector: u64) -> io::Result<CacheEntry> {
         let physical_sector = self.virtual_to_physical(sector).ok_or(io::ErrorKind::Other)?;
         //println!("reading {} -> {}", physical_sector, sector);
-        let mut data = Vec::with_capacity((self.device.sector_size() * self.factor()) as usize);
-        for i in 0..self.factor() {
-            self.device.read_all_sector(physical_sector + i, &mut data)?;
-        }
+        let mut data = vec![0u8; (self.device.sector_size() * self.factor()) as usize];
+        let read = self.device.read_sectors(physical_sector, self.factor(), &mut data)?;
+        data.truncate(read);
         Ok(CacheEntry {
             data : data,
             dirty : false,
         })
     }
 
+    /// Reads `count` sectors starting at `sector` straight from the device
+    /// into `buf` with a single device request, bypassing the cache.
+    fn read_sectors_disk(&mut self, sector: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let physical_sector = self.run_to_physical(sector, count).ok_or(io::ErrorKind::Other)?;
+        self.device.read_sectors(physical_sector, count * self.factor(), buf)
+    }
+
+    /// Writes `count` sectors starting at `sector` from `buf` straight to the
+    /// device with a single device request, bypassing the cache.
+    fn write_sectors_disk(&mut self, sector: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        let physical_sector = self.run_to_physical(sector, count).ok_or(io::ErrorKind::Other)?;
+        self.device.write_sectors(physical_sector, count * self.factor(), buf)
+    }
+
+    /// Returns the end of the run of uncached sectors that begins at `sector`,
+    /// stopping at `end`.
+    fn uncached_run_end(&self, sector: u64, end: u64) -> u64 {
+        (sector..end).find(|s| self.cache.contains_key(s)).unwrap_or(end)
+    }
+
     fn cache_sector(&mut self, sector: u64) -> io::Result<()> {
-        if !self.cache.contains_key(&sector) {
+        if self.cache.contains_key(&sector) {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
             let entry = self.read_sector_disk(sector)?;
             self.cache.insert(sector, entry);
         }
@@ -125,6 +174,38 @@ impl CachedPartition {
         self.cache_sector(sector)?;
         Ok(&self.cache.get(&sector).ok_or(io::ErrorKind::Other)?.data)
     }
+
+    /// Returns the number of cached and dirty sectors and the hit and miss
+    /// counts since the partition was created.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            cached: self.cache.len(),
+            dirty: self.cache.values().filter(|entry| entry.dirty).count(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+
+    /// Returns the first physical sector of the partition and the number of
+    /// physical sectors it spans.
+    pub fn device_range(&self) -> (u64, u64) {
+        (self.partition.start, self.partition.num_sectors * self.factor())
+    }
+
+    /// Writes every dirty cached sector back to the disk and marks it clean.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is an error writing a sector to the disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let factor = self.factor();
+        let start = self.partition.start;
+        for (&sector, entry) in self.cache.iter_mut().filter(|(_, entry)| entry.dirty) {
+            self.device.write_sectors(start + sector * factor, factor, &entry.data)?;
+            entry.dirty = false;
+        }
+        Ok(())
+    }
 }
 
 // FIXME: Implement `BlockDevice` for `CacheDevice`. The `read_sector` and
@@ -145,11 +226,67 @@ impl BlockDevice for CachedPartition {
     }
 
     fn write_sector(&mut self, sector: u64, buf: &[u8]) -> io::Result<usize> {
-        //TODO
         if buf.len() < self.sector_size() as usize {
             return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "buf must not be less than sector size"));
         }
-        Ok(0)
+        let data = self.get_mut(sector)?;
+        let len = data.len();
+        data.copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    /// Reads `count` sectors starting at `start` into `buf`. Sectors that are
+    /// already cached are served from the cache; every run of uncached sectors
+    /// is read from the device in one request and is not added to the cache.
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size() as usize;
+        let end = start + cmp::min(count, (buf.len() / sector_size) as u64);
+
+        let mut read = 0;
+        let mut sector = start;
+        while sector < end {
+            if self.cache.contains_key(&sector) {
+                read += self.read_sector(sector, &mut buf[read..])?;
+                sector += 1;
+            } else {
+                let run_end = self.uncached_run_end(sector, end);
+                self.misses += run_end - sector;
+                read += self.read_sectors_disk(sector, run_end - sector, &mut buf[read..])?;
+                sector = run_end;
+            }
+        }
+
+        // A trailing partial sector still goes through the cache.
+        if sector < start + count && read < buf.len() {
+            read += self.read_sector(sector, &mut buf[read..])?;
+        }
+        Ok(read)
+    }
+
+    /// Writes `count` sectors starting at `start` from `buf`. Sectors that are
+    /// already cached are updated in the cache and marked dirty; every run of
+    /// uncached sectors is written to the device in one request.
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size() as usize;
+        if buf.len() < (count as usize) * sector_size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "buf must cover every sector"));
+        }
+
+        let end = start + count;
+        let mut written = 0;
+        let mut sector = start;
+        while sector < end {
+            if self.cache.contains_key(&sector) {
+                written += self.write_sector(sector, &buf[written..])?;
+                sector += 1;
+            } else {
+                let run_end = self.uncached_run_end(sector, end);
+                self.misses += run_end - sector;
+                written += self.write_sectors_disk(sector, run_end - sector, &buf[written..])?;
+                sector = run_end;
+            }
+        }
+        Ok(written)
     }
 }
 
@@ -1,15 +1,15 @@
 use alloc::string::String;
-use alloc::vec::{ Vec, IntoIter };
+use alloc::vec::Vec;
 
 use shim::const_assert_size;
 use shim::ffi::OsStr;
 use shim::io;
+use shim::ioerr;
 use shim::newioerr;
 
 use crate::traits;
-use crate::util::VecExt;
 use crate::vfat::{Attributes, Date, Metadata, Time, Timestamp};
-use crate::vfat::{Cluster, Entry, File, VFatHandle};
+use crate::vfat::{Cluster, Entry, File, VFat, VFatHandle};
 
 
 #[derive(Debug)]
@@ -18,8 +18,36 @@ pub struct Dir<HANDLE: VFatHandle> {
     pub name: String,
     pub start_cluster: Cluster,
     pub metadata: Metadata,
+    pub(crate) slot: Option<Slot>,
 }
 
+/// Where an entry is stored: entries `first..=index` of the directory that
+/// begins at `dir`, the last being the 8.3 entry, at `position`, and any
+/// before it the entry's LFN entries. The root directory has no slot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Slot {
+    pub dir: Cluster,
+    pub first: usize,
+    pub index: usize,
+    pub position: Position,
+}
+
+/// The on-disk position of a directory entry: the logical sector of the
+/// volume holding it and its byte offset in that sector.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub sector: u64,
+    pub offset: usize,
+}
+
+/// Attribute of directory entries.
+const ATTR_DIRECTORY: u8 = 0x10;
+/// Attribute of file entries; marks them as changed since the last backup.
+const ATTR_ARCHIVE: u8 = 0x20;
+
+/// Number of UCS-2 characters held by one LFN entry.
+const LFN_CHARS: usize = 13;
+
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatRegularDirEntry {
@@ -39,6 +67,31 @@ pub struct VFatRegularDirEntry {
 }
 
 impl VFatRegularDirEntry {
+    pub(crate) fn new(short_name: [u8; 11], attr: Attributes, cluster: Cluster, size: u32) -> VFatRegularDirEntry {
+        let mut name = [0u8; 8];
+        let mut extension = [0u8; 3];
+        name.copy_from_slice(&short_name[..8]);
+        extension.copy_from_slice(&short_name[8..]);
+
+        let mut entry = VFatRegularDirEntry {
+            name: name,
+            extension: extension,
+            attr: attr,
+            windows_nt_reserved: 0,
+            ctime_tenths: 0,
+            ctime: Time::default(),
+            cdate: Date::default(),
+            adate: Date::default(),
+            cluster_num_high: 0,
+            mtime: Time::default(),
+            mdate: Date::default(),
+            cluster_num_low: 0,
+            file_size: size,
+        };
+        entry.set_cluster_number(cluster);
+        entry
+    }
+
     pub fn cluster_number(&self) -> Cluster {
         Cluster::from((self.cluster_num_high as u32) << 16 | self.cluster_num_low as u32)
     }
@@ -49,17 +102,86 @@ impl VFatRegularDirEntry {
             ctime: Timestamp {
                 time: self.ctime,
                 date: self.cdate,
+                tenths: self.ctime_tenths,
             },
+            // FAT records only the date of the last access.
             atime: Timestamp {
                 time: Time::from(0),
                 date: self.adate,
+                tenths: 0,
             },
             mtime: Timestamp {
                 time: self.mtime,
                 date: self.mdate,
+                tenths: 0,
             },
         }
     }
+
+    pub(crate) fn set_created(&mut self, time: Timestamp) {
+        self.ctime = time.time;
+        self.cdate = time.date;
+        self.ctime_tenths = time.tenths;
+    }
+
+    /// Sets the modification time, which has a resolution of two seconds.
+    pub(crate) fn set_modified(&mut self, time: Timestamp) {
+        self.mtime = time.time;
+        self.mdate = time.date;
+    }
+
+    pub(crate) fn set_accessed(&mut self, date: Date) {
+        self.adate = date;
+    }
+
+    pub(crate) fn attributes(&self) -> Attributes {
+        self.attr
+    }
+
+    pub(crate) fn file_size(&self) -> u32 {
+        self.file_size
+    }
+
+    pub(crate) fn set_file_size(&mut self, size: u32) {
+        self.file_size = size;
+    }
+
+    pub(crate) fn set_cluster_number(&mut self, cluster: Cluster) {
+        self.cluster_num_high = (cluster.get() >> 16) as u16;
+        self.cluster_num_low = cluster.get() as u16;
+    }
+
+    /// Returns the 8.3 name as it is displayed, e.g. `HELLO.TXT`.
+    pub(crate) fn short_name(&self) -> String {
+        let name = String::from_utf8_lossy(&self.name);
+        let extension = String::from_utf8_lossy(&self.extension);
+
+        let mut short_name = String::from(name.trim_end());
+        if extension.trim_end().len() > 0 {
+            short_name.push_str(".");
+            short_name.push_str(extension.trim_end());
+        }
+        short_name
+    }
+
+    /// Returns the checksum of the raw 8.3 name that every LFN entry belonging
+    /// to this entry must carry.
+    pub(crate) fn short_name_checksum(&self) -> u8 {
+        short_name_checksum(&self.raw_short_name())
+    }
+
+    /// Returns the 8.3 name as stored, space padded and without the dot.
+    pub(crate) fn raw_short_name(&self) -> [u8; 11] {
+        let mut raw = [0u8; 11];
+        raw[..8].copy_from_slice(&self.name);
+        raw[8..].copy_from_slice(&self.extension);
+        raw
+    }
+
+    /// Returns the on-disk representation of `self`.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        unsafe { ::core::slice::from_raw_parts(self as *const Self as *const u8, 32) }
+    }
 }
 
 const_assert_size!(VFatRegularDirEntry, 32);
@@ -77,6 +199,51 @@ pub struct VFatLfnDirEntry {
     name3: [u16; 2],
 }
 
+impl VFatLfnDirEntry {
+    /// Returns LFN entry number `sequence` (from 1, ORed with `0x40` for the
+    /// last) holding `chars` of the name whose 8.3 name has checksum
+    /// `checksum`.
+    pub(crate) fn new(sequence: u8, checksum: u8, chars: &[u16; LFN_CHARS]) -> VFatLfnDirEntry {
+        let (mut name1, mut name2, mut name3) = ([0u16; 5], [0u16; 6], [0u16; 2]);
+        name1.copy_from_slice(&chars[..5]);
+        name2.copy_from_slice(&chars[5..11]);
+        name3.copy_from_slice(&chars[11..]);
+
+        VFatLfnDirEntry {
+            sequence: sequence,
+            name1: name1,
+            attr: Attributes::from(0x0F),
+            zero_type: 0,
+            checksum: checksum,
+            name2: name2,
+            zeros: 0,
+            name3: name3,
+        }
+    }
+
+    /// Returns the on-disk representation of `self`.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        unsafe { ::core::slice::from_raw_parts(self as *const Self as *const u8, 32) }
+    }
+
+    pub(crate) fn sequence(&self) -> u8 {
+        self.sequence
+    }
+
+    pub(crate) fn checksum(&self) -> u8 {
+        self.checksum
+    }
+
+    /// Returns the 13 UCS-2 characters of the name stored in this entry.
+    pub(crate) fn name_chars(&self) -> [u16; 13] {
+        let mut chars = [0u16; 13];
+        chars[..5].copy_from_slice(&{ self.name1 });
+        chars[5..11].copy_from_slice(&{ self.name2 });
+        chars[11..].copy_from_slice(&{ self.name3 });
+        chars
+    }
+}
+
 const_assert_size!(VFatLfnDirEntry, 32);
 
 #[repr(C, packed)]
@@ -96,6 +263,26 @@ pub union VFatDirEntry {
     long_filename: VFatLfnDirEntry,
 }
 
+impl VFatDirEntry {
+    /// Returns the first byte of the entry: `0` marks the end of the
+    /// directory and `0xE5` a deleted entry.
+    pub(crate) fn marker(&self) -> u8 {
+        unsafe { self.unknown.sequence }
+    }
+
+    pub(crate) fn is_lfn(&self) -> bool {
+        unsafe { self.unknown.attr }.lfn()
+    }
+
+    pub(crate) fn regular(&self) -> VFatRegularDirEntry {
+        unsafe { self.regular }
+    }
+
+    pub(crate) fn long_filename(&self) -> VFatLfnDirEntry {
+        unsafe { self.long_filename }
+    }
+}
+
 impl<HANDLE: VFatHandle> Dir<HANDLE> {
     /// Finds the entry named `name` in `self` and returns it. Comparison is
     /// case-insensitive.
@@ -111,13 +298,21 @@ impl<HANDLE: VFatHandle> Dir<HANDLE> {
         use traits::Dir;
         use traits::Entry;
 
-        //TODO this
         let name_str = name.as_ref()
                            .to_str()
                            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "name contains invalid UTF-8 characters"))?;
 
-        self.entries()?.find(|entry| entry.name().eq_ignore_ascii_case(name_str))
-                       .ok_or(io::Error::new(io::ErrorKind::NotFound, "name not found"))
+        let dir = self.start_cluster;
+        if let Some((name, entry, slot)) = self.vfat.lock(|vfat| vfat.cached_lookup(dir, name_str))? {
+            return Ok(make_entry(self.vfat.clone(), name, &entry, slot));
+        }
+
+        let entry = self.entries()?.find(|entry| entry.name().eq_ignore_ascii_case(name_str))
+                                   .ok_or(io::Error::new(io::ErrorKind::NotFound, "name not found"))?;
+        if let Some(slot) = entry.slot() {
+            self.vfat.lock(|vfat| vfat.remember_lookup(String::from(entry.name()), slot));
+        }
+        Ok(entry)
     }
 
     pub fn metadata(&self) -> &Metadata {
@@ -127,13 +322,411 @@ impl<HANDLE: VFatHandle> Dir<HANDLE> {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Returns where the directory's 8.3 entry is stored, or `None` for the
+    /// root directory.
+    pub fn position(&self) -> Option<Position> {
+        self.slot.map(|slot| slot.position)
+    }
+
+    /// Creates an empty file named `name` in `self` and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if `name` is not a valid file name,
+    /// `AlreadyExists` if `self` already has an entry named `name`, and
+    /// `Other` if a FAT12/16 root directory is full or the volume is full.
+    pub fn create_file(&self, name: &str) -> io::Result<File<HANDLE>> {
+        let (slot, metadata) = self.insert(name, Attributes::from(ATTR_ARCHIVE), Cluster::from(0))?;
+
+        Ok(File {
+            vfat: self.vfat.clone(),
+            name: String::from(name),
+            start_cluster: Cluster::from(0),
+            metadata: metadata,
+            size: 0,
+            cursor: 0,
+            slot: Some(slot),
+        })
+    }
+
+    /// Creates an empty directory named `name` in `self` and returns it.
+    ///
+    /// # Errors
+    ///
+    /// As for `create_file()`.
+    pub fn create_dir(&self, name: &str) -> io::Result<Dir<HANDLE>> {
+        validate_name(name)?;
+
+        // `..` refers to the root directory as cluster 0.
+        let parent = match self.slot {
+            Some(_) => self.start_cluster,
+            None => Cluster::from(0),
+        };
+        let attr = Attributes::from(ATTR_DIRECTORY);
+        let cluster = self.vfat.lock(|vfat| -> io::Result<Cluster> {
+            let cluster = vfat.alloc_cluster(None)?;
+            let dot = new_entry(vfat, *b".          ", attr, cluster);
+            let dotdot = new_entry(vfat, *b"..         ", attr, parent);
+            vfat.set_dir_entry(cluster, 0, dot.as_bytes())?;
+            vfat.set_dir_entry(cluster, 1, dotdot.as_bytes())?;
+            Ok(cluster)
+        })?;
+
+        match self.insert(name, attr, cluster) {
+            Ok((slot, metadata)) => Ok(Dir {
+                vfat: self.vfat.clone(),
+                name: String::from(name),
+                start_cluster: cluster,
+                metadata: metadata,
+                slot: Some(slot),
+            }),
+            Err(e) => {
+                self.vfat.lock(|vfat| vfat.free_chain(cluster))?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Removes the entry named `name` from `self` and frees its clusters.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if `self` has no entry named `name` and `Other` if
+    /// the entry is a directory that is not empty.
+    pub fn remove(&self, name: &str) -> io::Result<()> {
+        use traits::{Dir, Entry};
+
+        validate_name(name)?;
+        let entry = self.find(name)?;
+        let (slot, cluster) = match entry {
+            super::Entry::EntryFile(ref f) => (f.slot, f.start_cluster),
+            super::Entry::EntryDir(ref d) => {
+                if d.entries()?.any(|e| e.name() != "." && e.name() != "..") {
+                    return ioerr!(Other, "directory not empty");
+                }
+                (d.slot, d.start_cluster)
+            }
+        };
+        let slot = slot.ok_or(newioerr!(InvalidInput, "cannot remove the root directory"))?;
+
+        self.vfat.lock(|vfat| {
+            vfat.forget_lookups(slot.dir);
+            vfat.forget_lookups(cluster);
+            for index in slot.first..=slot.index {
+                vfat.mark_dir_entry_deleted(slot.dir, index)?;
+            }
+            if cluster.get() != 0 {
+                vfat.free_chain(cluster)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Adds an entry named `name`, with its LFN entries, to `self` and
+    /// returns where it was stored and its metadata.
+    fn insert(&self, name: &str, attr: Attributes, cluster: Cluster) -> io::Result<(Slot, Metadata)> {
+        validate_name(name)?;
+        if self.find(name).is_ok() {
+            return ioerr!(AlreadyExists, "entry already exists");
+        }
+
+        // Only the name decides whether LFN entries are needed, and so how
+        // many free entries to look for.
+        let (_, needs_lfn) = short_name_for(name, &[]);
+        let len = if needs_lfn { (name.encode_utf16().count() + LFN_CHARS - 1) / LFN_CHARS + 1 } else { 1 };
+        let dir = self.start_cluster;
+        let (taken, first) = DirEntryIter::new(self.vfat.clone(), dir)?.free_slots(len)?;
+        let (short_name, _) = short_name_for(name, &taken);
+
+        self.vfat.lock(|vfat| {
+            let mut set: Vec<[u8; 32]> = Vec::new();
+            if needs_lfn {
+                set.extend(lfn_entries(name, short_name_checksum(&short_name)));
+            }
+            let entry = new_entry(vfat, short_name, attr, cluster);
+            let mut regular = [0u8; 32];
+            regular.copy_from_slice(entry.as_bytes());
+            set.push(regular);
+
+            vfat.grow_dir(dir, first + set.len())?;
+            for (i, raw) in set.iter().enumerate() {
+                vfat.set_dir_entry(dir, first + i, raw)?;
+            }
+
+            let index = first + set.len() - 1;
+            let position = vfat.dir_entry_position(dir, index)?;
+            Ok((Slot { dir: dir, first: first, index: index, position: position }, entry.metadata()))
+        })
+    }
+}
+
+/// Returns a new, empty 8.3 entry created, modified and accessed now, if
+/// `vfat` has a clock.
+fn new_entry<HANDLE: VFatHandle>(vfat: &VFat<HANDLE>, short_name: [u8; 11], attr: Attributes, cluster: Cluster)
+    -> VFatRegularDirEntry
+{
+    let mut entry = VFatRegularDirEntry::new(short_name, attr, cluster, 0);
+    if let Some(now) = vfat.now() {
+        entry.set_created(now);
+        entry.set_modified(now);
+        entry.set_accessed(now.date);
+    }
+    entry
+}
+
+/// Returns the checksum of the raw 8.3 name `short_name` that every LFN
+/// entry of the name must carry.
+fn short_name_checksum(short_name: &[u8; 11]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &b| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b))
+}
+
+/// Returns an error of `InvalidInput` if `name` cannot name an entry.
+fn validate_name(name: &str) -> io::Result<()> {
+    let invalid = |c: char| c < ' ' || "\"*/:<>?\\|".contains(c);
+    if name.is_empty() || name == "." || name == ".." || name.encode_utf16().count() > 255
+        || name.chars().any(invalid) {
+        return ioerr!(InvalidInput, "invalid file name");
+    }
+    Ok(())
 }
 
+/// Returns `true` if `c` may appear in an 8.3 name as is.
+fn is_short_name_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || "$%'-_@~`!(){}^#&".contains(c)
+}
+
+/// Returns the 8.3 name for `name` and whether LFN entries must accompany
+/// it. Names that are valid upper case 8.3 names are stored as is; others get
+/// a numbered `BASIS~N.EXT` name not among `taken`.
+fn short_name_for(name: &str, taken: &[[u8; 11]]) -> ([u8; 11], bool) {
+    let (base, extension) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+        _ => (name, ""),
+    };
+
+    let mut short_name = [b' '; 11];
+    if base.len() <= 8 && extension.len() <= 3 && base.chars().chain(extension.chars()).all(is_short_name_char) {
+        short_name[..base.len()].copy_from_slice(base.as_bytes());
+        short_name[8..8 + extension.len()].copy_from_slice(extension.as_bytes());
+        return (short_name, false);
+    }
+
+    let basis = |part: &str, len: usize| -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| c.to_ascii_uppercase())
+            .map(|c| if is_short_name_char(c) { c as u8 } else { b'_' })
+            .take(len)
+            .collect()
+    };
+    let base = basis(base, 8);
+    let extension = basis(extension, 3);
+
+    short_name[8..8 + extension.len()].copy_from_slice(&extension);
+    for n in 1.. {
+        let tail = format!("~{}", n);
+        let keep = ::core::cmp::min(base.len(), 8 - tail.len());
+        short_name[..8].copy_from_slice(b"        ");
+        short_name[..keep].copy_from_slice(&base[..keep]);
+        short_name[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        if !taken.contains(&short_name) {
+            break;
+        }
+    }
+    (short_name, true)
+}
+
+/// Returns the LFN entries for `name` in on-disk order, last part first.
+fn lfn_entries(name: &str, checksum: u8) -> Vec<[u8; 32]> {
+    let mut chars: Vec<u16> = name.encode_utf16().collect();
+    if chars.len() % LFN_CHARS != 0 {
+        chars.push(0);
+        while chars.len() % LFN_CHARS != 0 {
+            chars.push(0xFFFF);
+        }
+    }
+
+    let count = chars.len() / LFN_CHARS;
+    chars.chunks(LFN_CHARS)
+        .enumerate()
+        .rev()
+        .map(|(i, part)| {
+            let mut part_chars = [0u16; LFN_CHARS];
+            part_chars.copy_from_slice(part);
+            let sequence = (i + 1) as u8 | if i + 1 == count { 0x40 } else { 0 };
+
+            let mut raw = [0u8; 32];
+            raw.copy_from_slice(VFatLfnDirEntry::new(sequence, checksum, &part_chars).as_bytes());
+            raw
+        })
+        .collect()
+}
+
+/// Returns the entry described by the 8.3 entry `entry`, named `name` and
+/// stored at `slot`.
+fn make_entry<HANDLE: VFatHandle>(vfat: HANDLE, name: String, entry: &VFatRegularDirEntry, slot: Slot)
+    -> Entry<HANDLE>
+{
+    if entry.attr.directory() {
+        Entry::EntryDir(Dir {
+            name: name,
+            start_cluster: entry.cluster_number(),
+            vfat: vfat,
+            metadata: entry.metadata(),
+            slot: Some(slot),
+        })
+    } else {
+        Entry::EntryFile(File {
+            name: name,
+            start_cluster: entry.cluster_number(),
+            vfat: vfat,
+            metadata: entry.metadata(),
+            size: entry.file_size,
+            cursor: 0,
+            slot: Some(slot),
+        })
+    }
+}
+
+/// The part of a directory that a `DirEntryIter` reads next.
+#[derive(Debug, Copy, Clone)]
+enum Chunk {
+    /// A cluster of the directory's chain.
+    Cluster(Cluster),
+    /// A sector of a FAT12/16 root directory, and the sector past its end.
+    RootSector(u64, u64),
+    End,
+}
+
+/// An iterator over the entries of a directory that reads the directory one
+/// cluster at a time.
 pub struct DirEntryIter<HANDLE: VFatHandle> {
-    entries: IntoIter<VFatDirEntry>,
     vfat: HANDLE,
+    dir: Cluster,
+    /// The raw entries of the cluster, or root directory sector, being read.
+    buf: Vec<u8>,
+    /// The logical sector at which `buf` starts.
+    buf_sector: u64,
+    /// The byte offset in `buf` of the next entry.
+    offset: usize,
+    sector_size: usize,
+    next: Chunk,
+    /// The index in the directory of the next entry.
+    index: usize,
 }
 
+impl<HANDLE: VFatHandle> DirEntryIter<HANDLE> {
+    /// Returns an iterator over the directory that begins at `dir`, having
+    /// read its first cluster.
+    fn new(vfat: HANDLE, dir: Cluster) -> io::Result<DirEntryIter<HANDLE>> {
+        // `..` entries of directories below the root refer to it as cluster 0.
+        let (first, sector_size) = vfat.lock(|vfat| {
+            let first = match vfat.root_dir_region() {
+                _ if dir.get() != 0 => Chunk::Cluster(dir),
+                Some((sector, sectors)) => Chunk::RootSector(sector, sector + sectors),
+                None => Chunk::Cluster(vfat.root_cluster()),
+            };
+            (first, vfat.bytes_per_sector() as usize)
+        });
+
+        let mut iter = DirEntryIter {
+            vfat: vfat,
+            dir: dir,
+            buf: Vec::new(),
+            buf_sector: 0,
+            offset: 0,
+            sector_size: sector_size,
+            next: first,
+            index: 0,
+        };
+        // An unreadable directory is an error rather than an empty one.
+        iter.load()?;
+        Ok(iter)
+    }
+
+    /// Reads the next chunk of the directory into `buf`. Returns `false` if
+    /// there is none.
+    fn load(&mut self) -> io::Result<bool> {
+        let (chunk, sector_size) = (self.next, self.sector_size);
+        let buf = &mut self.buf;
+        let (sector, next) = match chunk {
+            Chunk::End => return Ok(false),
+            Chunk::Cluster(cluster) => self.vfat.lock(|vfat| -> io::Result<(u64, Chunk)> {
+                if cluster.get() < 2 {
+                    return ioerr!(InvalidData, "invalid directory cluster");
+                }
+                buf.resize(vfat.cluster_size(), 0);
+                vfat.read_cluster(cluster, 0, buf)?;
+                let next = vfat.next_cluster(cluster)?.map_or(Chunk::End, Chunk::Cluster);
+                Ok((vfat.cluster_start_sector(cluster), next))
+            })?,
+            Chunk::RootSector(sector, end) => self.vfat.lock(|vfat| -> io::Result<(u64, Chunk)> {
+                buf.resize(sector_size, 0);
+                vfat.read_sector(sector, buf)?;
+                let next = if sector + 1 < end { Chunk::RootSector(sector + 1, end) } else { Chunk::End };
+                Ok((sector, next))
+            })?,
+        };
+
+        self.buf_sector = sector;
+        self.offset = 0;
+        self.next = next;
+        Ok(true)
+    }
+
+    /// Returns the next raw entry and its position. A read error ends the
+    /// directory.
+    fn next_raw(&mut self) -> Option<(VFatDirEntry, Position)> {
+        self.read_raw().ok()?
+    }
+
+    /// Returns the next raw entry and its position, or `None` past the end of
+    /// the directory.
+    fn read_raw(&mut self) -> io::Result<Option<(VFatDirEntry, Position)>> {
+        if self.offset >= self.buf.len() && !self.load()? {
+            return Ok(None);
+        }
+
+        let position = Position {
+            sector: self.buf_sector + (self.offset / self.sector_size) as u64,
+            offset: self.offset % self.sector_size,
+        };
+        let mut raw = [0u8; 32];
+        raw.copy_from_slice(&self.buf[self.offset..self.offset + 32]);
+        self.offset += 32;
+        Ok(Some((unsafe { ::core::mem::transmute::<[u8; 32], VFatDirEntry>(raw) }, position)))
+    }
+
+    /// Reads the rest of the directory and returns the raw 8.3 names in it
+    /// and the index of the first run of `len` unused entries. Entries past
+    /// the end marker are unused, as are any past the end of the directory,
+    /// so the run may extend beyond it.
+    fn free_slots(&mut self, len: usize) -> io::Result<(Vec<[u8; 11]>, usize)> {
+        let mut taken = Vec::new();
+        let mut start = 0;
+        let mut run = None;
+        while let Some((entry, _)) = self.read_raw()? {
+            let index = self.index;
+            self.index += 1;
+            match entry.marker() {
+                0 => break,
+                0xE5 => {
+                    if run.is_none() && index + 1 - start >= len {
+                        run = Some(start);
+                    }
+                }
+                _ => {
+                    start = index + 1;
+                    if !entry.is_lfn() {
+                        taken.push(entry.regular().raw_short_name());
+                    }
+                }
+            }
+        }
+        Ok((taken, run.unwrap_or(start)))
+    }
+}
 
 impl<HANDLE: VFatHandle> Iterator for DirEntryIter<HANDLE> {
     type Item = Entry<HANDLE>;
@@ -141,11 +734,21 @@ impl<HANDLE: VFatHandle> Iterator for DirEntryIter<HANDLE> {
         const lfn_len: usize = 13;
         let mut lfn = [0u16; 31 * lfn_len];
         let mut is_lfn = false;
+        let mut corrupt = false;
+        let mut first = None;
+
+        while let Some((ref entry, position)) = self.next_raw() {
+            let index = self.index;
+            self.index += 1;
 
-        for ref entry in self.entries.by_ref() {
             let unknown = unsafe { entry.unknown };
             match unknown.sequence {
-                0 => return None,
+                0 => {
+                    // Nothing follows the end marker; don't read further.
+                    self.next = Chunk::End;
+                    self.offset = self.buf.len();
+                    return None;
+                }
                 0xe5 => continue,
                 _ => (),
             };
@@ -153,19 +756,22 @@ impl<HANDLE: VFatHandle> Iterator for DirEntryIter<HANDLE> {
             if unknown.attr.lfn() {
                 let entry = unsafe { entry.long_filename };
                 is_lfn = true;
-                let entry_pos = ((entry.sequence & 0x1f) - 1) as usize;
+                first = first.or(Some(index));
+                let sequence = entry.sequence & 0x1f;
+                if sequence == 0 {
+                    // A corrupt run: its entry falls back to its short name.
+                    corrupt = true;
+                    continue;
+                }
+                let entry_pos = (sequence - 1) as usize;
 
-                //lfn.copy_from_slice(&entry.name1);
-                //lfn.copy_from_slice(&entry.name2);
-                //lfn.copy_from_slice(&entry.name3);
                 lfn[entry_pos * lfn_len      ..entry_pos * lfn_len + 5 ].copy_from_slice(&entry.name1);
                 lfn[entry_pos * lfn_len + 5  ..entry_pos * lfn_len + 11].copy_from_slice(&entry.name2);
                 lfn[entry_pos * lfn_len + 11 ..entry_pos * lfn_len + 13].copy_from_slice(&entry.name3);
             } else {
                 let entry = unsafe { entry.regular };
-                let name = if !is_lfn {
-                    let mut name = entry.name.clone();
-                    let name = ::core::str::from_utf8(&name).ok()?.trim_end();
+                let name = if !is_lfn || corrupt {
+                    let name = ::core::str::from_utf8(&entry.name).ok()?.trim_end();
                     let extension = ::core::str::from_utf8(&entry.extension).ok()?.trim_end();
 
                     let mut name = String::from(name);
@@ -179,87 +785,17 @@ impl<HANDLE: VFatHandle> Iterator for DirEntryIter<HANDLE> {
                     String::from_utf16(&lfn[..len]).ok()?
                 };
 
-                //println!("name {} ", name);
-                return if entry.attr.directory() {
-                    Some(Entry::EntryDir(Dir {
-                        name: name,
-                        start_cluster: entry.cluster_number(),
-                        vfat: self.vfat.clone(),
-                        metadata: entry.metadata(),
-                    }))
-                } else {
-                    Some(Entry::EntryFile(File {
-                        name: name,
-                        start_cluster: entry.cluster_number(),
-                        vfat: self.vfat.clone(),
-                        metadata: entry.metadata(),
-                        size: entry.file_size,
-                        cursor: 0,
-                    }))
+                let slot = Slot {
+                    dir: self.dir,
+                    first: if is_lfn { first.unwrap_or(index) } else { index },
+                    index: index,
+                    position: position,
                 };
+                return Some(make_entry(self.vfat.clone(), name, &entry, slot));
             }
         }
         None
     }
-    /*fn next(&mut self) -> Option<Self::Item> {
-            // TODO
-        //println!("NEXT");
-        let mut lfn: Vec<u16> = Vec::new();
-
-        for entry in self.entries.as_slice().iter() {
-            let unknown = unsafe { entry.unknown };
-            match unknown.sequence {
-                0 => return None,
-                0xe5 => continue,
-                _ => (),
-
-
-            if unknown.attr.lfn() {
-                let entry = unsafe { entry.long_filename };
-                lfn.copy_from_slice(&entry.name1);
-                lfn.copy_from_slice(&entry.name2);
-                lfn.copy_from_slice(&entry.name3);
-
-            } else {
-                let entry = unsafe { entry.regular };
-
-                let name = if lfn.len() > 0 {
-                    let name = ::core::str::from_utf8(&entry.name).ok()?.trim_end();
-                    let ext = ::core::str::from_utf8(&entry.extension).ok()?.trim_end();
-                    let mut name_str = String::from(name);
-                    if ext.len() > 0 {
-                        name_str.push_str(".");
-                        name_str.push_str(ext);
-                    }
-                    name_str
-                } else {
-                    String::from_utf16(&lfn).ok()?
-                };
-
-                println!("name {} ", name);
-
-                return if entry.attr.directory() {
-                    Some(Entry::EntryDir(Dir {
-                        name: name,
-                        start_cluster: entry.cluster_number(),
-                        vfat: self.vfat.clone(),
-                        metadata: entry.metadata(),
-                    }))
-                } else {
-                    Some(Entry::EntryFile(File {
-                        name: name,
-                        start_cluster: entry.cluster_number(),
-                        vfat: self.vfat.clone(),
-                        metadata: entry.metadata(),
-                        size: entry.file_size,
-                        cursor: 0,
-                    }))
-                }
-
-            }
-        }
-        None
-    }*/
 }
 
 
@@ -270,13 +806,6 @@ impl<HANDLE: VFatHandle> traits::Dir for Dir<HANDLE> {
     type Iter = DirEntryIter<HANDLE>;
 
     fn entries(&self) -> io::Result<Self::Iter> {
-        let mut buf = Vec::new();
-        self.vfat.lock(|vfat| vfat.read_chain(self.start_cluster, &mut buf).expect("error reading chain"));
-
-        Ok(DirEntryIter {
-            entries: unsafe { buf.cast() }.into_iter(),
-            vfat: self.vfat.clone()
-        })
-
+        DirEntryIter::new(self.vfat.clone(), self.start_cluster)
     }
 }
@@ -1,8 +1,9 @@
 use core::{fmt, mem};
 use shim::const_assert_size;
 
+use crate::format::Layout;
 use crate::traits::BlockDevice;
-use crate::vfat::Error;
+use crate::vfat::{Error, FatType};
 
 #[repr(C, packed)]
 pub struct BiosParameterBlock {
@@ -63,14 +64,134 @@ impl BiosParameterBlock {
         Ok(ebpb)
     }
 
+    /// Returns the boot sector of a new volume laid out as `layout`.
+    pub(crate) fn new(layout: &Layout, volume_id: u32, volume_label: [u8; 11]) -> BiosParameterBlock {
+        let fat32 = layout.fat_type == FatType::Fat32;
+        // FAT12/16 record sizes in the 16-bit fields when they fit.
+        let short_sectors = if !fat32 && layout.sectors <= u16::max_value() as u64 { layout.sectors as u16 } else { 0 };
+        let ebpb = BiosParameterBlock {
+            jmp: [0xEB, if fat32 { 0x58 } else { 0x3C }, 0x90],
+            oem_id: u64::from_le_bytes(*b"MSWIN4.1"),
+            bytes_per_sector: layout.bytes_per_sector,
+            sectors_per_cluster: layout.sectors_per_cluster,
+            reserved_sectors: layout.reserved_sectors,
+            fats: layout.fats,
+            max_dir_entries: layout.root_dir_entries,
+            total_logical_sectors: short_sectors,
+            fat_id: 0xF8,
+            sectors_per_fat: if fat32 { 0 } else { layout.sectors_per_fat as u16 },
+            sectors_per_track: 63,
+            heads: 255,
+            hidden_sectors: layout.start as u32,
+            total_logical_sectors_ovf: if short_sectors == 0 { layout.sectors as u32 } else { 0 },
+            sectors_per_fat_ovf: layout.sectors_per_fat,
+            flags: 0,
+            fat_version: 0,
+            root_cluster: 2,
+            fsinfo_sector: layout.fsinfo_sector,
+            backup_boot_sector: layout.backup_boot_sector,
+            reserved: [0; 12],
+            drive_number: 0x80,
+            windows_nt_flags: 0,
+            signature: 0x29,
+            volume_id: volume_id,
+            volume_label: volume_label,
+            system_id_string: u64::from_le_bytes(*b"FAT32   "),
+            boot_code: [0; 420],
+            bootable_partition_signature: 0xAA55,
+        };
+        if fat32 {
+            return ebpb;
+        }
+
+        // FAT12/16 have a shorter extended block where FAT32's begins.
+        let mut raw = [0u8; 512];
+        raw.copy_from_slice(ebpb.as_bytes());
+        for b in raw[36..90].iter_mut() {
+            *b = 0;
+        }
+        raw[36] = 0x80;
+        raw[38] = 0x29;
+        raw[39..43].copy_from_slice(&volume_id.to_le_bytes());
+        raw[43..54].copy_from_slice(&volume_label);
+        raw[54..62].copy_from_slice(if layout.fat_type == FatType::Fat16 { b"FAT16   " } else { b"FAT12   " });
+        unsafe { mem::transmute::<[u8; 512], BiosParameterBlock>(raw) }
+    }
+
+    /// Returns `true` if `self` looks like a boot sector rather than, say, an
+    /// MBR: it starts with a jump and has a plausible geometry.
+    pub fn is_plausible(&self) -> bool {
+        (self.jmp[0] == 0xEB || self.jmp[0] == 0xE9)
+            && [512, 1024, 2048, 4096].contains(&{ self.bytes_per_sector })
+            && self.sectors_per_cluster.is_power_of_two()
+            && self.reserved_sectors != 0
+            && self.fats != 0
+    }
+
+    /// Returns the on-disk representation of `self`.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        unsafe { ::core::slice::from_raw_parts(self as *const Self as *const u8, 512) }
+    }
+
+    /// Returns the sector, relative to the start of the volume, of the FAT32
+    /// FSInfo sector.
+    pub fn fsinfo_sector(&self) -> u16 {
+        self.fsinfo_sector
+    }
+
+    /// Returns the sector, relative to the start of the volume, of the
+    /// backup copy of the boot sector, or `0` if there is none.
+    pub fn backup_boot_sector(&self) -> u16 {
+        self.backup_boot_sector
+    }
 
+
+    /// Returns the number of sectors in each FAT. FAT12/16 volumes record it
+    /// in the 16-bit field; FAT32 volumes set that field to zero.
     pub fn sectors_per_fat(&self) -> u32 {
-        //to_u32(self.sectors_per_fat_ovf)
-        self.sectors_per_fat_ovf
+        match self.sectors_per_fat {
+            0 => self.sectors_per_fat_ovf,
+            n => n as u32,
+        }
     }
 
     pub fn total_logical_sectors(&self) -> u64 {
-        self.total_logical_sectors_ovf as u64
+        match self.total_logical_sectors {
+            0 => self.total_logical_sectors_ovf as u64,
+            n => n as u64,
+        }
+    }
+
+    /// Returns the number of sectors in the fixed root directory region that
+    /// follows the FATs on FAT12/16 volumes. Always `0` on FAT32.
+    pub fn root_dir_sectors(&self) -> u64 {
+        let bytes_per_sector = self.bytes_per_sector as u64;
+        (self.max_dir_entries as u64 * 32 + bytes_per_sector - 1) / bytes_per_sector
+    }
+
+    /// Returns the first sector of the data region, relative to the start of
+    /// the volume.
+    pub fn data_start_sector(&self) -> u64 {
+        self.reserved_sectors as u64
+            + self.fats as u64 * self.sectors_per_fat() as u64
+            + self.root_dir_sectors()
+    }
+
+    /// Returns the number of data clusters in the volume.
+    pub fn cluster_count(&self) -> u32 {
+        let data_sectors = self.total_logical_sectors().saturating_sub(self.data_start_sector());
+        (data_sectors / self.sectors_per_cluster as u64) as u32
+    }
+
+    /// Returns the FAT type of the volume. The type follows from the cluster
+    /// count, except that a zero 16-bit FAT size marks the FAT32 layout of
+    /// this block, which is then used regardless of the count.
+    pub fn fat_type(&self) -> FatType {
+        match (self.sectors_per_fat, FatType::from_cluster_count(self.cluster_count())) {
+            (0, _) => FatType::Fat32,
+            (_, FatType::Fat32) => FatType::Fat16,
+            (_, fat_type) => fat_type,
+        }
     }
 }
 
@@ -1,5 +1,5 @@
 use crate::traits;
-use crate::vfat::{Dir, File, Metadata, VFatHandle};
+use crate::vfat::{Dir, File, Metadata, Position, Slot, VFatHandle};
 use core::fmt;
 
 // You can change this definition if you want
@@ -10,6 +10,23 @@ pub enum Entry<HANDLE: VFatHandle> {
 }
 
 // Implement any useful helper methods on `Entry`.
+impl<HANDLE: VFatHandle> Entry<HANDLE> {
+    /// Returns where the entry's 8.3 entry is stored, or `None` for the root
+    /// directory, which has none.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            Entry::EntryFile(f) => f.position(),
+            Entry::EntryDir(d) => d.position(),
+        }
+    }
+
+    pub(crate) fn slot(&self) -> Option<Slot> {
+        match self {
+            Entry::EntryFile(f) => f.slot,
+            Entry::EntryDir(d) => d.slot,
+        }
+    }
+}
 
 // Implement `traits::Entry` for `Entry`.
 impl<HANDLE: VFatHandle> traits::Entry for Entry<HANDLE> {
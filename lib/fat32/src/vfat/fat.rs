@@ -19,6 +19,95 @@ pub enum Status {
     Eoc(u32),
 }
 
+/// The FAT variant of a volume.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// Returns the FAT type of a volume with `count` data clusters. This is
+    /// the only rule the specification uses to tell the types apart.
+    pub fn from_cluster_count(count: u32) -> FatType {
+        if count < 4085 {
+            FatType::Fat12
+        } else if count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        }
+    }
+
+    /// Returns the number of bits in one FAT entry.
+    pub fn entry_bits(&self) -> usize {
+        match self {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32,
+        }
+    }
+
+    /// Returns the offset, in bytes from the start of the FAT, of the entry
+    /// for `cluster`.
+    pub fn entry_offset(&self, cluster: u32) -> usize {
+        cluster as usize * self.entry_bits() / 8
+    }
+
+    /// Returns the number of bytes that must be read at `entry_offset()` to
+    /// decode an entry. FAT12 entries straddle two bytes.
+    pub fn entry_len(&self) -> usize {
+        match self {
+            FatType::Fat12 | FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        }
+    }
+
+    /// Decodes the entry for `cluster` from the `entry_len()` bytes in `raw`.
+    /// Entries are widened so that the FAT32 interpretation in
+    /// `FatEntry::status()` applies to every type.
+    pub fn decode(&self, cluster: u32, raw: &[u8]) -> FatEntry {
+        match self {
+            FatType::Fat12 => {
+                let word = raw[0] as u32 | (raw[1] as u32) << 8;
+                let value = if cluster & 1 == 1 { word >> 4 } else { word & 0xFFF };
+                FatEntry(if value >= 0xFF0 { value | 0x0FFFF000 } else { value })
+            }
+            FatType::Fat16 => {
+                let value = raw[0] as u32 | (raw[1] as u32) << 8;
+                FatEntry(if value >= 0xFFF0 { value | 0x0FFF0000 } else { value })
+            }
+            FatType::Fat32 => {
+                FatEntry(raw[0] as u32 | (raw[1] as u32) << 8 | (raw[2] as u32) << 16 | (raw[3] as u32) << 24)
+            }
+        }
+    }
+
+    /// Encodes `value`, a FAT32-style entry, as the entry for `cluster` into
+    /// the `entry_len()` bytes in `raw`. Bits of `raw` that belong to
+    /// neighbouring entries, and the reserved high bits of FAT32 entries, are
+    /// preserved.
+    pub fn encode(&self, cluster: u32, value: u32, raw: &mut [u8]) {
+        let word = match self {
+            FatType::Fat12 => {
+                let word = raw[0] as u32 | (raw[1] as u32) << 8;
+                let value = value & 0xFFF;
+                if cluster & 1 == 1 { (word & 0x000F) | value << 4 } else { (word & 0xF000) | value }
+            }
+            FatType::Fat16 => value & 0xFFFF,
+            FatType::Fat32 => {
+                let old = raw[0] as u32 | (raw[1] as u32) << 8 | (raw[2] as u32) << 16 | (raw[3] as u32) << 24;
+                (old & (0xF << 28)) | (value & !(0xF << 28))
+            }
+        };
+
+        for (i, byte) in raw[..self.entry_len()].iter_mut().enumerate() {
+            *byte = (word >> (i * 8)) as u8;
+        }
+    }
+}
+
 #[repr(C, packed)]
 pub struct FatEntry(pub u32);
 
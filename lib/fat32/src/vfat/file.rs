@@ -1,9 +1,10 @@
 use alloc::string::String;
 
 use shim::io::{self, SeekFrom};
+use shim::ioerr;
 
 use crate::traits;
-use crate::vfat::{Cluster, Metadata, VFatHandle};
+use crate::vfat::{Cluster, Metadata, Position, Slot, VFat, VFatHandle};
 
 #[derive(Debug)]
 pub struct File<HANDLE: VFatHandle> {
@@ -13,6 +14,7 @@ pub struct File<HANDLE: VFatHandle> {
     pub metadata: Metadata,
     pub size: u32,
     pub cursor: u32,
+    pub(crate) slot: Option<Slot>,
 }
 
 impl<HANDLE: VFatHandle> File<HANDLE> {
@@ -23,28 +25,107 @@ impl<HANDLE: VFatHandle> File<HANDLE> {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Returns where the file's 8.3 entry is stored.
+    pub fn position(&self) -> Option<Position> {
+        self.slot.map(|slot| slot.position)
+    }
+
+    /// Returns the cluster holding byte `self.cursor`. With `allocate`, the
+    /// chain is extended as needed; otherwise `None` is returned past its end.
+    fn cursor_cluster(&mut self, vfat: &mut VFat<HANDLE>, allocate: bool) -> io::Result<Option<Cluster>> {
+        if self.start_cluster.get() == 0 {
+            if !allocate {
+                return Ok(None);
+            }
+            self.start_cluster = vfat.alloc_cluster(None)?;
+        }
+
+        let mut cluster = self.start_cluster;
+        for _ in 0..self.cursor as usize / vfat.cluster_size() {
+            cluster = match vfat.next_cluster(cluster)? {
+                Some(next) => next,
+                None if allocate => vfat.alloc_cluster(Some(cluster))?,
+                None => return Ok(None),
+            };
+        }
+        Ok(Some(cluster))
+    }
 }
 
 // Implement `traits::File` (and its supertraits) for `File`.
 
 impl<HANDLE: VFatHandle> io::Write for File<HANDLE> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        unimplemented!()
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.cursor as u64 + buf.len() as u64 > u32::max_value() as u64 {
+            return ioerr!(InvalidInput, "file too large");
+        }
+
+        let handle = self.vfat.clone();
+        handle.lock(|vfat| {
+            let cluster_size = vfat.cluster_size();
+            let mut cluster = self.cursor_cluster(vfat, true)?.expect("allocated");
+            let mut offset = self.cursor as usize % cluster_size;
+            let mut written = 0;
+            loop {
+                let len = ::core::cmp::min(cluster_size - offset, buf.len() - written);
+                vfat.write_cluster_bytes(cluster, offset, &buf[written..written + len])?;
+                written += len;
+                if written == buf.len() {
+                    break;
+                }
+
+                offset = 0;
+                cluster = match vfat.next_cluster(cluster)? {
+                    Some(next) => next,
+                    None => vfat.alloc_cluster(Some(cluster))?,
+                };
+            }
+
+            self.cursor += written as u32;
+            self.size = ::core::cmp::max(self.size, self.cursor);
+            if let Some(slot) = self.slot {
+                self.metadata = vfat.update_dir_entry(slot, self.start_cluster, self.size)?;
+            }
+            Ok(written)
+        })
     }
+
     fn flush(&mut self) -> io::Result<()> {
-        unimplemented!()
+        self.vfat.lock(|vfat| vfat.flush())
     }
 }
 
 impl<HANDLE: VFatHandle> io::Read for File<HANDLE> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut file_buf = vec!();
-        self.vfat.lock(|vfat| vfat.read_chain(self.start_cluster, &mut file_buf))?;
+        // Empty files have no chain; cluster 0 would name the root directory.
+        if self.cursor >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
 
-        let len = ::core::cmp::min(buf.len(), (self.size - self.cursor) as usize);
-        buf[..len].copy_from_slice(&file_buf[self.cursor as usize..(self.cursor as usize + len)]);
-        self.cursor += len as u32;
-        Ok(len)
+        let handle = self.vfat.clone();
+        handle.lock(|vfat| {
+            let cluster_size = vfat.cluster_size();
+            let cluster = self.cursor_cluster(vfat, false)?
+                .ok_or(io::Error::new(io::ErrorKind::UnexpectedEof, "cluster chain shorter than file"))?;
+
+            let offset = self.cursor as usize % cluster_size;
+            let len = ::core::cmp::min(buf.len(), (self.size - self.cursor) as usize);
+            let len = ::core::cmp::min(len, cluster_size - offset);
+            vfat.read_cluster_bytes(cluster, offset, &mut buf[..len])?;
+            self.cursor += len as u32;
+
+            // Access dates change at most once a day, so this rarely writes.
+            if let (Some(now), Some(slot)) = (vfat.now(), self.slot) {
+                if self.metadata.atime.date != now.date {
+                    self.metadata = vfat.set_access_date(slot, now.date)?;
+                }
+            }
+            Ok(len)
+        })
     }
 }
 
@@ -62,15 +143,25 @@ impl<HANDLE: VFatHandle> io::Seek for File<HANDLE> {
     ///
     /// Seeking before the start of a file or beyond the end of the file results
     /// in an `InvalidInput` error.
-    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
-        unimplemented!("File::seek()")
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let cursor = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.size as i64 + offset,
+            SeekFrom::Current(offset) => self.cursor as i64 + offset,
+        };
+
+        if cursor < 0 || cursor > self.size as i64 {
+            return ioerr!(InvalidInput, "seek out of bounds");
+        }
+        self.cursor = cursor as u32;
+        Ok(self.cursor as u64)
     }
 }
 
 impl<HANDLE: VFatHandle> traits::File for File<HANDLE> {
 
     fn sync(&mut self) -> io::Result<()> {
-        unimplemented!()
+        self.vfat.lock(|vfat| vfat.flush())
     }
 
     fn size(&self) -> u64 {
@@ -0,0 +1,92 @@
+use core::{fmt, mem};
+use shim::const_assert_size;
+
+use crate::traits::BlockDevice;
+use crate::vfat::Error;
+
+const LEAD_SIGNATURE: u32 = 0x41615252;
+const STRUCT_SIGNATURE: u32 = 0x61417272;
+const TRAIL_SIGNATURE: u32 = 0xAA550000;
+
+/// The value of `free_count` and `next_free` when they are not known.
+const UNKNOWN: u32 = 0xFFFFFFFF;
+
+/// The FAT32 FSInfo sector, which caches the number of free clusters and a
+/// hint for where to look for the next one.
+#[repr(C, packed)]
+pub struct FsInfo {
+    lead_signature: u32,
+    reserved: [u8; 480],
+    struct_signature: u32,
+    free_count: u32,
+    next_free: u32,
+    reserved2: [u8; 12],
+    trail_signature: u32,
+}
+
+const_assert_size!(FsInfo, 512);
+
+impl FsInfo {
+    /// The byte offset of the free count; the next-free hint follows it.
+    pub(crate) const FREE_COUNT_OFFSET: usize = 488;
+
+    /// Returns an FSInfo sector recording `free_count` free clusters, or an
+    /// unknown count, and `next_free` as the place to start looking for one.
+    pub(crate) fn new(free_count: Option<u32>, next_free: u32) -> FsInfo {
+        FsInfo {
+            lead_signature: LEAD_SIGNATURE,
+            reserved: [0; 480],
+            struct_signature: STRUCT_SIGNATURE,
+            free_count: free_count.unwrap_or(UNKNOWN),
+            next_free: next_free,
+            reserved2: [0; 12],
+            trail_signature: TRAIL_SIGNATURE,
+        }
+    }
+
+    /// Reads the FSInfo sector at sector `sector` of `device`.
+    ///
+    /// # Errors
+    ///
+    /// If any of the three signatures is invalid, returns an error of
+    /// `BadSignature`.
+    pub fn from<T: BlockDevice>(mut device: T, sector: u64) -> Result<FsInfo, Error> {
+        let mut buf = [0u8; 512];
+        device.read_sector(sector, &mut buf)?;
+        let fsinfo = unsafe { mem::transmute::<[u8; 512], FsInfo>(buf) };
+
+        if fsinfo.lead_signature != LEAD_SIGNATURE
+            || fsinfo.struct_signature != STRUCT_SIGNATURE
+            || fsinfo.trail_signature != TRAIL_SIGNATURE
+        {
+            return Err(Error::BadSignature);
+        }
+
+        Ok(fsinfo)
+    }
+
+    /// Returns the recorded number of free clusters, if known.
+    pub fn free_count(&self) -> Option<u32> {
+        if self.free_count == UNKNOWN { None } else { Some(self.free_count) }
+    }
+
+    /// Returns the cluster at which to start looking for a free cluster, if
+    /// known.
+    pub fn next_free(&self) -> Option<u32> {
+        if self.next_free == UNKNOWN { None } else { Some(self.next_free) }
+    }
+
+    /// Returns the on-disk representation of `self`.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        unsafe { ::core::slice::from_raw_parts(self as *const Self as *const u8, 512) }
+    }
+}
+
+impl fmt::Debug for FsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FsInfo")
+            .field("free_count", &{ self.free_count })
+            .field("next_free", &{ self.next_free })
+            .finish()
+    }
+}
@@ -1,4 +1,5 @@
 use core::fmt;
+use core::time::Duration;
 
 use alloc::string::String;
 
@@ -10,6 +11,21 @@ use crate::traits;
 pub struct Date(u16);
 
 impl Date {
+
+    pub fn from(date: u16) -> Self {
+        Date(date)
+    }
+
+    /// Returns the date `year`-`month`-`day`. `year` must be in 1980..=2107.
+    pub fn new(year: usize, month: u8, day: u8) -> Self {
+        Date(((year - 1980) as u16) << 9 | (month as u16 & 0xf) << 5 | day as u16 & 0x1f)
+    }
+
+    /// Returns the on-disk representation of the date.
+    pub fn raw(&self) -> u16 {
+        self.0
+    }
+
     pub fn year(&self) -> usize {
         (self.0 as usize >> 9) + 1980
     }
@@ -34,6 +50,17 @@ impl Time {
         Time(time)
     }
 
+    /// Returns the time `hour`:`minute`:`second`, rounding `second` down to
+    /// an even number as FAT stores seconds in units of two.
+    pub fn new(hour: u8, minute: u8, second: u8) -> Self {
+        Time((hour as u16) << 11 | (minute as u16 & 0x3f) << 5 | (second as u16 / 2) & 0x1f)
+    }
+
+    /// Returns the on-disk representation of the time.
+    pub fn raw(&self) -> u16 {
+        self.0
+    }
+
     pub fn hour(&self) -> u8 {
         (self.0 >> 11) as u8
     }
@@ -53,6 +80,11 @@ impl Time {
 pub struct Attributes(u8);
 
 impl Attributes {
+
+    pub fn from(attr: u8) -> Self {
+        Attributes(attr)
+    }
+
     pub fn read_only(&self) -> bool {
         self.0 & 0x1 != 0
     }
@@ -87,6 +119,74 @@ impl Attributes {
 pub struct Timestamp {
     pub date: Date,
     pub time: Time,
+    /// Units of 10 ms to add to `time`, in 0..200. Only creation times carry
+    /// them; FAT keeps no time zone, so timestamps are taken as UTC.
+    pub tenths: u8,
+}
+
+/// Days from 0000-03-01 to 1970-01-01 in the proleptic Gregorian calendar.
+const UNIX_EPOCH_DAYS: u64 = 719_468;
+
+/// The Unix times of 1980-01-01 00:00:00 and 2107-12-31 23:59:59, the range
+/// of FAT timestamps.
+const FAT_FIRST_SECOND: u64 = 315_532_800;
+const FAT_LAST_SECOND: u64 = 4_354_819_199;
+
+/// Returns the year, month and day of the day `days` after 1970-01-01. Years
+/// start on March 1 internally so that leap days fall at their end.
+fn civil_from_days(days: u64) -> (u64, u8, u8) {
+    let days = days + UNIX_EPOCH_DAYS;
+    let (era, day_of_era) = (days / 146_097, days % 146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u8;
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Returns the number of days from 1970-01-01 to `year`-`month`-`day`, which
+/// must not be earlier.
+fn days_from_civil(year: u64, month: u8, day: u8) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let (era, year_of_era) = (year / 400, year % 400);
+    let shifted_month = if month > 2 { month as u64 - 3 } else { month as u64 + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as u64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - UNIX_EPOCH_DAYS
+}
+
+impl Timestamp {
+    /// Returns the timestamp of `time`, a time since the Unix epoch, clamped
+    /// to the range FAT can represent.
+    pub fn from_unix(time: Duration) -> Timestamp {
+        let secs = ::core::cmp::min(::core::cmp::max(time.as_secs(), FAT_FIRST_SECOND), FAT_LAST_SECOND);
+        let nanos = if secs == time.as_secs() { time.subsec_nanos() } else { 0 };
+
+        let (year, month, day) = civil_from_days(secs / 86400);
+        let second_of_day = secs % 86400;
+        let second = (second_of_day % 60) as u8;
+        Timestamp {
+            date: Date::new(year as usize, month, day),
+            time: Time::new((second_of_day / 3600) as u8, (second_of_day / 60 % 60) as u8, second),
+            tenths: (second % 2) * 100 + (nanos / 10_000_000) as u8,
+        }
+    }
+
+    /// Returns the time since the Unix epoch of `self`. An invalid month or
+    /// day, as in an all-zero timestamp, is taken as the first.
+    pub fn to_unix(&self) -> Duration {
+        let month = ::core::cmp::min(::core::cmp::max(self.date.month(), 1), 12);
+        let day = ::core::cmp::max(self.date.day(), 1);
+        let days = days_from_civil(self.date.year() as u64, month, day);
+        let secs = days * 86400
+            + self.time.hour() as u64 * 3600
+            + self.time.minute() as u64 * 60
+            + self.time.second() as u64
+            + self.tenths as u64 / 100;
+        Duration::new(secs, (self.tenths as u32 % 100) * 10_000_000)
+    }
 }
 
 /// Metadata for a directory entry.
@@ -122,7 +222,7 @@ impl traits::Timestamp for Timestamp {
     }
 
     fn second(&self) -> u8 {
-        self.time.second()
+        self.time.second() + self.tenths / 100
     }
 }
 
@@ -6,17 +6,22 @@ pub(crate) mod entry;
 pub(crate) mod error;
 pub(crate) mod fat;
 pub(crate) mod file;
+pub(crate) mod fsinfo;
 pub(crate) mod metadata;
 pub(crate) mod vfat;
 
-pub use self::dir::Dir;
+pub use self::cache::CacheStats;
+pub use self::dir::{Dir, Position};
 pub use self::ebpb::BiosParameterBlock;
 pub use self::entry::Entry;
 pub use self::error::Error;
+pub use self::fat::FatType;
 pub use self::file::File;
+pub use self::fsinfo::FsInfo;
 pub use self::metadata::{Attributes, Date, Metadata, Time, Timestamp};
-pub use self::vfat::{VFat, VFatHandle};
+pub use self::vfat::{Clock, StatFs, VFat, VFatHandle};
 
 pub(crate) use self::cache::{CachedPartition, Partition};
 pub(crate) use self::cluster::Cluster;
+pub(crate) use self::dir::{Slot, VFatDirEntry, VFatRegularDirEntry};
 pub(crate) use self::fat::{FatEntry, Status};
@@ -1,6 +1,6 @@
 use core::fmt::Debug;
 use core::marker::PhantomData;
-use core::mem::size_of;
+use core::time::Duration;
 
 use alloc::vec::Vec;
 use alloc::string::String;
@@ -9,14 +9,14 @@ use shim::io;
 use shim::ioerr;
 use shim::newioerr;
 use shim::path;
-use shim::path::Path;
+use shim::path::{Path, PathBuf};
 use shim::path::{Component};
 
-use crate::mbr::MasterBootRecord;
+use crate::partition::{self, Selector};
 use crate::traits::{BlockDevice, FileSystem};
-use crate::util::SliceExt;
-use crate::vfat::{BiosParameterBlock, CachedPartition, Partition};
-use crate::vfat::{Cluster, Dir, Entry, Error, FatEntry, File, Status, Metadata};
+use crate::vfat::{BiosParameterBlock, CacheStats, CachedPartition, Partition};
+use crate::vfat::{Cluster, Dir, Entry, Error, FatEntry, FatType, File, FsInfo, Status, Metadata};
+use crate::vfat::{Date, Position, Slot, Timestamp, VFatDirEntry, VFatRegularDirEntry};
 
 /// A generic trait that handles a critical section as a closure
 pub trait VFatHandle: Clone + Debug + Send + Sync {
@@ -31,36 +31,130 @@ pub struct VFat<HANDLE: VFatHandle> {
     bytes_per_sector: u16,
     sectors_per_cluster: u8,
     sectors_per_fat: u32,
+    fats: u8,
+    fat_type: FatType,
     fat_start_sector: u64,
+    root_dir_sectors: u64,
     data_start_sector: u64,
+    cluster_count: u32,
     rootdir_cluster: Cluster,
+    /// The logical sector of the FSInfo sector, if the volume has a valid one.
+    fsinfo_sector: Option<u64>,
+    /// The number of free clusters, if known.
+    free_clusters: Option<u32>,
+    /// The cluster at which to start looking for a free cluster.
+    next_free: u32,
+    /// Whether `free_clusters` or `next_free` changed since FSInfo was last
+    /// written.
+    fsinfo_dirty: bool,
+    /// The source of timestamps for new and modified entries.
+    clock: Option<Clock>,
+    /// Recently resolved names, most recent first.
+    lookups: Vec<(String, Slot)>,
+}
+
+/// The number of resolved names `VFat` remembers.
+const LOOKUP_CACHE_LEN: usize = 64;
+
+/// A source of wall-clock time, returning the time since the Unix epoch.
+pub type Clock = fn() -> Duration;
+
+/// Space usage of a volume, as returned by `VFat::statfs()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StatFs {
+    /// The size of a cluster in bytes; the unit of allocation.
+    pub cluster_size: usize,
+    /// The size of the data region in bytes.
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+    pub used_bytes: u64,
 }
 
 impl<HANDLE: VFatHandle> VFat<HANDLE> {
+    /// Mounts the first partition of `device` whose type allows a FAT volume.
+    /// A device without such a partition is mounted whole if sector 0 holds
+    /// a FAT boot sector, as on a volume formatted without an MBR.
     pub fn from<T>(mut device: T) -> Result<HANDLE, Error>
     where
         T: BlockDevice + 'static,
     {
+        let fat_part = partition::read_partitions(&mut device)
+            .map(|parts| parts.into_iter().find(|part| part.may_be_fat()));
+        // A sector 0 that cannot be read as a boot sector holds no volume;
+        // the partition table's error, if any, says why better.
+        let whole = match fat_part {
+            Ok(Some(_)) => false,
+            _ => BiosParameterBlock::from(&mut device, 0).map_or(false, |ebpb| ebpb.is_plausible()),
+        };
 
-        let mbr = MasterBootRecord::from(&mut device)?;
-        let fat_part = mbr.partition_table.iter()
-            .find(|part| part.is_fat32())
+        match fat_part {
+            Ok(Some(part)) => VFat::mount(device, part.start),
+            _ if whole => VFat::mount(device, 0),
+            Ok(None) => Err(Error::NotFound),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Mounts the partition of `device` identified by `selector`, whatever
+    /// its partition type.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if no partition matches `selector` and
+    /// `BadSignature` if it does not hold a FAT volume.
+    pub fn from_partition<T>(mut device: T, selector: Selector) -> Result<HANDLE, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        let part = partition::read_partitions(&mut device)?
+            .into_iter()
+            .find(|part| part.matches(selector))
             .ok_or(Error::NotFound)?;
-        let ebpb_sector = fat_part.relative_sector as u64;
+        VFat::mount(device, part.start)
+    }
+
+    /// Mounts the FAT volume whose boot sector is sector `ebpb_sector` of
+    /// `device`.
+    fn mount<T>(mut device: T, ebpb_sector: u64) -> Result<HANDLE, Error>
+    where
+        T: BlockDevice + 'static,
+    {
         let ebpb = BiosParameterBlock::from(&mut device, ebpb_sector)?;
-      //  println!("{:#?}", mbr);
-    //    println!("{:#?}", ebpb);
-        let fat_sector = ebpb_sector + ebpb.reserved_sectors as u64;
         let fat_sector = ebpb.reserved_sectors as u64;
         //println!("fat sector {}", fat_sector);
-        let data_sector = fat_sector + (ebpb.fats as u64 * ebpb.sectors_per_fat() as u64);
+        let data_sector = ebpb.data_start_sector();
         //println!("data sector {}", data_sector);
-        let cached_part = CachedPartition::new(device, Partition {
+        let mut cached_part = CachedPartition::new(device, Partition {
                                                   start: ebpb_sector,
                                                   //num_sectors: fat_part.total_sectors as u64,
                                                   num_sectors: ebpb.total_logical_sectors(),
                                                   sector_size: ebpb.bytes_per_sector as u64});
         //println!("bps: {}", ebpb.bytes_per_sector);
+        let fat_type = ebpb.fat_type();
+
+        // FAT12/16 root directories live in a fixed region rather than in a
+        // cluster; cluster 0 stands for that region, as it does in `..`.
+        let rootdir_cluster = match fat_type {
+            FatType::Fat32 => Cluster::from(ebpb.root_cluster),
+            _ => Cluster::from(0),
+        };
+
+        // FSInfo is only a hint: a missing or implausible one just means the
+        // free count is unknown and allocation starts from the beginning.
+        let cluster_count = ebpb.cluster_count();
+        let fsinfo_sector = match (fat_type, ebpb.fsinfo_sector()) {
+            (FatType::Fat32, sector) if sector != 0 && sector != 0xFFFF => Some(sector as u64),
+            _ => None,
+        };
+        let fsinfo = fsinfo_sector.and_then(|sector| FsInfo::from(&mut cached_part, sector).ok());
+        let fsinfo_sector = fsinfo.as_ref().and(fsinfo_sector);
+        let free_clusters = fsinfo.as_ref()
+            .and_then(|fsinfo| fsinfo.free_count())
+            .filter(|&free| free <= cluster_count);
+        let next_free = fsinfo.as_ref()
+            .and_then(|fsinfo| fsinfo.next_free())
+            .filter(|&next| next >= 2 && next < cluster_count + 2)
+            .unwrap_or(2);
 
         Ok(VFatHandle::new(VFat {
             phantom: PhantomData,
@@ -68,9 +162,19 @@ impl<HANDLE: VFatHandle> VFat<HANDLE> {
             bytes_per_sector: ebpb.bytes_per_sector,
             sectors_per_cluster: ebpb.sectors_per_cluster,
             sectors_per_fat: ebpb.sectors_per_fat(),
+            fats: ebpb.fats,
+            fat_type: fat_type,
             fat_start_sector: fat_sector,
+            root_dir_sectors: ebpb.root_dir_sectors(),
             data_start_sector: data_sector,
-            rootdir_cluster: Cluster::from(ebpb.root_cluster),
+            cluster_count: cluster_count,
+            rootdir_cluster: rootdir_cluster,
+            fsinfo_sector: fsinfo_sector,
+            free_clusters: free_clusters,
+            next_free: next_free,
+            fsinfo_dirty: false,
+            clock: None,
+            lookups: Vec::new(),
         }))
 
 
@@ -81,27 +185,30 @@ impl<HANDLE: VFatHandle> VFat<HANDLE> {
     //  * A method to read from an offset of a cluster into a buffer.
     //
 
-    fn read_cluster(&mut self, cluster: Cluster, offset: usize, buf: &mut [u8]) -> io::Result<usize> {
+    pub(crate) fn read_cluster(&mut self, cluster: Cluster, offset: usize, buf: &mut [u8]) -> io::Result<usize> {
         //println!("read_cluster starting {:?} offset {}", cluster, offset);
         let cluster_start = self.data_start_sector + cluster.get_offset() * self.sectors_per_cluster as u64;
         let first_sector = cluster_start + offset as u64;
         let max_sectors_in_buf = buf.len() as u64 / self.bytes_per_sector as u64;
+        let sectors_left = (self.sectors_per_cluster as u64).saturating_sub(offset as u64);
+        let count = ::core::cmp::min(sectors_left, max_sectors_in_buf);
 
-        let last_sector = ::core::cmp::min(self.sectors_per_cluster as u64, max_sectors_in_buf) + cluster_start;
-
-        let mut read = 0;
-        //println!("reading {} sectors", last_sector - first_sector);
-        for i in first_sector..last_sector {
-            read += self.device.read_sector(i, &mut buf[read..])?;
-        }
-        //println!("read_cluster read {} bytes", read);
-        Ok(read)
+        self.device.read_sectors(first_sector, count, buf)
     }
     //
     //  * A method to read all of the clusters chained from a starting cluster
     //    into a vector.
     //
     pub fn read_chain(&mut self, start: Cluster, buf: &mut Vec<u8>) -> io::Result<usize> {
+        // `..` entries of directories below the root refer to it as cluster 0.
+        if start.get() == 0 {
+            return match self.fat_type {
+                FatType::Fat32 if self.rootdir_cluster.get() != 0 => self.read_chain(self.rootdir_cluster, buf),
+                FatType::Fat32 => ioerr!(InvalidData, "invalid root cluster"),
+                _ => self.read_root_dir(buf),
+            };
+        }
+
         let mut read = 0;
         let mut cluster = start;
 
@@ -117,23 +224,513 @@ impl<HANDLE: VFatHandle> VFat<HANDLE> {
             }
         }
     }
+
+    /// Reads the fixed root directory region of a FAT12/16 volume into `buf`.
+    fn read_root_dir(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let (first_sector, sectors) = self.root_dir_region().ok_or(io::ErrorKind::NotFound)?;
+        let start = buf.len();
+        buf.resize(start + sectors as usize * self.bytes_per_sector as usize, 0);
+        self.device.read_sectors(first_sector, sectors, &mut buf[start..])
+    }
+
+    /// Reads `buf.len()` bytes at byte `offset` of FAT copy `copy`. Entries
+    /// of FAT12 volumes may straddle a sector boundary.
+    fn read_fat_bytes(&mut self, copy: u8, offset: usize, buf: &mut [u8]) -> io::Result<()> {
+        let bytes_per_sector = self.bytes_per_sector as usize;
+        let fat_start = self.fat_start_sector + copy as u64 * self.sectors_per_fat as u64;
+
+        let mut read = 0;
+        while read < buf.len() {
+            let pos = offset + read;
+            let sector = self.device.get(fat_start + (pos / bytes_per_sector) as u64)?;
+            let start = pos % bytes_per_sector;
+            let len = ::core::cmp::min(bytes_per_sector - start, buf.len() - read);
+            buf[read..read + len].copy_from_slice(&sector[start..start + len]);
+            read += len;
+        }
+        Ok(())
+    }
+
+    /// Writes `buf` at byte `offset` of FAT copy `copy` through the sector
+    /// cache.
+    fn write_fat_bytes(&mut self, copy: u8, offset: usize, buf: &[u8]) -> io::Result<()> {
+        let bytes_per_sector = self.bytes_per_sector as usize;
+        let fat_start = self.fat_start_sector + copy as u64 * self.sectors_per_fat as u64;
+
+        let mut written = 0;
+        while written < buf.len() {
+            let pos = offset + written;
+            let sector = self.device.get_mut(fat_start + (pos / bytes_per_sector) as u64)?;
+            let start = pos % bytes_per_sector;
+            let len = ::core::cmp::min(bytes_per_sector - start, buf.len() - written);
+            sector[start..start + len].copy_from_slice(&buf[written..written + len]);
+            written += len;
+        }
+        Ok(())
+    }
+
     //
-    //  * A method to return a reference to a `FatEntry` for a cluster where the
-    //    reference points directly into a cached sector.
+    //  * A method to return the `FatEntry` for a cluster. Entries narrower
+    //    than 32 bits are widened; see `FatType::decode()`.
     //
-    fn fat_entry(&mut self, cluster: Cluster) -> io::Result<&FatEntry> {
+    pub(crate) fn fat_entry(&mut self, cluster: Cluster) -> io::Result<FatEntry> {
+        let mut raw = [0u8; 4];
+        let len = self.fat_type.entry_len();
+        self.read_fat_bytes(0, self.fat_type.entry_offset(cluster.get()), &mut raw[..len])?;
+        Ok(self.fat_type.decode(cluster.get(), &raw))
+    }
+
+    /// Returns the FAT type of the volume.
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
 
-        //println!("fat entry for {:#?}", cluster);
+    /// Returns the number of bytes in a cluster.
+    pub fn cluster_size(&self) -> usize {
+        self.bytes_per_sector as usize * self.sectors_per_cluster as usize
+    }
 
+    /// Returns the number of data clusters. Valid cluster numbers are
+    /// `2..cluster_count() + 2`.
+    pub fn cluster_count(&self) -> u32 {
+        self.cluster_count
+    }
 
-        let entries_per_sector = self.bytes_per_sector as usize / size_of::<FatEntry>();
-        let cluster_start = cluster.get() as usize;
-        let fat_entry_sec = (cluster_start / entries_per_sector) as u64;
-        let sec_entry = cluster_start % entries_per_sector;
-        let sec = self.device.get(self.fat_start_sector as u64 + fat_entry_sec)?;
-        let entries: &[FatEntry] = unsafe { sec.cast() };
-        //println!("{:?}", entries[sec_entry]);
-        Ok(&entries[sec_entry])
+    /// Sets the clock used to timestamp entries as they are created, written
+    /// and read. Without one, new entries carry zero timestamps and existing
+    /// ones are left alone, so reading never writes to the volume.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = Some(clock);
+    }
+
+    /// Returns the current time, if there is a clock.
+    pub(crate) fn now(&self) -> Option<Timestamp> {
+        self.clock.map(|clock| Timestamp::from_unix(clock()))
+    }
+
+    /// Returns the number of free clusters, counting them in the FAT if
+    /// FSInfo did not record it.
+    pub fn free_clusters(&mut self) -> io::Result<u32> {
+        if let Some(free) = self.free_clusters {
+            return Ok(free);
+        }
+
+        let mut free = 0;
+        for n in 2..self.cluster_count + 2 {
+            if self.fat_entry(Cluster::from(n))?.status() == Status::Free {
+                free += 1;
+            }
+        }
+        self.free_clusters = Some(free);
+        Ok(free)
+    }
+
+    /// Returns the number of free clusters recorded in FSInfo and kept up to
+    /// date since, without counting them.
+    pub(crate) fn recorded_free_clusters(&self) -> Option<u32> {
+        self.free_clusters
+    }
+
+    /// Replaces the count of free clusters, as when it was found to be wrong.
+    pub(crate) fn set_free_clusters(&mut self, free: u32) {
+        self.free_clusters = Some(free);
+        self.fsinfo_dirty = true;
+    }
+
+    /// Returns the statistics of the volume's sector cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.device.stats()
+    }
+
+    /// Returns the first sector of the volume on its device and the number of
+    /// device sectors it spans.
+    pub fn device_range(&self) -> (u64, u64) {
+        self.device.device_range()
+    }
+
+    /// Returns the space usage of the volume.
+    pub fn statfs(&mut self) -> io::Result<StatFs> {
+        let cluster_size = self.cluster_size();
+        let free = self.free_clusters()?;
+        Ok(StatFs {
+            cluster_size: cluster_size,
+            total_bytes: self.cluster_count as u64 * cluster_size as u64,
+            free_bytes: free as u64 * cluster_size as u64,
+            used_bytes: (self.cluster_count - free) as u64 * cluster_size as u64,
+        })
+    }
+
+    pub(crate) fn root_cluster(&self) -> Cluster {
+        self.rootdir_cluster
+    }
+
+    /// Returns the number of copies of the FAT and the sectors in each.
+    pub(crate) fn fat_layout(&self) -> (u8, u32) {
+        (self.fats, self.sectors_per_fat)
+    }
+
+    /// Returns sector `n` of FAT copy `copy`.
+    pub(crate) fn fat_sector(&mut self, copy: u8, n: u32) -> io::Result<&[u8]> {
+        let sector = self.fat_start_sector + (copy as u64 * self.sectors_per_fat as u64) + n as u64;
+        self.device.get(sector)
+    }
+
+    /// Overwrites sector `n` of FAT copy `copy` with `data`.
+    pub(crate) fn set_fat_sector(&mut self, copy: u8, n: u32, data: &[u8]) -> io::Result<()> {
+        let sector = self.fat_start_sector + (copy as u64 * self.sectors_per_fat as u64) + n as u64;
+        let sector = self.device.get_mut(sector)?;
+        sector.copy_from_slice(&data[..sector.len()]);
+        Ok(())
+    }
+
+    /// Returns the first sector and the number of sectors of the fixed root
+    /// directory region, or `None` on FAT32 volumes.
+    pub(crate) fn root_dir_region(&self) -> Option<(u64, u64)> {
+        match self.fat_type {
+            FatType::Fat32 => None,
+            _ => Some((self.data_start_sector - self.root_dir_sectors, self.root_dir_sectors)),
+        }
+    }
+
+    /// Returns the first sector of `cluster`.
+    pub(crate) fn cluster_start_sector(&self, cluster: Cluster) -> u64 {
+        self.data_start_sector + cluster.get_offset() * self.sectors_per_cluster as u64
+    }
+
+    pub(crate) fn sectors_per_cluster(&self) -> u8 {
+        self.sectors_per_cluster
+    }
+
+    pub(crate) fn bytes_per_sector(&self) -> u16 {
+        self.bytes_per_sector
+    }
+
+    /// Reads the logical sector `sector` through the sector cache.
+    pub(crate) fn read_sector(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.device.read_sector(sector, buf)
+    }
+
+    /// Sets the FAT entry for `cluster` to `value`, a FAT32-style entry, in
+    /// every copy of the FAT. The count of free clusters follows the first
+    /// copy.
+    pub(crate) fn set_fat_entry(&mut self, cluster: Cluster, value: u32) -> io::Result<()> {
+        let offset = self.fat_type.entry_offset(cluster.get());
+        let len = self.fat_type.entry_len();
+        let was_free = self.fat_entry(cluster)?.status() == Status::Free;
+
+        for copy in 0..self.fats {
+            let mut raw = [0u8; 4];
+            self.read_fat_bytes(copy, offset, &mut raw[..len])?;
+            self.fat_type.encode(cluster.get(), value, &mut raw);
+            self.write_fat_bytes(copy, offset, &raw[..len])?;
+        }
+
+        let is_free = self.fat_entry(cluster)?.status() == Status::Free;
+        if was_free != is_free {
+            if let Some(ref mut free) = self.free_clusters {
+                *free = if is_free { *free + 1 } else { free.saturating_sub(1) };
+            }
+            self.fsinfo_dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Overwrites `data.len()` bytes at byte `offset` of the logical sector
+    /// `sector` through the sector cache.
+    pub(crate) fn write_sector_bytes(&mut self, sector: u64, offset: usize, data: &[u8]) -> io::Result<()> {
+        let bytes_per_sector = self.bytes_per_sector as usize;
+
+        let mut written = 0;
+        while written < data.len() {
+            let pos = offset + written;
+            let sector = self.device.get_mut(sector + (pos / bytes_per_sector) as u64)?;
+            let start = pos % bytes_per_sector;
+            let len = ::core::cmp::min(bytes_per_sector - start, data.len() - written);
+            sector[start..start + len].copy_from_slice(&data[written..written + len]);
+            written += len;
+        }
+        Ok(())
+    }
+
+    /// Returns the cluster after `cluster` in its chain, or `None` if it is
+    /// the last.
+    pub(crate) fn next_cluster(&mut self, cluster: Cluster) -> io::Result<Option<Cluster>> {
+        match self.fat_entry(cluster)?.status() {
+            Status::Data(next) => Ok(Some(next)),
+            Status::Eoc(_) => Ok(None),
+            _ => ioerr!(InvalidData, "invalid cluster in chain"),
+        }
+    }
+
+    /// Reads `buf.len()` bytes at byte `offset` of `cluster`.
+    pub(crate) fn read_cluster_bytes(&mut self, cluster: Cluster, offset: usize, buf: &mut [u8]) -> io::Result<()> {
+        let bytes_per_sector = self.bytes_per_sector as usize;
+        let start = self.cluster_start_sector(cluster);
+
+        let mut read = 0;
+        while read < buf.len() {
+            let pos = offset + read;
+            let sector = self.device.get(start + (pos / bytes_per_sector) as u64)?;
+            let begin = pos % bytes_per_sector;
+            let len = ::core::cmp::min(bytes_per_sector - begin, buf.len() - read);
+            buf[read..read + len].copy_from_slice(&sector[begin..begin + len]);
+            read += len;
+        }
+        Ok(())
+    }
+
+    /// Writes `data` at byte `offset` of `cluster` through the sector cache.
+    pub(crate) fn write_cluster_bytes(&mut self, cluster: Cluster, offset: usize, data: &[u8]) -> io::Result<()> {
+        let start = self.cluster_start_sector(cluster);
+        self.write_sector_bytes(start, offset, data)
+    }
+
+    /// Allocates a free cluster, fills it with zeroes and marks it as the end
+    /// of its chain. If `prev` is given, the new cluster is linked after it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` if the volume has no free cluster.
+    pub(crate) fn alloc_cluster(&mut self, prev: Option<Cluster>) -> io::Result<Cluster> {
+        if self.free_clusters == Some(0) {
+            return ioerr!(Other, "no free clusters");
+        }
+
+        // Search from the next-free hint, wrapping around to cluster 2.
+        let mut found = None;
+        for i in 0..self.cluster_count {
+            let n = 2 + (self.next_free - 2 + i) % self.cluster_count;
+            if self.fat_entry(Cluster::from(n))?.status() == Status::Free {
+                found = Some(Cluster::from(n));
+                break;
+            }
+        }
+        let cluster = found.ok_or(newioerr!(Other, "no free clusters"))?;
+        self.next_free = if cluster.get() + 1 < self.cluster_count + 2 { cluster.get() + 1 } else { 2 };
+        self.fsinfo_dirty = true;
+
+        self.set_fat_entry(cluster, 0x0FFFFFFF)?;
+        if let Some(prev) = prev {
+            self.set_fat_entry(prev, cluster.get())?;
+        }
+
+        let zeroes = vec![0u8; self.cluster_size()];
+        self.write_cluster_bytes(cluster, 0, &zeroes)?;
+        Ok(cluster)
+    }
+
+    /// Marks every cluster of the chain starting at `start` as free.
+    pub(crate) fn free_chain(&mut self, start: Cluster) -> io::Result<()> {
+        let mut cluster = Some(start);
+        while let Some(current) = cluster {
+            cluster = self.next_cluster(current)?;
+            self.set_fat_entry(current, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the position of entry `index` of the directory that begins at
+    /// `dir`.
+    pub(crate) fn dir_entry_position(&mut self, dir: Cluster, index: usize) -> io::Result<Position> {
+        let bytes_per_sector = self.bytes_per_sector as usize;
+        let offset = index * 32;
+
+        if dir.get() == 0 && self.fat_type != FatType::Fat32 {
+            let (first_sector, sectors) = self.root_dir_region().ok_or(io::ErrorKind::NotFound)?;
+            if (offset / bytes_per_sector) as u64 >= sectors {
+                return ioerr!(Other, "root directory is full");
+            }
+            return Ok(Position {
+                sector: first_sector + (offset / bytes_per_sector) as u64,
+                offset: offset % bytes_per_sector,
+            });
+        }
+
+        let mut cluster = if dir.get() == 0 { self.rootdir_cluster } else { dir };
+        for _ in 0..offset / self.cluster_size() {
+            cluster = self.next_cluster(cluster)?.ok_or(newioerr!(UnexpectedEof, "entry past end of directory"))?;
+        }
+        let offset = offset % self.cluster_size();
+        Ok(Position {
+            sector: self.cluster_start_sector(cluster) + (offset / bytes_per_sector) as u64,
+            offset: offset % bytes_per_sector,
+        })
+    }
+
+    /// Overwrites entry `index` of the directory that begins at `dir` with
+    /// the 32 bytes `raw`.
+    pub(crate) fn set_dir_entry(&mut self, dir: Cluster, index: usize, raw: &[u8]) -> io::Result<()> {
+        let position = self.dir_entry_position(dir, index)?;
+        self.write_sector_bytes(position.sector, position.offset, &raw[..32])
+    }
+
+    /// Marks entry `index` of the directory that begins at `dir` as deleted.
+    pub(crate) fn mark_dir_entry_deleted(&mut self, dir: Cluster, index: usize) -> io::Result<()> {
+        let position = self.dir_entry_position(dir, index)?;
+        self.write_sector_bytes(position.sector, position.offset, &[0xE5])
+    }
+
+    /// Applies `update` to the 8.3 entry at `slot` and returns the entry's
+    /// new metadata.
+    fn modify_dir_entry(&mut self, slot: Slot, update: impl FnOnce(&mut VFatRegularDirEntry))
+        -> io::Result<Metadata>
+    {
+        let mut entry = self.read_regular_entry(slot.position)?;
+        update(&mut entry);
+        self.write_sector_bytes(slot.position.sector, slot.position.offset, entry.as_bytes())?;
+        Ok(entry.metadata())
+    }
+
+    /// Reads the 8.3 entry at `position`.
+    fn read_regular_entry(&mut self, position: Position) -> io::Result<VFatRegularDirEntry> {
+        let mut raw = [0u8; 32];
+        raw.copy_from_slice(&self.device.get(position.sector)?[position.offset..position.offset + 32]);
+        Ok(unsafe { ::core::mem::transmute::<[u8; 32], VFatRegularDirEntry>(raw) })
+    }
+
+    /// Returns the name, current 8.3 entry and slot of the entry named `name`,
+    /// ignoring case, in the directory that begins at `dir` if it was resolved
+    /// recently.
+    pub(crate) fn cached_lookup(&mut self, dir: Cluster, name: &str)
+        -> io::Result<Option<(String, VFatRegularDirEntry, Slot)>>
+    {
+        let found = self.lookups.iter()
+            .position(|(cached, slot)| slot.dir == dir && cached.eq_ignore_ascii_case(name));
+        let (name, slot) = match found {
+            Some(i) => self.lookups.remove(i),
+            None => return Ok(None),
+        };
+
+        // The entry is re-read so that its size and times are current.
+        let entry = self.read_regular_entry(slot.position)?;
+        let marker = entry.raw_short_name()[0];
+        if marker == 0 || marker == 0xE5 || entry.attributes().lfn() {
+            return Ok(None);
+        }
+        // The slot may have been freed and reused since it was remembered.
+        match self.slot_name(slot, &entry)? {
+            Some(ref current) if *current == name => (),
+            _ => return Ok(None),
+        }
+        self.lookups.insert(0, (name.clone(), slot));
+        Ok(Some((name, entry, slot)))
+    }
+
+    /// Returns the name stored at `slot`, decoded as a directory listing
+    /// would, where `entry` is the slot's 8.3 entry. Returns `None` if the
+    /// slot's LFN entries are no longer in use.
+    fn slot_name(&mut self, slot: Slot, entry: &VFatRegularDirEntry) -> io::Result<Option<String>> {
+        let mut lfn = vec![0xFFFFu16; (slot.index - slot.first) * 13];
+        for index in slot.first..slot.index {
+            let position = self.dir_entry_position(slot.dir, index)?;
+            let mut raw = [0u8; 32];
+            raw.copy_from_slice(&self.device.get(position.sector)?[position.offset..position.offset + 32]);
+            let raw = unsafe { ::core::mem::transmute::<[u8; 32], VFatDirEntry>(raw) };
+            if raw.marker() == 0 || raw.marker() == 0xE5 || !raw.is_lfn() {
+                return Ok(None);
+            }
+
+            let sequence = (raw.long_filename().sequence() & 0x1f) as usize;
+            if sequence == 0 {
+                // A corrupt run: its entry is listed under its short name.
+                return Ok(Some(entry.short_name()));
+            }
+            if sequence * 13 > lfn.len() {
+                return Ok(None);
+            }
+            lfn[(sequence - 1) * 13..sequence * 13].copy_from_slice(&raw.long_filename().name_chars());
+        }
+
+        if slot.first == slot.index {
+            return Ok(Some(entry.short_name()));
+        }
+        let len = lfn.iter().position(|&c| c == 0x0000 || c == 0xFFFF).unwrap_or_else(|| lfn.len());
+        Ok(String::from_utf16(&lfn[..len]).ok())
+    }
+
+    /// Remembers that the entry named `name` is stored at `slot`.
+    pub(crate) fn remember_lookup(&mut self, name: String, slot: Slot) {
+        self.lookups.retain(|(cached, cached_slot)| {
+            cached_slot.dir != slot.dir || !cached.eq_ignore_ascii_case(&name)
+        });
+        self.lookups.insert(0, (name, slot));
+        self.lookups.truncate(LOOKUP_CACHE_LEN);
+    }
+
+    /// Forgets the resolved names of entries in the directory that begins at
+    /// `dir`.
+    pub(crate) fn forget_lookups(&mut self, dir: Cluster) {
+        self.lookups.retain(|(_, slot)| slot.dir != dir);
+    }
+
+    /// Forgets every resolved name, for when directories may have changed
+    /// behind the cache's back.
+    pub(crate) fn forget_all_lookups(&mut self) {
+        self.lookups.clear();
+    }
+
+    /// Records `cluster` and `size` in the 8.3 entry at `slot`, stamping it
+    /// as modified now, and returns the entry's new metadata.
+    pub(crate) fn update_dir_entry(&mut self, slot: Slot, cluster: Cluster, size: u32) -> io::Result<Metadata> {
+        let now = self.now();
+        self.modify_dir_entry(slot, |entry| {
+            entry.set_cluster_number(cluster);
+            entry.set_file_size(size);
+            if let Some(now) = now {
+                entry.set_modified(now);
+                entry.set_accessed(now.date);
+            }
+        })
+    }
+
+    /// Records `date` as the last access date of the 8.3 entry at `slot` and
+    /// returns the entry's new metadata.
+    pub(crate) fn set_access_date(&mut self, slot: Slot, date: Date) -> io::Result<Metadata> {
+        self.modify_dir_entry(slot, |entry| entry.set_accessed(date))
+    }
+
+    /// Extends the directory that begins at `dir` with zeroed clusters until
+    /// it holds at least `needed` entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` if `dir` is a FAT12/16 root directory,
+    /// whose size is fixed, that is too small, or the volume is full.
+    pub(crate) fn grow_dir(&mut self, dir: Cluster, needed: usize) -> io::Result<()> {
+        if dir.get() == 0 && self.fat_type != FatType::Fat32 {
+            let (_, sectors) = self.root_dir_region().ok_or(io::ErrorKind::NotFound)?;
+            if needed > sectors as usize * self.bytes_per_sector as usize / 32 {
+                return ioerr!(Other, "root directory is full");
+            }
+            return Ok(());
+        }
+
+        let per_cluster = self.cluster_size() / 32;
+        let mut last = if dir.get() == 0 { self.rootdir_cluster } else { dir };
+        let mut len = per_cluster;
+        while let Some(next) = self.next_cluster(last)? {
+            last = next;
+            len += per_cluster;
+        }
+
+        if needed > len {
+            for _ in 0..(needed - len + per_cluster - 1) / per_cluster {
+                last = self.alloc_cluster(Some(last))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes every modified cached sector back to the device, updating the
+    /// FSInfo sector first if the free count or next-free hint changed.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let (true, Some(sector)) = (self.fsinfo_dirty, self.fsinfo_sector) {
+            let fsinfo = FsInfo::new(self.free_clusters, self.next_free);
+            let offset = FsInfo::FREE_COUNT_OFFSET;
+            self.write_sector_bytes(sector, offset, &fsinfo.as_bytes()[offset..offset + 8])?;
+            self.fsinfo_dirty = false;
+        }
+        self.device.flush()
     }
 }
 
@@ -147,18 +744,45 @@ impl<'a, HANDLE: VFatHandle> FileSystem for &'a HANDLE {
         use crate::traits::Entry;
         use crate::vfat::Entry as EntryEnum;
 
+        let path = crate::path::normalize(path)?;
         let mut entry = EntryEnum::EntryDir(Dir {
             vfat: self.clone(),
             name: String::from("/"),
             start_cluster: self.lock(|vfat| vfat.rootdir_cluster),
             metadata: Metadata::default(),
+            slot: None,
         });
-        for compontent in path.as_ref().components() {
+        for compontent in path.components() {
             if let Component::Normal(s) = compontent {
-                entry = entry.as_dir().expect("entry as dir").find(s)?;
+                entry = entry.as_dir().ok_or(newioerr!(InvalidInput, "not a directory"))?.find(s)?;
             }
         }
         //Err(io::Error::new(io::ErrorKind::Other, "hello"))
         Ok(entry)
     }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let (parent, name) = split_path(path.as_ref())?;
+        self.open_dir(parent)?.create_file(&name)
+    }
+
+    fn create_dir<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Dir> {
+        let (parent, name) = split_path(path.as_ref())?;
+        self.open_dir(parent)?.create_dir(&name)
+    }
+
+    fn remove<P: AsRef<Path>>(self, path: P) -> io::Result<()> {
+        let (parent, name) = split_path(path.as_ref())?;
+        self.open_dir(parent)?.remove(&name)
+    }
+}
+
+/// Splits the absolute `path`, once normalized, into its parent directory and
+/// final name.
+fn split_path(path: &Path) -> io::Result<(PathBuf, String)> {
+    let path = crate::path::normalize(path)?;
+    match (path.parent(), path.file_name().and_then(|name| name.to_str())) {
+        (Some(parent), Some(name)) => Ok((parent.to_path_buf(), String::from(name))),
+        _ => ioerr!(InvalidInput, "path has no file name"),
+    }
 }
@@ -0,0 +1,311 @@
+use structopt;
+use structopt_derive::StructOpt;
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use structopt::StructOpt;
+
+use fat32::format;
+use fat32::partition::{self, Selector};
+use fat32::traits::{Dir as DirTrait, Entry as EntryTrait, FileSystem, Metadata, Timestamp};
+use fat32::vfat::{Entry, VFat, VFatHandle};
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Inspect and modify FAT disk images.")]
+struct Opt {
+    #[structopt(short = "p", long = "partition", parse(try_from_str = "parse_partition"),
+                help = "Partition to use, by index or GUID (defaults to the first FAT partition)")]
+    partition: Option<Selector>,
+
+    #[structopt(help = "Path to the disk image", parse(from_os_str))]
+    image: PathBuf,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    #[structopt(name = "ls", about = "List a directory")]
+    Ls {
+        #[structopt(short = "l", help = "Show sizes and modification times")]
+        long: bool,
+        #[structopt(default_value = "/")]
+        path: String,
+    },
+
+    #[structopt(name = "cat", about = "Write a file to stdout")]
+    Cat { path: String },
+
+    #[structopt(name = "put", about = "Copy a host file into the image, or into a directory of it")]
+    Put {
+        #[structopt(parse(from_os_str))]
+        source: PathBuf,
+        path: String,
+    },
+
+    #[structopt(name = "get", about = "Copy a file out of the image")]
+    Get {
+        path: String,
+        #[structopt(parse(from_os_str))]
+        dest: PathBuf,
+    },
+
+    #[structopt(name = "mkdir", about = "Create a directory")]
+    Mkdir { path: String },
+
+    #[structopt(name = "rm", about = "Remove a file or an empty directory")]
+    Rm {
+        #[structopt(short = "r", help = "Remove directories and their contents")]
+        recursive: bool,
+        path: String,
+    },
+
+    #[structopt(name = "tree", about = "List a directory recursively")]
+    Tree {
+        #[structopt(default_value = "/")]
+        path: String,
+    },
+
+    #[structopt(name = "info", about = "Show the partition table and volume layout")]
+    Info,
+
+    #[structopt(name = "mkfs", about = "Create an empty FAT volume, creating the image if needed")]
+    Mkfs {
+        #[structopt(short = "s", long = "size", help = "Image size in MiB (defaults to the existing image's size)")]
+        size: Option<u64>,
+        #[structopt(short = "m", long = "mbr", help = "Write an MBR with a single partition holding the volume")]
+        mbr: bool,
+        #[structopt(short = "c", help = "Sectors per cluster (chosen from the volume size by default)")]
+        sectors_per_cluster: Option<u8>,
+        #[structopt(short = "L", help = "Volume label")]
+        label: Option<String>,
+    },
+}
+
+impl Command {
+    fn writes(&self) -> bool {
+        match self {
+            Command::Put { .. } | Command::Mkdir { .. } | Command::Rm { .. } | Command::Mkfs { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+fn parse_partition(s: &str) -> Result<Selector, String> {
+    s.parse().map_err(|_| format!("'{}' is neither a partition index nor a GUID", s))
+}
+
+#[derive(Clone)]
+struct StdVFatHandle(Arc<Mutex<VFat<Self>>>);
+
+impl fmt::Debug for StdVFatHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StdVFatHandle")
+    }
+}
+
+impl VFatHandle for StdVFatHandle {
+    fn new(val: VFat<StdVFatHandle>) -> Self {
+        StdVFatHandle(Arc::new(Mutex::new(val)))
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut VFat<StdVFatHandle>) -> R) -> R {
+        f(&mut self.0.lock().expect("poisoned lock"))
+    }
+}
+
+/// Paths in the image are absolute; a relative one is taken from the root.
+fn image_path(path: &str) -> String {
+    if path.starts_with('/') { path.to_string() } else { format!("/{}", path) }
+}
+
+fn ls(vfat: &StdVFatHandle, path: &str, long: bool) -> io::Result<()> {
+    let mut entries = vfat.open_dir(image_path(path))?.entries()?.collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.name().cmp(b.name()));
+
+    for entry in entries {
+        let name = if entry.is_dir() { format!("{}/", entry.name()) } else { entry.name().to_string() };
+        if long {
+            let size = entry.as_file().map_or(0, |f| fat32::traits::File::size(f));
+            let mtime = entry.metadata().modified();
+            println!("{:>10}  {:04}-{:02}-{:02} {:02}:{:02}  {}", size, mtime.year(), mtime.month(),
+                     mtime.day(), mtime.hour(), mtime.minute(), name);
+        } else {
+            println!("{}", name);
+        }
+    }
+    Ok(())
+}
+
+fn cat(vfat: &StdVFatHandle, path: &str) -> io::Result<()> {
+    let mut file = vfat.open_file(image_path(path))?;
+    io::copy(&mut file, &mut io::stdout())?;
+    Ok(())
+}
+
+/// Copies `source` to the file `path`, replacing it if it exists, or into the
+/// directory `path` under the source's name.
+fn put(vfat: &StdVFatHandle, source: &Path, path: &str) -> io::Result<()> {
+    let mut path = image_path(path);
+    if vfat.open(&path).map_or(false, |entry| entry.is_dir()) {
+        let name = source.file_name().and_then(|name| name.to_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "source has no file name"))?;
+        path = format!("{}/{}", path.trim_end_matches('/'), name);
+    }
+
+    match vfat.open(&path) {
+        Ok(ref entry) if entry.is_dir() => {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "destination is a directory"));
+        }
+        Ok(_) => vfat.remove(&path)?,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e),
+    }
+
+    let mut data = Vec::new();
+    File::open(source)?.read_to_end(&mut data)?;
+    let mut file = vfat.create_file(&path)?;
+    file.write_all(&data)?;
+    fat32::traits::File::sync(&mut file)
+}
+
+fn get(vfat: &StdVFatHandle, path: &str, dest: &Path) -> io::Result<()> {
+    let mut file = vfat.open_file(image_path(path))?;
+    io::copy(&mut file, &mut File::create(dest)?)?;
+    Ok(())
+}
+
+fn rm(vfat: &StdVFatHandle, path: &str, recursive: bool) -> io::Result<()> {
+    let path = image_path(path);
+    if recursive {
+        if let Entry::EntryDir(dir) = vfat.open(&path)? {
+            for entry in dir.entries()? {
+                if entry.name() != "." && entry.name() != ".." {
+                    rm(vfat, &format!("{}/{}", path.trim_end_matches('/'), entry.name()), true)?;
+                }
+            }
+        }
+    }
+    vfat.remove(&path)
+}
+
+fn tree(vfat: &StdVFatHandle, path: &str, depth: usize) -> io::Result<()> {
+    let mut entries = vfat.open_dir(path)?.entries()?
+        .filter(|entry| entry.name() != "." && entry.name() != "..")
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.name().cmp(b.name()));
+
+    for entry in entries {
+        if entry.is_dir() {
+            println!("{}{}/", "  ".repeat(depth), entry.name());
+            tree(vfat, &format!("{}/{}", path.trim_end_matches('/'), entry.name()), depth + 1)?;
+        } else {
+            println!("{}{}", "  ".repeat(depth), entry.name());
+        }
+    }
+    Ok(())
+}
+
+fn info(opt: &Opt, vfat: &StdVFatHandle) -> io::Result<()> {
+    let partitions = partition::read_partitions(open(opt)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+    println!("{:>5}  {:>10}  {:>10}  {}", "index", "start", "sectors", "type");
+    for part in partitions {
+        let kind = match part.kind {
+            partition::Kind::Mbr(partition_type) => format!("MBR {:#04x}", partition_type),
+            partition::Kind::Gpt { type_guid, guid } => format!("GPT {} ({})", type_guid, guid),
+        };
+        println!("{:>5}  {:>10}  {:>10}  {}", part.index, part.start, part.num_sectors, kind);
+    }
+
+    vfat.lock(|vfat| {
+        let stat = vfat.statfs()?;
+        println!();
+        println!("type:          {:?}", vfat.fat_type());
+        println!("cluster size:  {} bytes", vfat.cluster_size());
+        println!("clusters:      {}", vfat.cluster_count());
+        println!("free:          {} of {} bytes", stat.free_bytes, stat.total_bytes);
+        Ok(())
+    })
+}
+
+fn mkfs(opt: &Opt, size: Option<u64>, mbr: bool, sectors_per_cluster: Option<u8>, label: &Option<String>)
+    -> io::Result<()>
+{
+    let image = OpenOptions::new().read(true).write(true).create(size.is_some()).open(&opt.image)?;
+    if let Some(size) = size {
+        image.set_len(size << 20)?;
+    }
+
+    let sectors = image.metadata()?.len() / 512;
+    let mut options = format::Options { mbr: mbr, sectors_per_cluster: sectors_per_cluster, ..format::Options::new(sectors) };
+    if let Some(ref label) = *label {
+        options = options.label(label);
+    }
+    // Derive an arbitrary but varying volume ID from the current time.
+    options.volume_id = now().as_secs() as u32;
+
+    let layout = format::format(image, &options)?;
+    println!("{:?}: {} clusters of {} bytes, {} sectors per FAT", layout.fat_type, layout.clusters,
+             layout.sectors_per_cluster as u32 * layout.bytes_per_sector as u32, layout.sectors_per_fat);
+    Ok(())
+}
+
+fn now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0))
+}
+
+fn open(opt: &Opt) -> io::Result<File> {
+    OpenOptions::new().read(true).write(opt.command.writes()).open(&opt.image)
+}
+
+fn run(opt: &Opt) -> io::Result<()> {
+    // The image holds no volume yet, so there is nothing to mount.
+    if let Command::Mkfs { size, mbr, sectors_per_cluster, ref label } = opt.command {
+        return mkfs(opt, size, mbr, sectors_per_cluster, label);
+    }
+
+    let image = open(opt)?;
+    let vfat = match opt.partition {
+        Some(selector) => VFat::<StdVFatHandle>::from_partition(image, selector),
+        None => VFat::<StdVFatHandle>::from(image),
+    };
+    let vfat = vfat.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("not a FAT image: {:?}", e)))?;
+    // Without a clock reads leave access dates alone, keeping the image
+    // untouched by read-only commands.
+    if opt.command.writes() {
+        vfat.lock(|vfat| vfat.set_clock(now));
+    }
+
+    let result = match opt.command {
+        Command::Ls { ref path, long } => ls(&vfat, path, long),
+        Command::Cat { ref path } => cat(&vfat, path),
+        Command::Put { ref source, ref path } => put(&vfat, source, path),
+        Command::Get { ref path, ref dest } => get(&vfat, path, dest),
+        Command::Mkdir { ref path } => vfat.create_dir(image_path(path)).map(|_| ()),
+        Command::Rm { ref path, recursive } => rm(&vfat, path, recursive),
+        Command::Tree { ref path } => tree(&vfat, &image_path(path), 0),
+        Command::Info => info(opt, &vfat),
+        Command::Mkfs { .. } => unreachable!("handled before mounting"),
+    };
+
+    // Persist whatever was written, even if the command failed midway.
+    vfat.lock(|vfat| vfat.flush())?;
+    result
+}
+
+fn main() {
+    let opt = Opt::from_args();
+
+    if let Err(e) = run(&opt) {
+        eprintln!("fatimg: {}: {}", opt.image.display(), e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,82 @@
+use structopt;
+use structopt_derive::StructOpt;
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, Mutex};
+
+use structopt::StructOpt;
+
+use fat32::check;
+use fat32::vfat::{VFat, VFatHandle};
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Check the consistency of a FAT disk image.")]
+struct Opt {
+    #[structopt(short = "r", long = "repair", help = "Repair the problems found")]
+    repair: bool,
+
+    #[structopt(help = "Path to the disk image", parse(from_os_str))]
+    image: PathBuf,
+}
+
+#[derive(Clone)]
+struct StdVFatHandle(Arc<Mutex<VFat<Self>>>);
+
+impl fmt::Debug for StdVFatHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StdVFatHandle")
+    }
+}
+
+impl VFatHandle for StdVFatHandle {
+    fn new(val: VFat<StdVFatHandle>) -> Self {
+        StdVFatHandle(Arc::new(Mutex::new(val)))
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut VFat<StdVFatHandle>) -> R) -> R {
+        f(&mut self.0.lock().expect("poisoned lock"))
+    }
+}
+
+/// Exit codes as used by `fsck(8)`.
+const EXIT_CLEAN: i32 = 0;
+const EXIT_CORRECTED: i32 = 1;
+const EXIT_UNCORRECTED: i32 = 4;
+const EXIT_ERROR: i32 = 8;
+
+fn open(opt: &Opt) -> std::io::Result<File> {
+    OpenOptions::new().read(true).write(opt.repair).open(&opt.image)
+}
+
+fn main() {
+    let opt = Opt::from_args();
+
+    let image = open(&opt).unwrap_or_else(|e| {
+        eprintln!("fsck: {}: {}", opt.image.display(), e);
+        process::exit(EXIT_ERROR);
+    });
+
+    let vfat = VFat::<StdVFatHandle>::from(image).unwrap_or_else(|e| {
+        eprintln!("fsck: {}: not a FAT image: {:?}", opt.image.display(), e);
+        process::exit(EXIT_ERROR);
+    });
+
+    let report = check::check(&vfat, opt.repair).unwrap_or_else(|e| {
+        eprintln!("fsck: {}: {}", opt.image.display(), e);
+        process::exit(EXIT_ERROR);
+    });
+
+    for problem in &report.problems {
+        println!("{}", problem);
+    }
+    println!("{}: {}", opt.image.display(), report);
+
+    process::exit(match (report.is_clean(), report.repaired) {
+        (true, _) => EXIT_CLEAN,
+        (false, true) => EXIT_CORRECTED,
+        (false, false) => EXIT_UNCORRECTED,
+    });
+}
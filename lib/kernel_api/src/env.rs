@@ -0,0 +1,97 @@
+//! The command-line arguments and environment of the calling process, which
+//! the kernel passes to its entry point as `argc`, `argv` and `envp`.
+
+static mut ARGC: usize = 0;
+static mut ARGV: *const *const u8 = core::ptr::null();
+static mut ENVP: *const *const u8 = core::ptr::null();
+
+/// Records the arguments of the process's entry point, for `args()` and
+/// `vars()`.
+///
+/// # Safety
+///
+/// Must be called once, before any other function of this module, with the
+/// arguments the kernel passed: `argv` must point to `argc` pointers and
+/// `envp` to null-terminated pointers, each to a null-terminated UTF-8
+/// string that is never modified.
+pub unsafe fn init(argc: usize, argv: *const *const u8, envp: *const *const u8) {
+    ARGC = argc;
+    ARGV = argv;
+    ENVP = envp;
+}
+
+/// Returns the null-terminated string at `ptr`.
+unsafe fn c_str(ptr: *const u8) -> &'static str {
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+    core::str::from_utf8(core::slice::from_raw_parts(ptr, len)).unwrap_or("")
+}
+
+/// An iterator over the command-line arguments of the process, as returned
+/// by `args()`.
+#[derive(Debug, Clone)]
+pub struct Args {
+    next: usize,
+    end: usize,
+}
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        if self.next == self.end {
+            return None;
+        }
+        let arg = unsafe { c_str(*ARGV.add(self.next)) };
+        self.next += 1;
+        Some(arg)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.end - self.next, Some(self.end - self.next))
+    }
+}
+
+impl ExactSizeIterator for Args {}
+
+/// Returns the command-line arguments of the process. The first is the
+/// name the process was started by.
+pub fn args() -> Args {
+    Args { next: 0, end: unsafe { ARGC } }
+}
+
+/// An iterator over the environment variables of the process, as returned
+/// by `vars()`.
+#[derive(Debug, Clone)]
+pub struct Vars {
+    next: *const *const u8,
+}
+
+impl Iterator for Vars {
+    type Item = (&'static str, &'static str);
+
+    fn next(&mut self) -> Option<(&'static str, &'static str)> {
+        if self.next.is_null() || unsafe { (*self.next).is_null() } {
+            return None;
+        }
+        let var = unsafe { c_str(*self.next) };
+        self.next = unsafe { self.next.add(1) };
+        match var.find('=') {
+            Some(index) => Some((&var[..index], &var[index + 1..])),
+            None => Some((var, "")),
+        }
+    }
+}
+
+/// Returns the environment variables of the process, as `(key, value)`
+/// pairs.
+pub fn vars() -> Vars {
+    Vars { next: unsafe { ENVP } }
+}
+
+/// Returns the value of the environment variable `key`, if it is set.
+pub fn var(key: &str) -> Option<&'static str> {
+    vars().find(|&(name, _)| name == key).map(|(_, value)| value)
+}
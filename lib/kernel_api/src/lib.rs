@@ -5,6 +5,10 @@ use core::fmt;
 
 use shim::io;
 
+#[cfg(feature = "user-space")]
+pub mod env;
+#[cfg(feature = "user-space")]
+pub mod sync;
 #[cfg(feature = "user-space")]
 pub mod syscall;
 
@@ -22,12 +26,20 @@ pub enum OsError {
     BadAddress = 50,
     FileExists = 60,
     InvalidArgument = 70,
+    BadDescriptor = 80,
+    TooManyFiles = 81,
+    NotADirectory = 90,
+    IsADirectory = 91,
+    NoChild = 95,
+    WouldBlock = 96,
+    TimedOut = 97,
 
     IoError = 101,
     IoErrorEof = 102,
     IoErrorInvalidData = 103,
     IoErrorInvalidInput = 104,
     IoErrorTimedOut = 105,
+    IoErrorWriteZero = 106,
 
     InvalidSocket = 200,
     SocketAlreadyOpen = 201,
@@ -46,11 +58,20 @@ impl core::convert::From<u64> for OsError {
             50 => OsError::BadAddress,
             60 => OsError::FileExists,
             70 => OsError::InvalidArgument,
+            80 => OsError::BadDescriptor,
+            81 => OsError::TooManyFiles,
+            90 => OsError::NotADirectory,
+            91 => OsError::IsADirectory,
+            95 => OsError::NoChild,
+            96 => OsError::WouldBlock,
+            97 => OsError::TimedOut,
 
             101 => OsError::IoError,
             102 => OsError::IoErrorEof,
             103 => OsError::IoErrorInvalidData,
             104 => OsError::IoErrorInvalidInput,
+            105 => OsError::IoErrorTimedOut,
+            106 => OsError::IoErrorWriteZero,
 
             200 => OsError::InvalidSocket,
             201 => OsError::SocketAlreadyOpen,
@@ -68,7 +89,10 @@ impl core::convert::From<io::Error> for OsError {
             io::ErrorKind::InvalidData => OsError::IoErrorInvalidData,
             io::ErrorKind::InvalidInput => OsError::IoErrorInvalidInput,
             io::ErrorKind::TimedOut => OsError::IoErrorTimedOut,
+            io::ErrorKind::WriteZero => OsError::IoErrorWriteZero,
             io::ErrorKind::NotFound => OsError::NoEntry,
+            io::ErrorKind::PermissionDenied => OsError::NoAccess,
+            io::ErrorKind::AlreadyExists => OsError::FileExists,
             _ => OsError::IoError,
         }
     }
@@ -79,3 +103,122 @@ pub const NR_TIME: usize = 2;
 pub const NR_EXIT: usize = 3;
 pub const NR_WRITE: usize = 4;
 pub const NR_GETPID: usize = 5;
+pub const NR_OPEN: usize = 6;
+pub const NR_CLOSE: usize = 7;
+pub const NR_READ: usize = 8;
+pub const NR_SEEK: usize = 9;
+pub const NR_STAT: usize = 10;
+pub const NR_READDIR: usize = 11;
+pub const NR_GETCWD: usize = 12;
+pub const NR_CHDIR: usize = 13;
+pub const NR_SPAWN: usize = 14;
+pub const NR_WAIT: usize = 15;
+pub const NR_FORK: usize = 16;
+pub const NR_NICE: usize = 17;
+pub const NR_THREAD_SPAWN: usize = 18;
+pub const NR_THREAD_JOIN: usize = 19;
+pub const NR_THREAD_EXIT: usize = 20;
+pub const NR_GETTID: usize = 21;
+pub const NR_FUTEX_WAIT: usize = 22;
+pub const NR_FUTEX_WAKE: usize = 23;
+
+/// The most arguments `spawn` passes to a new process.
+pub const ARG_MAX: usize = 32;
+
+/// The exit status of a process that the kernel killed, e.g. on a fault.
+pub const EXIT_KILLED: i32 = -1;
+
+/// The lowest nice value: threads with lower nice values are scheduled
+/// first. A process's first thread starts with the nice value of its parent,
+/// and other threads with that of the thread that spawned them.
+pub const NICE_MIN: i32 = -20;
+/// The highest nice value.
+pub const NICE_MAX: i32 = 19;
+
+/// A file descriptor: an index into the descriptor table of a process.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Fd(pub u64);
+
+impl Fd {
+    /// The console, opened for reading in every process.
+    pub const STDIN: Fd = Fd(0);
+    /// The console, opened for writing in every process.
+    pub const STDOUT: Fd = Fd(1);
+    /// The console, opened for error messages in every process.
+    pub const STDERR: Fd = Fd(2);
+}
+
+/// `open` flag: create the file if it does not exist.
+pub const O_CREATE: u64 = 1 << 0;
+/// `open` flag: start at the end of the file rather than the beginning.
+pub const O_APPEND: u64 = 1 << 1;
+
+/// `seek` origin: the start of the file.
+pub const SEEK_START: u64 = 0;
+/// `seek` origin: the current offset.
+pub const SEEK_CURRENT: u64 = 1;
+/// `seek` origin: the end of the file.
+pub const SEEK_END: u64 = 2;
+
+/// The status of a file or directory, as returned by `stat` and `readdir`.
+#[repr(C)]
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Stat {
+    /// The size of a file in bytes; 0 for directories.
+    pub size: u64,
+    /// The time of the last modification, in seconds since the Unix epoch.
+    pub modified: u64,
+    /// `true` for directories.
+    pub is_dir: bool,
+    /// `true` if the entry cannot be written.
+    pub read_only: bool,
+}
+
+/// The longest name, in bytes, that `readdir` returns.
+pub const NAME_MAX: usize = 255;
+
+/// A directory entry, as returned by `readdir`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct DirEntry {
+    name: [u8; NAME_MAX],
+    name_len: u8,
+    /// The status of the entry.
+    pub stat: Stat,
+}
+
+impl DirEntry {
+    /// Returns an entry with an empty name.
+    pub fn empty() -> DirEntry {
+        DirEntry { name: [0; NAME_MAX], name_len: 0, stat: Stat::default() }
+    }
+
+    /// Returns an entry named `name`, cut to `NAME_MAX` bytes.
+    pub fn new(name: &str, stat: Stat) -> DirEntry {
+        let mut entry = DirEntry::empty();
+        let mut len = core::cmp::min(name.len(), NAME_MAX);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        entry.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        entry.name_len = len as u8;
+        entry.stat = stat;
+        entry
+    }
+
+    /// Returns the name of the entry.
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len as usize]).unwrap_or("")
+    }
+}
+
+impl fmt::Debug for DirEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DirEntry").field("name", &self.name()).field("stat", &self.stat).finish()
+    }
+}
+
+/// Request of the `control` operation of `/dev/fb0` that returns the display
+/// mode as five little-endian `u32`s: width and height in pixels, bytes per
+/// row, bits per pixel, and 1 if pixels are RGB or 0 if BGR.
+pub const FB_GET_MODE: u32 = 1;
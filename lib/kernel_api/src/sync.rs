@@ -0,0 +1,196 @@
+//! Blocking synchronization between threads, and between processes sharing
+//! memory, built on `futex_wait` and `futex_wake`: threads only make a
+//! system call when they have to block or wake a blocked thread.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+
+use crate::syscall::{futex_wait, futex_wake};
+use crate::OsError;
+
+/// The mutex is unlocked.
+const UNLOCKED: u32 = 0;
+/// The mutex is locked, and no thread is blocked on it.
+const LOCKED: u32 = 1;
+/// The mutex is locked, and threads may be blocked on it.
+const CONTENDED: u32 = 2;
+
+/// A mutual exclusion lock protecting a value of type `T`.
+pub struct Mutex<T> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// Returns an unlocked mutex protecting `value`.
+    pub const fn new(value: T) -> Mutex<T> {
+        Mutex { state: AtomicU32::new(UNLOCKED), data: UnsafeCell::new(value) }
+    }
+
+    /// Locks the mutex, blocking until it is unlocked, and returns a guard
+    /// that unlocks it when dropped.
+    pub fn lock(&self) -> MutexGuard<T> {
+        if self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            // Mark the mutex contended, so that whoever unlocks it wakes us.
+            while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+                let _ = futex_wait(&self.state, CONTENDED, None);
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+
+    /// Locks the mutex if it is unlocked, without blocking.
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        match self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => Some(MutexGuard { mutex: self }),
+            Err(_) => None,
+        }
+    }
+
+    /// Returns the protected value, which no other thread can lock any
+    /// longer.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Returns the protected value, which no other thread can lock while it
+    /// is borrowed.
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            let _ = futex_wake(&self.state, 1);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("Mutex").field("data", &*guard).finish(),
+            None => f.debug_struct("Mutex").field("data", &"<locked>").finish(),
+        }
+    }
+}
+
+/// A locked `Mutex`, as returned by `Mutex::lock()`. The mutex is unlocked
+/// when the guard is dropped.
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<'a, T> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// A condition variable: threads block on it, with a `Mutex` locked, until
+/// another thread notifies them of a change to the value the mutex protects.
+///
+/// Waits may end without a notification, so that a waiting thread must check
+/// the condition it waits for again, e.g. in a loop.
+#[derive(Debug)]
+pub struct Condvar {
+    /// The number of notifications so far, wrapping around.
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    /// Returns a condition variable with no threads waiting.
+    pub const fn new() -> Condvar {
+        Condvar { seq: AtomicU32::new(0) }
+    }
+
+    /// Unlocks the mutex of `guard` and blocks until the condition variable
+    /// is notified, then locks the mutex again.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_timeout(guard, None).0
+    }
+
+    /// As `wait()`, but blocks for at most `timeout`, if given. Also returns
+    /// `true` if the wait timed out.
+    pub fn wait_timeout<'a, T>(&self, guard: MutexGuard<'a, T>, timeout: Option<Duration>) -> (MutexGuard<'a, T>, bool) {
+        // A notification after the mutex is unlocked changes `seq`, so that
+        // the wait below returns at once rather than missing it.
+        let seq = self.seq.load(Ordering::Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+        let timed_out = futex_wait(&self.seq, seq, timeout) == Err(OsError::TimedOut);
+        (mutex.lock(), timed_out)
+    }
+
+    /// Wakes one of the threads waiting on the condition variable, if any.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        let _ = futex_wake(&self.seq, 1);
+    }
+
+    /// Wakes every thread waiting on the condition variable.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        let _ = futex_wake(&self.seq, core::usize::MAX);
+    }
+}
+
+/// A counting semaphore: threads acquire its permits, blocking until one is
+/// released if there are none left.
+#[derive(Debug)]
+pub struct Semaphore {
+    permits: AtomicU32,
+}
+
+impl Semaphore {
+    /// Returns a semaphore with `permits` permits.
+    pub const fn new(permits: u32) -> Semaphore {
+        Semaphore { permits: AtomicU32::new(permits) }
+    }
+
+    /// Takes a permit, blocking until one is released if there are none left.
+    pub fn acquire(&self) {
+        while !self.try_acquire() {
+            let _ = futex_wait(&self.permits, 0, None);
+        }
+    }
+
+    /// Takes a permit if there is one left, without blocking. Returns `false`
+    /// if there is none.
+    pub fn try_acquire(&self) -> bool {
+        let mut permits = self.permits.load(Ordering::Relaxed);
+        while permits > 0 {
+            match self.permits.compare_exchange_weak(permits, permits - 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return true,
+                Err(current) => permits = current,
+            }
+        }
+        false
+    }
+
+    /// Returns a permit, waking a thread blocked in `acquire()`, if any.
+    pub fn release(&self) {
+        self.permits.fetch_add(1, Ordering::Release);
+        let _ = futex_wake(&self.permits, 1);
+    }
+}
@@ -1,7 +1,10 @@
 use core::fmt;
 use core::fmt::Write;
+use core::sync::atomic::AtomicU32;
 use core::time::Duration;
 
+use shim::io::SeekFrom;
+
 use crate::*;
 
 macro_rules! err_or {
@@ -15,6 +18,42 @@ macro_rules! err_or {
     }};
 }
 
+/// Makes system call `$nr` with up to four arguments in `x0` to `x3`.
+/// Evaluates to the values left in `x0` and `x1` and the error code in `x7`.
+macro_rules! syscall {
+    ($nr:expr) => {
+        syscall!($nr, 0u64, 0u64, 0u64, 0u64)
+    };
+    ($nr:expr, $a0:expr) => {
+        syscall!($nr, $a0, 0u64, 0u64, 0u64)
+    };
+    ($nr:expr, $a0:expr, $a1:expr) => {
+        syscall!($nr, $a0, $a1, 0u64, 0u64)
+    };
+    ($nr:expr, $a0:expr, $a1:expr, $a2:expr) => {
+        syscall!($nr, $a0, $a1, $a2, 0u64)
+    };
+    ($nr:expr, $a0:expr, $a1:expr, $a2:expr, $a3:expr) => {{
+        let (r0, r1, ecode): (u64, u64, u64);
+        let (a0, a1, a2, a3) = ($a0 as u64, $a1 as u64, $a2 as u64, $a3 as u64);
+        unsafe {
+            asm!("mov x0, $3
+                  mov x1, $4
+                  mov x2, $5
+                  mov x3, $6
+                  svc $7
+                  mov $0, x0
+                  mov $1, x1
+                  mov $2, x7"
+                 : "=r"(r0), "=r"(r1), "=r"(ecode)
+                 : "r"(a0), "r"(a1), "r"(a2), "r"(a3), "i"($nr)
+                 : "x0", "x1", "x2", "x3", "x7", "memory"
+                 : "volatile");
+        }
+        (r0, r1, ecode)
+    }};
+}
+
 pub fn sleep(span: Duration) -> OsResult<Duration> {
     if span.as_millis() > core::u64::MAX as u128 {
         panic!("too big!");
@@ -39,28 +78,206 @@ pub fn sleep(span: Duration) -> OsResult<Duration> {
 }
 
 pub fn time() -> Duration {
-    unimplemented!("time()");
+    let (secs, nanos, _) = syscall!(NR_TIME);
+    Duration::new(secs, nanos as u32)
+}
+
+/// Terminates the calling process, with all of its threads, with exit
+/// status `code`, which its parent receives from `wait`.
+pub fn exit(code: i32) -> ! {
+    let _ = syscall!(NR_EXIT, code);
+    unreachable!("exit returned");
 }
 
-pub fn exit() -> ! {
-    unimplemented!("exit()");
+pub fn getpid() -> u64 {
+    let (pid, _, _) = syscall!(NR_GETPID);
+    pid
 }
 
-pub fn write(b: u8) {
-    unimplemented!("write()");
+/// Returns the ID of the calling thread. A process's first thread has the
+/// process's ID.
+pub fn gettid() -> u64 {
+    let (tid, _, _) = syscall!(NR_GETTID);
+    tid
 }
 
-pub fn getpid() -> u64 {
-    unimplemented!("getpid()");
+/// Opens the file or directory at `path`, relative to the working directory
+/// unless absolute. `flags` is a combination of `O_CREATE` and `O_APPEND`.
+pub fn open(path: &str, flags: u64) -> OsResult<Fd> {
+    let (fd, _, ecode) = syscall!(NR_OPEN, path.as_ptr(), path.len(), flags);
+    err_or!(ecode, Fd(fd))
+}
+
+/// Closes `fd`.
+pub fn close(fd: Fd) -> OsResult<()> {
+    let (_, _, ecode) = syscall!(NR_CLOSE, fd.0);
+    err_or!(ecode, ())
+}
+
+/// Reads from `fd` into `buf`. Returns the number of bytes read; 0 at the
+/// end of a file.
+pub fn read(fd: Fd, buf: &mut [u8]) -> OsResult<usize> {
+    let (read, _, ecode) = syscall!(NR_READ, fd.0, buf.as_mut_ptr(), buf.len());
+    err_or!(ecode, read as usize)
+}
+
+/// Writes `buf` to `fd`. Returns the number of bytes written.
+pub fn write(fd: Fd, buf: &[u8]) -> OsResult<usize> {
+    let (written, _, ecode) = syscall!(NR_WRITE, fd.0, buf.as_ptr(), buf.len());
+    err_or!(ecode, written as usize)
+}
+
+/// Moves the offset of `fd` to `pos`. Returns the new offset from the start
+/// of the file.
+pub fn seek(fd: Fd, pos: SeekFrom) -> OsResult<u64> {
+    let (whence, offset) = match pos {
+        SeekFrom::Start(n) => (SEEK_START, n),
+        SeekFrom::Current(n) => (SEEK_CURRENT, n as u64),
+        SeekFrom::End(n) => (SEEK_END, n as u64),
+    };
+    let (offset, _, ecode) = syscall!(NR_SEEK, fd.0, offset, whence);
+    err_or!(ecode, offset)
+}
+
+/// Returns the status of the file or directory at `path`.
+pub fn stat(path: &str) -> OsResult<Stat> {
+    let mut stat = Stat::default();
+    let (_, _, ecode) = syscall!(NR_STAT, path.as_ptr(), path.len(), &mut stat as *mut Stat);
+    err_or!(ecode, stat)
+}
+
+/// Returns the next entry of the directory open as `fd`, or `None` once
+/// every entry has been returned.
+pub fn readdir(fd: Fd) -> OsResult<Option<DirEntry>> {
+    let mut entry = DirEntry::empty();
+    let (read, _, ecode) = syscall!(NR_READDIR, fd.0, &mut entry as *mut DirEntry);
+    err_or!(ecode, if read == 0 { None } else { Some(entry) })
+}
+
+/// Writes the working directory into `buf` and returns it.
+pub fn getcwd(buf: &mut [u8]) -> OsResult<&str> {
+    let (len, _, ecode) = syscall!(NR_GETCWD, buf.as_mut_ptr(), buf.len());
+    err_or!(ecode, ()).and_then(|()| {
+        core::str::from_utf8(&buf[..len as usize]).map_err(|_| OsError::InvalidArgument)
+    })
 }
 
+/// Changes the working directory to `path`.
+pub fn chdir(path: &str) -> OsResult<()> {
+    let (_, _, ecode) = syscall!(NR_CHDIR, path.as_ptr(), path.len());
+    err_or!(ecode, ())
+}
+
+/// Starts the ELF executable at `path`, relative to the working directory
+/// unless absolute, as a child of the calling process with the arguments
+/// `args`, after `path` itself as its first argument. The child inherits the
+/// environment and the working directory. Returns the child's ID.
+///
+/// At most `ARG_MAX` arguments can be passed.
+pub fn spawn(path: &str, args: &[&str]) -> OsResult<u64> {
+    if args.len() > ARG_MAX {
+        return Err(OsError::InvalidArgument);
+    }
+
+    // Each argument is passed as its address and length.
+    let mut argv = [[0u64; 2]; ARG_MAX];
+    for (slot, arg) in argv.iter_mut().zip(args) {
+        *slot = [arg.as_ptr() as u64, arg.len() as u64];
+    }
+    let (id, _, ecode) = syscall!(NR_SPAWN, path.as_ptr(), path.len(), argv.as_ptr(), args.len());
+    err_or!(ecode, id)
+}
+
+/// Waits for the child `id` to exit and returns its exit status.
+/// `EXIT_KILLED` means the kernel killed it.
+pub fn wait(id: u64) -> OsResult<i32> {
+    let (status, _, ecode) = syscall!(NR_WAIT, id);
+    err_or!(ecode, status as i32)
+}
+
+/// Starts a child process running a copy of the calling one, which shares
+/// its open descriptors and working directory. Returns the child's ID in the
+/// calling process, and 0 in the child.
+pub fn fork() -> OsResult<u64> {
+    let (id, _, ecode) = syscall!(NR_FORK);
+    err_or!(ecode, id)
+}
+
+/// Adds `increment` to the nice value of the calling thread, keeping it
+/// within `NICE_MIN..=NICE_MAX`, and returns the new nice value.
+pub fn nice(increment: i32) -> OsResult<i32> {
+    let (nice, _, ecode) = syscall!(NR_NICE, gettid(), increment, 1);
+    err_or!(ecode, nice as i32)
+}
+
+/// Sets the nice value of the thread `id`, which must be a thread of the
+/// calling process or of one of its children, to `nice`. A process's ID is
+/// that of its first thread.
+pub fn renice(id: u64, nice: i32) -> OsResult<()> {
+    let (_, _, ecode) = syscall!(NR_NICE, id, nice, 0);
+    err_or!(ecode, ())
+}
+
+/// Starts a thread of the calling process that runs `entry` with `arg` on
+/// `stack`, sharing the process's memory, descriptors and working directory.
+/// Returns the thread's ID, which `thread_join` takes.
+///
+/// The thread ends by calling `thread_exit`, or with the whole process on
+/// `exit`.
+pub fn thread_spawn(entry: extern "C" fn(u64) -> !, arg: u64, stack: &'static mut [u8]) -> OsResult<u64> {
+    let top = (stack.as_mut_ptr() as u64 + stack.len() as u64) & !0xf;
+    let (id, _, ecode) = syscall!(NR_THREAD_SPAWN, entry as u64, arg, top);
+    err_or!(ecode, id)
+}
+
+/// Waits for the thread `id`, spawned by the calling process, to end and
+/// returns its exit status.
+pub fn thread_join(id: u64) -> OsResult<i32> {
+    let (status, _, ecode) = syscall!(NR_THREAD_JOIN, id);
+    err_or!(ecode, status as i32)
+}
+
+/// Ends the calling thread with exit status `code`, which the process
+/// receives from `thread_join`. If it is the last thread of the process, the
+/// process exits with `code` instead.
+pub fn thread_exit(code: i32) -> ! {
+    let _ = syscall!(NR_THREAD_EXIT, code);
+    unreachable!("thread_exit returned");
+}
+
+/// Blocks the calling thread until `futex_wake` is called on `futex`, if it
+/// holds `expected`, or until `timeout` is over, if given. Threads of
+/// different processes that share the page of `futex` wait on the same word.
+///
+/// Returns `WouldBlock` at once if `futex` does not hold `expected`, and
+/// `TimedOut` if the timeout is over first. The thread may also be woken for
+/// no reason: callers check the condition they wait for again.
+pub fn futex_wait(futex: &AtomicU32, expected: u32, timeout: Option<Duration>) -> OsResult<()> {
+    let ms = match timeout {
+        Some(timeout) => core::cmp::min(timeout.as_millis(), (core::u64::MAX - 1) as u128) as u64,
+        None => core::u64::MAX,
+    };
+    let (_, _, ecode) = syscall!(NR_FUTEX_WAIT, futex as *const AtomicU32, expected, ms);
+    err_or!(ecode, ())
+}
+
+/// Wakes up to `n` threads blocked in `futex_wait` on `futex`, in the order
+/// they blocked, and returns how many were woken.
+pub fn futex_wake(futex: &AtomicU32, n: usize) -> OsResult<usize> {
+    let (woken, _, ecode) = syscall!(NR_FUTEX_WAKE, futex as *const AtomicU32, n);
+    err_or!(ecode, woken as usize)
+}
 
 struct Console;
 
 impl fmt::Write for Console {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for b in s.bytes() {
-            write(b);
+        let mut bytes = s.as_bytes();
+        while !bytes.is_empty() {
+            match write(Fd::STDOUT, bytes) {
+                Ok(0) | Err(_) => return Err(fmt::Error),
+                Ok(n) => bytes = &bytes[n..],
+            }
         }
         Ok(())
     }
@@ -23,6 +23,8 @@ pub struct FrameBuffer {
     pub width: u32,
     pub height: u32,
     pub pitch: u32,
+    /// Bits per pixel, as the firmware allocated the buffer with.
+    pub depth: u32,
     pub isrgb: u32,
     pub te: u32,
     pub tn: u32,
@@ -85,6 +87,7 @@ impl FrameBuffer {
             width: mailbox.mailbox[5].read(),
             height: mailbox.mailbox[6].read(),
             pitch: mailbox.mailbox[33].read(),
+            depth: mailbox.mailbox[20].read(),
             isrgb: mailbox.mailbox[24].read(),
 
             te: mailbox.mailbox[28].read(),
@@ -109,6 +109,12 @@ impl Gpio<Uninitialized> {
         self.transition()
     }
 
+    /// Reads the pin's value without changing its function. Returns `true` if
+    /// the level is high and `false` if the level is low.
+    pub fn level(&mut self) -> bool {
+        self.registers.LEV[(self.pin / 32) as usize].read() & (1 << (self.pin % 32)) != 0
+    }
+
     /// Sets this pin to be an _output_ pin. Consumes self and returns a `Gpio`
     /// structure in the `Output` state.
     pub fn into_output(self) -> Gpio<Output> {
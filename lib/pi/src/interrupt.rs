@@ -5,7 +5,7 @@ use volatile::{Volatile, ReadVolatile};
 
 const INT_BASE: usize = IO_BASE + 0xB000 + 0x200;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Interrupt {
     Timer1 = 1,
     Timer3 = 3,
@@ -76,7 +76,13 @@ impl From<usize> for Interrupt {
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Registers {
-    // FIXME: Fill me in.
+    IRQ_BASIC_PENDING: ReadVolatile<u32>,
+    IRQ_PENDING: [ReadVolatile<u32>; 2],
+    FIQ_CONTROL: Volatile<u32>,
+    ENABLE_IRQS: [Volatile<u32>; 2],
+    ENABLE_BASIC_IRQS: Volatile<u32>,
+    DISABLE_IRQS: [Volatile<u32>; 2],
+    DISABLE_BASIC_IRQS: Volatile<u32>,
 }
 
 /// An interrupt controller. Used to enable and disable interrupts as well as to
@@ -95,16 +101,26 @@ impl Controller {
 
     /// Enables the interrupt `int`.
     pub fn enable(&mut self, int: Interrupt) {
-        unimplemented!()
+        let (bank, bit) = Self::locate(int);
+        self.registers.ENABLE_IRQS[bank].write(bit);
     }
 
     /// Disables the interrupt `int`.
     pub fn disable(&mut self, int: Interrupt) {
-        unimplemented!()
+        let (bank, bit) = Self::locate(int);
+        self.registers.DISABLE_IRQS[bank].write(bit);
     }
 
     /// Returns `true` if `int` is pending. Otherwise, returns `false`.
     pub fn is_pending(&self, int: Interrupt) -> bool {
-        unimplemented!()
+        let (bank, bit) = Self::locate(int);
+        self.registers.IRQ_PENDING[bank].has_mask(bit)
+    }
+
+    /// Returns the register bank of `int` and its bit in that bank's
+    /// registers.
+    fn locate(int: Interrupt) -> (usize, u32) {
+        let irq = int as usize;
+        (irq / 32, 1 << (irq % 32))
     }
 }
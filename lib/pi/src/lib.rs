@@ -13,4 +13,5 @@ pub mod timer;
 pub mod uart;
 pub mod fb;
 pub mod mbox;
+pub mod sdhci;
 pub mod data;
@@ -63,6 +63,68 @@ impl MailBox {
     }
 }
 
+/// Board information reported by the VideoCore firmware, as returned by
+/// `MailBox::board_info()`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct BoardInfo {
+    pub firmware_revision: u32,
+    pub model: u32,
+    pub revision: u32,
+    pub mac_address: [u8; 6],
+    pub serial: u64,
+    /// The base address and size of the memory given to the ARM cores.
+    pub arm_memory: (u32, u32),
+    /// The base address and size of the memory kept by the VideoCore.
+    pub vc_memory: (u32, u32),
+}
+
+impl MailBox {
+    /// Queries the firmware for board information over the property tags
+    /// channel.
+    pub fn board_info(&mut self) -> Result<BoardInfo, ()> {
+        // (tag, size of its value buffer in bytes), packed one after another.
+        const TAGS: [(u32, u32); 7] = [
+            (0x00001, 4), // firmware revision
+            (0x10001, 4), // board model
+            (0x10002, 4), // board revision
+            (0x10003, 8), // MAC address, 6 bytes padded to 8
+            (0x10004, 8), // board serial
+            (0x10005, 8), // ARM memory
+            (0x10006, 8), // VideoCore memory
+        ];
+
+        let mut index = 2;
+        let mut values = [0; 7];
+        for (i, &(tag, size)) in TAGS.iter().enumerate() {
+            self.mailbox[index].write(tag);
+            self.mailbox[index + 1].write(size);
+            self.mailbox[index + 2].write(0);
+            values[i] = index + 3;
+            for word in 0..size as usize / 4 {
+                self.mailbox[index + 3 + word].write(0);
+            }
+            index += 3 + size as usize / 4;
+        }
+        self.mailbox[index].write(0);
+        self.mailbox[0].write((index as u32 + 1) * 4);
+        self.mailbox[1].write(0);
+
+        self.mbox_call(8)?;
+
+        let word = |i: usize| self.mailbox[i].read();
+        let (mac_low, mac_high) = (word(values[3]).to_le_bytes(), word(values[3] + 1).to_le_bytes());
+        Ok(BoardInfo {
+            firmware_revision: word(values[0]),
+            model: word(values[1]),
+            revision: word(values[2]),
+            mac_address: [mac_low[0], mac_low[1], mac_low[2], mac_low[3], mac_high[0], mac_high[1]],
+            serial: (word(values[4] + 1) as u64) << 32 | word(values[4]) as u64,
+            arm_memory: (word(values[5]), word(values[5] + 1)),
+            vc_memory: (word(values[6]), word(values[6] + 1)),
+        })
+    }
+}
+
 impl core::fmt::Debug for MailBox {
 
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -0,0 +1,641 @@
+use core::fmt;
+use core::time::Duration;
+
+use shim::const_assert_size;
+use shim::io;
+
+use volatile::prelude::*;
+use volatile::{ReadVolatile, Reserved, Volatile, WriteVolatile};
+
+use crate::common::IO_BASE;
+use crate::gpio::{Function, Gpio};
+use crate::mbox::MailBox;
+use crate::timer::{current_time, spin_sleep};
+
+/// The base address for the Arasan EMMC (SDHCI) controller registers.
+const EMMC_REG_BASE: usize = IO_BASE + 0x300000;
+
+/// Mailbox property tag to query a clock rate, and the EMMC clock id.
+const MBOX_TAG_GET_CLOCK_RATE: u32 = 0x00030002;
+const MBOX_CLOCK_ID_EMMC: u32 = 1;
+
+/// Clock rates used during identification and data transfer.
+const CLOCK_IDENTIFICATION: u32 = 400_000;
+const CLOCK_NORMAL: u32 = 25_000_000;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    /// Argument for ACMD23, or the SDMA system address.
+    ARG2: Volatile<u32>,
+    BLKSIZECNT: Volatile<u32>,
+    ARG1: Volatile<u32>,
+    CMDTM: Volatile<u32>,
+    RESP: [ReadVolatile<u32>; 4],
+    DATA: Volatile<u32>,
+    STATUS: ReadVolatile<u32>,
+    CONTROL0: Volatile<u32>,
+    CONTROL1: Volatile<u32>,
+    INTERRUPT: Volatile<u32>,
+    IRPT_MASK: Volatile<u32>,
+    IRPT_EN: Volatile<u32>,
+    CONTROL2: Volatile<u32>,
+    CAPABILITIES: [ReadVolatile<u32>; 2],
+    __r0: [Reserved<u32>; 2],
+    FORCE_IRPT: WriteVolatile<u32>,
+    __r1: [Reserved<u32>; 7],
+    BOOT_TIMEOUT: Volatile<u32>,
+    DBG_SEL: Volatile<u32>,
+    __r2: [Reserved<u32>; 2],
+    EXRDFIFO_CFG: Volatile<u32>,
+    EXRDFIFO_EN: Volatile<u32>,
+    TUNE_STEP: Volatile<u32>,
+    TUNE_STEPS_STD: Volatile<u32>,
+    TUNE_STEPS_DDR: Volatile<u32>,
+    __r3: [Reserved<u32>; 23],
+    SPI_INT_SPT: Volatile<u32>,
+    __r4: [Reserved<u32>; 2],
+    SLOTISR_VER: ReadVolatile<u32>,
+}
+
+const_assert_size!(Registers, 0x100);
+
+/// Bit fields of the `CMDTM` register.
+mod cmdtm {
+    pub const TM_DMA_EN: u32 = 1 << 0;
+    pub const TM_BLKCNT_EN: u32 = 1 << 1;
+    pub const TM_AUTO_CMD12: u32 = 0b01 << 2;
+    pub const TM_DAT_DIR_READ: u32 = 1 << 4;
+    pub const TM_MULTI_BLOCK: u32 = 1 << 5;
+    pub const RSPNS_NONE: u32 = 0b00 << 16;
+    pub const RSPNS_136: u32 = 0b01 << 16;
+    pub const RSPNS_48: u32 = 0b10 << 16;
+    pub const RSPNS_48_BUSY: u32 = 0b11 << 16;
+    pub const RSPNS_MASK: u32 = 0b11 << 16;
+    pub const CRCCHK_EN: u32 = 1 << 19;
+    pub const IXCHK_EN: u32 = 1 << 20;
+    pub const ISDATA: u32 = 1 << 21;
+
+    pub const fn index(n: u32) -> u32 {
+        n << 24
+    }
+}
+
+/// Bit fields of the `STATUS` register.
+mod status {
+    pub const CMD_INHIBIT: u32 = 1 << 0;
+    pub const DAT_INHIBIT: u32 = 1 << 1;
+}
+
+/// Bit fields of the `CONTROL0` register.
+mod control0 {
+    pub const HCTL_DWIDTH: u32 = 1 << 1;
+    pub const DMA_SELECT_MASK: u32 = 0b11 << 3;
+    pub const BUS_POWER_3V3: u32 = 0b1111 << 8;
+}
+
+/// Bit fields of the `CONTROL1` register.
+mod control1 {
+    pub const CLK_INTLEN: u32 = 1 << 0;
+    pub const CLK_STABLE: u32 = 1 << 1;
+    pub const CLK_EN: u32 = 1 << 2;
+    pub const CLK_FREQ_MASK: u32 = 0xFFC0;
+    pub const DATA_TOUNIT_MAX: u32 = 0xE << 16;
+    pub const DATA_TOUNIT_MASK: u32 = 0xF << 16;
+    pub const SRST_HC: u32 = 1 << 24;
+    pub const SRST_CMD: u32 = 1 << 25;
+    pub const SRST_DATA: u32 = 1 << 26;
+}
+
+/// Bit fields of the `INTERRUPT` register.
+mod irpt {
+    pub const CMD_DONE: u32 = 1 << 0;
+    pub const DATA_DONE: u32 = 1 << 1;
+    pub const DMA: u32 = 1 << 3;
+    pub const WRITE_RDY: u32 = 1 << 4;
+    pub const READ_RDY: u32 = 1 << 5;
+    pub const ERR: u32 = 1 << 15;
+    pub const CTO_ERR: u32 = 1 << 16;
+    pub const CCRC_ERR: u32 = 1 << 17;
+    pub const CEND_ERR: u32 = 1 << 18;
+    pub const CBAD_ERR: u32 = 1 << 19;
+    pub const DTO_ERR: u32 = 1 << 20;
+    pub const DCRC_ERR: u32 = 1 << 21;
+    pub const DEND_ERR: u32 = 1 << 22;
+    pub const ACMD_ERR: u32 = 1 << 24;
+    pub const ERR_MASK: u32 = 0xFFFF_0000 | ERR;
+    pub const ALL: u32 = 0xFFFF_FFFF;
+}
+
+/// An SD command: the value written to `CMDTM` and whether it must be preceded
+/// by `APP_CMD` (CMD55).
+#[derive(Copy, Clone)]
+struct Command {
+    cmdtm: u32,
+    app: bool,
+}
+
+impl Command {
+    const fn new(index: u32, flags: u32) -> Command {
+        Command { cmdtm: cmdtm::index(index) | flags, app: false }
+    }
+
+    const fn app(index: u32, flags: u32) -> Command {
+        Command { cmdtm: cmdtm::index(index) | flags, app: true }
+    }
+
+    fn response(&self) -> u32 {
+        self.cmdtm & cmdtm::RSPNS_MASK
+    }
+}
+
+use self::cmdtm::*;
+
+const R1: u32 = RSPNS_48 | CRCCHK_EN | IXCHK_EN;
+const R1B: u32 = RSPNS_48_BUSY | CRCCHK_EN | IXCHK_EN;
+const R2: u32 = RSPNS_136 | CRCCHK_EN;
+const R3: u32 = RSPNS_48;
+const R6: u32 = RSPNS_48 | CRCCHK_EN | IXCHK_EN;
+const R7: u32 = RSPNS_48 | CRCCHK_EN | IXCHK_EN;
+
+const GO_IDLE_STATE: Command = Command::new(0, RSPNS_NONE);
+const ALL_SEND_CID: Command = Command::new(2, R2);
+const SEND_RELATIVE_ADDR: Command = Command::new(3, R6);
+const SELECT_CARD: Command = Command::new(7, R1B);
+const SEND_IF_COND: Command = Command::new(8, R7);
+const SEND_CSD: Command = Command::new(9, R2);
+const SET_BLOCKLEN: Command = Command::new(16, R1);
+const READ_SINGLE_BLOCK: Command = Command::new(17, R1 | ISDATA | TM_DAT_DIR_READ);
+const READ_MULTIPLE_BLOCK: Command = Command::new(
+    18,
+    R1 | ISDATA | TM_DAT_DIR_READ | TM_BLKCNT_EN | TM_MULTI_BLOCK | TM_AUTO_CMD12,
+);
+const WRITE_BLOCK: Command = Command::new(24, R1 | ISDATA);
+const WRITE_MULTIPLE_BLOCK: Command =
+    Command::new(25, R1 | ISDATA | TM_BLKCNT_EN | TM_MULTI_BLOCK | TM_AUTO_CMD12);
+const APP_CMD: Command = Command::new(55, R1);
+const SET_BUS_WIDTH: Command = Command::app(6, R1);
+const SD_SEND_OP_COND: Command = Command::app(41, R3);
+
+/// `SEND_IF_COND` argument: 2.7-3.6V and the check pattern `0xAA`.
+const IF_COND_3V3: u32 = 0x1AA;
+/// `SD_SEND_OP_COND` bits: card power up done, high capacity, 3.2-3.4V window.
+const OCR_BUSY: u32 = 1 << 31;
+const OCR_HCS: u32 = 1 << 30;
+const OCR_VOLTAGE_WINDOW: u32 = 0x00FF_8000;
+/// Error bits of an R1 card status.
+const R1_ERRORS_MASK: u32 = 0xFDF9_0008;
+
+/// Maximum number of blocks in one multi-block transfer.
+const MAX_BLOCKS_PER_TRANSFER: usize = 0xFFFF;
+/// `BLKSIZECNT` SDMA buffer boundary field: 512 KiB.
+const SDMA_BOUNDARY_512K: u32 = 0b111 << 12;
+
+/// Returns the capacity in blocks described by `csd`, a CSD register as the
+/// controller stores an R2 response: without its CRC, shifted right by 8.
+fn csd_blocks(csd: u128) -> u64 {
+    let bits = |high: u32, low: u32| ((csd >> (low - 8)) & ((1 << (high - low + 1)) - 1)) as u64;
+    match bits(127, 126) {
+        // Version 1.0: (C_SIZE + 1) * 2^(C_SIZE_MULT + 2) blocks of
+        // 2^READ_BL_LEN bytes.
+        0 => ((bits(73, 62) + 1) << (bits(49, 47) + 2 + bits(83, 80))) / Sdhci::BLOCK_SIZE as u64,
+        // Version 2.0: (C_SIZE + 1) * 512 KiB.
+        _ => (bits(69, 48) + 1) * 1024,
+    }
+}
+
+/// Maps the error bits of an `INTERRUPT` value to an `io::Error`.
+fn decode_error(irpt: u32) -> io::Error {
+    use self::irpt::*;
+
+    if irpt & CTO_ERR != 0 {
+        io::Error::new(io::ErrorKind::TimedOut, "sd command timed out")
+    } else if irpt & DTO_ERR != 0 {
+        io::Error::new(io::ErrorKind::TimedOut, "sd data transfer timed out")
+    } else if irpt & CCRC_ERR != 0 {
+        io::Error::new(io::ErrorKind::InvalidData, "sd command response crc error")
+    } else if irpt & DCRC_ERR != 0 {
+        io::Error::new(io::ErrorKind::InvalidData, "sd data crc error")
+    } else if irpt & (CEND_ERR | DEND_ERR) != 0 {
+        io::Error::new(io::ErrorKind::InvalidData, "sd end bit error")
+    } else if irpt & CBAD_ERR != 0 {
+        io::Error::new(io::ErrorKind::InvalidData, "sd command index mismatch")
+    } else if irpt & ACMD_ERR != 0 {
+        io::Error::new(io::ErrorKind::Other, "sd auto cmd12 error")
+    } else {
+        io::Error::new(io::ErrorKind::Other, "sd controller error")
+    }
+}
+
+/// Asks the firmware for the EMMC base clock rate in Hz.
+fn base_clock_from_mailbox() -> Option<u32> {
+    let mut mailbox = MailBox::new();
+
+    mailbox.mailbox[0].write(8 * 4);
+    mailbox.mailbox[1].write(0);
+
+    mailbox.mailbox[2].write(MBOX_TAG_GET_CLOCK_RATE);
+    mailbox.mailbox[3].write(8);
+    mailbox.mailbox[4].write(8);
+    mailbox.mailbox[5].write(MBOX_CLOCK_ID_EMMC);
+    mailbox.mailbox[6].write(0);            //clock rate
+
+    mailbox.mailbox[7].write(0);
+
+    mailbox.mbox_call(8).ok()?;
+    match mailbox.mailbox[6].read() {
+        0 => None,
+        rate => Some(rate),
+    }
+}
+
+/// Transfer mode used for block reads and writes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Transfer {
+    /// The CPU moves every word through the `DATA` register.
+    Pio,
+    /// The controller moves data with SDMA. Buffers must be 4-byte aligned
+    /// and their addresses must be physical addresses visible to the
+    /// controller and not held in the data cache.
+    Sdma,
+}
+
+/// A handle to the Arasan EMMC (SDHCI) controller and the card in its slot.
+pub struct Sdhci {
+    registers: &'static mut Registers,
+    base_clock: u32,
+    host_version: u8,
+    rca: u32,
+    high_capacity: bool,
+    blocks: u64,
+    transfer: Transfer,
+}
+
+impl Sdhci {
+    /// The size of one block in bytes.
+    pub const BLOCK_SIZE: usize = 512;
+
+    /// Resets the controller, routes the SD pins to it, and identifies and
+    /// selects the card in the slot. Transfers use `Transfer::Pio` until
+    /// `set_transfer()` is called.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that this method is invoked only once: the
+    /// returned handle assumes exclusive ownership of the controller.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `TimedOut` if the controller or the card does
+    /// not respond in time, `InvalidData` if a response is corrupted, and
+    /// `Other` if the card is unusable (for example, it does not support a
+    /// 3.3V supply).
+    pub unsafe fn new() -> io::Result<Sdhci> {
+        // GPIO 48-53 carry the SD signals on ALT3.
+        for pin in 48..54 {
+            Gpio::new(pin).into_alt(Function::Alt3);
+        }
+
+        let registers = &mut *(EMMC_REG_BASE as *mut Registers);
+        let host_version = ((registers.SLOTISR_VER.read() >> 16) & 0xFF) as u8;
+        let base_clock = base_clock_from_mailbox()
+            .unwrap_or_else(|| ((registers.CAPABILITIES[0].read() >> 8) & 0xFF) * 1_000_000);
+
+        let mut sdhci = Sdhci {
+            registers,
+            base_clock,
+            host_version,
+            rca: 0,
+            high_capacity: false,
+            blocks: 0,
+            transfer: Transfer::Pio,
+        };
+        sdhci.reset()?;
+        sdhci.identify()?;
+        Ok(sdhci)
+    }
+
+    /// Selects how block data is moved between the controller and memory.
+    pub fn set_transfer(&mut self, transfer: Transfer) {
+        self.transfer = transfer;
+    }
+
+    /// Returns `true` if the card is SDHC or SDXC and is therefore addressed
+    /// in blocks rather than bytes.
+    pub fn is_high_capacity(&self) -> bool {
+        self.high_capacity
+    }
+
+    /// Returns the capacity of the card in blocks.
+    pub fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    /// Spins until `done` returns `true`, for at most `timeout`.
+    fn wait_until<F: FnMut(&mut Self) -> bool>(&mut self, timeout: Duration, mut done: F) -> io::Result<()> {
+        let deadline = current_time() + timeout;
+        while !done(self) {
+            if current_time() > deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "sd controller timed out"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Spins until any bit in `mask` or an error is raised in `INTERRUPT`.
+    /// Acknowledges and returns the bits in `mask` that were raised.
+    fn wait_interrupt(&mut self, mask: u32, timeout: Duration) -> io::Result<u32> {
+        self.wait_until(timeout, |s| s.registers.INTERRUPT.read() & (mask | irpt::ERR_MASK) != 0)?;
+
+        let raised = self.registers.INTERRUPT.read();
+        if raised & irpt::ERR_MASK != 0 {
+            self.registers.INTERRUPT.write(raised);
+            return Err(decode_error(raised));
+        }
+        self.registers.INTERRUPT.write(raised & mask);
+        Ok(raised & mask)
+    }
+
+    /// Resets the whole host controller and enables the identification clock.
+    fn reset(&mut self) -> io::Result<()> {
+        self.registers.CONTROL0.write(0);
+        self.registers.CONTROL1.or_mask(control1::SRST_HC);
+        self.wait_until(Duration::from_millis(100), |s| {
+            !s.registers.CONTROL1.has_mask(control1::SRST_HC)
+        })?;
+
+        self.registers.CONTROL0.write(control0::BUS_POWER_3V3);
+        self.registers.CONTROL2.write(0);
+        let control1 = self.registers.CONTROL1.read() & !control1::DATA_TOUNIT_MASK;
+        self.registers
+            .CONTROL1
+            .write(control1 | control1::CLK_INTLEN | control1::DATA_TOUNIT_MAX);
+        self.set_clock(CLOCK_IDENTIFICATION)?;
+
+        // Latch every status bit but route none of them to the ARM IRQ line.
+        self.registers.IRPT_EN.write(0);
+        self.registers.IRPT_MASK.write(irpt::ALL);
+        self.registers.INTERRUPT.write(irpt::ALL);
+        Ok(())
+    }
+
+    /// Resets the command and/or data state machines selected by `lines`
+    /// after an error.
+    fn reset_lines(&mut self, lines: u32) -> io::Result<()> {
+        self.registers.CONTROL1.or_mask(lines);
+        self.wait_until(Duration::from_millis(100), |s| !s.registers.CONTROL1.has_mask(lines))?;
+        self.registers.INTERRUPT.write(irpt::ALL);
+        Ok(())
+    }
+
+    /// Returns the `CONTROL1` divider bits that bring the base clock down to
+    /// at most `target` Hz.
+    fn clock_divider(&self, target: u32) -> u32 {
+        if target >= self.base_clock {
+            return 0;
+        }
+
+        // SD clock = base / (2 * N).
+        let mut n = (self.base_clock + 2 * target - 1) / (2 * target);
+        if self.host_version < 2 {
+            // Spec 1.0/2.0 hosts only support power-of-two 8-bit dividers.
+            n = core::cmp::min(n.next_power_of_two(), 0x80);
+        } else {
+            n = core::cmp::min(n, 0x3FF);
+        }
+        ((n & 0xFF) << 8) | (((n >> 8) & 0b11) << 6)
+    }
+
+    /// Switches the SD clock to at most `target` Hz.
+    fn set_clock(&mut self, target: u32) -> io::Result<()> {
+        self.wait_until(Duration::from_millis(100), |s| {
+            s.registers.STATUS.read() & (status::CMD_INHIBIT | status::DAT_INHIBIT) == 0
+        })?;
+
+        self.registers.CONTROL1.and_mask(!control1::CLK_EN);
+        spin_sleep(Duration::from_millis(1));
+
+        let divider = self.clock_divider(target);
+        let control1 = self.registers.CONTROL1.read() & !control1::CLK_FREQ_MASK;
+        self.registers.CONTROL1.write(control1 | divider);
+        spin_sleep(Duration::from_millis(1));
+
+        self.wait_until(Duration::from_millis(100), |s| {
+            s.registers.CONTROL1.has_mask(control1::CLK_STABLE)
+        })?;
+        self.registers.CONTROL1.or_mask(control1::CLK_EN);
+        spin_sleep(Duration::from_millis(1));
+        Ok(())
+    }
+
+    /// Issues `cmd` with argument `arg` and waits for its response. `APP_CMD`
+    /// is sent first for application-specific commands. Returns the first
+    /// response word.
+    fn command(&mut self, cmd: Command, arg: u32) -> io::Result<u32> {
+        if cmd.app {
+            self.command(APP_CMD, self.rca << 16)?;
+        }
+
+        let inhibit = if cmd.cmdtm & ISDATA != 0 || cmd.response() == RSPNS_48_BUSY {
+            status::CMD_INHIBIT | status::DAT_INHIBIT
+        } else {
+            status::CMD_INHIBIT
+        };
+        self.wait_until(Duration::from_millis(500), |s| s.registers.STATUS.read() & inhibit == 0)?;
+
+        self.registers.INTERRUPT.write(irpt::ALL);
+        self.registers.ARG1.write(arg);
+        self.registers.CMDTM.write(cmd.cmdtm);
+        self.wait_interrupt(irpt::CMD_DONE, Duration::from_millis(500))?;
+
+        if cmd.response() == RSPNS_48_BUSY {
+            self.wait_interrupt(irpt::DATA_DONE, Duration::from_millis(500))?;
+        }
+        Ok(self.registers.RESP[0].read())
+    }
+
+    /// Runs the SD identification sequence and leaves the card selected in
+    /// transfer state with a 4-bit bus at the normal clock rate.
+    fn identify(&mut self) -> io::Result<()> {
+        self.command(GO_IDLE_STATE, 0)?;
+
+        // Only version 2.00+ cards answer CMD8; they may be high capacity.
+        let v2 = match self.command(SEND_IF_COND, IF_COND_3V3) {
+            Ok(resp) if resp & 0xFFF == IF_COND_3V3 => true,
+            Ok(_) => return Err(io::Error::new(io::ErrorKind::Other, "sd card voltage not supported")),
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                self.reset_lines(control1::SRST_CMD)?;
+                false
+            }
+            Err(e) => return Err(e),
+        };
+
+        let hcs = if v2 { OCR_HCS } else { 0 };
+        let deadline = current_time() + Duration::from_secs(1);
+        let ocr = loop {
+            let ocr = self.command(SD_SEND_OP_COND, OCR_VOLTAGE_WINDOW | hcs)?;
+            if ocr & OCR_BUSY != 0 {
+                break ocr;
+            }
+            if current_time() > deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "sd card did not power up"));
+            }
+            spin_sleep(Duration::from_millis(10));
+        };
+        self.high_capacity = ocr & OCR_HCS != 0;
+
+        self.command(ALL_SEND_CID, 0)?;
+        self.rca = self.command(SEND_RELATIVE_ADDR, 0)? >> 16;
+        self.command(SEND_CSD, self.rca << 16)?;
+        let csd = (0..4).fold(0u128, |csd, i| csd | (self.registers.RESP[i].read() as u128) << (32 * i));
+        self.blocks = csd_blocks(csd);
+
+        self.set_clock(CLOCK_NORMAL)?;
+        self.command(SELECT_CARD, self.rca << 16)?;
+        if !self.high_capacity {
+            self.command(SET_BLOCKLEN, Self::BLOCK_SIZE as u32)?;
+        }
+
+        self.command(SET_BUS_WIDTH, 0b10)?;
+        self.registers.CONTROL0.or_mask(control0::HCTL_DWIDTH);
+        Ok(())
+    }
+
+    /// Returns the command argument addressing block `lba`.
+    fn address(&self, lba: u64) -> io::Result<u32> {
+        let addr = if self.high_capacity { lba } else { lba * Self::BLOCK_SIZE as u64 };
+        if addr > core::u32::MAX as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "block address out of range"));
+        }
+        Ok(addr as u32)
+    }
+
+    /// Transfers `blocks` blocks between `buf` and the card starting at `lba`
+    /// in one command. `write` selects the direction. On failure, the command
+    /// and data lines are reset so the next transfer starts cleanly.
+    fn transfer(&mut self, lba: u64, buf: *mut u8, blocks: usize, write: bool) -> io::Result<usize> {
+        let result = self.do_transfer(lba, buf, blocks, write);
+        if result.is_err() {
+            let _ = self.reset_lines(control1::SRST_CMD | control1::SRST_DATA);
+        }
+        result
+    }
+
+    fn do_transfer(&mut self, lba: u64, buf: *mut u8, blocks: usize, write: bool) -> io::Result<usize> {
+        let cmd = match (write, blocks > 1) {
+            (false, false) => READ_SINGLE_BLOCK,
+            (false, true) => READ_MULTIPLE_BLOCK,
+            (true, false) => WRITE_BLOCK,
+            (true, true) => WRITE_MULTIPLE_BLOCK,
+        };
+        let addr = self.address(lba)?;
+
+        let mut cmdtm = cmd.cmdtm;
+        let mut blksizecnt = ((blocks as u32) << 16) | Self::BLOCK_SIZE as u32;
+        if self.transfer == Transfer::Sdma {
+            if buf as usize % 4 != 0 || buf as usize > core::u32::MAX as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "buffer not usable for sdma"));
+            }
+            self.registers.CONTROL0.and_mask(!control0::DMA_SELECT_MASK);
+            self.registers.ARG2.write(buf as u32);
+            blksizecnt |= SDMA_BOUNDARY_512K;
+            cmdtm |= TM_DMA_EN;
+        }
+        self.registers.BLKSIZECNT.write(blksizecnt);
+
+        let resp = self.command(Command { cmdtm, app: false }, addr)?;
+        if resp & R1_ERRORS_MASK != 0 {
+            return Err(io::Error::new(io::ErrorKind::Other, "sd card reported an error"));
+        }
+
+        if self.transfer == Transfer::Pio {
+            let ready = if write { irpt::WRITE_RDY } else { irpt::READ_RDY };
+            for block in 0..blocks {
+                self.wait_interrupt(ready, Duration::from_millis(500))?;
+                let block = unsafe { buf.add(block * Self::BLOCK_SIZE) };
+                for word in 0..Self::BLOCK_SIZE / 4 {
+                    unsafe {
+                        let ptr = block.add(word * 4) as *mut [u8; 4];
+                        if write {
+                            self.registers.DATA.write(u32::from_le_bytes(ptr.read_unaligned()));
+                        } else {
+                            ptr.write_unaligned(self.registers.DATA.read().to_le_bytes());
+                        }
+                    }
+                }
+            }
+        }
+
+        loop {
+            let raised = self.wait_interrupt(irpt::DATA_DONE | irpt::DMA, Duration::from_secs(1))?;
+            if raised & irpt::DATA_DONE != 0 {
+                break;
+            }
+            // SDMA stopped at a buffer boundary; resume at the next address.
+            let next = self.registers.ARG2.read();
+            self.registers.ARG2.write(next);
+        }
+        Ok(blocks * Self::BLOCK_SIZE)
+    }
+
+    /// Reads `buf.len() / BLOCK_SIZE` blocks starting at block `lba` into
+    /// `buf`, using multi-block transfers when more than one block is
+    /// requested. Returns the number of bytes read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `buf` is smaller than one
+    /// block or the address is out of range. Controller errors are decoded
+    /// into `TimedOut`, `InvalidData` or `Other`.
+    pub fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let blocks = buf.len() / Self::BLOCK_SIZE;
+        if blocks == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "buf too small"));
+        }
+
+        let mut done = 0;
+        while done < blocks {
+            let chunk = core::cmp::min(blocks - done, MAX_BLOCKS_PER_TRANSFER);
+            let ptr = buf[done * Self::BLOCK_SIZE..].as_mut_ptr();
+            self.transfer(lba + done as u64, ptr, chunk, false)?;
+            done += chunk;
+        }
+        Ok(done * Self::BLOCK_SIZE)
+    }
+
+    /// Writes `buf.len() / BLOCK_SIZE` blocks from `buf` starting at block
+    /// `lba`, using multi-block transfers when more than one block is
+    /// requested. Returns the number of bytes written.
+    ///
+    /// # Errors
+    ///
+    /// As for `read_blocks()`.
+    pub fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> io::Result<usize> {
+        let blocks = buf.len() / Self::BLOCK_SIZE;
+        if blocks == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "buf too small"));
+        }
+
+        let mut done = 0;
+        while done < blocks {
+            let chunk = core::cmp::min(blocks - done, MAX_BLOCKS_PER_TRANSFER);
+            let ptr = buf[done * Self::BLOCK_SIZE..].as_ptr() as *mut u8;
+            self.transfer(lba + done as u64, ptr, chunk, true)?;
+            done += chunk;
+        }
+        Ok(done * Self::BLOCK_SIZE)
+    }
+}
+
+impl fmt::Debug for Sdhci {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sdhci")
+            .field("base_clock", &self.base_clock)
+            .field("host_version", &self.host_version)
+            .field("rca", &self.rca)
+            .field("high_capacity", &self.high_capacity)
+            .field("blocks", &self.blocks)
+            .field("transfer", &self.transfer)
+            .finish()
+    }
+}
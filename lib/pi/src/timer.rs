@@ -7,6 +7,11 @@ use volatile::{ReadVolatile, Volatile};
 /// The base address for the ARM system timer registers.
 const TIMER_REG_BASE: usize = IO_BASE + 0x3000;
 
+/// The nearest a match can be set up, in microseconds. A match set up for a
+/// count the counter passes before the compare register is written would
+/// only occur once the counter wraps around, more than an hour later.
+const MIN_TICK_MICROS: u32 = 10;
+
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Registers {
@@ -38,11 +43,25 @@ impl Timer {
         Duration::from_micros(count)
     }
 
-    /// Sets up a match in timer 1 to occur `t` duration from now. If
-    /// interrupts for timer 1 are enabled and IRQs are unmasked, then a timer
-    /// interrupt will be issued in `t` duration.
+    /// Sets up a match in timer 1 to occur `t` duration from now, or
+    /// `MIN_TICK_MICROS` from now if `t` is shorter and `u32::MAX`
+    /// microseconds from now if it is longer. If interrupts for timer 1 are
+    /// enabled and IRQs are unmasked, then a timer interrupt will be issued
+    /// in `t` duration.
     pub fn tick_in(&mut self, t: Duration) {
-        unimplemented!()
+        // Acknowledge any earlier match before arming the next one.
+        self.registers.CS.write(1 << 1);
+        // The compare register matches only the low 32 bits of the counter.
+        let micros = core::cmp::min(t.as_micros(), core::u32::MAX as u128) as u32;
+        let micros = core::cmp::max(micros, MIN_TICK_MICROS);
+        let target = self.registers.CLO.read().wrapping_add(micros);
+        self.registers.COMPARE[1].write(target);
+    }
+
+    /// Acknowledges any match in timer 1 without setting up another, so that
+    /// no timer 1 interrupt is pending.
+    pub fn cancel_tick(&mut self) {
+        self.registers.CS.write(1 << 1);
     }
 }
 
@@ -60,9 +79,16 @@ pub fn spin_sleep(t: Duration) {
     }
 }
 
-/// Sets up a match in timer 1 to occur `t` duration from now. If
-/// interrupts for timer 1 are enabled and IRQs are unmasked, then a timer
-/// interrupt will be issued in `t` duration.
+/// Sets up a match in timer 1 to occur `t` duration from now, or
+/// `MIN_TICK_MICROS` from now if `t` is shorter and `u32::MAX` microseconds
+/// from now if it is longer. If interrupts for timer 1 are enabled and IRQs
+/// are unmasked, then a timer interrupt will be issued in `t` duration.
 pub fn tick_in(t: Duration) {
-    unimplemented!()
+    Timer::new().tick_in(t)
+}
+
+/// Acknowledges any match in timer 1 without setting up another, so that no
+/// timer 1 interrupt is pending.
+pub fn cancel_tick() {
+    Timer::new().cancel_tick()
 }
@@ -92,6 +92,11 @@ impl MiniUart {
         self.timeout = Some(t);
     }
 
+    /// Removes the read timeout; reads block until a byte is available.
+    pub fn clear_read_timeout(&mut self) {
+        self.timeout = None;
+    }
+
     /// Write the byte `byte`. This method blocks until there is space available
     /// in the output FIFO.
     pub fn write_byte(&mut self, byte: u8) {
@@ -22,9 +22,12 @@ unsafe fn zeros_bss() {
     }
 }
 
+/// The entry point, called by the kernel with the argument block it built on
+/// the stack.
 #[no_mangle]
-pub unsafe extern "C" fn _start() -> ! {
+pub unsafe extern "C" fn _start(argc: usize, argv: *const *const u8, envp: *const *const u8) -> ! {
     zeros_bss();
-    crate::main();
-    kernel_api::syscall::exit();
+    kernel_api::env::init(argc, argv, envp);
+    crate::main(kernel_api::env::args());
+    kernel_api::syscall::exit(0);
 }